@@ -0,0 +1,103 @@
+//! End-to-end run of the reference `RepoIndexer`
+//!
+//! Indexes a source tree with the hashing embedder, answers a query,
+//! reconciles a second time (expecting no work), and checks that a saved
+//! snapshot answers the query identically. Exits non-zero on any mismatch,
+//! so it doubles as an integration check.
+//!
+//! ```text
+//! cargo run --example repo_indexer --target x86_64-unknown-linux-gnu -- src "search layer"
+//! ```
+
+use hnsw::indexer::{HashingEmbedder, LineChunker, RepoIndexer};
+use hnsw::HNSWParams;
+use std::path::Path;
+use std::process::ExitCode;
+
+const EXTENSIONS: &[&str] = &["rs", "ts", "js", "py", "go", "md"];
+
+fn collect_files(dir: &Path, files: &mut Vec<(String, String)>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else if path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| EXTENSIONS.contains(&e))
+        {
+            if let Ok(content) = std::fs::read_to_string(&path) {
+                files.push((path.display().to_string(), content));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run(root: &str, query: &str) -> Result<(), String> {
+    let mut files = Vec::new();
+    collect_files(Path::new(root), &mut files).map_err(|e| format!("reading {}: {}", root, e))?;
+    files.sort();
+
+    let embedder = HashingEmbedder::new(256);
+    let mut indexer = RepoIndexer::new(embedder, HNSWParams::default(), LineChunker::default());
+
+    let report = indexer
+        .reconcile(files.clone())
+        .map_err(|e| e.to_string())?;
+    println!(
+        "indexed {} files into {} chunks ({:?})",
+        indexer.file_count(),
+        indexer.index().len(),
+        report
+    );
+
+    let hits = indexer.search(query, 5).map_err(|e| e.to_string())?;
+    for hit in &hits {
+        println!(
+            "  {:.3}  {}:{}-{}",
+            hit.score, hit.path, hit.start_line, hit.end_line
+        );
+    }
+
+    let again = indexer
+        .reconcile(files.clone())
+        .map_err(|e| e.to_string())?;
+    if again.unchanged != files.len() || again.added + again.updated + again.removed != 0 {
+        return Err(format!("second reconcile did work: {:?}", again));
+    }
+
+    let snapshot = indexer.save().map_err(|e| e.to_string())?;
+    let restored = RepoIndexer::load(embedder, &snapshot).map_err(|e| e.to_string())?;
+    if restored.search(query, 5).map_err(|e| e.to_string())? != hits {
+        return Err("restored snapshot answers differently".to_string());
+    }
+
+    let dropped = files.len() / 2;
+    let report = indexer
+        .reconcile(files.into_iter().skip(dropped))
+        .map_err(|e| e.to_string())?;
+    if report.removed != dropped {
+        return Err(format!("expected {} removals, got {:?}", dropped, report));
+    }
+    indexer.index().validate().map_err(|e| e.to_string())?;
+
+    println!("ok: snapshot {} bytes", snapshot.len());
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+    let root = args.next().unwrap_or_else(|| "src".to_string());
+    let query = args
+        .next()
+        .unwrap_or_else(|| "search layer neighbors".to_string());
+
+    match run(&root, &query) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("repo_indexer: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
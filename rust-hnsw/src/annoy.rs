@@ -0,0 +1,71 @@
+//! Annoy index import
+//!
+//! An Annoy file is an array of fixed-size nodes: the items by number,
+//! then the split nodes of its trees, the last of them a root whose
+//! descendant count is the item count. Nothing records the dimensions or
+//! metric that fix the node size, so callers pass both. Annoy's trees
+//! are no use to a graph index, so only the item vectors are read and
+//! the graph is built from them.
+
+use crate::index::Hnsw;
+use crate::scalar::Scalar;
+use crate::{HNSWParams, HnswError};
+
+impl<S: Scalar> Hnsw<S> {
+    /// Build an index from the items of an Annoy file, named by number
+    ///
+    /// `metric` is the one the file was built with: `angular`,
+    /// `euclidean`, `manhattan` or `dot`.
+    pub fn from_annoy(
+        data: &[u8],
+        dimensions: usize,
+        metric: &str,
+        params: HNSWParams,
+    ) -> Result<Hnsw<S>, HnswError> {
+        // Node header before the vector: descendant count, then children
+        // and the metric's extra float in some order
+        let header = match metric {
+            "angular" => 12,
+            "euclidean" | "manhattan" | "dot" => 16,
+            other => {
+                return Err(HnswError::InvalidParams(format!(
+                    "unknown Annoy metric {}",
+                    other
+                )))
+            }
+        };
+        let node_bytes = dimensions
+            .checked_mul(4)
+            .and_then(|bytes| bytes.checked_add(header))
+            .filter(|_| dimensions > 0)
+            .ok_or_else(|| HnswError::InvalidParams("invalid dimensions".to_string()))?;
+        if data.is_empty() || data.len() % node_bytes != 0 {
+            return Err(annoy_error("file size is not a whole number of nodes"));
+        }
+
+        let nodes: Vec<&[u8]> = data.chunks_exact(node_bytes).collect();
+        let descendants = |node: &[u8]| i32::from_le_bytes([node[0], node[1], node[2], node[3]]);
+        let items = usize::try_from(descendants(nodes[nodes.len() - 1]))
+            .ok()
+            .filter(|&items| items <= nodes.len())
+            .ok_or_else(|| annoy_error("root node is malformed"))?;
+
+        let mut index = Hnsw::with_params(params);
+        for (item, node) in nodes[..items].iter().enumerate() {
+            // Items never added are left zeroed
+            if descendants(node) != 1 {
+                continue;
+            }
+            let vector = node[header..]
+                .chunks_exact(4)
+                .map(|b| S::from_f64(f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64))
+                .collect();
+            index.insert(item.to_string(), vector)?;
+        }
+        Ok(index)
+    }
+}
+
+fn annoy_error(msg: &str) -> HnswError {
+    HnswError::Deserialization(format!("Annoy index: {}", msg))
+}
@@ -0,0 +1,465 @@
+//! Apache Arrow IPC stream export
+//!
+//! Points are written in id order, [`BATCH_ROWS`] per record batch, as an
+//! `id` utf8 column, a `vector` fixed-size list of float32 and one
+//! nullable column per top-level payload field. The flatbuffer metadata
+//! follows `Schema.fbs` and `Message.fbs` of metadata version 5 and is
+//! written directly rather than through a flatbuffers dependency.
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+
+use crate::ids::Id;
+use crate::index::Hnsw;
+use crate::scalar::Scalar;
+use crate::store::VectorStore;
+use crate::HnswError;
+
+/// Rows per record batch
+const BATCH_ROWS: usize = 64 * 1024;
+const CONTINUATION: u32 = 0xFFFF_FFFF;
+const METADATA_V5: i16 = 4;
+// `MessageHeader` union
+const HEADER_SCHEMA: u8 = 1;
+const HEADER_RECORD_BATCH: u8 = 3;
+// `Type` union
+const TYPE_INT: u8 = 2;
+const TYPE_FLOATING_POINT: u8 = 3;
+const TYPE_UTF8: u8 = 5;
+const TYPE_BOOL: u8 = 6;
+const TYPE_FIXED_SIZE_LIST: u8 = 16;
+const PRECISION_SINGLE: i16 = 1;
+const PRECISION_DOUBLE: i16 = 2;
+
+/// Value type of a payload column
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ColumnKind {
+    Bool,
+    Int,
+    Float,
+    Text,
+    /// Mixed or nested values, kept as JSON text
+    Json,
+}
+
+impl ColumnKind {
+    fn of(value: &Value) -> Option<ColumnKind> {
+        match value {
+            Value::Null => None,
+            Value::Bool(_) => Some(ColumnKind::Bool),
+            Value::Number(n) if n.is_i64() => Some(ColumnKind::Int),
+            Value::Number(_) => Some(ColumnKind::Float),
+            Value::String(_) => Some(ColumnKind::Text),
+            _ => Some(ColumnKind::Json),
+        }
+    }
+
+    fn merge(self, other: ColumnKind) -> ColumnKind {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (ColumnKind::Int, ColumnKind::Float) | (ColumnKind::Float, ColumnKind::Int) => {
+                ColumnKind::Float
+            }
+            _ => ColumnKind::Json,
+        }
+    }
+}
+
+/// A top-level payload field exported as its own column
+pub(crate) struct PayloadColumn {
+    /// Column name, the field prefixed with `payload.` where it would
+    /// clash with a point column
+    pub name: String,
+    pub field: String,
+    pub kind: ColumnKind,
+}
+
+impl<S: Scalar, V: VectorStore<S>> Hnsw<S, V> {
+    /// Payload fields in name order, typed by the values they hold
+    ///
+    /// `reserved` are the point columns of the export.
+    pub(crate) fn payload_columns(&self, reserved: &[&str]) -> Vec<PayloadColumn> {
+        let mut kinds: BTreeMap<&str, Option<ColumnKind>> = BTreeMap::new();
+        for payload in self.payloads().values() {
+            let Some(object) = payload.as_object() else {
+                continue;
+            };
+            for (field, value) in object {
+                let kind = kinds.entry(field).or_insert(None);
+                if let Some(other) = ColumnKind::of(value) {
+                    *kind = Some(kind.map_or(other, |kind| kind.merge(other)));
+                }
+            }
+        }
+        kinds
+            .into_iter()
+            .map(|(field, kind)| PayloadColumn {
+                name: if reserved.contains(&field) {
+                    format!("payload.{}", field)
+                } else {
+                    field.to_string()
+                },
+                field: field.to_string(),
+                kind: kind.unwrap_or(ColumnKind::Json),
+            })
+            .collect()
+    }
+
+    /// Export ids, stored vectors and payloads as an Arrow IPC stream
+    pub fn to_arrow(&self) -> Result<Vec<u8>, HnswError> {
+        let dim = self.stored_dimensions();
+        let columns = self.payload_columns(&["id", "vector"]);
+        let mut out = Vec::new();
+        write_message(&mut out, HEADER_SCHEMA, schema(dim, &columns), &[])?;
+
+        let ids: Vec<String> = self.ids().map(Id::to_string).collect();
+        for chunk in ids.chunks(BATCH_ROWS) {
+            let mut body = Body::default();
+            body.strings(chunk.iter().map(|id| Some(id.to_string())));
+
+            body.nodes.push((chunk.len() as i64, 0));
+            body.buffer(&[]);
+            let mut values = Vec::with_capacity(chunk.len() * dim * 4);
+            for id in chunk {
+                let vector = self
+                    .vector(id)
+                    .ok_or_else(|| HnswError::Storage(format!("vector of {} is missing", id)))?;
+                for x in vector.iter() {
+                    values.extend_from_slice(&(x.to_f64() as f32).to_le_bytes());
+                }
+            }
+            body.nodes.push(((chunk.len() * dim) as i64, 0));
+            body.buffer(&[]);
+            body.buffer(&values);
+
+            for column in &columns {
+                let cells: Vec<Option<&Value>> = chunk
+                    .iter()
+                    .map(|id| {
+                        self.payload(id)
+                            .and_then(|payload| payload.get(&column.field))
+                            .filter(|value| !value.is_null())
+                    })
+                    .collect();
+                body.column(column.kind, &cells);
+            }
+
+            let header = Table(vec![
+                (0, Fb::I64(chunk.len() as i64)),
+                (1, Fb::Pairs(std::mem::take(&mut body.nodes))),
+                (2, Fb::Pairs(std::mem::take(&mut body.buffers))),
+            ]);
+            write_message(&mut out, HEADER_RECORD_BATCH, header, &body.bytes)?;
+        }
+
+        // End-of-stream marker
+        out.extend_from_slice(&CONTINUATION.to_le_bytes());
+        out.extend_from_slice(&0u32.to_le_bytes());
+        Ok(out)
+    }
+}
+
+fn schema(dim: usize, columns: &[PayloadColumn]) -> Table {
+    let item = field(
+        "item",
+        false,
+        TYPE_FLOATING_POINT,
+        float(PRECISION_SINGLE),
+        vec![],
+    );
+    let mut fields = vec![
+        field("id", false, TYPE_UTF8, Table(vec![]), vec![]),
+        field(
+            "vector",
+            false,
+            TYPE_FIXED_SIZE_LIST,
+            Table(vec![(0, Fb::I32(dim as i32))]),
+            vec![item],
+        ),
+    ];
+    for column in columns {
+        let (type_id, ty) = match column.kind {
+            ColumnKind::Bool => (TYPE_BOOL, Table(vec![])),
+            ColumnKind::Int => (TYPE_INT, Table(vec![(0, Fb::I32(64)), (1, Fb::U8(1))])),
+            ColumnKind::Float => (TYPE_FLOATING_POINT, float(PRECISION_DOUBLE)),
+            ColumnKind::Text | ColumnKind::Json => (TYPE_UTF8, Table(vec![])),
+        };
+        fields.push(field(&column.name, true, type_id, ty, vec![]));
+    }
+    Table(vec![(1, Fb::Tables(fields))])
+}
+
+fn field(name: &str, nullable: bool, type_id: u8, ty: Table, children: Vec<Table>) -> Table {
+    Table(vec![
+        (0, Fb::Str(name.to_string())),
+        (1, Fb::U8(nullable as u8)),
+        (2, Fb::U8(type_id)),
+        (3, Fb::Table(ty)),
+        (5, Fb::Tables(children)),
+    ])
+}
+
+fn float(precision: i16) -> Table {
+    Table(vec![(0, Fb::I16(precision))])
+}
+
+/// Frame one message: continuation marker, metadata length, metadata,
+/// then the body
+fn write_message(out: &mut Vec<u8>, kind: u8, header: Table, body: &[u8]) -> Result<(), HnswError> {
+    let message = Table(vec![
+        (0, Fb::I16(METADATA_V5)),
+        (1, Fb::U8(kind)),
+        (2, Fb::Table(header)),
+        (3, Fb::I64(body.len() as i64)),
+    ]);
+    let metadata = FbWriter::finish(&message);
+    let len = i32::try_from(metadata.len())
+        .map_err(|_| HnswError::Serialization("Arrow metadata exceeds 2 GiB".to_string()))?;
+    out.extend_from_slice(&CONTINUATION.to_le_bytes());
+    out.extend_from_slice(&len.to_le_bytes());
+    out.extend_from_slice(&metadata);
+    out.extend_from_slice(body);
+    Ok(())
+}
+
+/// Buffers and field nodes of a record batch being written
+#[derive(Default)]
+struct Body {
+    bytes: Vec<u8>,
+    nodes: Vec<(i64, i64)>,
+    buffers: Vec<(i64, i64)>,
+}
+
+impl Body {
+    fn buffer(&mut self, data: &[u8]) {
+        self.buffers
+            .push((self.bytes.len() as i64, data.len() as i64));
+        self.bytes.extend_from_slice(data);
+        self.bytes.resize(self.bytes.len().next_multiple_of(8), 0);
+    }
+
+    /// Field node and validity buffer, empty when nothing is null
+    fn validity(&mut self, present: impl ExactSizeIterator<Item = bool>) {
+        let len = present.len();
+        let (bitmap, count) = bitmap(present);
+        self.nodes.push((len as i64, (len - count) as i64));
+        self.buffer(if count == len { &[] } else { &bitmap });
+    }
+
+    fn strings(&mut self, values: impl ExactSizeIterator<Item = Option<String>>) {
+        let values: Vec<Option<String>> = values.collect();
+        self.validity(values.iter().map(Option::is_some));
+        let mut offsets = Vec::with_capacity((values.len() + 1) * 4);
+        let mut data = Vec::new();
+        offsets.extend_from_slice(&0i32.to_le_bytes());
+        for value in &values {
+            data.extend_from_slice(value.as_deref().unwrap_or("").as_bytes());
+            offsets.extend_from_slice(&(data.len() as i32).to_le_bytes());
+        }
+        self.buffer(&offsets);
+        self.buffer(&data);
+    }
+
+    fn column(&mut self, kind: ColumnKind, cells: &[Option<&Value>]) {
+        match kind {
+            ColumnKind::Bool => {
+                self.validity(cells.iter().map(Option::is_some));
+                let values = cells
+                    .iter()
+                    .map(|v| v.and_then(Value::as_bool) == Some(true));
+                self.buffer(&bitmap(values).0);
+            }
+            ColumnKind::Int => {
+                self.validity(cells.iter().map(Option::is_some));
+                let values: Vec<u8> = cells
+                    .iter()
+                    .flat_map(|v| v.and_then(Value::as_i64).unwrap_or(0).to_le_bytes())
+                    .collect();
+                self.buffer(&values);
+            }
+            ColumnKind::Float => {
+                self.validity(cells.iter().map(Option::is_some));
+                let values: Vec<u8> = cells
+                    .iter()
+                    .flat_map(|v| v.and_then(Value::as_f64).unwrap_or(0.0).to_le_bytes())
+                    .collect();
+                self.buffer(&values);
+            }
+            ColumnKind::Text => self.strings(
+                cells
+                    .iter()
+                    .map(|v| v.and_then(Value::as_str).map(str::to_string)),
+            ),
+            ColumnKind::Json => self.strings(cells.iter().map(|v| v.map(Value::to_string))),
+        }
+    }
+}
+
+/// LSB-first bitmap and its count of set bits
+fn bitmap(bits: impl Iterator<Item = bool>) -> (Vec<u8>, usize) {
+    let mut out = Vec::new();
+    let mut count = 0;
+    for (i, bit) in bits.enumerate() {
+        if i % 8 == 0 {
+            out.push(0);
+        }
+        if bit {
+            out[i / 8] |= 1 << (i % 8);
+            count += 1;
+        }
+    }
+    (out, count)
+}
+
+/// A flatbuffer table as `(field id, value)` pairs
+struct Table(Vec<(u16, Fb)>);
+
+/// Flatbuffer field values used by the IPC metadata
+enum Fb {
+    U8(u8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    Str(String),
+    Table(Table),
+    Tables(Vec<Table>),
+    /// Vector of `FieldNode` or `Buffer` structs, two `i64` each
+    Pairs(Vec<(i64, i64)>),
+}
+
+impl Fb {
+    /// Bytes the value takes inside its table; 4 for offsets
+    fn inline_size(&self) -> usize {
+        match self {
+            Fb::U8(_) => 1,
+            Fb::I16(_) => 2,
+            Fb::I64(_) => 8,
+            _ => 4,
+        }
+    }
+}
+
+/// Writes a flatbuffer front to back: every table is preceded by its
+/// vtable and followed by the objects it references, whose forward
+/// offsets are patched in once they are placed
+struct FbWriter {
+    out: Vec<u8>,
+}
+
+impl FbWriter {
+    /// Serialize `root`, padded to 8 bytes
+    fn finish(root: &Table) -> Vec<u8> {
+        let mut writer = FbWriter { out: vec![0; 4] };
+        let at = writer.table(root);
+        writer.patch(0, at);
+        writer.pad(8, 0);
+        writer.out
+    }
+
+    fn pad(&mut self, align: usize, rem: usize) {
+        while self.out.len() % align != rem {
+            self.out.push(0);
+        }
+    }
+
+    /// Point the `uoffset` at `at` to `target`
+    fn patch(&mut self, at: usize, target: usize) {
+        self.out[at..at + 4].copy_from_slice(&((target - at) as u32).to_le_bytes());
+    }
+
+    fn table(&mut self, table: &Table) -> usize {
+        // Widest fields first, so starting them 8-aligned aligns them all
+        let mut fields: Vec<&(u16, Fb)> = table.0.iter().collect();
+        fields.sort_by_key(|(_, value)| Reverse(value.inline_size()));
+        let slots_len = fields
+            .iter()
+            .map(|(id, _)| *id as usize + 1)
+            .max()
+            .unwrap_or(0);
+        let mut slots = vec![0u16; slots_len];
+        let mut size = 4;
+        for (id, value) in &fields {
+            slots[*id as usize] = size as u16;
+            size += value.inline_size();
+        }
+
+        self.pad(2, 0);
+        let vtable = self.out.len();
+        self.out
+            .extend_from_slice(&((4 + 2 * slots_len) as u16).to_le_bytes());
+        self.out.extend_from_slice(&(size as u16).to_le_bytes());
+        for slot in &slots {
+            self.out.extend_from_slice(&slot.to_le_bytes());
+        }
+        self.pad(8, 4);
+        let start = self.out.len();
+        self.out
+            .extend_from_slice(&((start - vtable) as i32).to_le_bytes());
+        self.out.resize(start + size, 0);
+
+        let mut children = Vec::new();
+        for (id, value) in fields {
+            let at = start + slots[*id as usize] as usize;
+            let bytes: &[u8] = match value {
+                Fb::U8(v) => &[*v],
+                Fb::I16(v) => &v.to_le_bytes(),
+                Fb::I32(v) => &v.to_le_bytes(),
+                Fb::I64(v) => &v.to_le_bytes(),
+                _ => {
+                    children.push((at, value));
+                    continue;
+                }
+            };
+            self.out[at..at + bytes.len()].copy_from_slice(bytes);
+        }
+        for (at, value) in children {
+            let target = self.object(value);
+            self.patch(at, target);
+        }
+        start
+    }
+
+    /// Place a referenced object, returning its position
+    fn object(&mut self, value: &Fb) -> usize {
+        match value {
+            Fb::Str(s) => {
+                self.pad(4, 0);
+                let at = self.out.len();
+                self.out.extend_from_slice(&(s.len() as u32).to_le_bytes());
+                self.out.extend_from_slice(s.as_bytes());
+                self.out.push(0);
+                at
+            }
+            Fb::Table(table) => self.table(table),
+            Fb::Tables(tables) => {
+                self.pad(4, 0);
+                let at = self.out.len();
+                self.out
+                    .extend_from_slice(&(tables.len() as u32).to_le_bytes());
+                self.out.resize(at + 4 + 4 * tables.len(), 0);
+                for (i, table) in tables.iter().enumerate() {
+                    let target = self.table(table);
+                    self.patch(at + 4 + 4 * i, target);
+                }
+                at
+            }
+            Fb::Pairs(pairs) => {
+                // Elements hold i64s, so they start 8-aligned
+                self.pad(8, 4);
+                let at = self.out.len();
+                self.out
+                    .extend_from_slice(&(pairs.len() as u32).to_le_bytes());
+                for (a, b) in pairs {
+                    self.out.extend_from_slice(&a.to_le_bytes());
+                    self.out.extend_from_slice(&b.to_le_bytes());
+                }
+                at
+            }
+            Fb::U8(_) | Fb::I16(_) | Fb::I32(_) | Fb::I64(_) => {
+                unreachable!("scalars are stored inline")
+            }
+        }
+    }
+}
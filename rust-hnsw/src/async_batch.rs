@@ -0,0 +1,90 @@
+//! Cooperative bulk inserts for the wasm index classes
+//!
+//! `addBatchAsync` inserts a batch a slice at a time. Each slice runs for
+//! about [`SLICE_MS`] and then hands control back to the event loop, so a
+//! page keeps painting and handling input while a large batch goes in.
+//! The returned promise settles once the last slice has run, or with the
+//! first insert that fails.
+
+use js_sys::{Date, Function, Promise};
+use wasm_bindgen::prelude::*;
+
+use crate::index::Hnsw;
+use crate::scalar::Scalar;
+use crate::turns::Turns;
+
+/// Milliseconds of inserting before yielding, well within one frame
+const SLICE_MS: f64 = 8.0;
+
+/// Batch being inserted between turns of the event loop
+///
+/// Dropping it, as freeing the index does, cancels the remaining slices
+/// and rejects the promise.
+pub(crate) struct AsyncBatch {
+    turns: Turns,
+}
+
+impl AsyncBatch {
+    /// Insert `ids.len()` vectors packed in `vectors` into `index`, a
+    /// slice per turn of the event loop
+    ///
+    /// `progress(done, total)` is called after each slice. The promise
+    /// resolves to the number of vectors inserted.
+    ///
+    /// # Safety
+    ///
+    /// `index` must point to an index that outlives the returned value and
+    /// does not move. The wasm classes keep theirs boxed by wasm-bindgen
+    /// and drop this alongside it. Timers only fire between calls into
+    /// wasm, so no other reference to the index is live during a slice.
+    pub(crate) unsafe fn start<S: Scalar>(
+        index: *mut Hnsw<S>,
+        ids: Vec<String>,
+        vectors: Vec<S>,
+        dim: usize,
+        progress: Option<Function>,
+    ) -> Result<(AsyncBatch, Promise), JsValue> {
+        let total = ids.len();
+        let mut ids = ids.into_iter();
+        let mut done = 0;
+        let slice = move || {
+            let failed = {
+                // SAFETY: see `start`; the reference ends before `progress`
+                // runs, which may call back into the index
+                let index = unsafe { &mut *index };
+                let deadline = Date::now() + SLICE_MS;
+                let mut failed = None;
+                for id in ids.by_ref() {
+                    let vector = vectors[done * dim..(done + 1) * dim].to_vec();
+                    if let Err(e) = index.insert(id, vector) {
+                        failed = Some(e);
+                        break;
+                    }
+                    done += 1;
+                    if Date::now() >= deadline {
+                        break;
+                    }
+                }
+                failed
+            };
+            if let Some(progress) = &progress {
+                let _ = progress.call2(
+                    &JsValue::NULL,
+                    &JsValue::from_f64(done as f64),
+                    &JsValue::from_f64(total as f64),
+                );
+            }
+            match failed {
+                Some(e) => Err(JsValue::from(e)),
+                None => Ok((done == total).then(|| JsValue::from_f64(done as f64))),
+            }
+        };
+        let (turns, promise) = Turns::start(slice, "index was freed before the batch was added")?;
+        Ok((AsyncBatch { turns }, promise))
+    }
+
+    /// Whether slices are still to run
+    pub(crate) fn is_running(&self) -> bool {
+        self.turns.is_running()
+    }
+}
@@ -0,0 +1,110 @@
+//! Periodic saving for the wasm index classes
+//!
+//! `enableAutoSave` arms a timer that hands a snapshot to a JS sink
+//! whenever the index [generation](crate::Hnsw::generation) has moved
+//! since the last save. Saves are debounced: a tick that sees the index
+//! still changing waits for it to settle, up to [`MAX_DEFERRED_TICKS`].
+
+use js_sys::{Function, Reflect, Uint8Array};
+use std::cell::Cell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use crate::index::Hnsw;
+use crate::scalar::Scalar;
+
+/// Ticks a save may be put off while writes keep arriving
+const MAX_DEFERRED_TICKS: u32 = 4;
+
+/// Running auto-save timer, cleared on drop
+pub(crate) struct AutoSave {
+    timer: JsValue,
+    _tick: Closure<dyn FnMut()>,
+    /// Generation of the last save, shared with the tick
+    saved: Rc<Cell<u64>>,
+    /// Generation seen by the last tick, shared with the tick
+    seen: Rc<Cell<u64>>,
+}
+
+impl AutoSave {
+    /// Call `sink(bytes)` every `interval_ms` when `index` has changed
+    ///
+    /// # Safety
+    ///
+    /// `index` must point to an index that outlives the returned value and
+    /// does not move. The wasm classes keep theirs boxed by wasm-bindgen
+    /// and drop this alongside it. Timers only fire between calls into
+    /// wasm, so no other reference to the index is live during a tick.
+    pub(crate) unsafe fn start<S: Scalar>(
+        index: *const Hnsw<S>,
+        interval_ms: u32,
+        sink: Function,
+    ) -> Result<AutoSave, JsValue> {
+        let start = (*index).generation();
+        let saved = Rc::new(Cell::new(start));
+        let seen = Rc::new(Cell::new(start));
+        let deferred = Cell::new(0);
+        let (tick_saved, tick_seen) = (saved.clone(), seen.clone());
+        let tick = Closure::<dyn FnMut()>::new(move || {
+            let (saved, seen) = (&tick_saved, &tick_seen);
+            // SAFETY: see `start`; the reference ends before `sink` runs
+            let index = unsafe { &*index };
+            let generation = index.generation();
+            let settled = generation == seen.replace(generation);
+            if generation == saved.get() {
+                return;
+            }
+            if !settled && deferred.get() < MAX_DEFERRED_TICKS {
+                deferred.set(deferred.get() + 1);
+                return;
+            }
+            let bytes = match index.to_bytes() {
+                Ok(bytes) => Uint8Array::from(&bytes[..]),
+                Err(e) => {
+                    web_sys::console::error_1(&format!("auto-save failed: {}", e).into());
+                    return;
+                }
+            };
+            match sink.call1(&JsValue::NULL, &bytes) {
+                Ok(_) => {
+                    saved.set(generation);
+                    deferred.set(0);
+                }
+                Err(e) => web_sys::console::error_2(&"auto-save sink threw".into(), &e),
+            }
+        });
+
+        let global = js_sys::global();
+        let set_interval: Function = Reflect::get(&global, &"setInterval".into())?.dyn_into()?;
+        let timer = set_interval.call2(
+            &global,
+            tick.as_ref().unchecked_ref(),
+            &JsValue::from(interval_ms),
+        )?;
+        Ok(AutoSave {
+            timer,
+            _tick: tick,
+            saved,
+            seen,
+        })
+    }
+
+    /// Count the index as saved at `generation`, e.g. after it was
+    /// replaced by a loaded snapshot
+    pub(crate) fn rebase(&self, generation: u64) {
+        self.saved.set(generation);
+        self.seen.set(generation);
+    }
+}
+
+impl Drop for AutoSave {
+    fn drop(&mut self) {
+        let global = js_sys::global();
+        if let Ok(clear) = Reflect::get(&global, &"clearInterval".into()) {
+            if let Some(clear) = clear.dyn_ref::<Function>() {
+                let _ = clear.call1(&global, &self.timer);
+            }
+        }
+    }
+}
@@ -0,0 +1,43 @@
+//! Beam width of a base-layer walk
+//!
+//! A walk keeps its `ef` best candidates; a wider beam finds more of the
+//! true neighbors for more distance computations. A fixed beam pays for
+//! `ef_search` on every query, though most queries settle well before
+//! that. An adaptive beam starts at `k` and doubles, each round resuming
+//! the walk where the narrower one stopped, until the top `k` survive
+//! [`SETTLED_ROUNDS`] doublings unchanged or the beam reaches its cap.
+//! One unchanged doubling is too weak a sign on its own: a narrow beam
+//! stuck in the wrong neighborhood often stays stuck for one more round.
+
+use crate::search::SearchOptions;
+
+/// Doublings in a row that must leave the top `k` unchanged before an
+/// adaptive beam stops widening
+pub(crate) const SETTLED_ROUNDS: usize = 2;
+
+/// How many candidates a search keeps while walking the base layer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Beam {
+    /// This many, or `k` if more
+    Fixed(usize),
+    /// From `k` up to this many, until the top `k` stop changing
+    Adaptive(usize),
+}
+
+impl Beam {
+    /// Beam for a search under `options` on an index searching at
+    /// `ef_search` unless the options say otherwise
+    pub fn for_search(options: &SearchOptions, ef_search: usize) -> Beam {
+        match options.adaptive_ef {
+            Some(cap) => Beam::Adaptive(cap),
+            None => Beam::Fixed(options.ef.unwrap_or(ef_search)),
+        }
+    }
+
+    /// Widest beam walked for `k` results
+    pub fn widest(self, k: usize) -> usize {
+        match self {
+            Beam::Fixed(ef) | Beam::Adaptive(ef) => ef.max(k),
+        }
+    }
+}
@@ -0,0 +1,142 @@
+//! Recall and throughput on an ann-benchmarks dataset
+//!
+//! Builds an index over the `train` vectors of an ann-benchmarks HDF5
+//! file, then searches its `test` queries at each `--ef` setting and
+//! reports recall against the file's ground truth with queries per
+//! second.
+//!
+//! ```text
+//! cargo run --release --bin ann_bench --features hdf5 \
+//!     --target x86_64-unknown-linux-gnu -- glove-100-angular.hdf5 --ef 10,40,160
+//! ```
+
+use hnsw::{AnnDataset, HNSWParams};
+use std::process::ExitCode;
+use std::time::Instant;
+
+/// Benchmark configuration, parsed from the dataset path and
+/// `--flag value` pairs
+struct Config {
+    path: String,
+    m: usize,
+    ef_construction: usize,
+    k: usize,
+    ef: Vec<usize>,
+    queries: Option<usize>,
+}
+
+impl Config {
+    fn from_args() -> Result<Config, String> {
+        let mut args = std::env::args().skip(1);
+        let mut config = Config {
+            path: String::new(),
+            m: HNSWParams::default().m,
+            ef_construction: HNSWParams::default().ef_construction,
+            k: 10,
+            ef: vec![10, 20, 40, 80, 160, 320],
+            queries: None,
+        };
+
+        while let Some(flag) = args.next() {
+            if !flag.starts_with("--") {
+                config.path = flag;
+                continue;
+            }
+            let value = args
+                .next()
+                .ok_or_else(|| format!("missing value for {}", flag))?;
+            let bad = |e: &dyn std::fmt::Display| format!("invalid value for {}: {}", flag, e);
+
+            match flag.as_str() {
+                "--m" => config.m = value.parse().map_err(|e| bad(&e))?,
+                "--ef-construction" => {
+                    config.ef_construction = value.parse().map_err(|e| bad(&e))?
+                }
+                "--k" => config.k = value.parse().map_err(|e| bad(&e))?,
+                "--ef" => {
+                    config.ef = value
+                        .split(',')
+                        .map(|ef| ef.trim().parse())
+                        .collect::<Result<_, _>>()
+                        .map_err(|e| bad(&e))?
+                }
+                "--queries" => config.queries = Some(value.parse().map_err(|e| bad(&e))?),
+                _ => return Err(format!("unknown flag {}", flag)),
+            }
+        }
+
+        if config.path.is_empty() {
+            return Err("usage: ann_bench <dataset.hdf5> [--m N] [--ef-construction N] [--k N] [--ef N,N,..] [--queries N]".to_string());
+        }
+        if config.m == 0 || config.k == 0 || config.ef.is_empty() {
+            return Err("--m and --k must be positive and --ef non-empty".to_string());
+        }
+        Ok(config)
+    }
+}
+
+fn run(config: Config) -> Result<(), String> {
+    let mut dataset = AnnDataset::open(&config.path).map_err(|e| e.to_string())?;
+    if let Some(queries) = config.queries {
+        dataset.test.truncate(queries);
+        dataset.neighbors.truncate(queries);
+    }
+    let distance = dataset.distance.as_deref().unwrap_or("unknown");
+    println!(
+        "ann_bench: {} train, {} queries, {} dimensions, {} distance",
+        dataset.train.len(),
+        dataset.test.len(),
+        dataset.train.first().map_or(0, Vec::len),
+        distance
+    );
+    if distance != "angular" {
+        println!(
+            "ann_bench: note the index ranks by cosine, not {}",
+            distance
+        );
+    }
+
+    let params = HNSWParams {
+        m: config.m,
+        ef_construction: config.ef_construction,
+        ef_search: config.k,
+        ..HNSWParams::default()
+    };
+    let started = Instant::now();
+    let index = dataset.build(params).map_err(|e| e.to_string())?;
+    println!(
+        "ann_bench: built m={} ef_construction={} in {:.1}s",
+        config.m,
+        config.ef_construction,
+        started.elapsed().as_secs_f64()
+    );
+
+    println!("{:>8} {:>8} {:>12}", "ef", "recall", "qps");
+    for ef in config.ef {
+        let evaluation = dataset
+            .evaluate(&index, config.k, ef)
+            .map_err(|e| e.to_string())?;
+        println!(
+            "{:>8} {:>8.4} {:>12.0}",
+            evaluation.ef_search, evaluation.recall, evaluation.queries_per_second
+        );
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let config = match Config::from_args() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("ann_bench: {}", e);
+            return ExitCode::from(2);
+        }
+    };
+    match run(config) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("ann_bench: FAILED: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,469 @@
+//! Long-running soak test for the HNSW index
+//!
+//! Drives a mixed add/delete/search/persist workload through the native
+//! API for a configurable wall-clock duration, checking graph invariants,
+//! result sanity, recall against brute force, and heap growth as it goes.
+//! Meant to run nightly for hours; any violation exits non-zero.
+//!
+//! ```text
+//! cargo run --release --bin soak --target x86_64-unknown-linux-gnu -- \
+//!     --duration-secs 14400 --max-points 50000
+//! ```
+
+use hnsw::{best_first, HNSWParams, Hnsw};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::{HashMap, HashSet};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Global allocator wrapper tracking live and peak heap bytes
+struct CountingAllocator;
+
+static HEAP_CURRENT: AtomicUsize = AtomicUsize::new(0);
+static HEAP_PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let current = HEAP_CURRENT.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            HEAP_PEAK.fetch_max(current, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        HEAP_CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Soak run configuration, parsed from `--flag value` pairs
+struct Config {
+    duration: Duration,
+    dim: usize,
+    max_points: usize,
+    seed: u64,
+    k: usize,
+    ef_search: usize,
+    round_ops: usize,
+    persist_every: usize,
+    recall_queries: usize,
+    min_recall: f64,
+    max_growth: f64,
+    report_every: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            duration: Duration::from_secs(3600),
+            dim: 64,
+            max_points: 20_000,
+            seed: 0x5eed,
+            k: 10,
+            ef_search: 128,
+            round_ops: 2_000,
+            persist_every: 10,
+            recall_queries: 20,
+            min_recall: 0.7,
+            max_growth: 2.0,
+            report_every: Duration::from_secs(60),
+        }
+    }
+}
+
+impl Config {
+    fn from_args() -> Result<Config, String> {
+        let mut config = Config::default();
+        let mut args = std::env::args().skip(1);
+
+        while let Some(flag) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("missing value for {}", flag))?;
+            let bad = |e: &dyn std::fmt::Display| format!("invalid value for {}: {}", flag, e);
+
+            match flag.as_str() {
+                "--duration-secs" => {
+                    config.duration = Duration::from_secs(value.parse().map_err(|e| bad(&e))?)
+                }
+                "--dim" => config.dim = value.parse().map_err(|e| bad(&e))?,
+                "--max-points" => config.max_points = value.parse().map_err(|e| bad(&e))?,
+                "--seed" => config.seed = value.parse().map_err(|e| bad(&e))?,
+                "--k" => config.k = value.parse().map_err(|e| bad(&e))?,
+                "--ef-search" => config.ef_search = value.parse().map_err(|e| bad(&e))?,
+                "--round-ops" => config.round_ops = value.parse().map_err(|e| bad(&e))?,
+                "--persist-every" => config.persist_every = value.parse().map_err(|e| bad(&e))?,
+                "--recall-queries" => config.recall_queries = value.parse().map_err(|e| bad(&e))?,
+                "--min-recall" => config.min_recall = value.parse().map_err(|e| bad(&e))?,
+                "--max-growth" => config.max_growth = value.parse().map_err(|e| bad(&e))?,
+                "--report-secs" => {
+                    config.report_every = Duration::from_secs(value.parse().map_err(|e| bad(&e))?)
+                }
+                _ => return Err(format!("unknown flag {}", flag)),
+            }
+        }
+
+        if config.dim == 0 || config.k == 0 || config.ef_search == 0 || config.max_points == 0 {
+            return Err("--dim, --k, --ef-search and --max-points must be positive".to_string());
+        }
+        Ok(config)
+    }
+}
+
+/// Ground-truth copy of what the index should contain
+#[derive(Default)]
+struct Model {
+    ids: Vec<String>,
+    positions: HashMap<String, usize>,
+    vectors: HashMap<String, Vec<f32>>,
+}
+
+impl Model {
+    fn insert(&mut self, id: String, vector: Vec<f32>) {
+        self.positions.insert(id.clone(), self.ids.len());
+        self.ids.push(id.clone());
+        self.vectors.insert(id, vector);
+    }
+
+    fn remove(&mut self, id: &str) {
+        if let Some(pos) = self.positions.remove(id) {
+            self.ids.swap_remove(pos);
+            if let Some(moved) = self.ids.get(pos) {
+                self.positions.insert(moved.clone(), pos);
+            }
+            self.vectors.remove(id);
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Exact top-k by cosine similarity
+    fn brute_force(&self, query: &[f32], k: usize) -> Vec<String> {
+        let mut scored: Vec<(&String, f32)> = self
+            .vectors
+            .iter()
+            .map(|(id, v)| (id, cosine_similarity(query, v)))
+            .collect();
+        scored.sort_by(best_first);
+        scored
+            .into_iter()
+            .take(k)
+            .map(|(id, _)| id.clone())
+            .collect()
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    adds: u64,
+    deletes: u64,
+    upserts: u64,
+    updates: u64,
+    searches: u64,
+    persists: u64,
+}
+
+struct Soak {
+    config: Config,
+    rng: StdRng,
+    index: Hnsw,
+    model: Model,
+    counters: Counters,
+    next_id: u64,
+    baseline_bytes_per_point: Option<f64>,
+}
+
+impl Soak {
+    fn new(config: Config) -> Soak {
+        let rng = StdRng::seed_from_u64(config.seed);
+        let mut index = Hnsw::with_params(HNSWParams {
+            ef_search: config.ef_search,
+            ..HNSWParams::default()
+        });
+        index.seed(config.seed);
+        Soak {
+            config,
+            rng,
+            index,
+            model: Model::default(),
+            counters: Counters::default(),
+            next_id: 0,
+            baseline_bytes_per_point: None,
+        }
+    }
+
+    fn random_vector(&mut self) -> Vec<f32> {
+        (0..self.config.dim)
+            .map(|_| self.rng.gen_range(-1.0..1.0))
+            .collect()
+    }
+
+    fn run(&mut self) -> Result<(), String> {
+        let start = Instant::now();
+        let mut last_report = start;
+        let mut round = 0usize;
+        let mut next_persist = self.config.persist_every.max(1);
+
+        while start.elapsed() < self.config.duration {
+            round += 1;
+            for _ in 0..self.config.round_ops {
+                self.step()?;
+            }
+
+            self.index.validate().map_err(|e| e.to_string())?;
+            if self.index.len() != self.model.len() {
+                return Err(format!(
+                    "index holds {} points, model holds {}",
+                    self.index.len(),
+                    self.model.len()
+                ));
+            }
+
+            if round >= next_persist {
+                next_persist = round + self.config.persist_every.max(1);
+                self.persist_round_trip()?;
+            }
+
+            if last_report.elapsed() >= self.config.report_every {
+                last_report = Instant::now();
+                self.report(start.elapsed(), round)?;
+            }
+        }
+
+        self.report(start.elapsed(), round)
+    }
+
+    /// Run a single randomly chosen operation
+    fn step(&mut self) -> Result<(), String> {
+        let roll: f64 = self.rng.gen();
+        let full = self.model.len() >= self.config.max_points;
+
+        if self.model.len() == 0 || (roll < 0.5 && !full) {
+            let id = format!("chunk-{}", self.next_id);
+            self.next_id += 1;
+            let vector = self.random_vector();
+            self.index
+                .insert(id.clone(), vector.clone())
+                .map_err(|e| format!("insert {} failed: {}", id, e))?;
+            self.model.insert(id, vector);
+            self.counters.adds += 1;
+        } else if roll < 0.7 || full {
+            let id = self.model.ids[self.rng.gen_range(0..self.model.len())].clone();
+            if !self.index.remove(&id) {
+                return Err(format!("delete {} reported a missing point", id));
+            }
+            self.model.remove(&id);
+            self.counters.deletes += 1;
+        } else if roll < 0.75 {
+            let id = self.model.ids[self.rng.gen_range(0..self.model.len())].clone();
+            let vector = self.random_vector();
+            let replaced = self
+                .index
+                .upsert(id.clone(), vector.clone())
+                .map_err(|e| format!("upsert {} failed: {}", id, e))?;
+            if !replaced {
+                return Err(format!("upsert {} did not find the existing point", id));
+            }
+            self.model.vectors.insert(id, vector);
+            self.counters.upserts += 1;
+        } else if roll < 0.8 {
+            let id = self.model.ids[self.rng.gen_range(0..self.model.len())].clone();
+            let vector = self.random_vector();
+            let updated = self
+                .index
+                .update_vector(&id, vector.clone())
+                .map_err(|e| format!("update {} failed: {}", id, e))?;
+            if !updated {
+                return Err(format!("update {} reported a missing point", id));
+            }
+            self.model.vectors.insert(id, vector);
+            self.counters.updates += 1;
+        } else {
+            let query = self.random_vector();
+            self.check_search(&query)?;
+            self.counters.searches += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Search and verify the result set is well formed
+    fn check_search(&self, query: &[f32]) -> Result<Vec<String>, String> {
+        let results = self
+            .index
+            .nearest(query, self.config.k)
+            .map_err(|e| format!("search failed: {}", e))?;
+
+        if results.len() > self.config.k {
+            return Err(format!("search returned {} > k results", results.len()));
+        }
+        if results.windows(2).any(|w| w[0].1 < w[1].1) {
+            return Err("search results are not sorted by score".to_string());
+        }
+
+        let mut seen = HashSet::new();
+        for (id, score) in &results {
+            if !self.model.vectors.contains_key(id) {
+                return Err(format!("search returned deleted id {}", id));
+            }
+            if !seen.insert(id) {
+                return Err(format!("search returned {} twice", id));
+            }
+            if !score.is_finite() {
+                return Err(format!("search returned non-finite score for {}", id));
+            }
+        }
+
+        Ok(results.into_iter().map(|(id, _)| id).collect())
+    }
+
+    /// Save, reload, and continue the run on the reloaded index
+    fn persist_round_trip(&mut self) -> Result<(), String> {
+        let bytes = self.index.to_bytes().map_err(|e| e.to_string())?;
+        let mut loaded = Hnsw::deserialize(&bytes).map_err(|e| e.to_string())?;
+        loaded
+            .validate()
+            .map_err(|e| format!("reloaded index: {}", e))?;
+
+        if loaded.len() != self.index.len() {
+            return Err(format!(
+                "reloaded index holds {} points, saved {}",
+                loaded.len(),
+                self.index.len()
+            ));
+        }
+
+        let query = self.random_vector();
+        let before = self.index.nearest(&query, self.config.k);
+        let after = loaded.nearest(&query, self.config.k);
+        if before.map_err(|e| e.to_string())? != after.map_err(|e| e.to_string())? {
+            return Err("reloaded index answers a query differently".to_string());
+        }
+
+        // Keep later levels reproducible from --seed
+        loaded.seed(self.rng.gen());
+        self.index = loaded;
+        self.counters.persists += 1;
+        Ok(())
+    }
+
+    /// Mean recall@k over random queries against brute force
+    fn measure_recall(&mut self) -> Result<f64, String> {
+        if self.model.len() == 0 || self.config.recall_queries == 0 {
+            return Ok(1.0);
+        }
+
+        let mut total = 0.0;
+        for _ in 0..self.config.recall_queries {
+            let query = self.random_vector();
+            let truth: HashSet<String> = self
+                .model
+                .brute_force(&query, self.config.k)
+                .into_iter()
+                .collect();
+            let found = self.check_search(&query)?;
+            let hits = found.iter().filter(|id| truth.contains(*id)).count();
+            total += hits as f64 / truth.len() as f64;
+        }
+
+        Ok(total / self.config.recall_queries as f64)
+    }
+
+    fn report(&mut self, elapsed: Duration, round: usize) -> Result<(), String> {
+        let recall = self.measure_recall()?;
+        let heap = HEAP_CURRENT.load(Ordering::Relaxed);
+        let peak = HEAP_PEAK.load(Ordering::Relaxed);
+        let bytes_per_point = heap as f64 / self.model.len().max(1) as f64;
+
+        println!(
+            "[{:>7}s] round {} points={} adds={} deletes={} upserts={} updates={} searches={} persists={} \
+             recall@{}={:.3} heap={:.1}MiB peak={:.1}MiB bytes/point={:.0}",
+            elapsed.as_secs(),
+            round,
+            self.model.len(),
+            self.counters.adds,
+            self.counters.deletes,
+            self.counters.upserts,
+            self.counters.updates,
+            self.counters.searches,
+            self.counters.persists,
+            self.config.k,
+            recall,
+            heap as f64 / (1024.0 * 1024.0),
+            peak as f64 / (1024.0 * 1024.0),
+            bytes_per_point,
+        );
+
+        if recall < self.config.min_recall {
+            return Err(format!(
+                "recall {:.3} fell below {:.3}",
+                recall, self.config.min_recall
+            ));
+        }
+
+        // Only judge growth once the index has filled up to steady state
+        if self.model.len() * 2 >= self.config.max_points {
+            match self.baseline_bytes_per_point {
+                None => self.baseline_bytes_per_point = Some(bytes_per_point),
+                Some(baseline) if bytes_per_point > baseline * self.config.max_growth => {
+                    return Err(format!(
+                        "heap per point grew from {:.0} to {:.0} bytes",
+                        baseline, bytes_per_point
+                    ));
+                }
+                Some(_) => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
+
+fn main() -> ExitCode {
+    let config = match Config::from_args() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("soak: {}", e);
+            return ExitCode::from(2);
+        }
+    };
+
+    println!(
+        "soak: duration={}s dim={} max_points={} seed={}",
+        config.duration.as_secs(),
+        config.dim,
+        config.max_points,
+        config.seed
+    );
+
+    match Soak::new(config).run() {
+        Ok(()) => {
+            println!("soak: passed");
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("soak: FAILED: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,84 @@
+//! Per-query work limits
+//!
+//! A search given `maxVisits` or `timeoutMs` carries a [`Meter`] through
+//! its graph walks. Every scored node counts as a visit, and once either
+//! limit is reached the walks stop where they are and return the best
+//! nodes seen so far, flagged as truncated. The clock is read only every
+//! [`CLOCK_STRIDE`] visits, as a timer call costs more than a distance.
+
+use std::cell::Cell;
+
+/// Visits between looks at the clock
+const CLOCK_STRIDE: usize = 32;
+
+/// Work done by one search against its limits
+pub(crate) struct Meter {
+    max_visits: usize,
+    /// Clock reading in milliseconds past which the search stops
+    deadline: Option<f64>,
+    visits: Cell<usize>,
+    /// Visits at the last look at the clock
+    checked: Cell<usize>,
+    truncated: Cell<bool>,
+}
+
+impl Meter {
+    /// A meter that never runs out
+    pub fn unlimited() -> Meter {
+        Meter::start(None, None)
+    }
+
+    /// Start metering a search allowed `max_visits` scored nodes and
+    /// `timeout_ms` milliseconds, either unbounded when `None`
+    pub fn start(max_visits: Option<usize>, timeout_ms: Option<f64>) -> Meter {
+        Meter {
+            max_visits: max_visits.unwrap_or(usize::MAX),
+            deadline: timeout_ms.map(|timeout| now_ms() + timeout),
+            visits: Cell::new(0),
+            checked: Cell::new(0),
+            truncated: Cell::new(false),
+        }
+    }
+
+    /// Count one scored node
+    pub fn visit(&self) {
+        self.visits.set(self.visits.get() + 1);
+    }
+
+    /// Whether the search must stop, marking it truncated if so
+    pub fn exhausted(&self) -> bool {
+        if self.truncated.get() {
+            return true;
+        }
+        let visits = self.visits.get();
+        let out_of_time = self.deadline.is_some_and(|deadline| {
+            if visits - self.checked.get() < CLOCK_STRIDE {
+                return false;
+            }
+            self.checked.set(visits);
+            now_ms() >= deadline
+        });
+        if visits >= self.max_visits || out_of_time {
+            self.truncated.set(true);
+        }
+        self.truncated.get()
+    }
+
+    /// Whether a limit cut the search short
+    pub fn truncated(&self) -> bool {
+        self.truncated.get()
+    }
+}
+
+/// Milliseconds on a clock that only needs to be consistent within a query
+#[cfg(target_arch = "wasm32")]
+fn now_ms() -> f64 {
+    js_sys::Date::now()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn now_ms() -> f64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0.0, |since| since.as_secs_f64() * 1000.0)
+}
@@ -0,0 +1,160 @@
+//! Pluggable graph construction
+//!
+//! [`Hnsw`] stores points and answers queries; a [`GraphBuilder`] decides
+//! which links a new point gets and how overfull neighbor lists are cut
+//! back. The default [`HnswBuilder`] is the construction from the HNSW
+//! paper; NSG or Vamana-style strategies can be swapped in through
+//! [`Hnsw::set_builder`] without touching storage, search, or persistence.
+
+use crate::index::Hnsw;
+use crate::scalar::Scalar;
+use crate::store::VectorStore;
+
+/// Strategy for linking points into the graph
+///
+/// Only [`GraphBuilder::select_neighbors`] is required; the provided
+/// `insert` and `prune` follow the standard HNSW procedure on top of it.
+/// Builders are shared with the index across threads, so they must be
+/// `Send` and `Sync`.
+pub trait GraphBuilder<S: Scalar, V: VectorStore<S>>: Send + Sync {
+    /// Choose up to `m` links for a node at `base` among `candidates`
+    ///
+    /// `candidates` are `(node, distance to base)` pairs sorted nearest
+    /// first and never contain the node itself.
+    fn select_neighbors(
+        &self,
+        index: &Hnsw<S, V>,
+        base: &[S],
+        candidates: &[(u32, f32)],
+        m: usize,
+    ) -> Vec<u32>;
+
+    /// Link `node`, already stored with the given top `level`, into the
+    /// graph
+    ///
+    /// Called before the entry point is updated, so the current entry
+    /// point is still the descent start.
+    fn insert(&self, index: &mut Hnsw<S, V>, node: u32, level: usize) {
+        let query = match index.node_vector(node) {
+            Some(vector) => vector.into_owned(),
+            None => return,
+        };
+        let (entry, top) = match index.entry_node() {
+            Some(entry) if entry != node => (entry, index.node_level(entry).unwrap_or(0)),
+            _ => return,
+        };
+
+        // Greedy descent through the layers above the new point
+        let mut entry_points = vec![entry];
+        for layer in (level + 1..=top).rev() {
+            if let Some((closest, _)) = index
+                .search_layer(&query, &entry_points, 1, layer)
+                .into_iter()
+                .next()
+            {
+                entry_points = vec![closest];
+            }
+        }
+
+        let ef = index.params().ef_construction.max(1);
+        for layer in (0..=level.min(top)).rev() {
+            let mut found = index.search_layer(&query, &entry_points, ef, layer);
+            found.retain(|&(candidate, _)| candidate != node);
+
+            let max_links = max_links(index, layer);
+            let selected = self.select_neighbors(index, &query, &found, index.params().m);
+            for &neighbor in &selected {
+                let mut links = index.links(neighbor, layer).unwrap_or(&[]).to_vec();
+                if !links.contains(&node) {
+                    links.push(node);
+                }
+                let overfull = links.len() > max_links;
+                index.set_links(neighbor, layer, links);
+                if overfull {
+                    self.prune(index, neighbor, layer, max_links);
+                }
+            }
+            index.set_links(node, layer, selected);
+
+            if !found.is_empty() {
+                entry_points = found.into_iter().map(|(candidate, _)| candidate).collect();
+            }
+        }
+    }
+
+    /// Cut the links of `node` on `layer` back to at most `max_links`
+    fn prune(&self, index: &mut Hnsw<S, V>, node: u32, layer: usize, max_links: usize) {
+        let base = match index.node_vector(node) {
+            Some(vector) => vector.into_owned(),
+            None => return,
+        };
+        let mut candidates: Vec<(u32, f32)> = index
+            .links(node, layer)
+            .unwrap_or(&[])
+            .iter()
+            .filter_map(|&link| index.node_distance(&base, link).map(|d| (link, d)))
+            .collect();
+        candidates.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+        let kept = self.select_neighbors(index, &base, &candidates, max_links);
+        index.set_links(node, layer, kept);
+    }
+}
+
+/// Maximum links per node on `layer`: `2 * m` on the base layer, `m` above
+pub fn max_links<S: Scalar, V: VectorStore<S>>(index: &Hnsw<S, V>, layer: usize) -> usize {
+    let m = index.params().m.max(1);
+    if layer == 0 {
+        2 * m
+    } else {
+        m
+    }
+}
+
+/// Standard HNSW construction with the neighbor-selection heuristic
+///
+/// A candidate is kept only if it is closer to the base than to every
+/// neighbor already kept, which spreads links across directions; the
+/// remaining slots are then filled with the nearest discarded candidates.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HnswBuilder;
+
+impl<S: Scalar, V: VectorStore<S>> GraphBuilder<S, V> for HnswBuilder {
+    fn select_neighbors(
+        &self,
+        index: &Hnsw<S, V>,
+        _base: &[S],
+        candidates: &[(u32, f32)],
+        m: usize,
+    ) -> Vec<u32> {
+        let mut selected: Vec<(u32, Vec<S>)> = Vec::with_capacity(m);
+        let mut discarded = Vec::new();
+
+        for &(candidate, dist) in candidates {
+            if selected.len() >= m {
+                break;
+            }
+            let vector = match index.node_vector(candidate) {
+                Some(vector) => vector.into_owned(),
+                None => continue,
+            };
+            let dominated = selected
+                .iter()
+                .any(|(_, kept)| S::cosine_distance(&vector, kept) < dist);
+            if dominated {
+                discarded.push(candidate);
+            } else {
+                selected.push((candidate, vector));
+            }
+        }
+
+        let mut links: Vec<u32> = selected.into_iter().map(|(id, _)| id).collect();
+        for candidate in discarded {
+            if links.len() >= m {
+                break;
+            }
+            links.push(candidate);
+        }
+        links
+    }
+}
@@ -0,0 +1,67 @@
+//! One-shot construction from a full set of vectors
+//!
+//! Incremental inserts cannot know what comes next: each point draws its
+//! level as it arrives, and the entry point moves whenever a taller point
+//! shows up, so early points are linked through upper layers that are
+//! later rebuilt around them. [`Hnsw::from_vectors`] sees every point up
+//! front. It draws all levels first and inserts the tallest points first,
+//! so the upper layers are complete and the entry point is fixed before
+//! the bulk of base-layer points descends through them. It sizes every
+//! table once, and finally lays the graph out breadth-first.
+
+use std::collections::HashMap;
+
+use crate::index::Hnsw;
+use crate::scalar::Scalar;
+use crate::{DuplicatePolicy, HNSWParams, HnswError};
+
+impl<S: Scalar> Hnsw<S> {
+    /// Build an index over `ids.len()` vectors packed back to back in
+    /// `vectors`, `dim` values each
+    ///
+    /// The graph is as good as one built by inserting the points one by
+    /// one, with less relinking of upper layers along the way, and is laid
+    /// out as after [`Hnsw::optimize_layout`]. Repeated ids follow
+    /// `params.on_duplicate`: the last vector wins, the first does, or the
+    /// build fails.
+    pub fn from_vectors(
+        ids: Vec<String>,
+        vectors: &[S],
+        dim: usize,
+        params: HNSWParams,
+    ) -> Result<Hnsw<S>, HnswError> {
+        let mut index = Hnsw::with_params(params);
+        index.check_batch(ids.len(), vectors, dim)?;
+
+        // Row kept for each id under the duplicate policy
+        let mut rows: HashMap<&str, usize> = HashMap::with_capacity(ids.len());
+        for (row, id) in ids.iter().enumerate() {
+            match rows.insert(id, row) {
+                Some(first) if params.on_duplicate == DuplicatePolicy::Ignore => {
+                    rows.insert(id, first);
+                }
+                Some(_) if params.on_duplicate == DuplicatePolicy::Error => {
+                    return Err(HnswError::DuplicateId(id.clone()));
+                }
+                _ => {}
+            }
+        }
+
+        // Tallest first, input order within a level
+        let mut order: Vec<(usize, usize)> = rows
+            .into_values()
+            .map(|row| (index.random_level(), row))
+            .collect();
+        order.sort_unstable_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+
+        index.reserve(order.len());
+        let mut ids: Vec<Option<String>> = ids.into_iter().map(Some).collect();
+        for (level, row) in order {
+            let vector = index.prepare_vector(vectors[row * dim..(row + 1) * dim].to_vec())?;
+            let id = ids[row].take().unwrap_or_default();
+            index.insert_at(id, vector, level)?;
+        }
+        index.optimize_layout()?;
+        Ok(index)
+    }
+}
@@ -0,0 +1,134 @@
+//! LRU cache of recent search results
+
+use serde::Serialize;
+use std::sync::{Mutex, PoisonError};
+
+use crate::scalar::Scalar;
+use crate::search::SearchOptions;
+
+/// Steps per unit of a normalized query component in cache keys
+///
+/// Queries closer than this in every component share results; their
+/// cosines to any point differ by far less than search noise.
+const QUERY_KEY_RESOLUTION: f64 = 10_000.0;
+
+/// Results of recent `search` calls, most recently used last
+///
+/// Disabled with a capacity of 0. Every write to the index clears it.
+/// Entries sit behind locks so searches from several threads can share
+/// the index.
+#[derive(Default)]
+pub(crate) struct QueryCache {
+    capacity: usize,
+    entries: Mutex<Vec<CacheEntry>>,
+    stats: Mutex<CacheStats>,
+}
+
+struct CacheEntry {
+    query: Vec<i32>,
+    k: usize,
+    options: SearchOptions,
+    results: Vec<(String, f32)>,
+}
+
+/// Hit and miss counts of the query cache since it was last sized
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Results currently cached
+    pub entries: usize,
+}
+
+impl QueryCache {
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        get_mut(&mut self.entries).clear();
+        *get_mut(&mut self.stats) = CacheStats::default();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            entries: lock(&self.entries).len(),
+            ..*lock(&self.stats)
+        }
+    }
+
+    /// Drop every entry after the index changed
+    pub fn invalidate(&mut self) {
+        get_mut(&mut self.entries).clear();
+    }
+
+    /// Cached results for a stored-space query, marking them recently used
+    pub fn get<S: Scalar>(
+        &self,
+        query: &[S],
+        k: usize,
+        options: &SearchOptions,
+    ) -> Option<Vec<(String, f32)>> {
+        if self.capacity == 0 {
+            return None;
+        }
+        let key = query_key(query);
+        let mut entries = lock(&self.entries);
+        let mut stats = lock(&self.stats);
+        let Some(pos) = entries
+            .iter()
+            .position(|e| e.k == k && e.query == key && e.options == *options)
+        else {
+            stats.misses += 1;
+            return None;
+        };
+        stats.hits += 1;
+        let entry = entries.remove(pos);
+        let results = entry.results.clone();
+        entries.push(entry);
+        Some(results)
+    }
+
+    /// Remember results, evicting the least recently used beyond capacity
+    pub fn put<S: Scalar>(
+        &self,
+        query: &[S],
+        k: usize,
+        options: &SearchOptions,
+        results: &[(String, f32)],
+    ) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = lock(&self.entries);
+        if entries.len() >= self.capacity {
+            entries.remove(0);
+        }
+        entries.push(CacheEntry {
+            query: query_key(query),
+            k,
+            options: options.clone(),
+            results: results.to_vec(),
+        });
+    }
+}
+
+/// Lock `mutex`; a search that panicked holding it left nothing half written
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn get_mut<T>(mutex: &mut Mutex<T>) -> &mut T {
+    mutex.get_mut().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Quantized direction of a query; cosine search ignores its length
+fn query_key<S: Scalar>(query: &[S]) -> Vec<i32> {
+    let norm = query.iter().map(|x| x.to_f64().powi(2)).sum::<f64>().sqrt();
+    let scale = if norm > 0.0 {
+        QUERY_KEY_RESOLUTION / norm
+    } else {
+        0.0
+    };
+    query
+        .iter()
+        .map(|x| (x.to_f64() * scale).round() as i32)
+        .collect()
+}
@@ -0,0 +1,817 @@
+//! Compact binary encoding for snapshots
+//!
+//! A self-describing serde format in the spirit of MessagePack: every
+//! value starts with a tag byte, integers are LEB128 varints, floats keep
+//! their native width, and structs are maps keyed by field name so
+//! `#[serde(default)]` fields can be added without breaking old
+//! snapshots. Sequences made only of `f32` are packed as raw
+//! little-endian words, which is what vectors are.
+//!
+//! Tags never collide with the first byte of a JSON document, so
+//! [`decode`] still reads snapshots written before the binary format.
+//! Nor do they collide with a zlib header, so compressed snapshots are
+//! inflated transparently.
+
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::ser::{self, Serialize};
+use std::fmt;
+
+use crate::deflate;
+use crate::HnswError;
+
+const NULL: u8 = 0xC0;
+const FALSE: u8 = 0xC2;
+const TRUE: u8 = 0xC3;
+/// Non-negative integer as a varint
+const UINT: u8 = 0xC4;
+/// Negative integer `-1 - n` as a varint of `n`
+const NINT: u8 = 0xC5;
+const F32: u8 = 0xC6;
+const F64: u8 = 0xC7;
+const STR: u8 = 0xC8;
+const BYTES: u8 = 0xC9;
+const SEQ: u8 = 0xCA;
+const MAP: u8 = 0xCB;
+/// Count followed by that many little-endian `f32` words
+const F32_ARRAY: u8 = 0xCC;
+
+/// Nesting allowed while decoding, so hostile input cannot exhaust the stack
+const MAX_DEPTH: usize = 128;
+
+/// Encode `value` in the binary format
+pub(crate) fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, HnswError> {
+    let mut encoder = Encoder { out: Vec::new() };
+    value
+        .serialize(&mut encoder)
+        .map_err(|e| HnswError::Serialization(e.0))?;
+    Ok(encoder.out)
+}
+
+/// Decode a value written by [`encode`], deflated or not, or a legacy
+/// JSON snapshot
+pub(crate) fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T, HnswError> {
+    if deflate::is_zlib(data) {
+        return decode(&deflate::decompress(data)?);
+    }
+    let is_json = data
+        .iter()
+        .find(|b| !b.is_ascii_whitespace())
+        .is_some_and(|b| *b == b'{' || *b == b'[');
+    if is_json {
+        return serde_json::from_slice(data).map_err(|e| HnswError::Deserialization(e.to_string()));
+    }
+    let mut decoder = Decoder {
+        input: data,
+        depth: 0,
+    };
+    let value = T::deserialize(&mut decoder).map_err(|e| HnswError::Deserialization(e.0))?;
+    if !decoder.input.is_empty() {
+        return Err(HnswError::Deserialization(format!(
+            "{} trailing bytes after snapshot",
+            decoder.input.len()
+        )));
+    }
+    Ok(value)
+}
+
+#[derive(Debug)]
+pub(crate) struct Error(String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Error {
+        Error(msg.to_string())
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Error {
+        Error(msg.to_string())
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+struct Encoder {
+    out: Vec<u8>,
+}
+
+impl Encoder {
+    fn write_header(&mut self, tag: u8, len: usize) {
+        self.out.push(tag);
+        write_varint(&mut self.out, len as u64);
+    }
+
+    fn write_str(&mut self, value: &str) {
+        self.write_header(STR, value.len());
+        self.out.extend_from_slice(value.as_bytes());
+    }
+
+    /// Start a compound value, wrapping it in `{ variant: ... }` for enums
+    fn compound(&mut self, tag: u8, variant: Option<&str>) -> Compound<'_> {
+        if let Some(variant) = variant {
+            self.write_header(MAP, 1);
+            self.write_str(variant);
+        }
+        Compound {
+            parent: self,
+            tag,
+            items: Encoder { out: Vec::new() },
+            count: 0,
+            all_f32: true,
+        }
+    }
+}
+
+impl<'a> ser::Serializer for &'a mut Encoder {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Compound<'a>;
+    type SerializeTuple = Compound<'a>;
+    type SerializeTupleStruct = Compound<'a>;
+    type SerializeTupleVariant = Compound<'a>;
+    type SerializeMap = Compound<'a>;
+    type SerializeStruct = Compound<'a>;
+    type SerializeStructVariant = Compound<'a>;
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    fn serialize_bool(self, v: bool) -> Result<(), Error> {
+        self.out.push(if v { TRUE } else { FALSE });
+        Ok(())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<(), Error> {
+        if v >= 0 {
+            self.serialize_u64(v as u64)
+        } else {
+            self.out.push(NINT);
+            write_varint(&mut self.out, !(v as u64));
+            Ok(())
+        }
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), Error> {
+        self.serialize_u64(v as u64)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), Error> {
+        self.serialize_u64(v as u64)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), Error> {
+        self.serialize_u64(v as u64)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<(), Error> {
+        self.out.push(UINT);
+        write_varint(&mut self.out, v);
+        Ok(())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), Error> {
+        self.out.push(F32);
+        self.out.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<(), Error> {
+        self.out.push(F64);
+        self.out.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), Error> {
+        self.write_str(v.encode_utf8(&mut [0; 4]));
+        Ok(())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), Error> {
+        self.write_str(v);
+        Ok(())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), Error> {
+        self.write_header(BYTES, v.len());
+        self.out.extend_from_slice(v);
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<(), Error> {
+        self.out.push(NULL);
+        Ok(())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), Error> {
+        self.out.push(NULL);
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), Error> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<(), Error> {
+        self.write_str(variant);
+        Ok(())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.write_header(MAP, 1);
+        self.write_str(variant);
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Compound<'a>, Error> {
+        Ok(self.compound(SEQ, None))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Compound<'a>, Error> {
+        Ok(self.compound(SEQ, None))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Compound<'a>, Error> {
+        Ok(self.compound(SEQ, None))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Compound<'a>, Error> {
+        Ok(self.compound(SEQ, Some(variant)))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Compound<'a>, Error> {
+        Ok(self.compound(MAP, None))
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Compound<'a>, Error> {
+        Ok(self.compound(MAP, None))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Compound<'a>, Error> {
+        Ok(self.compound(MAP, Some(variant)))
+    }
+}
+
+/// A sequence or map being encoded
+///
+/// Items are buffered because their count is not always known upfront,
+/// and so a sequence of floats can be packed once it is complete.
+struct Compound<'a> {
+    parent: &'a mut Encoder,
+    tag: u8,
+    items: Encoder,
+    /// Sequence items or map entries written so far
+    count: usize,
+    all_f32: bool,
+}
+
+impl Compound<'_> {
+    fn finish(self) -> Result<(), Error> {
+        if self.tag == SEQ && self.all_f32 && self.count > 0 {
+            self.parent.write_header(F32_ARRAY, self.count);
+            for word in self.items.out.chunks_exact(5) {
+                self.parent.out.extend_from_slice(&word[1..]);
+            }
+        } else {
+            self.parent.write_header(self.tag, self.count);
+            self.parent.out.extend_from_slice(&self.items.out);
+        }
+        Ok(())
+    }
+
+    fn element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let start = self.items.out.len();
+        value.serialize(&mut self.items)?;
+        let written = &self.items.out[start..];
+        self.all_f32 &= written.len() == 5 && written[0] == F32;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        self.items.write_str(key);
+        value.serialize(&mut self.items)?;
+        self.count += 1;
+        Ok(())
+    }
+}
+
+impl ser::SerializeSeq for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeMap for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        key.serialize(&mut self.items)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        value.serialize(&mut self.items)?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+struct Decoder<'de> {
+    input: &'de [u8],
+    depth: usize,
+}
+
+impl<'de> Decoder<'de> {
+    fn peek(&self) -> Result<u8, Error> {
+        self.input
+            .first()
+            .copied()
+            .ok_or_else(|| Error("unexpected end of snapshot".to_string()))
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'de [u8], Error> {
+        if len > self.input.len() {
+            return Err(Error("unexpected end of snapshot".to_string()));
+        }
+        let (head, rest) = self.input.split_at(len);
+        self.input = rest;
+        Ok(head)
+    }
+
+    fn byte(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> Result<u64, Error> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            let bits = (byte & 0x7F) as u64;
+            if shift == 63 && bits > 1 {
+                break;
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(Error("integer overflows 64 bits".to_string()))
+    }
+
+    /// Length prefix, checked against the bytes left so it cannot
+    /// promise more items than could possibly follow
+    fn len(&mut self, item_bytes: usize) -> Result<usize, Error> {
+        let len = self.varint()?;
+        match usize::try_from(len) {
+            Ok(len) if len.saturating_mul(item_bytes) <= self.input.len() => Ok(len),
+            _ => Err(Error(format!("length {} runs past the snapshot", len))),
+        }
+    }
+
+    fn nested<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T, Error>) -> Result<T, Error> {
+        if self.depth >= MAX_DEPTH {
+            return Err(Error("snapshot nests too deeply".to_string()));
+        }
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+}
+
+impl<'de> de::Deserializer<'de> for &mut Decoder<'de> {
+    type Error = Error;
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.byte()? {
+            NULL => visitor.visit_unit(),
+            FALSE => visitor.visit_bool(false),
+            TRUE => visitor.visit_bool(true),
+            UINT => visitor.visit_u64(self.varint()?),
+            NINT => {
+                let n = self.varint()?;
+                match i64::try_from(n) {
+                    Ok(n) => visitor.visit_i64(-1 - n),
+                    Err(_) => Err(Error("negative integer overflows 64 bits".to_string())),
+                }
+            }
+            F32 => {
+                let bytes = self.take(4)?;
+                visitor.visit_f32(f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            }
+            F64 => {
+                let mut word = [0; 8];
+                word.copy_from_slice(self.take(8)?);
+                visitor.visit_f64(f64::from_le_bytes(word))
+            }
+            STR => {
+                let len = self.len(1)?;
+                let bytes = self.take(len)?;
+                let s = std::str::from_utf8(bytes)
+                    .map_err(|_| Error("string is not valid UTF-8".to_string()))?;
+                visitor.visit_borrowed_str(s)
+            }
+            BYTES => {
+                let len = self.len(1)?;
+                visitor.visit_borrowed_bytes(self.take(len)?)
+            }
+            SEQ => {
+                let remaining = self.len(1)?;
+                self.nested(|decoder| visitor.visit_seq(SeqDecoder { decoder, remaining }))
+            }
+            MAP => {
+                let remaining = self.len(2)?;
+                self.nested(|decoder| visitor.visit_map(MapDecoder { decoder, remaining }))
+            }
+            F32_ARRAY => {
+                let len = self.len(4)?;
+                let words = self.take(len * 4)?;
+                visitor.visit_seq(F32Seq { words })
+            }
+            tag => Err(Error(format!("unknown tag 0x{:02x}", tag))),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if self.peek()? == NULL {
+            self.byte()?;
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.peek()? {
+            STR => {
+                self.byte()?;
+                let len = self.len(1)?;
+                let name = std::str::from_utf8(self.take(len)?)
+                    .map_err(|_| Error("variant name is not valid UTF-8".to_string()))?;
+                visitor.visit_enum(name.into_deserializer())
+            }
+            MAP => {
+                self.byte()?;
+                if self.varint()? != 1 {
+                    return Err(Error("enum map must hold exactly one variant".to_string()));
+                }
+                self.nested(|decoder| visitor.visit_enum(VariantDecoder { decoder }))
+            }
+            tag => Err(Error(format!("expected an enum, found tag 0x{:02x}", tag))),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+struct SeqDecoder<'a, 'de> {
+    decoder: &'a mut Decoder<'de>,
+    remaining: usize,
+}
+
+impl<'de> de::SeqAccess<'de> for SeqDecoder<'_, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        seed.deserialize(&mut *self.decoder).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+struct MapDecoder<'a, 'de> {
+    decoder: &'a mut Decoder<'de>,
+    remaining: usize,
+}
+
+impl<'de> de::MapAccess<'de> for MapDecoder<'_, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        seed.deserialize(MapKey {
+            decoder: &mut *self.decoder,
+        })
+        .map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        seed.deserialize(&mut *self.decoder)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+/// A map key, read as any value except that integers asked for as strings
+/// come back in decimal, so integer-keyed maps decode into a JSON document
+/// for migration
+struct MapKey<'a, 'de> {
+    decoder: &'a mut Decoder<'de>,
+}
+
+impl<'de> de::Deserializer<'de> for MapKey<'_, 'de> {
+    type Error = Error;
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_any(self.decoder, visitor)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.decoder.peek()? {
+            UINT | NINT => {
+                let value: serde_json::Value = de::Deserialize::deserialize(&mut *self.decoder)?;
+                visitor.visit_string(value.to_string())
+            }
+            _ => de::Deserializer::deserialize_any(self.decoder, visitor),
+        }
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_option(self.decoder, visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_newtype_struct(self.decoder, name, visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_enum(self.decoder, name, variants, visitor)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+/// Packed `f32` words read back as a sequence
+struct F32Seq<'de> {
+    words: &'de [u8],
+}
+
+impl<'de> de::SeqAccess<'de> for F32Seq<'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        if self.words.len() < 4 {
+            return Ok(None);
+        }
+        let (word, rest) = self.words.split_at(4);
+        self.words = rest;
+        let value = f32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+        let value: de::value::F32Deserializer<Error> = value.into_deserializer();
+        seed.deserialize(value).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.words.len() / 4)
+    }
+}
+
+/// The `{ variant: content }` form of a data-carrying enum
+struct VariantDecoder<'a, 'de> {
+    decoder: &'a mut Decoder<'de>,
+}
+
+impl<'a, 'de> de::EnumAccess<'de> for VariantDecoder<'a, 'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), Error> {
+        let variant = seed.deserialize(&mut *self.decoder)?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for VariantDecoder<'_, 'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        de::Deserialize::deserialize(&mut *self.decoder)
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(&mut *self.decoder)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_any(&mut *self.decoder, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_any(&mut *self.decoder, visitor)
+    }
+}
@@ -0,0 +1,82 @@
+//! Query-time score decay over a numeric payload field, e.g. recency
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::filter::lookup;
+use crate::index::Hnsw;
+use crate::scalar::Scalar;
+use crate::search::{best_first, SearchOptions};
+use crate::store::VectorStore;
+use crate::HnswError;
+
+/// Shape of the falloff away from the origin
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DecayFunction {
+    /// Halves every `scale` away from the origin, never reaching 0
+    #[default]
+    Exponential,
+    /// Falls by half per `scale`, reaching 0 at twice the scale
+    Linear,
+}
+
+/// Scales scores by how far a payload number is from `origin`
+///
+/// Parsed from `{ field, origin, scale, function }`, e.g. a last-modified
+/// `field` with the current time as `origin` and a week as `scale` halves
+/// the score of week-old files. Both sides of the origin decay alike.
+/// Points without a numeric value keep their score.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Decay {
+    /// Dotted payload field holding the number
+    pub field: String,
+    /// Where the factor is 1
+    pub origin: f64,
+    /// Distance from the origin at which the factor is 0.5
+    pub scale: f64,
+    #[serde(default)]
+    pub function: DecayFunction,
+}
+
+impl Decay {
+    pub fn parse(value: Value) -> Result<Decay, HnswError> {
+        let decay: Decay = serde_json::from_value(value)
+            .map_err(|e| HnswError::InvalidParams(format!("invalid decay: {}", e)))?;
+        if !(decay.scale.is_finite() && decay.scale > 0.0 && decay.origin.is_finite()) {
+            return Err(HnswError::InvalidParams(format!(
+                "decay needs a finite origin and a positive scale, got {} and {}",
+                decay.origin, decay.scale
+            )));
+        }
+        Ok(decay)
+    }
+
+    /// Factor in `[0, 1]` for a point with `payload`
+    pub fn factor(&self, payload: Option<&Value>) -> f32 {
+        let Some(value) = lookup(payload, &self.field).and_then(Value::as_f64) else {
+            return 1.0;
+        };
+        let distance = (value - self.origin).abs() / self.scale;
+        let factor = match self.function {
+            DecayFunction::Exponential => 0.5f64.powf(distance),
+            DecayFunction::Linear => (1.0 - 0.5 * distance).max(0.0),
+        };
+        factor as f32
+    }
+}
+
+impl<S: Scalar, V: VectorStore<S>> Hnsw<S, V> {
+    /// Multiply scores by the decay factor of `options`, if any, and
+    /// restore best-first order
+    pub(crate) fn apply_decay(&self, results: &mut [(String, f32)], options: &SearchOptions) {
+        let Some(decay) = &options.decay else {
+            return;
+        };
+        for (id, score) in results.iter_mut() {
+            *score *= decay.factor(self.payload(id));
+        }
+        results.sort_by(best_first);
+    }
+}
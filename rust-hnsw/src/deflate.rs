@@ -0,0 +1,655 @@
+//! zlib-wrapped DEFLATE (RFC 1950/1951) for compressed snapshots
+//!
+//! A small LZ77 matcher with per-block dynamic Huffman codes, so
+//! compressed snapshots need no extra dependency in the wasm build and
+//! can still be inflated by any zlib implementation, including the
+//! browser's `DecompressionStream("deflate")`. The inflater reads every
+//! block type.
+
+use std::collections::BinaryHeap;
+
+use crate::HnswError;
+
+/// LZ77 window size, the largest distance DEFLATE can express
+const WINDOW: usize = 1 << 15;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+/// Candidates tried per position; longer chains find longer matches slower
+const MAX_CHAIN: usize = 48;
+const HASH_BITS: u32 = 15;
+/// Symbols per block, bounding memory and letting codes adapt
+const BLOCK_TOKENS: usize = 1 << 16;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// Order in which code length code lengths are stored
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Whether `data` starts with a zlib header using DEFLATE
+pub(crate) fn is_zlib(data: &[u8]) -> bool {
+    match data {
+        [cmf, flg, ..] => {
+            cmf & 0x0F == 8 && u16::from_be_bytes([*cmf, *flg]).checked_rem(31) == Some(0)
+        }
+        _ => false,
+    }
+}
+
+/// Compress `data` into a zlib stream
+pub(crate) fn compress(data: &[u8]) -> Vec<u8> {
+    let mut out = BitWriter::default();
+    // 32K window, default compression level
+    out.bytes.extend_from_slice(&[0x78, 0x9C]);
+
+    let tokens = lz77(data);
+    let mut blocks = tokens.chunks(BLOCK_TOKENS).peekable();
+    if blocks.peek().is_none() {
+        write_block(&mut out, &[], true);
+    }
+    while let Some(block) = blocks.next() {
+        write_block(&mut out, block, blocks.peek().is_none());
+    }
+    out.flush();
+    out.bytes.extend_from_slice(&adler32(data).to_be_bytes());
+    out.bytes
+}
+
+/// Inflate a zlib stream, checking its checksum
+pub(crate) fn decompress(data: &[u8]) -> Result<Vec<u8>, HnswError> {
+    if !is_zlib(data) {
+        return Err(corrupt("missing zlib header"));
+    }
+    if data[1] & 0x20 != 0 {
+        return Err(corrupt("preset dictionaries are not supported"));
+    }
+    let mut input = BitReader {
+        data: &data[2..],
+        pos: 0,
+        bits: 0,
+        count: 0,
+    };
+    let out = inflate_blocks(&mut input, data.len())?;
+    input.align();
+    let trailer = input
+        .data
+        .get(input.pos..input.pos + 4)
+        .ok_or_else(|| corrupt("missing checksum"))?;
+    let expected = u32::from_be_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+    if adler32(&out) != expected {
+        return Err(corrupt("checksum mismatch"));
+    }
+    Ok(out)
+}
+
+/// Inflate a raw DEFLATE stream, as stored in zip archives
+pub(crate) fn inflate(data: &[u8]) -> Result<Vec<u8>, HnswError> {
+    let mut input = BitReader {
+        data,
+        pos: 0,
+        bits: 0,
+        count: 0,
+    };
+    inflate_blocks(&mut input, data.len())
+}
+
+fn inflate_blocks(input: &mut BitReader, size_hint: usize) -> Result<Vec<u8>, HnswError> {
+    let mut out = Vec::with_capacity(size_hint.saturating_mul(2));
+    loop {
+        let last = input.bits(1)? == 1;
+        match input.bits(2)? {
+            0 => stored_block(input, &mut out)?,
+            1 => {
+                let (lit, dist) = fixed_codes();
+                huffman_block(input, &mut out, &lit, &dist)?;
+            }
+            2 => {
+                let (lit, dist) = dynamic_codes(input)?;
+                huffman_block(input, &mut out, &lit, &dist)?;
+            }
+            _ => return Err(corrupt("invalid block type")),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+fn corrupt(msg: &str) -> HnswError {
+    HnswError::CorruptSnapshot(format!("compressed body: {}", msg))
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    // Largest run before `b` could overflow
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    (b << 16) | a
+}
+
+#[derive(Clone, Copy)]
+enum Token {
+    Literal(u8),
+    Match { len: u16, dist: u16 },
+}
+
+/// Greedy LZ77 over hash chains of 3-byte prefixes
+fn lz77(data: &[u8]) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; WINDOW];
+    let hash = |i: usize| {
+        let key = (data[i] as u32) << 16 | (data[i + 1] as u32) << 8 | data[i + 2] as u32;
+        (key.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+    };
+    let insert = |i: usize, head: &mut Vec<usize>, prev: &mut Vec<usize>| {
+        if i + MIN_MATCH <= data.len() {
+            let h = hash(i);
+            prev[i % WINDOW] = head[h];
+            head[h] = i;
+        }
+    };
+
+    let mut i = 0;
+    while i < data.len() {
+        let mut best = (0, 0);
+        if i + MIN_MATCH <= data.len() {
+            let limit = MAX_MATCH.min(data.len() - i);
+            let mut candidate = head[hash(i)];
+            for _ in 0..MAX_CHAIN {
+                if candidate == usize::MAX || i - candidate > WINDOW - 1 {
+                    break;
+                }
+                let len = data[candidate..]
+                    .iter()
+                    .zip(&data[i..i + limit])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if len > best.0 {
+                    best = (len, i - candidate);
+                    if len == limit {
+                        break;
+                    }
+                }
+                let next = prev[candidate % WINDOW];
+                if next == usize::MAX || next >= candidate {
+                    break;
+                }
+                candidate = next;
+            }
+        }
+        if best.0 >= MIN_MATCH {
+            tokens.push(Token::Match {
+                len: best.0 as u16,
+                dist: best.1 as u16,
+            });
+            for j in i..i + best.0 {
+                insert(j, &mut head, &mut prev);
+            }
+            i += best.0;
+        } else {
+            tokens.push(Token::Literal(data[i]));
+            insert(i, &mut head, &mut prev);
+            i += 1;
+        }
+    }
+    tokens
+}
+
+fn length_code(len: u16) -> usize {
+    LENGTH_BASE
+        .iter()
+        .rposition(|&base| base <= len)
+        .unwrap_or(0)
+}
+
+fn dist_code(dist: u16) -> usize {
+    DIST_BASE
+        .iter()
+        .rposition(|&base| base <= dist)
+        .unwrap_or(0)
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    bits: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    /// Append the low `n` bits of `value`, least significant first
+    fn write(&mut self, value: u32, n: u32) {
+        self.bits |= (value as u64) << self.count;
+        self.count += n;
+        while self.count >= 8 {
+            self.bytes.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Append a Huffman code, which DEFLATE stores most significant first
+    fn write_code(&mut self, code: u16, len: u8) {
+        let reversed = code.reverse_bits() >> (16 - len as u32);
+        self.write(reversed as u32, len as u32);
+    }
+
+    fn flush(&mut self) {
+        if self.count > 0 {
+            self.bytes.push(self.bits as u8);
+            self.bits = 0;
+            self.count = 0;
+        }
+    }
+}
+
+fn write_block(out: &mut BitWriter, tokens: &[Token], last: bool) {
+    let mut lit_freq = [0u32; 286];
+    let mut dist_freq = [0u32; 30];
+    lit_freq[256] = 1;
+    for token in tokens {
+        match *token {
+            Token::Literal(byte) => lit_freq[byte as usize] += 1,
+            Token::Match { len, dist } => {
+                lit_freq[257 + length_code(len)] += 1;
+                dist_freq[dist_code(dist)] += 1;
+            }
+        }
+    }
+    let lit_lengths = code_lengths(&lit_freq, 15);
+    let dist_lengths = code_lengths(&dist_freq, 15);
+    let lit_codes = canonical_codes(&lit_lengths);
+    let dist_codes = canonical_codes(&dist_lengths);
+
+    let hlit = 257.max(lit_lengths.iter().rposition(|&l| l > 0).unwrap_or(0) + 1);
+    let hdist = 1.max(dist_lengths.iter().rposition(|&l| l > 0).unwrap_or(0) + 1);
+    let mut all_lengths = lit_lengths[..hlit].to_vec();
+    all_lengths.extend_from_slice(&dist_lengths[..hdist]);
+    let runs = run_lengths(&all_lengths);
+
+    let mut cl_freq = [0u32; 19];
+    for (symbol, _) in &runs {
+        cl_freq[*symbol as usize] += 1;
+    }
+    let cl_lengths = code_lengths(&cl_freq, 7);
+    let cl_codes = canonical_codes(&cl_lengths);
+    let hclen = 4.max(
+        CODE_LENGTH_ORDER
+            .iter()
+            .rposition(|&s| cl_lengths[s] > 0)
+            .unwrap_or(0)
+            + 1,
+    );
+
+    out.write(last as u32, 1);
+    out.write(2, 2);
+    out.write((hlit - 257) as u32, 5);
+    out.write((hdist - 1) as u32, 5);
+    out.write((hclen - 4) as u32, 4);
+    for &symbol in &CODE_LENGTH_ORDER[..hclen] {
+        out.write(cl_lengths[symbol] as u32, 3);
+    }
+    for (symbol, extra) in runs {
+        let s = symbol as usize;
+        out.write_code(cl_codes[s], cl_lengths[s]);
+        match symbol {
+            16 => out.write(extra as u32, 2),
+            17 => out.write(extra as u32, 3),
+            18 => out.write(extra as u32, 7),
+            _ => {}
+        }
+    }
+
+    for token in tokens {
+        match *token {
+            Token::Literal(byte) => {
+                out.write_code(lit_codes[byte as usize], lit_lengths[byte as usize])
+            }
+            Token::Match { len, dist } => {
+                let lc = length_code(len);
+                out.write_code(lit_codes[257 + lc], lit_lengths[257 + lc]);
+                out.write((len - LENGTH_BASE[lc]) as u32, LENGTH_EXTRA[lc] as u32);
+                let dc = dist_code(dist);
+                out.write_code(dist_codes[dc], dist_lengths[dc]);
+                out.write((dist - DIST_BASE[dc]) as u32, DIST_EXTRA[dc] as u32);
+            }
+        }
+    }
+    out.write_code(lit_codes[256], lit_lengths[256]);
+}
+
+/// Code length symbols for a run of lengths as `(symbol, extra bits)`
+fn run_lengths(lengths: &[u8]) -> Vec<(u8, u8)> {
+    let mut runs = Vec::new();
+    let mut i = 0;
+    while i < lengths.len() {
+        let value = lengths[i];
+        let run = lengths[i..].iter().take_while(|&&l| l == value).count();
+        if value == 0 && run >= 11 {
+            let n = run.min(138);
+            runs.push((18, (n - 11) as u8));
+            i += n;
+        } else if value == 0 && run >= 3 {
+            runs.push((17, (run - 3) as u8));
+            i += run;
+        } else if value != 0 && run >= 4 {
+            runs.push((value, 0));
+            let n = (run - 1).min(6);
+            runs.push((16, (n - 3) as u8));
+            i += 1 + n;
+        } else {
+            runs.push((value, 0));
+            i += 1;
+        }
+    }
+    runs
+}
+
+/// Huffman code lengths for `freq`, none longer than `limit`
+///
+/// Frequencies are flattened and the tree rebuilt until it fits. Unused
+/// symbols get length 0; a lone used symbol is paired with a dummy so the
+/// code stays complete.
+fn code_lengths(freq: &[u32], limit: u8) -> Vec<u8> {
+    let mut lengths = vec![0u8; freq.len()];
+    let used: Vec<usize> = (0..freq.len()).filter(|&s| freq[s] > 0).collect();
+    match used.len() {
+        0 => {
+            lengths[0] = 1;
+            lengths[1] = 1;
+            return lengths;
+        }
+        1 => {
+            lengths[used[0]] = 1;
+            lengths[if used[0] == 0 { 1 } else { 0 }] = 1;
+            return lengths;
+        }
+        _ => {}
+    }
+
+    let mut weights: Vec<u64> = used.iter().map(|&s| freq[s] as u64).collect();
+    loop {
+        let depths = huffman_depths(&weights);
+        if depths.iter().all(|&d| d <= limit as usize) {
+            for (&s, &d) in used.iter().zip(&depths) {
+                lengths[s] = d as u8;
+            }
+            return lengths;
+        }
+        for w in &mut weights {
+            *w = (*w >> 1).max(1);
+        }
+    }
+}
+
+/// Depth of each leaf in a Huffman tree over `weights`
+fn huffman_depths(weights: &[u64]) -> Vec<usize> {
+    // Nodes 0..n are leaves; parents are appended as they are made
+    let mut parent: Vec<usize> = vec![usize::MAX; weights.len()];
+    let mut heap: BinaryHeap<std::cmp::Reverse<(u64, usize)>> = weights
+        .iter()
+        .enumerate()
+        .map(|(i, &w)| std::cmp::Reverse((w, i)))
+        .collect();
+    while heap.len() > 1 {
+        let std::cmp::Reverse((wa, a)) = heap.pop().unwrap_or(std::cmp::Reverse((0, 0)));
+        let std::cmp::Reverse((wb, b)) = heap.pop().unwrap_or(std::cmp::Reverse((0, 0)));
+        let node = parent.len();
+        parent.push(usize::MAX);
+        parent[a] = node;
+        parent[b] = node;
+        heap.push(std::cmp::Reverse((wa + wb, node)));
+    }
+    (0..weights.len())
+        .map(|leaf| {
+            let mut depth = 0;
+            let mut node = leaf;
+            while parent[node] != usize::MAX {
+                node = parent[node];
+                depth += 1;
+            }
+            depth
+        })
+        .collect()
+}
+
+/// Canonical Huffman codes for code lengths, as in RFC 1951 3.2.2
+fn canonical_codes(lengths: &[u8]) -> Vec<u16> {
+    let mut count = [0u16; 16];
+    for &l in lengths {
+        count[l as usize] += 1;
+    }
+    count[0] = 0;
+    let mut next = [0u16; 16];
+    let mut code = 0u16;
+    for bits in 1..16 {
+        code = (code + count[bits - 1]) << 1;
+        next[bits] = code;
+    }
+    lengths
+        .iter()
+        .map(|&l| {
+            if l == 0 {
+                return 0;
+            }
+            let code = next[l as usize];
+            next[l as usize] += 1;
+            code
+        })
+        .collect()
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bits: u64,
+    count: u32,
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, n: u32) -> Result<u32, HnswError> {
+        while self.count < n {
+            let byte = *self
+                .data
+                .get(self.pos)
+                .ok_or_else(|| corrupt("unexpected end of data"))?;
+            self.pos += 1;
+            self.bits |= (byte as u64) << self.count;
+            self.count += 8;
+        }
+        let value = (self.bits & ((1u64 << n) - 1)) as u32;
+        self.bits >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    /// Drop bits up to the next byte boundary
+    fn align(&mut self) {
+        self.bits = 0;
+        self.count = 0;
+    }
+}
+
+/// Canonical decoding table: symbols sorted by code, counted per length
+struct Huffman {
+    count: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Huffman, HnswError> {
+        let mut count = [0u16; 16];
+        for &l in lengths {
+            count[l as usize] += 1;
+        }
+        count[0] = 0;
+        // Over-subscribed codes cannot be decoded
+        let mut left = 1i32;
+        for &c in &count[1..] {
+            left = (left << 1) - c as i32;
+            if left < 0 {
+                return Err(corrupt("over-subscribed Huffman code"));
+            }
+        }
+        let mut offsets = [0u16; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + count[len];
+        }
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, &l) in lengths.iter().enumerate() {
+            if l != 0 {
+                symbols[offsets[l as usize] as usize] = symbol as u16;
+                offsets[l as usize] += 1;
+            }
+        }
+        Ok(Huffman { count, symbols })
+    }
+
+    fn decode(&self, input: &mut BitReader) -> Result<usize, HnswError> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..16 {
+            code |= input.bits(1)? as i32;
+            let count = self.count[len] as i32;
+            if code - count < first {
+                return Ok(self.symbols[(index + code - first) as usize] as usize);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(corrupt("invalid Huffman code"))
+    }
+}
+
+fn stored_block(input: &mut BitReader, out: &mut Vec<u8>) -> Result<(), HnswError> {
+    input.align();
+    let header = input
+        .data
+        .get(input.pos..input.pos + 4)
+        .ok_or_else(|| corrupt("truncated stored block"))?;
+    let len = u16::from_le_bytes([header[0], header[1]]);
+    let nlen = u16::from_le_bytes([header[2], header[3]]);
+    if len != !nlen {
+        return Err(corrupt("stored block length mismatch"));
+    }
+    let start = input.pos + 4;
+    let bytes = input
+        .data
+        .get(start..start + len as usize)
+        .ok_or_else(|| corrupt("truncated stored block"))?;
+    out.extend_from_slice(bytes);
+    input.pos = start + len as usize;
+    Ok(())
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    // Both tables are complete, so construction cannot fail
+    let lit = Huffman::new(&lengths).unwrap_or(Huffman {
+        count: [0; 16],
+        symbols: Vec::new(),
+    });
+    let dist = Huffman::new(&[5; 30]).unwrap_or(Huffman {
+        count: [0; 16],
+        symbols: Vec::new(),
+    });
+    (lit, dist)
+}
+
+fn dynamic_codes(input: &mut BitReader) -> Result<(Huffman, Huffman), HnswError> {
+    let hlit = input.bits(5)? as usize + 257;
+    let hdist = input.bits(5)? as usize + 1;
+    let hclen = input.bits(4)? as usize + 4;
+    if hlit > 286 || hdist > 30 {
+        return Err(corrupt("too many length or distance codes"));
+    }
+    let mut cl_lengths = [0u8; 19];
+    for &symbol in &CODE_LENGTH_ORDER[..hclen] {
+        cl_lengths[symbol] = input.bits(3)? as u8;
+    }
+    let cl = Huffman::new(&cl_lengths)?;
+
+    let mut lengths = Vec::with_capacity(hlit + hdist);
+    while lengths.len() < hlit + hdist {
+        let (value, repeat) = match cl.decode(input)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths
+                    .last()
+                    .ok_or_else(|| corrupt("repeat with no previous length"))?;
+                (previous, 3 + input.bits(2)? as usize)
+            }
+            17 => (0, 3 + input.bits(3)? as usize),
+            _ => (0, 11 + input.bits(7)? as usize),
+        };
+        if lengths.len() + repeat > hlit + hdist {
+            return Err(corrupt("code lengths overrun"));
+        }
+        lengths.resize(lengths.len() + repeat, value);
+    }
+    if lengths[256] == 0 {
+        return Err(corrupt("missing end-of-block code"));
+    }
+    Ok((
+        Huffman::new(&lengths[..hlit])?,
+        Huffman::new(&lengths[hlit..])?,
+    ))
+}
+
+fn huffman_block(
+    input: &mut BitReader,
+    out: &mut Vec<u8>,
+    lit: &Huffman,
+    dist: &Huffman,
+) -> Result<(), HnswError> {
+    loop {
+        let symbol = lit.decode(input)?;
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            257..=285 => {
+                let lc = symbol - 257;
+                let len = LENGTH_BASE[lc] as usize + input.bits(LENGTH_EXTRA[lc] as u32)? as usize;
+                let dc = dist.decode(input)?;
+                if dc >= 30 {
+                    return Err(corrupt("invalid distance code"));
+                }
+                let distance = DIST_BASE[dc] as usize + input.bits(DIST_EXTRA[dc] as u32)? as usize;
+                if distance > out.len() {
+                    return Err(corrupt("distance before start of data"));
+                }
+                let start = out.len() - distance;
+                for i in 0..len {
+                    out.push(out[start + i]);
+                }
+            }
+            _ => return Err(corrupt("invalid length code")),
+        }
+    }
+}
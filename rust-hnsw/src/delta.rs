@@ -0,0 +1,184 @@
+//! Incremental delta snapshots
+//!
+//! Every change to a point is stamped with the index
+//! [`generation`](Hnsw::generation). A delta holds the current state of
+//! the points changed after some generation and the ids deleted since,
+//! so a long-lived session can persist a few changed points instead of
+//! rewriting the whole index. Deltas apply in the order they were taken,
+//! on top of a full snapshot or an earlier delta chain.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::borrow::Cow;
+
+use crate::ids::Id;
+use crate::index::Hnsw;
+use crate::projection::RandomProjection;
+use crate::scalar::Scalar;
+use crate::snapshot::{self, Kind};
+use crate::sparse::SparseVector;
+use crate::store::VectorStore;
+use crate::text::Terms;
+use crate::HnswError;
+
+#[derive(Serialize)]
+#[serde(bound = "")]
+struct DeltaRef<'a, S: Scalar> {
+    generation: u64,
+    dimensions: usize,
+    /// Only in deltas since generation 0, which can seed an empty index
+    projection: Option<&'a RandomProjection>,
+    upserts: Vec<PointRef<'a, S>>,
+    deletes: Vec<&'a str>,
+}
+
+#[derive(Serialize)]
+#[serde(bound = "")]
+struct PointRef<'a, S: Scalar> {
+    id: &'a str,
+    /// Stored-space vector
+    vector: Cow<'a, [S]>,
+    payload: Option<&'a Value>,
+    sparse: Option<&'a SparseVector>,
+    terms: Option<&'a Terms>,
+}
+
+#[derive(Deserialize)]
+#[serde(bound = "")]
+struct Delta<S: Scalar> {
+    generation: u64,
+    dimensions: usize,
+    projection: Option<RandomProjection>,
+    upserts: Vec<DeltaPoint<S>>,
+    deletes: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(bound = "")]
+struct DeltaPoint<S: Scalar> {
+    id: String,
+    vector: Vec<S>,
+    payload: Option<Value>,
+    sparse: Option<SparseVector>,
+    terms: Option<Terms>,
+}
+
+impl<S: Scalar, V: VectorStore<S>> Hnsw<S, V> {
+    /// Serialize the points changed after generation `since`
+    ///
+    /// Pass the [`Hnsw::generation`] seen at the previous save; 0 gives a
+    /// delta holding every point. Generations before those the index
+    /// stopped tracking, see [`Hnsw::forget_changes`], are refused.
+    pub fn save_delta(&self, since: u64, compress: bool) -> Result<Vec<u8>, HnswError> {
+        if since > self.generation() {
+            return Err(HnswError::InvalidParams(format!(
+                "generation {} is ahead of the index at {}",
+                since,
+                self.generation()
+            )));
+        }
+        if since != 0 && since < self.changes_floor() {
+            return Err(HnswError::InvalidParams(format!(
+                "changes before generation {} are no longer tracked; save a full snapshot",
+                self.changes_floor()
+            )));
+        }
+        let mut changed: Vec<String> = self.changed_since(since).map(Id::to_string).collect();
+        changed.sort();
+        let mut upserts = Vec::new();
+        let mut deletes = Vec::new();
+        for id in &changed {
+            match self.vector(id) {
+                Some(vector) => upserts.push(PointRef {
+                    id,
+                    vector,
+                    payload: self.payload(id),
+                    sparse: self.sparse().get(id),
+                    terms: self.text().terms(id),
+                }),
+                None => deletes.push(id.as_str()),
+            }
+        }
+        let delta = DeltaRef {
+            generation: self.generation(),
+            dimensions: self.dimensions(),
+            projection: self.projection().filter(|_| since == 0),
+            upserts,
+            deletes,
+        };
+        let mut flags = snapshot::FLAG_DELTA;
+        if compress {
+            flags |= snapshot::FLAG_COMPRESSED;
+        }
+        snapshot::encode(&delta, S::NAME, self.dimensions(), flags)
+    }
+
+    /// Apply a delta from [`Hnsw::save_delta`], returning the generation
+    /// of the source index it brings this one up to
+    ///
+    /// Changed points replace any existing ones with the same id. The
+    /// delta is checked before anything is applied.
+    pub fn apply_delta(&mut self, data: &[u8]) -> Result<u64, HnswError> {
+        let delta: Delta<S> =
+            snapshot::decode(data, S::NAME, Kind::Delta, |d: &Delta<S>| d.dimensions)?;
+
+        let empty = self.dimensions() == 0;
+        let stored_dimensions = if empty {
+            if self.params().project_to > 0 && delta.projection.is_none() {
+                return Err(HnswError::InvalidParams(
+                    "an empty projected index needs a delta since generation 0 first".to_string(),
+                ));
+            }
+            match &delta.projection {
+                Some(p) if !p.is_well_formed() || p.input_dim() != delta.dimensions => {
+                    return Err(HnswError::CorruptSnapshot(
+                        "delta projection does not match its dimensions".to_string(),
+                    ));
+                }
+                Some(p) => p.output_dim(),
+                None => delta.dimensions,
+            }
+        } else {
+            if delta.dimensions != self.dimensions() && !delta.upserts.is_empty() {
+                return Err(HnswError::DimensionMismatch {
+                    expected: self.dimensions(),
+                    got: delta.dimensions,
+                });
+            }
+            self.stored_dimensions()
+        };
+        for point in &delta.upserts {
+            if point.vector.len() != stored_dimensions {
+                return Err(HnswError::CorruptSnapshot(format!(
+                    "point {} has {} dimensions, index stores {}",
+                    point.id,
+                    point.vector.len(),
+                    stored_dimensions
+                )));
+            }
+            if let Some(payload) = &point.payload {
+                self.schema().check_payload(payload)?;
+            }
+        }
+
+        if empty && !delta.upserts.is_empty() {
+            self.set_shape(delta.dimensions, delta.projection);
+        }
+        self.remove_many(&delta.deletes);
+        for point in delta.upserts {
+            self.remove(&point.id);
+            self.insert_stored(point.id.clone(), point.vector)?;
+            if let Some(payload) = point.payload {
+                self.set_payload(&point.id, payload)?;
+            }
+            let id = self.interned(&point.id).cloned();
+            if let (Some(id), Some(sparse)) = (&id, point.sparse) {
+                self.sparse_mut().insert(id.clone(), sparse);
+            }
+            if let (Some(id), Some(terms)) = (id, point.terms) {
+                self.text_mut().insert_terms(id, terms);
+            }
+        }
+        Ok(delta.generation)
+    }
+}
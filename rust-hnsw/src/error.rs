@@ -0,0 +1,69 @@
+use std::fmt;
+use wasm_bindgen::JsValue;
+
+/// Errors returned by the native index API
+///
+/// The wasm bindings convert these into `JsValue` strings; native callers
+/// (such as the soak binary) get them unchanged.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HnswError {
+    /// Vector length does not match the index dimensions
+    DimensionMismatch { expected: usize, got: usize },
+    /// Construction parameters could not be parsed or are out of range
+    InvalidParams(String),
+    /// The index could not be serialized
+    Serialization(String),
+    /// Snapshot bytes could not be deserialized
+    Deserialization(String),
+    /// Snapshot bytes fail their integrity check, e.g. after an
+    /// interrupted write
+    CorruptSnapshot(String),
+    /// An internal graph invariant does not hold
+    Invariant(String),
+    /// The vector store backend failed
+    Storage(String),
+    /// An embedding provider failed or returned malformed vectors
+    Embedding(String),
+    /// The id is already indexed and the duplicate policy is `Error`
+    DuplicateId(String),
+    /// A payload filter could not be parsed
+    InvalidFilter(String),
+    /// A payload does not match the declared schema
+    SchemaViolation(String),
+    /// The id is not indexed
+    UnknownId(String),
+    /// A scoring formula could not be parsed or evaluated
+    InvalidFormula(String),
+}
+
+impl fmt::Display for HnswError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HnswError::DimensionMismatch { expected, got } => write!(
+                f,
+                "Vector dimension mismatch: expected {}, got {}",
+                expected, got
+            ),
+            HnswError::InvalidParams(msg) => write!(f, "Invalid params: {}", msg),
+            HnswError::Serialization(msg) => write!(f, "Serialization error: {}", msg),
+            HnswError::Deserialization(msg) => write!(f, "Deserialization error: {}", msg),
+            HnswError::CorruptSnapshot(msg) => write!(f, "Corrupt snapshot: {}", msg),
+            HnswError::Invariant(msg) => write!(f, "Index invariant violated: {}", msg),
+            HnswError::Storage(msg) => write!(f, "Storage error: {}", msg),
+            HnswError::Embedding(msg) => write!(f, "Embedding error: {}", msg),
+            HnswError::DuplicateId(id) => write!(f, "Duplicate id: {}", id),
+            HnswError::InvalidFilter(msg) => write!(f, "Invalid filter: {}", msg),
+            HnswError::SchemaViolation(msg) => write!(f, "Schema violation: {}", msg),
+            HnswError::UnknownId(id) => write!(f, "Unknown id: {}", id),
+            HnswError::InvalidFormula(msg) => write!(f, "Invalid formula: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for HnswError {}
+
+impl From<HnswError> for JsValue {
+    fn from(err: HnswError) -> Self {
+        JsValue::from_str(&err.to_string())
+    }
+}
@@ -0,0 +1,293 @@
+//! FAISS index files
+//!
+//! Writes and reads the `IndexFlat` and `IndexHNSWFlat` layouts of FAISS
+//! `write_index`/`read_index`, little-endian as FAISS writes them on
+//! common platforms. FAISS has no cosine metric, so exports normalize the
+//! stored vectors and use inner product, which ranks them the same way.
+//! FAISS numbers points from 0; exports number them in id order, and
+//! imports name them from a caller's id list or by number. Imports take
+//! only the vectors and build the graph here, as FAISS graphs may be
+//! built for L2.
+
+use std::collections::HashMap;
+
+use crate::ids::Id;
+use crate::index::Hnsw;
+use crate::scalar::Scalar;
+use crate::store::VectorStore;
+use crate::{HNSWParams, HnswError};
+
+const FLAT_INNER_PRODUCT: [u8; 4] = *b"IxFI";
+const FLAT_L2: [u8; 4] = *b"IxF2";
+const FLAT: [u8; 4] = *b"IxFl";
+const HNSW_FLAT: [u8; 4] = *b"IHNf";
+const METRIC_INNER_PRODUCT: i32 = 0;
+/// Written twice in every header, unused since FAISS 1.0
+const HEADER_DUMMY: i64 = 1 << 20;
+
+impl<S: Scalar, V: VectorStore<S>> Hnsw<S, V> {
+    /// Export as a FAISS `IndexHNSWFlat` file, or as an `IndexFlatIP`
+    /// without the graph
+    ///
+    /// Point `i` is the `i`-th id in ascending order. Vectors are the
+    /// stored ones, after any random projection, as `f32`.
+    pub fn to_faiss(&self, with_graph: bool) -> Result<Vec<u8>, HnswError> {
+        let ids: Vec<String> = self.ids().map(Id::to_string).collect();
+        let d = self.stored_dimensions();
+        let mut vectors = Vec::with_capacity(ids.len() * d);
+        for id in &ids {
+            let vector = self
+                .vector(id)
+                .ok_or_else(|| HnswError::Storage(format!("vector of {} is missing", id)))?;
+            let start = vectors.len();
+            vectors.extend(vector.iter().map(|x| x.to_f64() as f32));
+            let norm = vectors[start..].iter().map(|x| x * x).sum::<f32>().sqrt();
+            if norm > 0.0 {
+                vectors[start..].iter_mut().for_each(|x| *x /= norm);
+            }
+        }
+
+        let mut out = Vec::new();
+        if with_graph {
+            out.extend_from_slice(&HNSW_FLAT);
+            write_header(&mut out, d, ids.len())?;
+            self.write_faiss_graph(&mut out, &ids);
+        }
+        out.extend_from_slice(&FLAT_INNER_PRODUCT);
+        write_header(&mut out, d, ids.len())?;
+        out.extend_from_slice(&(vectors.len() as u64).to_le_bytes());
+        for x in vectors {
+            out.extend_from_slice(&x.to_le_bytes());
+        }
+        Ok(out)
+    }
+
+    /// The `HNSW` struct of an `IndexHNSWFlat`, points numbered as `ids`
+    fn write_faiss_graph(&self, out: &mut Vec<u8>, ids: &[String]) {
+        let nodes: Vec<u32> = ids.iter().filter_map(|id| self.node(id)).collect();
+        let number: HashMap<u32, i32> = nodes
+            .iter()
+            .enumerate()
+            .map(|(i, &node)| (node, i as i32))
+            .collect();
+        let levels: Vec<usize> = nodes
+            .iter()
+            .map(|&node| self.node_level(node).unwrap_or(0))
+            .collect();
+        let top = levels.iter().copied().max().unwrap_or(0);
+
+        // HNSW::set_default_probas, extended to cover every level present
+        let m = self.params().m.max(2);
+        let level_mult = 1.0 / (m as f64).ln();
+        let mut probas = Vec::new();
+        let mut cumulative = vec![0usize];
+        for level in 0.. {
+            let proba = (-(level as f64) / level_mult).exp() * (1.0 - (-1.0 / level_mult).exp());
+            if proba < 1e-9 && level > top {
+                break;
+            }
+            probas.push(proba);
+            cumulative.push(cumulative[level] + if level == 0 { 2 * m } else { m });
+        }
+
+        let mut offsets = vec![0usize];
+        for &level in &levels {
+            offsets.push(offsets[offsets.len() - 1] + cumulative[level + 1]);
+        }
+        let mut neighbors = vec![-1i32; offsets[offsets.len() - 1]];
+        for (i, &node) in nodes.iter().enumerate() {
+            for layer in 0..=levels[i] {
+                let start = offsets[i] + cumulative[layer];
+                let width = cumulative[layer + 1] - cumulative[layer];
+                let links = self.links(node, layer).unwrap_or_default();
+                let links = links.iter().filter_map(|link| number.get(link));
+                for (slot, &link) in neighbors[start..start + width].iter_mut().zip(links) {
+                    *slot = link;
+                }
+            }
+        }
+
+        out.extend_from_slice(&(probas.len() as u64).to_le_bytes());
+        for p in probas {
+            out.extend_from_slice(&p.to_le_bytes());
+        }
+        write_i32s(out, cumulative.iter().map(|&n| n as i32));
+        write_i32s(out, levels.iter().map(|&level| level as i32 + 1));
+        out.extend_from_slice(&(offsets.len() as u64).to_le_bytes());
+        for offset in offsets {
+            out.extend_from_slice(&(offset as u64).to_le_bytes());
+        }
+        write_i32s(out, neighbors);
+        let entry = self.entry_node();
+        for value in [
+            entry
+                .and_then(|node| number.get(&node))
+                .copied()
+                .unwrap_or(-1),
+            entry
+                .and_then(|node| self.node_level(node))
+                .map_or(-1, |level| level as i32),
+            self.params().ef_construction as i32,
+            self.params().ef_search as i32,
+            1, // upper_beam
+        ] {
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+}
+
+impl<S: Scalar> Hnsw<S> {
+    /// Build an index from the vectors of a FAISS flat or `IndexHNSWFlat`
+    /// file
+    ///
+    /// Points are named by `ids` in FAISS order, or `"0"`, `"1"`, ...
+    /// without it.
+    pub fn from_faiss(
+        data: &[u8],
+        ids: Option<Vec<String>>,
+        params: HNSWParams,
+    ) -> Result<Hnsw<S>, HnswError> {
+        let mut reader = Reader { data, pos: 0 };
+        let (d, vectors) = reader.index()?;
+        let count = vectors.len().checked_div(d).unwrap_or(0);
+        let ids = match ids {
+            Some(ids) if ids.len() != count => {
+                return Err(HnswError::InvalidParams(format!(
+                    "{} ids given for {} FAISS vectors",
+                    ids.len(),
+                    count
+                )));
+            }
+            Some(ids) => ids,
+            None => (0..count).map(|i| i.to_string()).collect(),
+        };
+        let mut index = Hnsw::with_params(params);
+        if count > 0 {
+            let vectors: Vec<S> = vectors.iter().map(|&x| S::from_f64(x as f64)).collect();
+            index.insert_batch(ids, &vectors, d)?;
+        }
+        Ok(index)
+    }
+}
+
+fn write_header(out: &mut Vec<u8>, d: usize, ntotal: usize) -> Result<(), HnswError> {
+    let d = i32::try_from(d)
+        .map_err(|_| HnswError::Serialization("dimensions exceed FAISS limits".to_string()))?;
+    out.extend_from_slice(&d.to_le_bytes());
+    out.extend_from_slice(&(ntotal as i64).to_le_bytes());
+    out.extend_from_slice(&HEADER_DUMMY.to_le_bytes());
+    out.extend_from_slice(&HEADER_DUMMY.to_le_bytes());
+    out.push(1); // is_trained
+    out.extend_from_slice(&METRIC_INNER_PRODUCT.to_le_bytes());
+    Ok(())
+}
+
+/// A `std::vector<int>` as FAISS writes it: element count, then elements
+fn write_i32s(out: &mut Vec<u8>, values: impl IntoIterator<Item = i32>) {
+    let at = out.len();
+    out.extend_from_slice(&0u64.to_le_bytes());
+    let mut count = 0u64;
+    for value in values {
+        out.extend_from_slice(&value.to_le_bytes());
+        count += 1;
+    }
+    out[at..at + 8].copy_from_slice(&count.to_le_bytes());
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8], HnswError> {
+        let bytes = self
+            .pos
+            .checked_add(n)
+            .and_then(|end| self.data.get(self.pos..end))
+            .ok_or_else(|| faiss_error("file is truncated"))?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn fixed<const N: usize>(&mut self) -> Result<[u8; N], HnswError> {
+        let mut out = [0u8; N];
+        out.copy_from_slice(self.take(N)?);
+        Ok(out)
+    }
+
+    fn i32(&mut self) -> Result<i32, HnswError> {
+        Ok(i32::from_le_bytes(self.fixed()?))
+    }
+
+    fn i64(&mut self) -> Result<i64, HnswError> {
+        Ok(i64::from_le_bytes(self.fixed()?))
+    }
+
+    /// Skip a `std::vector` of `width`-byte elements
+    fn skip_vector(&mut self, width: usize) -> Result<(), HnswError> {
+        let count = u64::from_le_bytes(self.fixed()?) as usize;
+        let len = count
+            .checked_mul(width)
+            .ok_or_else(|| faiss_error("vector size overflows"))?;
+        self.take(len).map(|_| ())
+    }
+
+    /// `d` and `ntotal` of an index header, checking its metric
+    fn header(&mut self) -> Result<(usize, usize), HnswError> {
+        let d = self.i32()?;
+        let ntotal = self.i64()?;
+        self.i64()?;
+        self.i64()?;
+        self.take(1)?; // is_trained
+        if self.i32()? > 1 {
+            self.take(4)?; // metric_arg
+        }
+        match (usize::try_from(d), usize::try_from(ntotal)) {
+            (Ok(d), Ok(ntotal)) => Ok((d, ntotal)),
+            _ => Err(faiss_error("negative dimensions or count")),
+        }
+    }
+
+    /// Dimensions and packed vectors of a flat or HNSW-over-flat index
+    fn index(&mut self) -> Result<(usize, Vec<f32>), HnswError> {
+        let fourcc: [u8; 4] = self.fixed()?;
+        match fourcc {
+            FLAT_INNER_PRODUCT | FLAT_L2 | FLAT => {
+                let (d, ntotal) = self.header()?;
+                let words = u64::from_le_bytes(self.fixed()?) as usize;
+                if Some(words) != d.checked_mul(ntotal) {
+                    return Err(faiss_error("vector data does not match the header"));
+                }
+                let bytes = self.take(words.saturating_mul(4))?;
+                let vectors = bytes
+                    .chunks_exact(4)
+                    .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                    .collect();
+                Ok((d, vectors))
+            }
+            HNSW_FLAT => {
+                let (d, ntotal) = self.header()?;
+                self.skip_vector(8)?; // assign_probas
+                self.skip_vector(4)?; // cum_nneighbor_per_level
+                self.skip_vector(4)?; // levels
+                self.skip_vector(8)?; // offsets
+                self.skip_vector(4)?; // neighbors
+                self.take(5 * 4)?; // entry point, max level, efs, upper beam
+                let (storage_d, vectors) = self.index()?;
+                if storage_d != d || vectors.len() != d * ntotal {
+                    return Err(faiss_error("HNSW storage does not match its header"));
+                }
+                Ok((d, vectors))
+            }
+            other => Err(faiss_error(&format!(
+                "unsupported index type {}",
+                String::from_utf8_lossy(&other)
+            ))),
+        }
+    }
+}
+
+fn faiss_error(msg: &str) -> HnswError {
+    HnswError::Deserialization(format!("FAISS index: {}", msg))
+}
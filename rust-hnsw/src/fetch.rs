@@ -0,0 +1,103 @@
+//! Streaming snapshot downloads for browser builds
+//!
+//! The body of a `fetch` response is read chunk by chunk into one buffer,
+//! reporting progress as it goes, and handed to a decoder once the
+//! stream ends. Works wherever `fetch` and readable streams exist,
+//! windows and workers alike.
+
+use js_sys::{Function, Promise, Reflect, Uint8Array};
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{ReadableStreamDefaultReader, Response};
+
+/// Turns the downloaded bytes into the value the promise resolves to
+type Finish = Box<dyn FnOnce(Vec<u8>) -> Result<JsValue, JsValue>>;
+
+struct Download {
+    reader: ReadableStreamDefaultReader,
+    data: Vec<u8>,
+    /// From `Content-Length`, when the server sent it
+    total: Option<f64>,
+    progress: Option<Function>,
+    finish: Option<Finish>,
+}
+
+/// Fetch `url` and resolve to `finish(body)`
+///
+/// `progress(loaded, total)` is called after each chunk, with `total`
+/// `undefined` when the response has no length.
+pub(crate) fn stream_url(
+    url: &str,
+    progress: Option<Function>,
+    finish: impl FnOnce(Vec<u8>) -> Result<JsValue, JsValue> + 'static,
+) -> Result<Promise, JsValue> {
+    let global = js_sys::global();
+    let fetch: Function = Reflect::get(&global, &"fetch".into())?
+        .dyn_into()
+        .map_err(|_| JsValue::from_str("fetch is not available here"))?;
+    let response: Promise = fetch.call1(&global, &url.into())?.dyn_into()?;
+
+    let url = url.to_string();
+    let finish: Finish = Box::new(finish);
+    let start = Closure::once_into_js(move |response: JsValue| -> Result<JsValue, JsValue> {
+        let response: Response = response.dyn_into()?;
+        if !response.ok() {
+            return Err(JsValue::from_str(&format!(
+                "fetching {} failed with status {}",
+                url,
+                response.status()
+            )));
+        }
+        let total = response
+            .headers()
+            .get("content-length")?
+            .and_then(|length| length.parse::<f64>().ok());
+        let body = response
+            .body()
+            .ok_or_else(|| JsValue::from_str("response has no body"))?;
+        let download = Download {
+            reader: body.get_reader().unchecked_into(),
+            data: Vec::new(),
+            total,
+            progress,
+            finish: Some(finish),
+        };
+        read_next(Rc::new(RefCell::new(download))).map(JsValue::from)
+    });
+    then(&response, &start)
+}
+
+/// Read the next chunk, chaining reads until the stream ends
+fn read_next(download: Rc<RefCell<Download>>) -> Result<Promise, JsValue> {
+    let read = download.borrow().reader.read();
+    let next = Closure::once_into_js(move |chunk: JsValue| -> Result<JsValue, JsValue> {
+        let mut state = download.borrow_mut();
+        if Reflect::get(&chunk, &"done".into())?.is_truthy() {
+            let data = std::mem::take(&mut state.data);
+            let finish = state
+                .finish
+                .take()
+                .ok_or_else(|| JsValue::from_str("download already finished"))?;
+            return finish(data);
+        }
+        let value: Uint8Array = Reflect::get(&chunk, &"value".into())?.dyn_into()?;
+        let start = state.data.len();
+        state.data.resize(start + value.length() as usize, 0);
+        value.copy_to(&mut state.data[start..]);
+        if let Some(progress) = &state.progress {
+            let total = state.total.map_or(JsValue::UNDEFINED, JsValue::from_f64);
+            progress.call2(&JsValue::NULL, &(state.data.len() as f64).into(), &total)?;
+        }
+        drop(state);
+        read_next(download).map(JsValue::from)
+    });
+    then(&read, &next)
+}
+
+/// `promise.then(callback)`, where `callback` may return a promise
+fn then(promise: &Promise, callback: &JsValue) -> Result<Promise, JsValue> {
+    let then: Function = Reflect::get(promise, &"then".into())?.dyn_into()?;
+    Ok(then.call1(promise, callback)?.unchecked_into())
+}
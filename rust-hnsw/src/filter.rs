@@ -0,0 +1,236 @@
+use serde_json::{Map, Value};
+
+use crate::HnswError;
+
+/// Predicate over point payloads
+///
+/// Parsed from a JSON object in one of two forms. The shorthand maps
+/// fields to expected values, e.g. `{ "path": "src/old.rs" }`, and matches
+/// when every field is equal. The full form combines clauses:
+///
+/// ```json
+/// {
+///   "must": [{ "key": "language", "match": { "any": ["rust", "go"] } }],
+///   "should": [{ "key": "lines", "range": { "gte": 10, "lt": 200 } }],
+///   "must_not": [{ "must": [{ "key": "generated", "match": { "value": true } }] }]
+/// }
+/// ```
+///
+/// `must` clauses all hold, at least one `should` clause holds (when any
+/// are given), and no `must_not` clause holds. A clause is either a field
+/// condition or a nested filter. Dotted keys reach into nested objects.
+///
+/// Conditions on array fields hold when any element satisfies them, so
+/// `{ "key": "tags", "match": { "value": "test" } }` reads as "tags
+/// contains test" and `"any"` as "tags contain one of".
+#[derive(Clone, Debug, PartialEq)]
+pub enum Filter {
+    /// Every filter matches
+    All(Vec<Filter>),
+    /// At least one filter matches
+    Any(Vec<Filter>),
+    /// The filter does not match
+    Not(Box<Filter>),
+    /// A condition on one payload field
+    Field { key: String, condition: Condition },
+}
+
+/// Test applied to a single payload field
+#[derive(Clone, Debug, PartialEq)]
+pub enum Condition {
+    /// The field equals the value
+    Equals(Value),
+    /// The field equals one of the values
+    In(Vec<Value>),
+    /// The field is a number within the bounds
+    Range(Range),
+}
+
+/// Numeric bounds; unset bounds are open
+///
+/// Missing and non-numeric fields never fall within a range, so under
+/// `must_not` they always pass.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Range {
+    pub gt: Option<f64>,
+    pub gte: Option<f64>,
+    pub lt: Option<f64>,
+    pub lte: Option<f64>,
+}
+
+impl Range {
+    /// Whether `x` lies within every set bound
+    pub fn contains(&self, x: f64) -> bool {
+        self.gt.iter().all(|&b| x > b)
+            && self.gte.iter().all(|&b| x >= b)
+            && self.lt.iter().all(|&b| x < b)
+            && self.lte.iter().all(|&b| x <= b)
+    }
+
+    fn parse(key: &str, spec: Map<String, Value>) -> Result<Range, HnswError> {
+        let mut range = Range::default();
+        for (bound, value) in spec {
+            let value = value
+                .as_f64()
+                .ok_or_else(|| invalid(format!("range {} on {} must be a number", bound, key)))?;
+            match bound.as_str() {
+                "gt" => range.gt = Some(value),
+                "gte" => range.gte = Some(value),
+                "lt" => range.lt = Some(value),
+                "lte" => range.lte = Some(value),
+                other => return Err(invalid(format!("unknown range bound {} on {}", other, key))),
+            }
+        }
+        if range == Range::default() {
+            return Err(invalid(format!("range on {} needs a bound", key)));
+        }
+        Ok(range)
+    }
+}
+
+impl Filter {
+    /// Parse a filter from its JSON form
+    pub fn parse(value: Value) -> Result<Filter, HnswError> {
+        match value {
+            Value::Object(map) if is_boolean(&map) => parse_boolean(map),
+            Value::Object(map) => Ok(Filter::All(
+                map.into_iter()
+                    .map(|(key, value)| Filter::Field {
+                        key,
+                        condition: Condition::Equals(value),
+                    })
+                    .collect(),
+            )),
+            other => Err(invalid(format!("expected an object, got {}", other))),
+        }
+    }
+
+    /// Whether a point with this payload passes the filter
+    pub fn matches(&self, payload: Option<&Value>) -> bool {
+        match self {
+            Filter::All(filters) => filters.iter().all(|f| f.matches(payload)),
+            Filter::Any(filters) => filters.iter().any(|f| f.matches(payload)),
+            Filter::Not(filter) => !filter.matches(payload),
+            Filter::Field { key, condition } => condition.matches(lookup(payload, key)),
+        }
+    }
+}
+
+impl Condition {
+    /// Whether a field value, `None` when missing, satisfies the condition
+    pub fn matches(&self, value: Option<&Value>) -> bool {
+        match value {
+            Some(value @ Value::Array(items)) => {
+                self.matches_value(value) || items.iter().any(|item| self.matches_value(item))
+            }
+            Some(value) => self.matches_value(value),
+            None => false,
+        }
+    }
+
+    fn matches_value(&self, value: &Value) -> bool {
+        match self {
+            Condition::Equals(expected) => same_value(value, expected),
+            Condition::In(options) => options.iter().any(|option| same_value(value, option)),
+            Condition::Range(range) => value.as_f64().is_some_and(|x| range.contains(x)),
+        }
+    }
+}
+
+/// JSON equality that treats `1` and `1.0` as the same number
+fn same_value(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => x.as_f64() == y.as_f64(),
+        _ => a == b,
+    }
+}
+
+fn is_boolean(map: &Map<String, Value>) -> bool {
+    ["must", "should", "must_not"]
+        .iter()
+        .any(|clause| map.contains_key(*clause))
+}
+
+fn parse_boolean(map: Map<String, Value>) -> Result<Filter, HnswError> {
+    let mut parts = Vec::new();
+    for (clause, value) in map {
+        let filters = match value {
+            Value::Array(items) => items
+                .into_iter()
+                .map(parse_clause)
+                .collect::<Result<Vec<_>, _>>()?,
+            other => vec![parse_clause(other)?],
+        };
+        match clause.as_str() {
+            "must" => parts.push(Filter::All(filters)),
+            "should" if filters.is_empty() => {}
+            "should" => parts.push(Filter::Any(filters)),
+            "must_not" => parts.push(Filter::Not(Box::new(Filter::Any(filters)))),
+            other => return Err(invalid(format!("unknown clause {}", other))),
+        }
+    }
+    Ok(Filter::All(parts))
+}
+
+/// Parse one entry of a `must` / `should` / `must_not` list
+fn parse_clause(value: Value) -> Result<Filter, HnswError> {
+    let mut map = match value {
+        Value::Object(map) => map,
+        other => return Err(invalid(format!("expected a clause object, got {}", other))),
+    };
+    if is_boolean(&map) {
+        return parse_boolean(map);
+    }
+
+    let key = match map.remove("key") {
+        Some(Value::String(key)) => key,
+        _ => return Err(invalid("condition needs a string \"key\"".to_string())),
+    };
+    let condition = match (map.remove("match"), map.remove("range")) {
+        (Some(Value::Object(mut spec)), None) => match (spec.remove("value"), spec.remove("any")) {
+            (Some(value), None) => Condition::Equals(value),
+            (None, Some(Value::Array(options))) => Condition::In(options),
+            _ => {
+                return Err(invalid(format!(
+                    "match on {} needs exactly one of \"value\" or an \"any\" array",
+                    key
+                )))
+            }
+        },
+        (None, Some(Value::Object(spec))) => Condition::Range(Range::parse(&key, spec)?),
+        _ => {
+            return Err(invalid(format!(
+                "condition on {} needs one match or range object",
+                key
+            )))
+        }
+    };
+    if let Some(extra) = map.keys().next() {
+        return Err(invalid(format!(
+            "unexpected {} in condition on {}",
+            extra, key
+        )));
+    }
+
+    Ok(Filter::Field { key, condition })
+}
+
+fn invalid(msg: String) -> HnswError {
+    HnswError::InvalidFilter(msg)
+}
+
+/// Resolve a dotted field path inside a payload
+pub(crate) fn lookup<'a>(payload: Option<&'a Value>, field: &str) -> Option<&'a Value> {
+    field
+        .split('.')
+        .try_fold(payload?, |value, key| value.as_object()?.get(key))
+}
+
+/// Values of a dotted field, flattening one level of arrays
+pub(crate) fn lookup_values<'a>(payload: Option<&'a Value>, field: &str) -> Vec<&'a Value> {
+    match lookup(payload, field) {
+        Some(Value::Array(items)) => items.iter().collect(),
+        Some(value) => vec![value],
+        None => Vec::new(),
+    }
+}
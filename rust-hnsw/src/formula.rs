@@ -0,0 +1,390 @@
+//! Scoring formulas mixing the vector score with payload fields
+
+use serde_json::Value;
+use std::fmt;
+
+use crate::filter::lookup;
+use crate::index::Hnsw;
+use crate::rerank::{RerankCandidate, Reranker};
+use crate::scalar::Scalar;
+use crate::search::SearchOptions;
+use crate::store::VectorStore;
+use crate::HnswError;
+
+/// Candidates fetched per requested result, so payload boosts can lift
+/// hits from below the top `k`
+const FORMULA_CANDIDATES_PER_RESULT: usize = 4;
+
+/// A parsed scoring expression such as `score + 0.1 * payload.stars`
+///
+/// Supports numbers, `"strings"`, `true`/`false`, `score`, dotted
+/// `payload.` fields, `+ - * /`, parentheses, the comparisons
+/// `== != < <= > >=` (1 when true, else 0) and the functions `abs`, `sqrt`,
+/// `ln`, `exp`, `min` and `max`. In arithmetic, booleans count as 1 or 0
+/// and missing or non-numeric fields as 0, so
+/// `score + 0.2 * (payload.repo == "mine")` boosts one repository.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScoreFormula {
+    expr: Expr,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    Number(f64),
+    Text(String),
+    Score,
+    Field(String),
+    Neg(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+    Call(Function, Vec<Expr>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Function {
+    Abs,
+    Sqrt,
+    Ln,
+    Exp,
+    Min,
+    Max,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Text(String),
+    Ident(String),
+    Op(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Text(s) => write!(f, "\"{}\"", s),
+            Token::Ident(name) => write!(f, "{}", name),
+            Token::Op(op) => write!(f, "{}", op),
+        }
+    }
+}
+
+/// A value met while evaluating, before it is forced to a number
+enum Operand<'a> {
+    Number(f64),
+    Text(&'a str),
+    Missing,
+}
+
+impl Operand<'_> {
+    fn number(&self) -> f64 {
+        match self {
+            Operand::Number(n) => *n,
+            _ => 0.0,
+        }
+    }
+}
+
+impl ScoreFormula {
+    pub fn parse(source: &str) -> Result<ScoreFormula, HnswError> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.comparison()?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(ScoreFormula { expr }),
+            Some(token) => Err(invalid(format!("unexpected {}", token))),
+        }
+    }
+
+    /// Score of a hit with vector similarity `score` and `payload`
+    pub fn evaluate(&self, score: f32, payload: Option<&Value>) -> f64 {
+        eval(&self.expr, score as f64, payload).number()
+    }
+
+    fn scores(&self, candidates: &[RerankCandidate<'_>]) -> Result<Vec<f32>, HnswError> {
+        candidates
+            .iter()
+            .map(|c| match self.evaluate(c.score, c.payload) as f32 {
+                score if score.is_finite() => Ok(score),
+                score => Err(invalid(format!("score is {} for {}", score, c.id))),
+            })
+            .collect()
+    }
+}
+
+impl Reranker for ScoreFormula {
+    fn rerank(&mut self, candidates: &[RerankCandidate<'_>]) -> Result<Vec<f32>, HnswError> {
+        self.scores(candidates)
+    }
+}
+
+fn invalid(msg: String) -> HnswError {
+    HnswError::InvalidFormula(msg)
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, HnswError> {
+    const OPS: [&str; 14] = [
+        "==", "!=", "<=", ">=", "<", ">", "+", "-", "*", "/", "(", ")", ",", ".",
+    ];
+    let mut tokens = Vec::new();
+    let mut rest = source.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let len = if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .unwrap_or(rest.len());
+            let number = rest[..len]
+                .parse()
+                .map_err(|_| invalid(format!("bad number {}", &rest[..len])))?;
+            tokens.push(Token::Number(number));
+            len
+        } else if c.is_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Ident(rest[..len].to_string()));
+            len
+        } else if c == '"' {
+            let end = rest[1..]
+                .find('"')
+                .ok_or_else(|| invalid("unterminated string".to_string()))?;
+            tokens.push(Token::Text(rest[1..end + 1].to_string()));
+            end + 2
+        } else {
+            let op = OPS
+                .iter()
+                .find(|op| rest.starts_with(**op))
+                .ok_or_else(|| invalid(format!("unexpected character {}", c)))?;
+            tokens.push(Token::Op(op));
+            op.len()
+        };
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent over the tokens, loosest binding first
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// Consume `op` if it is next
+    fn eat(&mut self, op: &str) -> bool {
+        let found = matches!(self.tokens.get(self.pos), Some(Token::Op(o)) if *o == op);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), HnswError> {
+        if self.eat(op) {
+            Ok(())
+        } else {
+            Err(invalid(format!("expected {}", op)))
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Expr, HnswError> {
+        let left = self.additive()?;
+        let ops = [
+            ("==", Op::Eq),
+            ("!=", Op::Ne),
+            ("<=", Op::Le),
+            (">=", Op::Ge),
+            ("<", Op::Lt),
+            (">", Op::Gt),
+        ];
+        for (symbol, op) in ops {
+            if self.eat(symbol) {
+                let right = self.additive()?;
+                return Ok(Expr::Binary(op, Box::new(left), Box::new(right)));
+            }
+        }
+        Ok(left)
+    }
+
+    fn additive(&mut self) -> Result<Expr, HnswError> {
+        let mut expr = self.term()?;
+        loop {
+            let op = if self.eat("+") {
+                Op::Add
+            } else if self.eat("-") {
+                Op::Sub
+            } else {
+                return Ok(expr);
+            };
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.term()?));
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, HnswError> {
+        let mut expr = self.unary()?;
+        loop {
+            let op = if self.eat("*") {
+                Op::Mul
+            } else if self.eat("/") {
+                Op::Div
+            } else {
+                return Ok(expr);
+            };
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, HnswError> {
+        if self.eat("-") {
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, HnswError> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Expr::Number(n)),
+            Some(Token::Text(s)) => Ok(Expr::Text(s)),
+            Some(Token::Op("(")) => {
+                let expr = self.comparison()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(Token::Ident(name)) => match name.as_str() {
+                "score" => Ok(Expr::Score),
+                "true" => Ok(Expr::Number(1.0)),
+                "false" => Ok(Expr::Number(0.0)),
+                "payload" => {
+                    let mut path = Vec::new();
+                    while self.eat(".") {
+                        match self.next() {
+                            Some(Token::Ident(key)) => path.push(key),
+                            _ => return Err(invalid("expected a field name".to_string())),
+                        }
+                    }
+                    if path.is_empty() {
+                        return Err(invalid("payload needs a field, e.g. payload.stars".into()));
+                    }
+                    Ok(Expr::Field(path.join(".")))
+                }
+                _ => self.call(&name),
+            },
+            Some(token) => Err(invalid(format!("unexpected {}", token))),
+            None => Err(invalid("unexpected end".to_string())),
+        }
+    }
+
+    fn call(&mut self, name: &str) -> Result<Expr, HnswError> {
+        let (function, arity) = match name {
+            "abs" => (Function::Abs, Some(1)),
+            "sqrt" => (Function::Sqrt, Some(1)),
+            "ln" => (Function::Ln, Some(1)),
+            "exp" => (Function::Exp, Some(1)),
+            "min" => (Function::Min, None),
+            "max" => (Function::Max, None),
+            other => return Err(invalid(format!("unknown name {}", other))),
+        };
+        self.expect("(")?;
+        let mut args = vec![self.comparison()?];
+        while self.eat(",") {
+            args.push(self.comparison()?);
+        }
+        self.expect(")")?;
+        if arity.is_some_and(|n| n != args.len()) {
+            return Err(invalid(format!("{} takes one argument", name)));
+        }
+        Ok(Expr::Call(function, args))
+    }
+}
+
+fn eval<'a>(expr: &'a Expr, score: f64, payload: Option<&'a Value>) -> Operand<'a> {
+    let number = |expr: &'a Expr| eval(expr, score, payload).number();
+    match expr {
+        Expr::Number(n) => Operand::Number(*n),
+        Expr::Text(s) => Operand::Text(s),
+        Expr::Score => Operand::Number(score),
+        Expr::Field(path) => match lookup(payload, path) {
+            Some(Value::Number(n)) => n.as_f64().map_or(Operand::Missing, Operand::Number),
+            Some(Value::Bool(b)) => Operand::Number(if *b { 1.0 } else { 0.0 }),
+            Some(Value::String(s)) => Operand::Text(s),
+            _ => Operand::Missing,
+        },
+        Expr::Neg(inner) => Operand::Number(-number(inner)),
+        Expr::Binary(op, left, right) => {
+            let (l, r) = (eval(left, score, payload), eval(right, score, payload));
+            let truth = |b: bool| Operand::Number(if b { 1.0 } else { 0.0 });
+            match op {
+                Op::Add => Operand::Number(l.number() + r.number()),
+                Op::Sub => Operand::Number(l.number() - r.number()),
+                Op::Mul => Operand::Number(l.number() * r.number()),
+                Op::Div => Operand::Number(l.number() / r.number()),
+                Op::Eq | Op::Ne => {
+                    let equal = match (&l, &r) {
+                        (Operand::Number(a), Operand::Number(b)) => a == b,
+                        (Operand::Text(a), Operand::Text(b)) => a == b,
+                        _ => false,
+                    };
+                    truth(equal == (*op == Op::Eq))
+                }
+                Op::Lt | Op::Le | Op::Gt | Op::Ge => match (l, r) {
+                    (Operand::Number(a), Operand::Number(b)) => truth(match op {
+                        Op::Lt => a < b,
+                        Op::Le => a <= b,
+                        Op::Gt => a > b,
+                        _ => a >= b,
+                    }),
+                    _ => truth(false),
+                },
+            }
+        }
+        Expr::Call(function, args) => {
+            let values = args.iter().map(number);
+            Operand::Number(match function {
+                Function::Abs => number(&args[0]).abs(),
+                Function::Sqrt => number(&args[0]).sqrt(),
+                Function::Ln => number(&args[0]).ln(),
+                Function::Exp => number(&args[0]).exp(),
+                Function::Min => values.fold(f64::INFINITY, f64::min),
+                Function::Max => values.fold(f64::NEG_INFINITY, f64::max),
+            })
+        }
+    }
+}
+
+impl<S: Scalar, V: VectorStore<S>> Hnsw<S, V> {
+    /// Find the `k` best matches for `vector` ranked by `formula`
+    ///
+    /// A few times `k` candidates are fetched by similarity and rescored,
+    /// so results carry formula scores and `minScore` applies to them.
+    /// `diversity` is not supported.
+    pub fn search_scored(
+        &self,
+        vector: &[S],
+        k: usize,
+        formula: &ScoreFormula,
+        options: &SearchOptions,
+    ) -> Result<Vec<(String, f32)>, HnswError> {
+        let candidates = k.saturating_mul(FORMULA_CANDIDATES_PER_RESULT);
+        let mut reranker = |hits: &[RerankCandidate<'_>]| formula.scores(hits);
+        self.search_reranked(vector, k, candidates, &mut reranker, options)
+    }
+}
@@ -0,0 +1,337 @@
+//! Searches combining several queries or retrievers into one ranking
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::index::Hnsw;
+use crate::scalar::Scalar;
+use crate::search::{best_first, SearchOptions};
+use crate::sparse::SparseVector;
+use crate::store::VectorStore;
+use crate::HnswError;
+
+/// How per-query similarities are combined into one score
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Fusion {
+    /// Weighted mean of the similarities: hits must fit every query
+    #[default]
+    Sum,
+    /// Largest weighted similarity: fitting any one query is enough
+    Max,
+}
+
+impl Fusion {
+    /// Parse `"sum"` or `"max"`
+    pub fn parse(fusion: &str) -> Result<Fusion, HnswError> {
+        match fusion {
+            "sum" => Ok(Fusion::Sum),
+            "max" => Ok(Fusion::Max),
+            other => Err(HnswError::InvalidParams(format!(
+                "unknown fusion {}",
+                other
+            ))),
+        }
+    }
+}
+
+/// How a list of scores is mapped onto a common scale before fusion or
+/// thresholding
+///
+/// Cosine similarities, L2 distances and BM25 scores live on unrelated
+/// scales; each method keeps the order of the list.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Normalization {
+    /// `(score - min) / (max - min)`, into `[0, 1]`; a flat list maps to 1
+    #[default]
+    MinMax,
+    /// `(score - mean) / stddev`; a flat list maps to 0
+    ZScore,
+    /// `exp(score) / sum(exp)`, into `(0, 1]` summing to 1
+    Softmax,
+}
+
+impl Normalization {
+    /// Parse `"minMax"`, `"zScore"` or `"softmax"`
+    pub fn parse(normalization: &str) -> Result<Normalization, HnswError> {
+        match normalization {
+            "minMax" => Ok(Normalization::MinMax),
+            "zScore" => Ok(Normalization::ZScore),
+            "softmax" => Ok(Normalization::Softmax),
+            other => Err(HnswError::InvalidParams(format!(
+                "unknown normalization {}",
+                other
+            ))),
+        }
+    }
+
+    /// Normalize `scores` in place
+    pub fn apply(self, scores: &mut [f32]) {
+        if scores.is_empty() {
+            return;
+        }
+        let n = scores.len() as f32;
+        match self {
+            Normalization::MinMax => {
+                let (low, high) = scores
+                    .iter()
+                    .fold((f32::INFINITY, f32::NEG_INFINITY), |(low, high), s| {
+                        (low.min(*s), high.max(*s))
+                    });
+                for score in scores {
+                    *score = if high > low {
+                        (*score - low) / (high - low)
+                    } else {
+                        1.0
+                    };
+                }
+            }
+            Normalization::ZScore => {
+                let mean = scores.iter().sum::<f32>() / n;
+                let deviation = (scores.iter().map(|s| (s - mean).powi(2)).sum::<f32>() / n).sqrt();
+                for score in scores {
+                    *score = if deviation > 0.0 {
+                        (*score - mean) / deviation
+                    } else {
+                        0.0
+                    };
+                }
+            }
+            Normalization::Softmax => {
+                // Shifting by the max keeps exp from overflowing
+                let high = scores.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+                for score in scores.iter_mut() {
+                    *score = (*score - high).exp();
+                }
+                let total: f32 = scores.iter().sum();
+                for score in scores {
+                    *score /= total;
+                }
+            }
+        }
+    }
+}
+
+/// How dense and keyword rankings are merged by [`Hnsw::search_hybrid`]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum HybridFusion {
+    /// Reciprocal rank fusion: `sum(1 / (RRF_K + rank))` over both lists
+    ///
+    /// Uses ranks only, so the scales of the two scores never meet.
+    #[default]
+    Rrf,
+    /// `dense_weight * dense + (1 - dense_weight) * keyword`, each score
+    /// normalized over its own list first
+    Weighted {
+        dense_weight: f32,
+        normalization: Normalization,
+    },
+}
+
+impl HybridFusion {
+    /// Parse `"rrf"` or `"weighted"`, the latter with a dense weight in
+    /// `[0, 1]` defaulting to 0.5 and min-max normalization by default
+    pub fn parse(
+        fusion: &str,
+        dense_weight: Option<f64>,
+        normalization: Option<Normalization>,
+    ) -> Result<HybridFusion, HnswError> {
+        match fusion {
+            "rrf" if dense_weight.is_some() || normalization.is_some() => {
+                Err(HnswError::InvalidParams(
+                    "denseWeight and normalization only apply to weighted fusion".to_string(),
+                ))
+            }
+            "rrf" => Ok(HybridFusion::Rrf),
+            "weighted" => match dense_weight.unwrap_or(0.5) {
+                w if (0.0..=1.0).contains(&w) => Ok(HybridFusion::Weighted {
+                    dense_weight: w as f32,
+                    normalization: normalization.unwrap_or_default(),
+                }),
+                w => Err(HnswError::InvalidParams(format!(
+                    "denseWeight must be between 0 and 1, got {}",
+                    w
+                ))),
+            },
+            other => Err(HnswError::InvalidParams(format!(
+                "unknown hybrid fusion {}",
+                other
+            ))),
+        }
+    }
+}
+
+/// Rank offset of reciprocal rank fusion, damping the head of each list
+const RRF_K: f32 = 60.0;
+
+/// Candidates fetched per result and query, leaving room for the exact
+/// fused scores to reorder them
+const FUSION_CANDIDATES_PER_RESULT: usize = 2;
+
+impl<S: Scalar, V: VectorStore<S>> Hnsw<S, V> {
+    /// Find the `k` best matches for several `(vector, weight)` queries at once
+    ///
+    /// E.g. a natural-language description and a code example. Weights
+    /// must be positive. Candidates are scored against every query exactly
+    /// and fused before the top `k` are kept; `options` apply as in
+    /// [`Hnsw::search`].
+    pub fn search_fused(
+        &self,
+        queries: &[(&[S], f32)],
+        k: usize,
+        fusion: Fusion,
+        options: &SearchOptions,
+    ) -> Result<Vec<(String, f32)>, HnswError> {
+        if queries.is_empty() {
+            return Err(HnswError::InvalidParams(
+                "fused search needs at least one query".to_string(),
+            ));
+        }
+        if let Some((_, weight)) = queries.iter().find(|(_, w)| !(w.is_finite() && *w > 0.0)) {
+            return Err(HnswError::InvalidParams(format!(
+                "query weights must be positive, got {}",
+                weight
+            )));
+        }
+        let prepared = queries
+            .iter()
+            .map(|(vector, _)| self.prepare_query(vector))
+            .collect::<Result<Vec<_>, _>>()?;
+        if self.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let depth = options.candidates_per_result(FUSION_CANDIDATES_PER_RESULT);
+        let candidates = match fusion {
+            // The weighted sum of cosines is the cosine with the weighted sum
+            // of unit queries, so that one search finds the fused neighbors
+            Fusion::Sum => {
+                let combined = combine(&prepared, queries);
+                self.gather(&[combined], k.saturating_mul(depth), options)?
+            }
+            Fusion::Max => self.gather(&prepared, k.saturating_mul(depth), options)?,
+        };
+
+        let total_weight: f32 = queries.iter().map(|(_, w)| w).sum();
+        Ok(self.rerank(candidates, k, options, |stored| {
+            let weighted = prepared
+                .iter()
+                .zip(queries)
+                .map(|(query, (_, weight))| weight * (1.0 - S::cosine_distance(query, stored)));
+            match fusion {
+                Fusion::Sum => weighted.sum::<f32>() / total_weight,
+                Fusion::Max => weighted.fold(f32::NEG_INFINITY, f32::max),
+            }
+        }))
+    }
+
+    /// Find the `k` best matches for a dense and a sparse query together
+    ///
+    /// Both retrievers run with `options`, fetching a few times `k` each,
+    /// and their rankings are merged by `fusion`; `minScore` applies to
+    /// the fused score. `diversity` is not supported.
+    pub fn search_hybrid(
+        &self,
+        dense: &[S],
+        sparse: &SparseVector,
+        k: usize,
+        fusion: HybridFusion,
+        options: &SearchOptions,
+    ) -> Result<Vec<(String, f32)>, HnswError> {
+        self.fuse_with_dense(dense, k, fusion, options, |fetch, plain| {
+            self.search_sparse(sparse, fetch, plain)
+        })
+    }
+
+    /// [`Hnsw::search_hybrid`] with BM25 over point text as the keyword side
+    pub fn search_hybrid_text(
+        &self,
+        dense: &[S],
+        text: &str,
+        k: usize,
+        fusion: HybridFusion,
+        options: &SearchOptions,
+    ) -> Result<Vec<(String, f32)>, HnswError> {
+        self.fuse_with_dense(dense, k, fusion, options, |fetch, plain| {
+            self.search_text(text, fetch, plain)
+        })
+    }
+
+    /// Merge a dense search with the ranking from `keyword`, which is
+    /// given the number of hits to fetch and the options to fetch them with
+    fn fuse_with_dense(
+        &self,
+        dense: &[S],
+        k: usize,
+        fusion: HybridFusion,
+        options: &SearchOptions,
+        keyword: impl FnOnce(usize, &SearchOptions) -> Result<Vec<(String, f32)>, HnswError>,
+    ) -> Result<Vec<(String, f32)>, HnswError> {
+        if options.diversity.is_some() {
+            return Err(HnswError::InvalidParams(
+                "diversity cannot be combined with hybrid search".to_string(),
+            ));
+        }
+        // Per-list scores are not comparable with the fused one, which
+        // decays once
+        let mut plain = options.clone();
+        plain.min_score = None;
+        plain.decay = None;
+        let fetch = k.saturating_mul(FUSION_CANDIDATES_PER_RESULT);
+        let lists = [self.search(dense, fetch, &plain)?, keyword(fetch, &plain)?];
+
+        let mut fused: HashMap<String, f32> = HashMap::new();
+        for (list, results) in lists.iter().enumerate() {
+            match fusion {
+                HybridFusion::Rrf => {
+                    for (rank, (id, _)) in results.iter().enumerate() {
+                        *fused.entry(id.clone()).or_default() += 1.0 / (RRF_K + rank as f32 + 1.0);
+                    }
+                }
+                HybridFusion::Weighted {
+                    dense_weight,
+                    normalization,
+                } => {
+                    let weight = if list == 0 {
+                        dense_weight
+                    } else {
+                        1.0 - dense_weight
+                    };
+                    let mut scores: Vec<f32> = results.iter().map(|(_, score)| *score).collect();
+                    normalization.apply(&mut scores);
+                    for ((id, _), score) in results.iter().zip(scores) {
+                        *fused.entry(id.clone()).or_default() += weight * score;
+                    }
+                }
+            }
+        }
+
+        let mut results: Vec<(String, f32)> = fused.into_iter().collect();
+        results.sort_by(best_first);
+        self.apply_decay(&mut results, options);
+        results.truncate(k);
+        Ok(options.finish(results))
+    }
+}
+
+/// `sum(weight * query / |query|)`, scaled to fill the range of `S`
+fn combine<S: Scalar>(prepared: &[Vec<S>], queries: &[(&[S], f32)]) -> Vec<S> {
+    let dim = prepared.first().map_or(0, Vec::len);
+    let mut combined = vec![0.0f64; dim];
+    for (query, (_, weight)) in prepared.iter().zip(queries) {
+        let norm = query.iter().map(|x| x.to_f64().powi(2)).sum::<f64>().sqrt();
+        if norm > 0.0 {
+            for (total, x) in combined.iter_mut().zip(query) {
+                *total += *weight as f64 * x.to_f64() / norm;
+            }
+        }
+    }
+    // Cosine ignores scale, but integer scalars would round unit vectors away
+    let peak = combined.iter().fold(0.0f64, |m, x| m.max(x.abs()));
+    let scale = if peak > 0.0 { 255.0 / peak } else { 0.0 };
+    combined
+        .into_iter()
+        .map(|x| S::from_f64(x * scale))
+        .collect()
+}
@@ -0,0 +1,458 @@
+//! WebGPU scoring for exact search
+//!
+//! A [`GpuScorer`] runs a compute shader that scores one query against
+//! many stored vectors at once, one invocation per vector. It backs the
+//! index methods `rescoreGpu`, which scores a large candidate set exactly,
+//! and `searchExactGpu`, which brute-forces an index small enough to fit
+//! in one GPU buffer. The vectors of the last index searched stay on the
+//! GPU between queries until that index changes.
+//!
+//! WebGPU is reached through `Reflect` rather than `web-sys`, whose
+//! bindings for it still need an unstable cfg flag. Where WebGPU is
+//! missing, or a block is larger than the device can bind, the same
+//! scores are computed on the CPU, so callers need only one code path.
+
+use js_sys::{Array, Float32Array, Function, Object, Promise, Reflect, Uint32Array};
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use crate::index::Hnsw;
+use crate::scalar::Scalar;
+use crate::search::SearchOptions;
+use crate::store::VectorStore;
+
+/// Invocations per workgroup, as `@workgroup_size` in [`SHADER`]
+const WORKGROUP: usize = 64;
+
+/// `GPUBufferUsage` flags
+const MAP_READ: u32 = 0x1;
+const COPY_SRC: u32 = 0x4;
+const COPY_DST: u32 = 0x8;
+const UNIFORM: u32 = 0x40;
+const STORAGE: u32 = 0x80;
+
+/// `GPUMapMode.READ`
+const MAP_MODE_READ: u32 = 0x1;
+
+/// Cosine distance from the query to each row, 1.0 for zero vectors
+///
+/// Rows past what one dispatch dimension allows continue on `y`.
+const SHADER: &str = r#"
+struct Params {
+    dim: u32,
+    rows: u32,
+    query_norm: f32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> query: array<f32>;
+@group(0) @binding(2) var<storage, read> rows: array<f32>;
+@group(0) @binding(3) var<storage, read_write> distances: array<f32>;
+
+@compute @workgroup_size(64)
+fn main(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(num_workgroups) groups: vec3<u32>,
+) {
+    let row = id.y * groups.x * 64u + id.x;
+    if (row >= params.rows) {
+        return;
+    }
+    let base = row * params.dim;
+    var dot = 0.0;
+    var norm = 0.0;
+    for (var i = 0u; i < params.dim; i++) {
+        let x = rows[base + i];
+        dot += query[i] * x;
+        norm += x * x;
+    }
+    if (params.query_norm == 0.0 || norm == 0.0) {
+        distances[row] = 1.0;
+    } else {
+        distances[row] = 1.0 - dot / (sqrt(params.query_norm) * sqrt(norm));
+    }
+}
+"#;
+
+/// Exact cosine scoring on a WebGPU device, or on the CPU without one
+///
+/// ```js
+/// const scorer = await GpuScorer.request();
+/// const hits = await index.searchExactGpu(scorer, query, 10);
+/// const best = await index.rescoreGpu(scorer, query, candidateIds, 10);
+/// ```
+///
+/// One scorer serves any number of indexes. It keeps the vectors of the
+/// last index given to `searchExactGpu` on the GPU until that index
+/// changes or `release` is called.
+#[wasm_bindgen]
+pub struct GpuScorer {
+    /// `None` when scoring runs on the CPU
+    device: Option<Device>,
+    resident: RefCell<Option<Resident>>,
+}
+
+struct Device {
+    device: JsValue,
+    queue: JsValue,
+    pipeline: JsValue,
+    /// Largest storage buffer the shader may bind, in bytes
+    max_binding: f64,
+    /// Largest workgroup count of one dispatch dimension
+    max_groups: usize,
+}
+
+/// Contents of an index, as of one write
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) struct IndexState {
+    instance: u64,
+    generation: u64,
+    len: usize,
+}
+
+/// Vectors of one index held on the GPU, in the order of `ids`
+struct Resident {
+    state: IndexState,
+    buffer: JsValue,
+    ids: Rc<Vec<String>>,
+}
+
+#[wasm_bindgen]
+impl GpuScorer {
+    /// Resolve to a scorer on the default WebGPU adapter, or on the CPU
+    /// when WebGPU or an adapter is not available
+    pub fn request() -> Result<Promise, JsValue> {
+        let gpu = Reflect::get(&js_sys::global(), &"navigator".into())
+            .ok()
+            .filter(JsValue::is_object)
+            .and_then(|navigator| Reflect::get(&navigator, &"gpu".into()).ok())
+            .filter(JsValue::is_object);
+        let Some(gpu) = gpu else {
+            return Ok(Promise::resolve(&JsValue::from(GpuScorer::cpu())));
+        };
+        let adapter: Promise = call(&gpu, "requestAdapter", &[])?.dyn_into()?;
+        let device = Closure::once_into_js(|adapter: JsValue| -> Result<JsValue, JsValue> {
+            if adapter.is_object() {
+                call(&adapter, "requestDevice", &[])
+            } else {
+                Ok(JsValue::NULL)
+            }
+        });
+        let scorer = Closure::once_into_js(|device: JsValue| -> Result<JsValue, JsValue> {
+            let scorer = if device.is_object() {
+                GpuScorer::on(device)?
+            } else {
+                GpuScorer::cpu()
+            };
+            Ok(scorer.into())
+        });
+        let fallback = Closure::once_into_js(|_: JsValue| JsValue::from(GpuScorer::cpu()));
+        let device = then(&adapter, &device)?;
+        let scorer = then(&device, &scorer)?;
+        Ok(call(&scorer, "catch", &[&fallback])?.unchecked_into())
+    }
+
+    /// Whether scoring runs on a GPU rather than the CPU
+    #[wasm_bindgen(getter, js_name = isGpu)]
+    pub fn is_gpu(&self) -> bool {
+        self.device.is_some()
+    }
+
+    /// Free the vectors held on the GPU for `searchExactGpu`
+    pub fn release(&self) {
+        if let Some(resident) = self.resident.borrow_mut().take() {
+            destroy(&resident.buffer);
+        }
+    }
+}
+
+impl GpuScorer {
+    fn cpu() -> GpuScorer {
+        GpuScorer {
+            device: None,
+            resident: RefCell::new(None),
+        }
+    }
+
+    /// Compile the shader on `device`
+    fn on(device: JsValue) -> Result<GpuScorer, JsValue> {
+        let queue = Reflect::get(&device, &"queue".into())?;
+        let module = call(
+            &device,
+            "createShaderModule",
+            &[&object(&[("code", SHADER.into())])?],
+        )?;
+        let compute = object(&[("module", module), ("entryPoint", "main".into())])?;
+        let pipeline = call(
+            &device,
+            "createComputePipeline",
+            &[&object(&[("layout", "auto".into()), ("compute", compute)])?],
+        )?;
+        let limits = Reflect::get(&device, &"limits".into())?;
+        let limit = |name: &str, default: f64| {
+            Reflect::get(&limits, &name.into())
+                .ok()
+                .and_then(|value| value.as_f64())
+                .unwrap_or(default)
+        };
+        Ok(GpuScorer {
+            device: Some(Device {
+                max_binding: limit("maxStorageBufferBindingSize", (128 << 20) as f64),
+                max_groups: limit("maxComputeWorkgroupsPerDimension", 65535.0) as usize,
+                device,
+                queue,
+                pipeline,
+            }),
+            resident: RefCell::new(None),
+        })
+    }
+
+    /// Cosine distances from `query` to each `query.len()`-wide row of
+    /// `block`, resolving to `finish(distances)`
+    pub(crate) fn score(
+        &self,
+        query: &[f32],
+        block: Vec<f32>,
+        finish: impl FnOnce(Vec<f32>) -> Result<JsValue, JsValue> + 'static,
+    ) -> Result<Promise, JsValue> {
+        let rows = block.len().checked_div(query.len()).unwrap_or(0);
+        match &self.device {
+            Some(device) if rows > 0 && device.fits(block.len()) => {
+                let buffer = device.upload(&block)?;
+                device.dispatch(query, &buffer, rows, Some(buffer.clone()), finish)
+            }
+            _ => Ok(settle(finish(cpu_distances(query, &block)))),
+        }
+    }
+
+    /// [`GpuScorer::score`] against every vector of an index in `state`,
+    /// whose ids and vectors `rows` lists when they are not on the GPU
+    /// already
+    ///
+    /// `finish` also gets the ids of the rows scored, in order.
+    pub(crate) fn score_resident(
+        &self,
+        state: IndexState,
+        query: &[f32],
+        rows: impl FnOnce() -> (Vec<String>, Vec<f32>),
+        finish: impl FnOnce(Vec<f32>, Rc<Vec<String>>) -> Result<JsValue, JsValue> + 'static,
+    ) -> Result<Promise, JsValue> {
+        let Some(device) = &self.device else {
+            let (ids, block) = rows();
+            return Ok(settle(finish(cpu_distances(query, &block), Rc::new(ids))));
+        };
+        let mut resident = self.resident.borrow_mut();
+        if resident.as_ref().map(|resident| resident.state) != Some(state) {
+            if let Some(stale) = resident.take() {
+                destroy(&stale.buffer);
+            }
+            let (ids, block) = rows();
+            if ids.is_empty() || !device.fits(block.len()) {
+                return Ok(settle(finish(cpu_distances(query, &block), Rc::new(ids))));
+            }
+            *resident = Some(Resident {
+                state,
+                buffer: device.upload(&block)?,
+                ids: Rc::new(ids),
+            });
+        }
+        let Some(resident) = resident.as_ref() else {
+            return Err(JsValue::from_str("no vectors on the GPU"));
+        };
+        let ids = Rc::clone(&resident.ids);
+        device.dispatch(query, &resident.buffer, ids.len(), None, move |distances| {
+            finish(distances, ids)
+        })
+    }
+}
+
+impl Drop for GpuScorer {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+impl Device {
+    /// Whether `values` floats fit in one storage binding
+    fn fits(&self, values: usize) -> bool {
+        (values * 4) as f64 <= self.max_binding
+    }
+
+    fn buffer(&self, bytes: usize, usage: u32) -> Result<JsValue, JsValue> {
+        let descriptor = object(&[
+            ("size", (bytes.max(4) as f64).into()),
+            ("usage", usage.into()),
+        ])?;
+        call(&self.device, "createBuffer", &[&descriptor])
+    }
+
+    /// Copy `values` into a new storage buffer
+    fn upload(&self, values: &[f32]) -> Result<JsValue, JsValue> {
+        let buffer = self.buffer(values.len() * 4, STORAGE | COPY_DST)?;
+        self.write(&buffer, &Float32Array::from(values))?;
+        Ok(buffer)
+    }
+
+    fn write(&self, buffer: &JsValue, data: &JsValue) -> Result<(), JsValue> {
+        call(&self.queue, "writeBuffer", &[buffer, &0.into(), data]).map(drop)
+    }
+
+    /// Score `query` against the first `count` rows of `rows`, then read
+    /// the distances back and destroy the buffers of this dispatch,
+    /// `owned` among them
+    fn dispatch(
+        &self,
+        query: &[f32],
+        rows: &JsValue,
+        count: usize,
+        owned: Option<JsValue>,
+        finish: impl FnOnce(Vec<f32>) -> Result<JsValue, JsValue> + 'static,
+    ) -> Result<Promise, JsValue> {
+        let query_norm: f32 = query.iter().map(|x| x * x).sum();
+        let params = [query.len() as u32, count as u32, query_norm.to_bits(), 0];
+        let uniform = self.buffer(16, UNIFORM | COPY_DST)?;
+        self.write(&uniform, &Uint32Array::from(&params[..]))?;
+        let query = self.upload(query)?;
+        let distances = self.buffer(count * 4, STORAGE | COPY_SRC)?;
+        let readback = self.buffer(count * 4, MAP_READ | COPY_DST)?;
+
+        let entries = [&uniform, &query, rows, &distances]
+            .into_iter()
+            .enumerate()
+            .map(|(binding, buffer)| {
+                let resource = object(&[("buffer", buffer.clone())])?;
+                object(&[("binding", binding.into()), ("resource", resource)])
+            })
+            .collect::<Result<Array, JsValue>>()?;
+        let layout = call(&self.pipeline, "getBindGroupLayout", &[&0.into()])?;
+        let bind_group = call(
+            &self.device,
+            "createBindGroup",
+            &[&object(&[("layout", layout), ("entries", entries.into())])?],
+        )?;
+
+        let groups = count.div_ceil(WORKGROUP);
+        let x = groups.min(self.max_groups);
+        let y = groups.div_ceil(x);
+        let encoder = call(&self.device, "createCommandEncoder", &[])?;
+        let pass = call(&encoder, "beginComputePass", &[])?;
+        call(&pass, "setPipeline", &[&self.pipeline])?;
+        call(&pass, "setBindGroup", &[&0.into(), &bind_group])?;
+        call(&pass, "dispatchWorkgroups", &[&x.into(), &y.into()])?;
+        call(&pass, "end", &[])?;
+        call(
+            &encoder,
+            "copyBufferToBuffer",
+            &[
+                &distances,
+                &0.into(),
+                &readback,
+                &0.into(),
+                &(count * 4).into(),
+            ],
+        )?;
+        let commands = call(&encoder, "finish", &[])?;
+        call(&self.queue, "submit", &[&Array::of1(&commands)])?;
+
+        let mapped: Promise = call(&readback, "mapAsync", &[&MAP_MODE_READ.into()])?.dyn_into()?;
+        let mut buffers = vec![uniform, query, distances];
+        buffers.extend(owned);
+        let read = Closure::once_into_js(move |_: JsValue| -> Result<JsValue, JsValue> {
+            let range = call(&readback, "getMappedRange", &[])?;
+            let scores = Float32Array::new(&range).to_vec();
+            call(&readback, "unmap", &[])?;
+            buffers.push(readback);
+            for buffer in &buffers {
+                destroy(buffer);
+            }
+            finish(scores)
+        });
+        then(&mapped, &read)
+    }
+}
+
+impl<S: Scalar, V: VectorStore<S>> Hnsw<S, V> {
+    /// State of the index for [`GpuScorer::score_resident`]
+    pub(crate) fn gpu_state(&self) -> IndexState {
+        IndexState {
+            instance: self.instance(),
+            generation: self.generation(),
+            len: self.len(),
+        }
+    }
+
+    /// Whether `id` may appear in results under the filter and
+    /// exclusions of `options`
+    pub(crate) fn admits(&self, id: &str, options: &SearchOptions) -> bool {
+        !options.excludes(id)
+            && options
+                .filter
+                .as_ref()
+                .map_or(true, |filter| filter.matches(self.payload(id)))
+    }
+
+    /// The indexed ids among `ids` and their stored vectors as `f32`,
+    /// back to back
+    pub(crate) fn widened_rows<I: AsRef<str>>(
+        &self,
+        ids: impl IntoIterator<Item = I>,
+    ) -> (Vec<String>, Vec<f32>) {
+        let mut found = Vec::new();
+        let mut block = Vec::new();
+        for id in ids {
+            if let Some(vector) = self.vector(id.as_ref()) {
+                block.extend(widen(&vector));
+                found.push(id.as_ref().to_string());
+            }
+        }
+        (found, block)
+    }
+}
+
+/// `vector` as `f32`, the one element type the shader reads
+pub(crate) fn widen<S: Scalar>(vector: &[S]) -> Vec<f32> {
+    vector.iter().map(|x| x.to_f64() as f32).collect()
+}
+
+/// `f32` kernel of graph search over a block
+fn cpu_distances(query: &[f32], block: &[f32]) -> Vec<f32> {
+    let mut distances = Vec::new();
+    f32::cosine_distances(query, block, &mut distances);
+    distances
+}
+
+/// A promise already settled with `result`
+fn settle(result: Result<JsValue, JsValue>) -> Promise {
+    match result {
+        Ok(value) => Promise::resolve(&value),
+        Err(e) => Promise::reject(&e),
+    }
+}
+
+/// `target[method](...args)`
+fn call(target: &JsValue, method: &str, args: &[&JsValue]) -> Result<JsValue, JsValue> {
+    let function: Function = Reflect::get(target, &method.into())?
+        .dyn_into()
+        .map_err(|_| JsValue::from_str(&format!("WebGPU method {} is missing", method)))?;
+    function.apply(target, &args.iter().copied().collect::<Array>())
+}
+
+/// A plain object with `fields`
+fn object(fields: &[(&str, JsValue)]) -> Result<JsValue, JsValue> {
+    let object = Object::new();
+    for (name, value) in fields {
+        Reflect::set(&object, &(*name).into(), value)?;
+    }
+    Ok(object.into())
+}
+
+/// `promise.then(callback)`, where `callback` may return a promise
+fn then(promise: &JsValue, callback: &JsValue) -> Result<Promise, JsValue> {
+    Ok(call(promise, "then", &[callback])?.unchecked_into())
+}
+
+fn destroy(buffer: &JsValue) {
+    let _ = call(buffer, "destroy", &[]);
+}
@@ -0,0 +1,1014 @@
+//! ann-benchmarks datasets for recall and throughput evaluation
+//!
+//! Reads the HDF5 files published by ann-benchmarks: `train` vectors to
+//! index, `test` queries, the true `neighbors` of each query as train
+//! positions, and a `distance` attribute naming the metric. The reader
+//! covers what h5py writes: superblocks of any version, object headers
+//! v1 and v2, symbol-table and compact link groups, and contiguous,
+//! compact or chunked datasets with deflate, shuffle and fletcher32.
+//!
+//! This index ranks by cosine, so only `angular` datasets measure recall
+//! against the metric it searches with.
+
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::path::Path;
+use std::time::Instant;
+
+use crate::deflate;
+use crate::index::Hnsw;
+use crate::search::SearchOptions;
+use crate::{HNSWParams, HnswError};
+
+const SIGNATURE: &[u8] = b"\x89HDF\r\n\x1a\n";
+/// Object header continuation blocks followed before giving up
+const MAX_HEADER_BLOCKS: usize = 1024;
+/// B-tree depth followed before giving up
+const MAX_BTREE_DEPTH: usize = 32;
+/// Best case of DEFLATE, bounding what compressed chunks can expand to
+const MAX_DEFLATE_RATIO: usize = 1032;
+
+// Object header message types
+const MSG_DATASPACE: u16 = 0x01;
+const MSG_LINK_INFO: u16 = 0x02;
+const MSG_DATATYPE: u16 = 0x03;
+const MSG_LINK: u16 = 0x06;
+const MSG_LAYOUT: u16 = 0x08;
+const MSG_FILTERS: u16 = 0x0B;
+const MSG_ATTRIBUTE: u16 = 0x0C;
+const MSG_CONTINUATION: u16 = 0x10;
+const MSG_SYMBOL_TABLE: u16 = 0x11;
+
+// Filter ids
+const FILTER_DEFLATE: u16 = 1;
+const FILTER_SHUFFLE: u16 = 2;
+const FILTER_FLETCHER32: u16 = 3;
+
+/// An ann-benchmarks dataset
+pub struct AnnDataset {
+    /// Vectors to index, numbered by position
+    pub train: Vec<Vec<f32>>,
+    /// Query vectors
+    pub test: Vec<Vec<f32>>,
+    /// Train positions of each query's true neighbors, nearest first
+    pub neighbors: Vec<Vec<usize>>,
+    /// Metric of the ground truth, e.g. `angular` or `euclidean`
+    pub distance: Option<String>,
+}
+
+/// Recall and throughput of one search setting, from
+/// [`AnnDataset::evaluate`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Evaluation {
+    pub k: usize,
+    pub ef_search: usize,
+    /// Mean fraction of the true `k` nearest neighbors returned
+    pub recall: f64,
+    pub queries_per_second: f64,
+}
+
+impl AnnDataset {
+    /// Read a dataset file
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<AnnDataset, HnswError> {
+        let data = std::fs::read(path).map_err(|e| HnswError::Storage(e.to_string()))?;
+        AnnDataset::parse(&data)
+    }
+
+    /// Read a dataset from the bytes of its file
+    pub fn parse(data: &[u8]) -> Result<AnnDataset, HnswError> {
+        let (file, root) = File::open(data)?;
+        let links = file.group_links(root)?;
+        let dataset = |name: &str| {
+            links
+                .iter()
+                .find(|(link, _)| link == name)
+                .map(|(_, address)| *address)
+                .ok_or_else(|| hdf5_error(&format!("no {} dataset", name)))
+        };
+        let train = file.dataset(dataset("train")?)?;
+        let test = file.dataset(dataset("test")?)?;
+        let neighbors = file.dataset(dataset("neighbors")?)?;
+        if train.shape.len() != 2 || test.shape.len() != 2 || neighbors.shape.len() != 2 {
+            return Err(hdf5_error("train, test and neighbors must be matrices"));
+        }
+        if train.shape[1] != test.shape[1] || neighbors.shape[0] != test.shape[0] {
+            return Err(hdf5_error(
+                "train, test and neighbors do not match in shape",
+            ));
+        }
+
+        let neighbors = neighbors
+            .rows(|bytes| neighbors.kind.int(bytes))
+            .into_iter()
+            .map(|row| {
+                row.into_iter()
+                    .map(|n| {
+                        usize::try_from(n)
+                            .ok()
+                            .filter(|&n| n < train.shape[0])
+                            .ok_or_else(|| hdf5_error(&format!("neighbor {} out of range", n)))
+                    })
+                    .collect()
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(AnnDataset {
+            train: train.rows(|bytes| train.kind.float(bytes) as f32),
+            test: test.rows(|bytes| test.kind.float(bytes) as f32),
+            neighbors,
+            distance: file.string_attribute(root, "distance")?,
+        })
+    }
+
+    /// Index the train vectors, each under its position
+    pub fn build(&self, params: HNSWParams) -> Result<Hnsw<f32>, HnswError> {
+        let mut index = Hnsw::with_params(params);
+        for (i, vector) in self.train.iter().enumerate() {
+            index.insert(i.to_string(), vector.clone())?;
+        }
+        Ok(index)
+    }
+
+    /// Search every query for `k` results at `ef_search` and compare
+    /// with the true neighbors
+    pub fn evaluate(
+        &self,
+        index: &Hnsw<f32>,
+        k: usize,
+        ef_search: usize,
+    ) -> Result<Evaluation, HnswError> {
+        let options = SearchOptions {
+            ef: Some(ef_search),
+            ..SearchOptions::default()
+        };
+        let started = Instant::now();
+        let mut results = Vec::with_capacity(self.test.len());
+        for query in &self.test {
+            results.push(index.search(query, k, &options)?);
+        }
+        let elapsed = started.elapsed().as_secs_f64();
+
+        let mut found = 0;
+        let mut expected = 0;
+        for (hits, truth) in results.iter().zip(&self.neighbors) {
+            let truth: HashSet<usize> = truth.iter().take(k).copied().collect();
+            expected += truth.len();
+            found += hits
+                .iter()
+                .filter(|(id, _)| id.parse::<usize>().is_ok_and(|n| truth.contains(&n)))
+                .count();
+        }
+        Ok(Evaluation {
+            k,
+            ef_search,
+            recall: if expected == 0 {
+                1.0
+            } else {
+                found as f64 / expected as f64
+            },
+            queries_per_second: self.test.len() as f64 / elapsed.max(f64::EPSILON),
+        })
+    }
+}
+
+/// A decoded dataset: its shape, element type and raw elements
+struct Dataset<'a> {
+    shape: Vec<usize>,
+    kind: Kind,
+    data: Cow<'a, [u8]>,
+}
+
+impl Dataset<'_> {
+    /// Rows of a matrix, converting each element with `element`
+    fn rows<T>(&self, element: impl Fn(&[u8]) -> T) -> Vec<Vec<T>> {
+        let width = self.shape.get(1).copied().unwrap_or(1) * self.kind.size;
+        if width == 0 {
+            return (0..self.shape[0]).map(|_| Vec::new()).collect();
+        }
+        self.data
+            .chunks_exact(width)
+            .map(|row| row.chunks_exact(self.kind.size).map(&element).collect())
+            .collect()
+    }
+}
+
+/// Element type of a numeric dataset
+#[derive(Clone, Copy)]
+struct Kind {
+    float: bool,
+    signed: bool,
+    big_endian: bool,
+    size: usize,
+}
+
+impl Kind {
+    fn parse(message: &[u8]) -> Result<Kind, HnswError> {
+        let mut cursor = Cursor::new(message);
+        let class = cursor.u8()? & 0x0F;
+        let bits = cursor.u8()?;
+        cursor.take(2)?;
+        let size = cursor.u32()? as usize;
+        let kind = Kind {
+            float: class == 1,
+            signed: class == 1 || bits & 0x08 != 0,
+            big_endian: bits & 0x01 != 0,
+            size,
+        };
+        let supported = match class {
+            0 => matches!(size, 1 | 2 | 4 | 8),
+            // Bit 6 marks VAX byte order
+            1 => matches!(size, 4 | 8) && bits & 0x40 == 0,
+            _ => false,
+        };
+        if supported {
+            Ok(kind)
+        } else {
+            Err(hdf5_error(&format!(
+                "unsupported element type of class {}",
+                class
+            )))
+        }
+    }
+
+    fn bytes<const N: usize>(&self, raw: &[u8]) -> [u8; N] {
+        let mut out = [0u8; N];
+        out.copy_from_slice(&raw[..N]);
+        if self.big_endian {
+            out.reverse();
+        }
+        out
+    }
+
+    fn float(&self, raw: &[u8]) -> f64 {
+        match (self.float, self.size) {
+            (true, 4) => f32::from_le_bytes(self.bytes(raw)) as f64,
+            (true, _) => f64::from_le_bytes(self.bytes(raw)),
+            (false, _) => self.int(raw) as f64,
+        }
+    }
+
+    fn int(&self, raw: &[u8]) -> i64 {
+        if self.float {
+            return self.float(raw) as i64;
+        }
+        let mut bytes = [0u8; 8];
+        for (i, byte) in bytes.iter_mut().take(self.size).enumerate() {
+            *byte = raw[if self.big_endian {
+                self.size - 1 - i
+            } else {
+                i
+            }];
+        }
+        let negative = self.signed && bytes[self.size - 1] & 0x80 != 0;
+        if negative {
+            for byte in &mut bytes[self.size..] {
+                *byte = 0xFF;
+            }
+        }
+        i64::from_le_bytes(bytes)
+    }
+}
+
+/// An open HDF5 file
+struct File<'a> {
+    data: &'a [u8],
+    /// Addresses are relative to this offset
+    base: u64,
+    offset_size: usize,
+    length_size: usize,
+}
+
+impl<'a> File<'a> {
+    /// Find the superblock and return the file with its root group address
+    fn open(data: &'a [u8]) -> Result<(File<'a>, u64), HnswError> {
+        let start = std::iter::once(0)
+            .chain((9..).map(|shift| 1usize << shift))
+            .take_while(|&at| at < data.len())
+            .find(|&at| data[at..].starts_with(SIGNATURE))
+            .ok_or_else(|| hdf5_error("not an HDF5 file"))?;
+        let mut cursor = Cursor::new(&data[start..]);
+        cursor.take(SIGNATURE.len())?;
+        let version = cursor.u8()?;
+        let mut file = File {
+            data,
+            base: 0,
+            offset_size: 8,
+            length_size: 8,
+        };
+        let root = match version {
+            0 | 1 => {
+                cursor.take(4)?;
+                file.offset_size = cursor.u8()? as usize;
+                file.length_size = cursor.u8()? as usize;
+                cursor.take(1 + 4 + 4)?;
+                if version == 1 {
+                    cursor.take(4)?;
+                }
+                file.check_sizes()?;
+                file.base = file.offset(&mut cursor)?;
+                for _ in 0..3 {
+                    file.offset(&mut cursor)?; // free space, end of file, driver
+                }
+                file.offset(&mut cursor)?; // root link name
+                file.offset(&mut cursor)?
+            }
+            2 | 3 => {
+                file.offset_size = cursor.u8()? as usize;
+                file.length_size = cursor.u8()? as usize;
+                cursor.take(1)?;
+                file.check_sizes()?;
+                file.base = file.offset(&mut cursor)?;
+                file.offset(&mut cursor)?; // superblock extension
+                file.offset(&mut cursor)?; // end of file
+                file.offset(&mut cursor)?
+            }
+            other => {
+                return Err(hdf5_error(&format!(
+                    "unsupported superblock version {}",
+                    other
+                )))
+            }
+        };
+        if file.base == u64::MAX {
+            file.base = start as u64;
+        }
+        Ok((file, root))
+    }
+
+    fn check_sizes(&self) -> Result<(), HnswError> {
+        let valid = |size: usize| matches!(size, 2 | 4 | 8);
+        if valid(self.offset_size) && valid(self.length_size) {
+            Ok(())
+        } else {
+            Err(hdf5_error("unsupported offset or length size"))
+        }
+    }
+
+    /// Read an address, `u64::MAX` when undefined
+    fn offset(&self, cursor: &mut Cursor) -> Result<u64, HnswError> {
+        let bytes = cursor.take(self.offset_size)?;
+        if bytes.iter().all(|&b| b == 0xFF) {
+            return Ok(u64::MAX);
+        }
+        Ok(bytes
+            .iter()
+            .rev()
+            .fold(0u64, |value, &b| value << 8 | b as u64))
+    }
+
+    fn length(&self, cursor: &mut Cursor) -> Result<u64, HnswError> {
+        let bytes = cursor.take(self.length_size)?;
+        Ok(bytes
+            .iter()
+            .rev()
+            .fold(0u64, |value, &b| value << 8 | b as u64))
+    }
+
+    /// The file from `address` on
+    fn at(&self, address: u64) -> Result<Cursor<'a>, HnswError> {
+        usize::try_from(address)
+            .ok()
+            .and_then(|address| address.checked_add(self.base as usize))
+            .and_then(|at| self.data.get(at..))
+            .map(Cursor::new)
+            .ok_or_else(|| hdf5_error("address out of range"))
+    }
+
+    /// `len` bytes at `address`
+    fn bytes(&self, address: u64, len: u64) -> Result<&'a [u8], HnswError> {
+        let len = usize::try_from(len).map_err(|_| hdf5_error("length out of range"))?;
+        self.at(address)?.take(len)
+    }
+
+    /// Messages of the object header at `address`
+    fn messages(&self, address: u64) -> Result<Vec<(u16, &'a [u8])>, HnswError> {
+        let mut cursor = self.at(address)?;
+        let mut messages = Vec::new();
+        let mut blocks = Vec::new();
+        let v2 = cursor.data.starts_with(b"OHDR");
+        let mut creation_order = false;
+        if v2 {
+            cursor.take(5)?;
+            let flags = cursor.u8()?;
+            if flags & 0x20 != 0 {
+                cursor.take(16)?;
+            }
+            if flags & 0x10 != 0 {
+                cursor.take(4)?;
+            }
+            creation_order = flags & 0x04 != 0;
+            let size = cursor.take(1 << (flags & 3))?;
+            let size = size
+                .iter()
+                .rev()
+                .fold(0usize, |value, &b| value << 8 | b as usize);
+            blocks.push(cursor.take(size)?);
+        } else {
+            if cursor.u8()? != 1 {
+                return Err(hdf5_error("unsupported object header version"));
+            }
+            cursor.take(1 + 2 + 4)?;
+            let size = cursor.u32()? as usize;
+            cursor.take(4)?; // padding to 8 bytes
+            blocks.push(cursor.take(size)?);
+        }
+
+        let mut followed = 0;
+        while let Some(block) = blocks.pop() {
+            followed += 1;
+            if followed > MAX_HEADER_BLOCKS {
+                return Err(hdf5_error("too many object header blocks"));
+            }
+            let header_size = if v2 {
+                4 + 2 * creation_order as usize
+            } else {
+                8
+            };
+            let mut cursor = Cursor::new(block);
+            while cursor.remaining() >= header_size {
+                let kind = if v2 {
+                    cursor.u8()? as u16
+                } else {
+                    cursor.u16()?
+                };
+                let size = cursor.u16()? as usize;
+                cursor.take(if v2 { 1 } else { 4 })?;
+                if creation_order {
+                    cursor.take(2)?;
+                }
+                let body = cursor.take(size)?;
+                if kind == MSG_CONTINUATION {
+                    let mut body = Cursor::new(body);
+                    let at = self.offset(&mut body)?;
+                    let len = self.length(&mut body)?;
+                    let block = self.bytes(at, len)?;
+                    blocks.push(if v2 {
+                        if !block.starts_with(b"OCHK") || block.len() < 8 {
+                            return Err(hdf5_error("malformed header continuation"));
+                        }
+                        &block[4..block.len() - 4]
+                    } else {
+                        block
+                    });
+                } else {
+                    messages.push((kind, body));
+                }
+            }
+        }
+        Ok(messages)
+    }
+
+    /// Names and object addresses of the members of a group
+    fn group_links(&self, address: u64) -> Result<Vec<(String, u64)>, HnswError> {
+        let mut links = Vec::new();
+        let mut dense = false;
+        for (kind, body) in self.messages(address)? {
+            let mut cursor = Cursor::new(body);
+            match kind {
+                MSG_SYMBOL_TABLE => {
+                    let tree = self.offset(&mut cursor)?;
+                    let heap = self.offset(&mut cursor)?;
+                    let heap = self.local_heap(heap)?;
+                    self.symbol_tree(tree, heap, 0, &mut links)?;
+                }
+                MSG_LINK => {
+                    if let Some(link) = self.link(&mut cursor)? {
+                        links.push(link);
+                    }
+                }
+                MSG_LINK_INFO => {
+                    let flags = cursor.take(2)?[1];
+                    if flags & 0x01 != 0 {
+                        cursor.take(8)?;
+                    }
+                    dense = self.offset(&mut cursor)? != u64::MAX;
+                }
+                _ => {}
+            }
+        }
+        if links.is_empty() && dense {
+            return Err(hdf5_error(
+                "groups with dense link storage are not supported",
+            ));
+        }
+        Ok(links)
+    }
+
+    /// A link message; `None` for soft and external links
+    fn link(&self, cursor: &mut Cursor) -> Result<Option<(String, u64)>, HnswError> {
+        cursor.take(1)?;
+        let flags = cursor.u8()?;
+        let kind = if flags & 0x08 != 0 { cursor.u8()? } else { 0 };
+        if flags & 0x04 != 0 {
+            cursor.take(8)?;
+        }
+        if flags & 0x10 != 0 {
+            cursor.take(1)?;
+        }
+        let len = cursor.take(1 << (flags & 3))?;
+        let len = len
+            .iter()
+            .rev()
+            .fold(0usize, |value, &b| value << 8 | b as usize);
+        let name = String::from_utf8_lossy(cursor.take(len)?).into_owned();
+        if kind != 0 {
+            return Ok(None);
+        }
+        Ok(Some((name, self.offset(cursor)?)))
+    }
+
+    /// Data segment of a local heap
+    fn local_heap(&self, address: u64) -> Result<&'a [u8], HnswError> {
+        let mut cursor = self.at(address)?;
+        if cursor.take(4)? != b"HEAP" {
+            return Err(hdf5_error("missing local heap"));
+        }
+        cursor.take(4)?;
+        let size = self.length(&mut cursor)?;
+        self.length(&mut cursor)?; // free list
+        let data = self.offset(&mut cursor)?;
+        self.bytes(data, size)
+    }
+
+    /// Walk a group B-tree, collecting the entries of its symbol nodes
+    fn symbol_tree(
+        &self,
+        address: u64,
+        heap: &[u8],
+        depth: usize,
+        links: &mut Vec<(String, u64)>,
+    ) -> Result<(), HnswError> {
+        if depth > MAX_BTREE_DEPTH {
+            return Err(hdf5_error("B-tree is too deep"));
+        }
+        let (level, children) =
+            self.btree_node(address, 0, |file, cursor| file.length(cursor).map(|_| ()))?;
+        for (_, child) in children {
+            if level > 0 {
+                self.symbol_tree(child, heap, depth + 1, links)?;
+                continue;
+            }
+            let mut cursor = self.at(child)?;
+            if cursor.take(4)? != b"SNOD" {
+                return Err(hdf5_error("missing symbol node"));
+            }
+            cursor.take(2)?;
+            for _ in 0..cursor.u16()? {
+                let name = self.offset(&mut cursor)? as usize;
+                let object = self.offset(&mut cursor)?;
+                cursor.take(4 + 4 + 16)?;
+                let name = heap
+                    .get(name..)
+                    .and_then(|rest| rest.split(|&b| b == 0).next())
+                    .ok_or_else(|| hdf5_error("link name out of range"))?;
+                links.push((String::from_utf8_lossy(name).into_owned(), object));
+            }
+        }
+        Ok(())
+    }
+
+    /// Level and `(key, child)` entries of a version 1 B-tree node of
+    /// `node_type`, reading each key with `key`
+    fn btree_node<K>(
+        &self,
+        address: u64,
+        node_type: u8,
+        key: impl Fn(&File, &mut Cursor) -> Result<K, HnswError>,
+    ) -> Result<(u8, Vec<(K, u64)>), HnswError> {
+        let mut cursor = self.at(address)?;
+        if cursor.take(4)? != b"TREE" || cursor.u8()? != node_type {
+            return Err(hdf5_error("missing B-tree node"));
+        }
+        let level = cursor.u8()?;
+        let entries = cursor.u16()?;
+        self.offset(&mut cursor)?; // siblings
+        self.offset(&mut cursor)?;
+        let mut children = Vec::with_capacity(entries as usize);
+        for _ in 0..entries {
+            let key = key(self, &mut cursor)?;
+            children.push((key, self.offset(&mut cursor)?));
+        }
+        Ok((level, children))
+    }
+
+    /// Shape, element type and raw elements of the dataset at `address`
+    fn dataset(&self, address: u64) -> Result<Dataset<'a>, HnswError> {
+        let messages = self.messages(address)?;
+        let find = |kind: u16| {
+            messages
+                .iter()
+                .find(|(k, _)| *k == kind)
+                .map(|(_, body)| *body)
+        };
+        let missing = |what: &str| hdf5_error(&format!("dataset has no {} message", what));
+        let shape = self.dataspace(find(MSG_DATASPACE).ok_or_else(|| missing("dataspace"))?)?;
+        let kind = Kind::parse(find(MSG_DATATYPE).ok_or_else(|| missing("datatype"))?)?;
+        let filters = find(MSG_FILTERS)
+            .map(filters)
+            .transpose()?
+            .unwrap_or_default();
+        let size = shape
+            .iter()
+            .try_fold(kind.size, |size, &n| size.checked_mul(n))
+            .ok_or_else(|| hdf5_error("dataset is too large"))?;
+
+        let mut cursor = Cursor::new(find(MSG_LAYOUT).ok_or_else(|| missing("layout"))?);
+        let version = cursor.u8()?;
+        let data = match version {
+            1 | 2 => {
+                let rank = cursor.u8()? as usize;
+                let class = cursor.u8()?;
+                cursor.take(5)?;
+                let address = if class != 0 {
+                    self.offset(&mut cursor)?
+                } else {
+                    0
+                };
+                let dims: Vec<usize> = (0..rank)
+                    .map(|_| cursor.u32().map(|d| d as usize))
+                    .collect::<Result<_, _>>()?;
+                match class {
+                    0 => {
+                        let len = cursor.u32()? as usize;
+                        Cow::Borrowed(cursor.take(len)?)
+                    }
+                    1 => Cow::Borrowed(self.bytes(address, size as u64)?),
+                    _ => {
+                        let chunk = &dims[..rank.saturating_sub(1)];
+                        Cow::Owned(self.chunked(address, &shape, chunk, &kind, &filters, size)?)
+                    }
+                }
+            }
+            3 | 4 => match cursor.u8()? {
+                0 => {
+                    let len = cursor.u16()? as usize;
+                    Cow::Borrowed(cursor.take(len)?)
+                }
+                1 => {
+                    let address = self.offset(&mut cursor)?;
+                    Cow::Borrowed(self.bytes(address, size as u64)?)
+                }
+                2 if version == 3 => {
+                    let rank = cursor.u8()? as usize;
+                    let address = self.offset(&mut cursor)?;
+                    let chunk: Vec<usize> = (0..rank.saturating_sub(1))
+                        .map(|_| cursor.u32().map(|d| d as usize))
+                        .collect::<Result<_, _>>()?;
+                    Cow::Owned(self.chunked(address, &shape, &chunk, &kind, &filters, size)?)
+                }
+                _ => return Err(hdf5_error("unsupported dataset layout")),
+            },
+            other => return Err(hdf5_error(&format!("unsupported layout version {}", other))),
+        };
+        if data.len() < size {
+            return Err(hdf5_error("dataset is truncated"));
+        }
+        Ok(Dataset {
+            shape,
+            kind,
+            data: match data {
+                Cow::Borrowed(data) => Cow::Borrowed(&data[..size]),
+                Cow::Owned(mut data) => {
+                    data.truncate(size);
+                    Cow::Owned(data)
+                }
+            },
+        })
+    }
+
+    /// Dimensions of a dataspace message
+    fn dataspace(&self, message: &[u8]) -> Result<Vec<usize>, HnswError> {
+        let mut cursor = Cursor::new(message);
+        let version = cursor.u8()?;
+        let rank = cursor.u8()? as usize;
+        cursor.take(if version == 1 { 6 } else { 2 })?;
+        (0..rank)
+            .map(|_| {
+                usize::try_from(self.length(&mut cursor)?)
+                    .map_err(|_| hdf5_error("dimension out of range"))
+            })
+            .collect()
+    }
+
+    /// Assemble a chunked dataset of `size` bytes from its B-tree
+    fn chunked(
+        &self,
+        tree: u64,
+        shape: &[usize],
+        chunk: &[usize],
+        kind: &Kind,
+        filters: &[Filter],
+        size: usize,
+    ) -> Result<Vec<u8>, HnswError> {
+        if chunk.len() != shape.len() || chunk.contains(&0) {
+            return Err(hdf5_error("chunk shape does not match the dataspace"));
+        }
+        if size / MAX_DEFLATE_RATIO > self.data.len() {
+            return Err(hdf5_error("dataset is larger than its file can hold"));
+        }
+        let mut out = vec![0u8; size];
+        let mut pending = vec![(tree, 0)];
+        while let Some((address, depth)) = pending.pop() {
+            if depth > MAX_BTREE_DEPTH {
+                return Err(hdf5_error("B-tree is too deep"));
+            }
+            let rank = shape.len();
+            let (level, children) = self.btree_node(address, 1, |_, cursor| {
+                let size = cursor.u32()? as u64;
+                let mask = cursor.u32()?;
+                let offsets: Vec<u64> =
+                    (0..=rank).map(|_| cursor.u64()).collect::<Result<_, _>>()?;
+                Ok((size, mask, offsets))
+            })?;
+            for ((stored, mask, offsets), child) in children {
+                if level > 0 {
+                    pending.push((child, depth + 1));
+                    continue;
+                }
+                let raw = self.bytes(child, stored)?;
+                let raw = unfilter(raw, filters, mask, kind.size)?;
+                copy_chunk(&mut out, &raw, shape, chunk, &offsets[..rank], kind.size)?;
+            }
+        }
+        Ok(out)
+    }
+
+    /// A string attribute of the object at `address`
+    fn string_attribute(&self, address: u64, name: &str) -> Result<Option<String>, HnswError> {
+        for (kind, body) in self.messages(address)? {
+            if kind != MSG_ATTRIBUTE {
+                continue;
+            }
+            let mut cursor = Cursor::new(body);
+            let version = cursor.u8()?;
+            cursor.take(1)?;
+            let name_size = cursor.u16()? as usize;
+            let type_size = cursor.u16()? as usize;
+            let space_size = cursor.u16()? as usize;
+            if version == 3 {
+                cursor.take(1)?;
+            }
+            let padded = |size: usize| {
+                if version == 1 {
+                    size.next_multiple_of(8)
+                } else {
+                    size
+                }
+            };
+            let found = cursor.take(padded(name_size))?;
+            let found = found.split(|&b| b == 0).next().unwrap_or(found);
+            if found != name.as_bytes() {
+                continue;
+            }
+            let datatype = cursor.take(padded(type_size))?;
+            cursor.take(padded(space_size))?;
+            return self.string_value(datatype, cursor.rest()).map(Some);
+        }
+        Ok(None)
+    }
+
+    /// First element of a fixed or variable-length string attribute
+    fn string_value(&self, datatype: &[u8], data: &[u8]) -> Result<String, HnswError> {
+        let mut cursor = Cursor::new(datatype);
+        let class = cursor.u8()? & 0x0F;
+        let bits = cursor.u8()?;
+        cursor.take(2)?;
+        let size = cursor.u32()? as usize;
+        let bytes = match class {
+            3 => {
+                let bytes = data
+                    .get(..size)
+                    .ok_or_else(|| hdf5_error("attribute is truncated"))?;
+                bytes.split(|&b| b == 0).next().unwrap_or(bytes)
+            }
+            9 if bits & 0x0F == 1 => {
+                let mut cursor = Cursor::new(data);
+                let len = cursor.u32()? as usize;
+                let heap = self.offset(&mut cursor)?;
+                let index = cursor.u32()?;
+                let object = self.global_object(heap, index)?;
+                object
+                    .get(..len)
+                    .ok_or_else(|| hdf5_error("attribute is truncated"))?
+            }
+            _ => return Err(hdf5_error("attribute is not a string")),
+        };
+        Ok(String::from_utf8_lossy(bytes).trim_end().to_string())
+    }
+
+    /// Object `index` of the global heap collection at `address`
+    fn global_object(&self, address: u64, index: u32) -> Result<&'a [u8], HnswError> {
+        let mut cursor = self.at(address)?;
+        if cursor.take(4)? != b"GCOL" {
+            return Err(hdf5_error("missing global heap"));
+        }
+        cursor.take(4)?;
+        let size = self.length(&mut cursor)? as usize;
+        let header = 8 + self.length_size;
+        let mut cursor = Cursor::new(
+            self.at(address)?
+                .take(size)?
+                .get(header..)
+                .ok_or_else(|| hdf5_error("global heap is truncated"))?,
+        );
+        while cursor.remaining() >= 8 + self.length_size {
+            let found = cursor.u16()?;
+            cursor.take(6)?;
+            let len = self.length(&mut cursor)? as usize;
+            if found == 0 {
+                break;
+            }
+            let object = cursor.take(len)?;
+            if found as u32 == index {
+                return Ok(object);
+            }
+            cursor.take(len.next_multiple_of(8) - len)?;
+        }
+        Err(hdf5_error("global heap object not found"))
+    }
+}
+
+/// One stage of a filter pipeline
+struct Filter {
+    id: u16,
+    values: Vec<u32>,
+}
+
+fn filters(message: &[u8]) -> Result<Vec<Filter>, HnswError> {
+    let mut cursor = Cursor::new(message);
+    let version = cursor.u8()?;
+    let count = cursor.u8()?;
+    if version == 1 {
+        cursor.take(6)?;
+    }
+    let mut filters = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let id = cursor.u16()?;
+        let name_len = if version == 1 || id >= 256 {
+            cursor.u16()? as usize
+        } else {
+            0
+        };
+        cursor.take(2)?; // flags
+        let values = cursor.u16()? as usize;
+        cursor.take(if version == 1 {
+            name_len.next_multiple_of(8)
+        } else {
+            name_len
+        })?;
+        let values = (0..values)
+            .map(|_| cursor.u32())
+            .collect::<Result<Vec<_>, _>>()?;
+        if version == 1 && values.len() % 2 == 1 {
+            cursor.take(4)?;
+        }
+        filters.push(Filter { id, values });
+    }
+    Ok(filters)
+}
+
+/// Undo the filters not skipped in `mask`, last first
+fn unfilter<'a>(
+    raw: &'a [u8],
+    filters: &[Filter],
+    mask: u32,
+    element: usize,
+) -> Result<Cow<'a, [u8]>, HnswError> {
+    let mut data = Cow::Borrowed(raw);
+    for (i, filter) in filters.iter().enumerate().rev() {
+        if mask & (1 << i) != 0 {
+            continue;
+        }
+        data = match filter.id {
+            FILTER_DEFLATE => Cow::Owned(deflate::decompress(&data)?),
+            FILTER_SHUFFLE => {
+                let size = filter
+                    .values
+                    .first()
+                    .map_or(element, |&s| s as usize)
+                    .max(1);
+                let count = data.len() / size;
+                let mut out = data.to_vec();
+                for (i, byte) in data[..count * size].iter().enumerate() {
+                    out[(i % count) * size + i / count] = *byte;
+                }
+                Cow::Owned(out)
+            }
+            FILTER_FLETCHER32 => {
+                let len = data.len().saturating_sub(4);
+                Cow::Owned(data[..len].to_vec())
+            }
+            other => return Err(hdf5_error(&format!("unsupported filter {}", other))),
+        };
+    }
+    Ok(data)
+}
+
+/// Copy the rows of a decoded chunk at `offsets` into the dataset,
+/// clipping at its edges
+fn copy_chunk(
+    out: &mut [u8],
+    chunk_data: &[u8],
+    shape: &[usize],
+    chunk: &[usize],
+    offsets: &[u64],
+    element: usize,
+) -> Result<(), HnswError> {
+    let Some((&width, outer)) = chunk.split_last() else {
+        let n = element.min(chunk_data.len()).min(out.len());
+        out[..n].copy_from_slice(&chunk_data[..n]);
+        return Ok(());
+    };
+    let offsets: Vec<usize> = offsets
+        .iter()
+        .map(|&o| usize::try_from(o).map_err(|_| hdf5_error("chunk offset out of range")))
+        .collect::<Result<_, _>>()?;
+    let last = shape.len() - 1;
+    if offsets[last] >= shape[last] {
+        return Ok(());
+    }
+    let row_elements = width.min(shape[last] - offsets[last]);
+    let rows: usize = outer.iter().product();
+    for row in 0..rows {
+        // Coordinates of the row within the chunk, last outer axis fastest
+        let mut rest = row;
+        let mut target = 0usize;
+        let mut inside = true;
+        for axis in (0..last).rev() {
+            let local = rest % chunk[axis];
+            rest /= chunk[axis];
+            let global = offsets[axis] + local;
+            if global >= shape[axis] {
+                inside = false;
+                break;
+            }
+            let stride: usize = shape[axis + 1..].iter().product();
+            target += global * stride;
+        }
+        if !inside {
+            continue;
+        }
+        let target = (target + offsets[last]) * element;
+        let source = row * width * element;
+        let len = row_elements * element;
+        let from = chunk_data
+            .get(source..source + len)
+            .ok_or_else(|| hdf5_error("chunk is truncated"))?;
+        out.get_mut(target..target + len)
+            .ok_or_else(|| hdf5_error("chunk out of range"))?
+            .copy_from_slice(from);
+    }
+    Ok(())
+}
+
+struct Cursor<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(data: &'a [u8]) -> Cursor<'a> {
+        Cursor { data, pos: 0 }
+    }
+
+    fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    fn rest(&self) -> &'a [u8] {
+        &self.data[self.pos..]
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], HnswError> {
+        let bytes = self
+            .pos
+            .checked_add(n)
+            .and_then(|end| self.data.get(self.pos..end))
+            .ok_or_else(|| hdf5_error("file is truncated"))?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, HnswError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, HnswError> {
+        let b = self.take(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, HnswError> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn u64(&mut self) -> Result<u64, HnswError> {
+        Ok(u64::from_le_bytes(
+            self.take(8)?.try_into().expect("8 bytes"),
+        ))
+    }
+}
+
+fn hdf5_error(msg: &str) -> HnswError {
+    HnswError::Deserialization(format!("HDF5 dataset: {}", msg))
+}
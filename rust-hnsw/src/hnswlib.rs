@@ -0,0 +1,128 @@
+//! hnswlib index import
+//!
+//! Reads the `HierarchicalNSW::saveIndex` layout of float spaces: a
+//! header of `size_t` offsets and sizes, the level-0 block of every
+//! element (link count, `maxM0` link slots, vector, `u64` label), then
+//! each element's upper-level link blocks behind a `u32` byte length.
+//! Labels become decimal ids, elements marked deleted are skipped, and
+//! the graph is kept as built, whatever the space it was built for.
+
+use crate::import::{GraphNode, MAX_LEVEL};
+use crate::index::Hnsw;
+use crate::scalar::Scalar;
+use crate::{HNSWParams, HnswError};
+
+/// Flag in the third byte of an element's level-0 link header
+const DELETE_MARK: u8 = 0x01;
+
+impl<S: Scalar> Hnsw<S> {
+    /// Load a file written by hnswlib's `save_index`, keeping its graph
+    ///
+    /// `params.m` is taken from the file; see [`Hnsw::from_graph`] for
+    /// what else is kept.
+    pub fn from_hnswlib(data: &[u8], mut params: HNSWParams) -> Result<Hnsw<S>, HnswError> {
+        let mut reader = Reader { data, pos: 0 };
+        let offset_level0 = reader.size()?;
+        let _max_elements = reader.size()?;
+        let count = reader.size()?;
+        let element_bytes = reader.size()?;
+        let label_offset = reader.size()?;
+        let data_offset = reader.size()?;
+        let _max_level = reader.u32()?;
+        let _entry_point = reader.u32()?;
+        let max_m = reader.size()?;
+        let max_m0 = reader.size()?;
+        let m = reader.size()?;
+        reader.take(8)?; // level multiplier
+        let _ef_construction = reader.size()?;
+
+        let links0_bytes = max_m0.saturating_mul(4).saturating_add(4);
+        let links_bytes = max_m.saturating_mul(4).saturating_add(4);
+        let vector_bytes = label_offset.saturating_sub(data_offset);
+        if offset_level0 != 0
+            || data_offset != links0_bytes
+            || vector_bytes == 0
+            || vector_bytes % 4 != 0
+            || label_offset.checked_add(8) != Some(element_bytes)
+        {
+            return Err(hnswlib_error("unsupported element layout"));
+        }
+        let level0 = reader.take(count.saturating_mul(element_bytes))?;
+        params.m = m.max(1);
+
+        let mut nodes = Vec::with_capacity(count);
+        for element in level0.chunks_exact(element_bytes) {
+            let link_bytes = reader.u32()? as usize;
+            let upper = reader.take(link_bytes)?;
+            if link_bytes % links_bytes != 0 || link_bytes / links_bytes > MAX_LEVEL {
+                return Err(hnswlib_error("malformed upper-level links"));
+            }
+            if element[2] & DELETE_MARK != 0 {
+                nodes.push(None);
+                continue;
+            }
+
+            let mut links = vec![link_list(&element[..links0_bytes], max_m0)?];
+            for block in upper.chunks_exact(links_bytes) {
+                links.push(link_list(block, max_m)?);
+            }
+            let label = &element[label_offset..];
+            let label = u64::from_le_bytes(label.try_into().expect("8 bytes"));
+            nodes.push(Some(GraphNode {
+                id: label.to_string(),
+                vector: element[data_offset..label_offset]
+                    .chunks_exact(4)
+                    .map(|b| S::from_f64(f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64))
+                    .collect(),
+                links,
+            }));
+        }
+        Hnsw::from_graph(params, nodes)
+    }
+}
+
+/// Links of one block: a count in the low 16 bits of the header, then
+/// `capacity` slots
+fn link_list(block: &[u8], capacity: usize) -> Result<Vec<u32>, HnswError> {
+    let count = u16::from_le_bytes([block[0], block[1]]) as usize;
+    if count > capacity {
+        return Err(hnswlib_error("link list overflows its capacity"));
+    }
+    Ok(block[4..4 + 4 * count]
+        .chunks_exact(4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect())
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], HnswError> {
+        let bytes = self
+            .pos
+            .checked_add(n)
+            .and_then(|end| self.data.get(self.pos..end))
+            .ok_or_else(|| hnswlib_error("file is truncated"))?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, HnswError> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    /// A 64-bit `size_t`
+    fn size(&mut self) -> Result<usize, HnswError> {
+        let b = self.take(8)?;
+        usize::try_from(u64::from_le_bytes(b.try_into().expect("8 bytes")))
+            .map_err(|_| hnswlib_error("size exceeds address space"))
+    }
+}
+
+fn hnswlib_error(msg: &str) -> HnswError {
+    HnswError::Deserialization(format!("hnswlib index: {}", msg))
+}
@@ -0,0 +1,332 @@
+//! IndexedDB persistence for browser builds
+//!
+//! [`IndexedDbStore`] keeps a snapshot and the deltas saved after it under
+//! one name, split into chunks so a large index is never written as a
+//! single structured-clone value. Records are keyed by arrays, which
+//! IndexedDB orders element by element:
+//!
+//! | key                 | value                              |
+//! |---------------------|------------------------------------|
+//! | `[name]`            | `{ chunks, deltas }` chunk counts  |
+//! | `[name, "d", j, i]` | chunk `i` of delta `j`             |
+//! | `[name, "s", i]`    | chunk `i` of the snapshot          |
+//!
+//! Each operation is one transaction, so a save is either stored whole or
+//! not at all, and its promise settles when the transaction commits.
+
+use js_sys::{Array, Function, Object, Promise, Reflect, Uint8Array};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{
+    IdbDatabase, IdbFactory, IdbKeyRange, IdbObjectStore, IdbRequest, IdbTransaction,
+    IdbTransactionMode,
+};
+
+const OBJECT_STORE: &str = "snapshots";
+const DEFAULT_CHUNK_SIZE: usize = 4 << 20;
+
+/// Chunk counts stored under the bare name
+#[derive(Default, Serialize, Deserialize)]
+struct Manifest {
+    /// 0 when only deltas are stored
+    chunks: u32,
+    /// One entry per delta, in the order they were appended
+    deltas: Vec<u32>,
+}
+
+/// What a transaction resolves or rejects with
+type Outcome = Rc<RefCell<Result<JsValue, JsValue>>>;
+
+/// Snapshot and delta storage in an IndexedDB database
+///
+/// ```js
+/// const store = await IndexedDbStore.open("codevector");
+/// await store.save("repo", index.save());
+/// await store.appendDelta("repo", index.saveDelta(savedGeneration));
+///
+/// const saved = await store.load("repo");
+/// const index = HnswIndex.fromBytes(saved.snapshot);
+/// for (const delta of saved.deltas) index.applyDelta(delta);
+/// ```
+#[wasm_bindgen]
+pub struct IndexedDbStore {
+    db: IdbDatabase,
+    chunk_size: usize,
+}
+
+#[wasm_bindgen]
+impl IndexedDbStore {
+    /// Open the database `name`, creating it if needed
+    ///
+    /// Resolves to a store. Values are written in chunks of at most
+    /// `chunkSize` bytes, 4 MiB by default.
+    pub fn open(name: &str, chunk_size: Option<usize>) -> Result<Promise, JsValue> {
+        let chunk_size = chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE);
+        if chunk_size == 0 {
+            return Err(JsValue::from_str("chunkSize must be positive"));
+        }
+        let factory: IdbFactory = Reflect::get(&js_sys::global(), &"indexedDB".into())?
+            .dyn_into()
+            .map_err(|_| JsValue::from_str("IndexedDB is not available here"))?;
+        let request = factory.open_with_u32(name, 1)?;
+
+        let opened = request.clone();
+        let upgrade = Closure::once_into_js(move || {
+            if let Ok(db) = opened.result() {
+                let _ = db
+                    .unchecked_into::<IdbDatabase>()
+                    .create_object_store(OBJECT_STORE);
+            }
+        });
+        request.set_onupgradeneeded(Some(upgrade.unchecked_ref()));
+
+        Ok(Promise::new(&mut |resolve: Function, reject: Function| {
+            on_success(&request, move |db| {
+                let store = IndexedDbStore {
+                    db: db.unchecked_into(),
+                    chunk_size,
+                };
+                let _ = resolve.call1(&JsValue::UNDEFINED, &store.into());
+            });
+            let failed = request.clone();
+            let error = Closure::once_into_js(move || {
+                let error = failed
+                    .error()
+                    .ok()
+                    .flatten()
+                    .map(JsValue::from)
+                    .unwrap_or_else(|| JsValue::from_str("could not open IndexedDB"));
+                let _ = reject.call1(&JsValue::UNDEFINED, &error);
+            });
+            request.set_onerror(Some(error.unchecked_ref()));
+        }))
+    }
+
+    /// Store a snapshot under `name`, replacing it and its deltas
+    pub fn save(&self, name: &str, data: &[u8]) -> Result<Promise, JsValue> {
+        let tx = self.transaction(IdbTransactionMode::Readwrite)?;
+        let store = tx.object_store(OBJECT_STORE)?;
+        let name = JsValue::from_str(name);
+        store.delete(&range_under(std::slice::from_ref(&name))?)?;
+        let chunks = put_chunks(&store, &[name.clone(), "s".into()], data, self.chunk_size)?;
+        let manifest = Manifest {
+            chunks,
+            deltas: Vec::new(),
+        };
+        store.put_with_key(&to_js(&manifest)?, &key(&[name]))?;
+        Ok(settle(&tx, Rc::new(RefCell::new(Ok(JsValue::UNDEFINED)))))
+    }
+
+    /// Store a delta after the snapshot and deltas already under `name`
+    ///
+    /// A name with no snapshot yet can hold deltas since generation 0.
+    #[wasm_bindgen(js_name = appendDelta)]
+    pub fn append_delta(&self, name: &str, data: &[u8]) -> Result<Promise, JsValue> {
+        let tx = self.transaction(IdbTransactionMode::Readwrite)?;
+        let store = tx.object_store(OBJECT_STORE)?;
+        let name = JsValue::from_str(name);
+        let outcome: Outcome = Rc::new(RefCell::new(Ok(JsValue::UNDEFINED)));
+        let request = store.get(&key(std::slice::from_ref(&name)))?;
+
+        let data = data.to_vec();
+        let chunk_size = self.chunk_size;
+        let failure = outcome.clone();
+        let aborted = tx.clone();
+        on_success(&request, move |manifest| {
+            let append = || -> Result<(), JsValue> {
+                let mut manifest: Manifest = if manifest.is_undefined() {
+                    Manifest::default()
+                } else {
+                    serde_wasm_bindgen::from_value(manifest)?
+                };
+                let prefix = [name.clone(), "d".into(), manifest.deltas.len().into()];
+                manifest
+                    .deltas
+                    .push(put_chunks(&store, &prefix, &data, chunk_size)?);
+                store.put_with_key(&to_js(&manifest)?, &key(&[name]))?;
+                Ok(())
+            };
+            if let Err(e) = append() {
+                *failure.borrow_mut() = Err(e);
+                let _ = aborted.abort();
+            }
+        });
+        Ok(settle(&tx, outcome))
+    }
+
+    /// Read back what is stored under `name`
+    ///
+    /// Resolves to `{ snapshot, deltas }`, where `snapshot` is a
+    /// `Uint8Array` or `null` when only deltas were stored, and `deltas`
+    /// lists them in the order they were appended; `undefined` when
+    /// nothing is stored under `name`.
+    pub fn load(&self, name: &str) -> Result<Promise, JsValue> {
+        let tx = self.transaction(IdbTransactionMode::Readonly)?;
+        let store = tx.object_store(OBJECT_STORE)?;
+        let name = JsValue::from_str(name);
+        let outcome: Outcome = Rc::new(RefCell::new(Ok(JsValue::UNDEFINED)));
+        // In key order: the manifest, delta chunks, then snapshot chunks
+        let request = store.get_all_with_key(&range_under(std::slice::from_ref(&name))?)?;
+
+        let result = outcome.clone();
+        let aborted = tx.clone();
+        on_success(&request, move |values| {
+            let values: Array = values.unchecked_into();
+            let loaded = assemble(&values, &name.as_string().unwrap_or_default());
+            let failed = loaded.is_err();
+            *result.borrow_mut() = loaded;
+            if failed {
+                let _ = aborted.abort();
+            }
+        });
+        Ok(settle(&tx, outcome))
+    }
+
+    /// Delete the snapshot and deltas stored under `name`
+    pub fn remove(&self, name: &str) -> Result<Promise, JsValue> {
+        let tx = self.transaction(IdbTransactionMode::Readwrite)?;
+        let store = tx.object_store(OBJECT_STORE)?;
+        store.delete(&range_under(&[JsValue::from_str(name)])?)?;
+        Ok(settle(&tx, Rc::new(RefCell::new(Ok(JsValue::UNDEFINED)))))
+    }
+
+    /// Close the database connection
+    pub fn close(&self) {
+        self.db.close();
+    }
+}
+
+impl IndexedDbStore {
+    fn transaction(&self, mode: IdbTransactionMode) -> Result<IdbTransaction, JsValue> {
+        self.db.transaction_with_str_and_mode(OBJECT_STORE, mode)
+    }
+}
+
+fn to_js<T: Serialize>(value: &T) -> Result<JsValue, JsValue> {
+    Ok(serde_wasm_bindgen::to_value(value)?)
+}
+
+fn key(parts: &[JsValue]) -> JsValue {
+    parts.iter().collect::<Array>().into()
+}
+
+/// Every key that starts with `prefix`, including `prefix` itself
+///
+/// Arrays sort after numbers and strings, so `[..prefix, []]` is above
+/// any key that extends `prefix`.
+fn range_under(prefix: &[JsValue]) -> Result<JsValue, JsValue> {
+    let mut upper = prefix.to_vec();
+    upper.push(Array::new().into());
+    Ok(IdbKeyRange::bound(&key(prefix), &key(&upper))?.into())
+}
+
+/// Write `data` under `[..prefix, i]`, returning the number of chunks
+fn put_chunks(
+    store: &IdbObjectStore,
+    prefix: &[JsValue],
+    data: &[u8],
+    chunk_size: usize,
+) -> Result<u32, JsValue> {
+    let mut key_parts = prefix.to_vec();
+    key_parts.push(JsValue::UNDEFINED);
+    let mut chunks = 0u32;
+    for chunk in data.chunks(chunk_size) {
+        key_parts[prefix.len()] = chunks.into();
+        store.put_with_key(&Uint8Array::from(chunk), &key(&key_parts))?;
+        chunks += 1;
+    }
+    Ok(chunks)
+}
+
+/// Build the `load` result from the values under a name, in key order
+fn assemble(values: &Array, name: &str) -> Result<JsValue, JsValue> {
+    if values.length() == 0 {
+        return Ok(JsValue::UNDEFINED);
+    }
+    let manifest: Manifest = serde_wasm_bindgen::from_value(values.get(0))?;
+    let expected = 1 + manifest.chunks + manifest.deltas.iter().sum::<u32>();
+    if values.length() != expected {
+        return Err(JsValue::from_str(&format!(
+            "stored chunks of {} do not match its manifest",
+            name
+        )));
+    }
+    let mut next = 1;
+    let mut take = |count: u32| {
+        let parts: Vec<JsValue> = (next..next + count).map(|i| values.get(i)).collect();
+        next += count;
+        concat(&parts)
+    };
+    let deltas = manifest
+        .deltas
+        .iter()
+        .map(|&count| take(count).map(JsValue::from))
+        .collect::<Result<Array, JsValue>>()?;
+    let snapshot = if manifest.chunks == 0 {
+        JsValue::NULL
+    } else {
+        take(manifest.chunks)?.into()
+    };
+    let loaded = Object::new();
+    Reflect::set(&loaded, &"snapshot".into(), &snapshot)?;
+    Reflect::set(&loaded, &"deltas".into(), &deltas)?;
+    Ok(loaded.into())
+}
+
+/// Join stored chunks into one array
+fn concat(parts: &[JsValue]) -> Result<Uint8Array, JsValue> {
+    let parts = parts
+        .iter()
+        .map(|part| {
+            part.clone()
+                .dyn_into::<Uint8Array>()
+                .map_err(|_| JsValue::from_str("stored chunk is not a Uint8Array"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let out = Uint8Array::new_with_length(parts.iter().map(|p| p.length()).sum());
+    let mut offset = 0;
+    for part in parts {
+        out.set(&part, offset);
+        offset += part.length();
+    }
+    Ok(out)
+}
+
+/// Call `f` with the result of `request` once it succeeds
+///
+/// Failed requests abort their transaction, which `settle` reports.
+fn on_success(request: &IdbRequest, f: impl FnOnce(JsValue) + 'static) {
+    let done = request.clone();
+    let callback = Closure::once_into_js(move || f(done.result().unwrap_or(JsValue::UNDEFINED)));
+    request.set_onsuccess(Some(callback.unchecked_ref()));
+}
+
+/// Promise for the outcome of `tx`, settled when it commits or aborts
+fn settle(tx: &IdbTransaction, outcome: Outcome) -> Promise {
+    Promise::new(&mut |resolve: Function, reject: Function| {
+        let result = outcome.clone();
+        let complete = Closure::once_into_js(move || {
+            if let Ok(value) = &*result.borrow() {
+                let _ = resolve.call1(&JsValue::UNDEFINED, value);
+            }
+        });
+        tx.set_oncomplete(Some(complete.unchecked_ref()));
+
+        let result = outcome.clone();
+        let aborted = tx.clone();
+        let abort = Closure::once_into_js(move || {
+            let error = match &*result.borrow() {
+                Err(e) => e.clone(),
+                Ok(_) => aborted
+                    .error()
+                    .map(JsValue::from)
+                    .unwrap_or_else(|| JsValue::from_str("IndexedDB transaction aborted")),
+            };
+            let _ = reject.call1(&JsValue::UNDEFINED, &error);
+        });
+        tx.set_onabort(Some(abort.unchecked_ref()));
+    })
+}
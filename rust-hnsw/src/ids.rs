@@ -0,0 +1,287 @@
+//! Interned point ids
+//!
+//! Ids are usually paths, and chunk ids repeat their file's path before a
+//! line range, so most of an id's bytes are shared with other ids. An
+//! [`Id`] splits after its last separator: the prefix is shared through
+//! the index's [`IdArena`] and only the tail is its own. Clones share both
+//! halves, so the id map, the graph node and the change log hold one copy.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+/// Characters an id is split after
+const SEPARATORS: [char; 4] = ['/', '\\', '#', ':'];
+
+/// Byte offset `id` splits at
+fn split(id: &str) -> usize {
+    id.rfind(SEPARATORS).map_or(0, |at| at + 1)
+}
+
+/// External id of a point, stored as a shared prefix and its own tail
+///
+/// Compares, orders and displays as the whole id; use `to_string` for
+/// an owned copy.
+#[derive(Clone)]
+pub struct Id {
+    prefix: Arc<str>,
+    tail: Arc<str>,
+}
+
+impl Id {
+    /// The prefix up to and including the last separator, and the rest
+    pub fn parts(&self) -> (&str, &str) {
+        (&self.prefix, &self.tail)
+    }
+
+    pub fn len(&self) -> usize {
+        self.prefix.len() + self.tail.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether the id starts with `prefix`, without spelling it out
+    pub fn starts_with(&self, prefix: &str) -> bool {
+        match prefix.strip_prefix(&*self.prefix) {
+            Some(rest) => self.tail.starts_with(rest),
+            None => self.prefix.starts_with(prefix),
+        }
+    }
+
+    /// Spell the id out in `buf`, reusing its allocation
+    #[cfg(all(feature = "sqlite", not(target_arch = "wasm32")))]
+    pub(crate) fn spell<'b>(&self, buf: &'b mut String) -> &'b str {
+        buf.clear();
+        buf.push_str(&self.prefix);
+        buf.push_str(&self.tail);
+        buf
+    }
+}
+
+impl From<&str> for Id {
+    /// An id with a prefix of its own; [`IdArena::intern`] shares it
+    fn from(id: &str) -> Id {
+        let (prefix, tail) = id.split_at(split(id));
+        Id {
+            prefix: prefix.into(),
+            tail: tail.into(),
+        }
+    }
+}
+
+impl fmt::Display for Id {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.prefix)?;
+        f.write_str(&self.tail)
+    }
+}
+
+impl fmt::Debug for Id {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.to_string(), f)
+    }
+}
+
+// Every id of the same text splits at the same place, so comparing and
+// hashing the halves agrees with comparing the whole text.
+impl PartialEq for Id {
+    fn eq(&self, other: &Id) -> bool {
+        self.tail == other.tail
+            && (Arc::ptr_eq(&self.prefix, &other.prefix) || self.prefix == other.prefix)
+    }
+}
+
+impl Eq for Id {}
+
+impl PartialEq<str> for Id {
+    fn eq(&self, other: &str) -> bool {
+        other.strip_prefix(&*self.prefix) == Some(&*self.tail)
+    }
+}
+
+impl PartialEq<&str> for Id {
+    fn eq(&self, other: &&str) -> bool {
+        *self == **other
+    }
+}
+
+impl Hash for Id {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.prefix.hash(state);
+        self.tail.hash(state);
+    }
+}
+
+impl Ord for Id {
+    fn cmp(&self, other: &Id) -> Ordering {
+        compare(self.parts(), other.parts())
+    }
+}
+
+impl PartialOrd for Id {
+    fn partial_cmp(&self, other: &Id) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Serialize for Id {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Id {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Id, D::Error> {
+        String::deserialize(deserializer).map(|id| Id::from(id.as_str()))
+    }
+}
+
+/// An id to look up an [`Id`]-keyed map by, interned or not
+pub(crate) trait IdKey {
+    fn parts(&self) -> (&str, &str);
+}
+
+impl IdKey for &str {
+    fn parts(&self) -> (&str, &str) {
+        ("", self)
+    }
+}
+
+impl IdKey for Id {
+    fn parts(&self) -> (&str, &str) {
+        Id::parts(self)
+    }
+}
+
+/// View `id` as a key of an [`Id`]-keyed map
+pub(crate) fn key<'a>(id: &'a &str) -> &'a dyn IdKey {
+    id
+}
+
+impl<'a> Borrow<dyn IdKey + 'a> for Id {
+    fn borrow(&self) -> &(dyn IdKey + 'a) {
+        self
+    }
+}
+
+impl PartialEq for dyn IdKey + '_ {
+    fn eq(&self, other: &Self) -> bool {
+        compare(self.parts(), other.parts()) == Ordering::Equal
+    }
+}
+
+impl Eq for dyn IdKey + '_ {}
+
+// Hashes the halves an `Id` of the same text would have, so `Id`-keyed
+// hash maps can be probed with a plain `&str`
+impl Hash for dyn IdKey + '_ {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let (prefix, tail) = match self.parts() {
+            ("", id) => id.split_at(split(id)),
+            parts => parts,
+        };
+        prefix.hash(state);
+        tail.hash(state);
+    }
+}
+
+impl Ord for dyn IdKey + '_ {
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare(self.parts(), other.parts())
+    }
+}
+
+impl PartialOrd for dyn IdKey + '_ {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Order two split strings as if they were joined, without joining them
+fn compare(a: (&str, &str), b: (&str, &str)) -> Ordering {
+    let mut a = [a.0.as_bytes(), a.1.as_bytes()]
+        .into_iter()
+        .filter(|s| !s.is_empty());
+    let mut b = [b.0.as_bytes(), b.1.as_bytes()]
+        .into_iter()
+        .filter(|s| !s.is_empty());
+    let (mut x, mut y) = (a.next().unwrap_or_default(), b.next().unwrap_or_default());
+    loop {
+        if x.is_empty() || y.is_empty() {
+            // One side ran out; it is less unless both did
+            return (!x.is_empty()).cmp(&!y.is_empty());
+        }
+        let n = x.len().min(y.len());
+        match x[..n].cmp(&y[..n]) {
+            Ordering::Equal => {}
+            order => return order,
+        }
+        x = &x[n..];
+        y = &y[n..];
+        if x.is_empty() {
+            x = a.next().unwrap_or_default();
+        }
+        if y.is_empty() {
+            y = b.next().unwrap_or_default();
+        }
+    }
+}
+
+/// Shared id prefixes of one index
+///
+/// Prefixes stay until [`IdArena::shrink`], so ids removed and added again
+/// do not churn the set.
+#[derive(Default)]
+pub(crate) struct IdArena {
+    prefixes: HashSet<Arc<str>>,
+}
+
+impl IdArena {
+    /// An id for `id` whose prefix is shared with every other id of it
+    pub fn intern(&mut self, id: &str) -> Id {
+        let (prefix, tail) = id.split_at(split(id));
+        Id {
+            prefix: self.prefix(prefix),
+            tail: tail.into(),
+        }
+    }
+
+    /// `id` with its prefix swapped for the shared one, as after decoding
+    pub fn share(&mut self, id: &Id) -> Id {
+        Id {
+            prefix: self.prefix(&id.prefix),
+            tail: id.tail.clone(),
+        }
+    }
+
+    fn prefix(&mut self, prefix: &str) -> Arc<str> {
+        if let Some(shared) = self.prefixes.get(prefix) {
+            return shared.clone();
+        }
+        let shared: Arc<str> = prefix.into();
+        self.prefixes.insert(shared.clone());
+        shared
+    }
+
+    /// Drop prefixes no id uses any more
+    pub fn shrink(&mut self) {
+        self.prefixes.retain(|prefix| Arc::strong_count(prefix) > 1);
+        self.prefixes.shrink_to_fit();
+    }
+
+    /// Approximate heap size of the prefixes and the set holding them
+    pub fn heap_bytes(&self) -> usize {
+        self.prefixes.capacity() * std::mem::size_of::<Arc<str>>()
+            + self
+                .prefixes
+                .iter()
+                .map(|prefix| prefix.len() + 2 * std::mem::size_of::<usize>())
+                .sum::<usize>()
+    }
+}
@@ -0,0 +1,71 @@
+//! Shared tail of the foreign graph importers
+//!
+//! Other HNSW libraries number their points and store each point's
+//! neighbor lists by number. Importers decode a file into [`GraphNode`]s
+//! and [`Hnsw::from_graph`] stores every point at its level and wires the
+//! links as given, so the imported index searches the graph it was built
+//! with instead of a rebuild.
+
+use crate::index::Hnsw;
+use crate::scalar::Scalar;
+use crate::{HNSWParams, HnswError};
+
+/// Highest level accepted from a foreign file
+pub(crate) const MAX_LEVEL: usize = 64;
+
+/// One numbered point of a foreign graph
+pub(crate) struct GraphNode<S> {
+    pub id: String,
+    pub vector: Vec<S>,
+    /// Neighbor numbers on each layer from 0 up to the point's level
+    pub links: Vec<Vec<u32>>,
+}
+
+impl<S: Scalar> Hnsw<S> {
+    /// Build an index around a foreign graph, node `i` being number `i`
+    ///
+    /// Removed points are `None`; links to them or to numbers out of
+    /// range are dropped. `params.project_to` is ignored, as the graph
+    /// only holds for the vectors it was built on.
+    pub(crate) fn from_graph(
+        mut params: HNSWParams,
+        nodes: Vec<Option<GraphNode<S>>>,
+    ) -> Result<Hnsw<S>, HnswError> {
+        params.project_to = 0;
+        let mut index = Hnsw::with_params(params);
+        let mut numbered = Vec::with_capacity(nodes.len());
+        let mut links = Vec::with_capacity(nodes.len());
+        for node in nodes {
+            let Some(node) = node else {
+                numbered.push(None);
+                links.push(Vec::new());
+                continue;
+            };
+            if node.links.is_empty() || node.links.len() > MAX_LEVEL + 1 {
+                return Err(HnswError::CorruptSnapshot(format!(
+                    "point {} has {} layers",
+                    node.id,
+                    node.links.len()
+                )));
+            }
+            let level = node.links.len() - 1;
+            numbered.push(Some(index.insert_unlinked(node.id, node.vector, level)?));
+            links.push(node.links);
+        }
+
+        for (node, layers) in numbered.iter().zip(links) {
+            let Some(node) = *node else {
+                continue;
+            };
+            for (layer, neighbors) in layers.into_iter().enumerate() {
+                let neighbors = neighbors
+                    .into_iter()
+                    .filter_map(|n| numbered.get(n as usize).copied().flatten())
+                    .collect();
+                index.set_links(node, layer, neighbors);
+            }
+        }
+        index.validate()?;
+        Ok(index)
+    }
+}
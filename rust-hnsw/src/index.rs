@@ -0,0 +1,2578 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Bound;
+#[cfg(any(feature = "webgpu", feature = "sqlite"))]
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::beam::{Beam, SETTLED_ROUNDS};
+use crate::budget::Meter;
+use crate::builder::{max_links, GraphBuilder, HnswBuilder};
+use crate::cache::{CacheStats, QueryCache};
+use crate::filter::{lookup_values, Filter};
+use crate::hash::fnv1a;
+use crate::ids::{key, Id, IdArena, IdKey};
+use crate::levels::IndexRng;
+use crate::links::LinkList;
+use crate::payload_index::{PayloadIndexKind, PayloadIndexes};
+use crate::planner::{self, FilterStrategy, QueryPlan};
+use crate::projection::RandomProjection;
+use crate::quantize::Codes;
+use crate::scalar::Scalar;
+use crate::schema::PayloadSchema;
+use crate::scratch::{Scored, Scratch, ScratchPool};
+use crate::snapshot::{self, Kind};
+use crate::sparse::SparseIndex;
+use crate::store::{MemoryStore, VectorStore};
+use crate::text::TextIndex;
+use crate::transform::QueryTransform;
+use crate::{DuplicatePolicy, HNSWParams, HnswError};
+
+/// A single point in the HNSW graph
+#[derive(Clone, Deserialize)]
+#[serde(try_from = "StoredNode")]
+struct Node {
+    id: Id,
+    /// Dense slot of the vector in the store
+    slot: u32,
+    /// Outgoing links by node on layer 0, kept in the node table
+    base: LinkList,
+    /// Outgoing links on layers 1 up to the point's level
+    upper: Vec<LinkList>,
+}
+
+impl Node {
+    fn new(id: Id, slot: u32, level: usize) -> Node {
+        Node {
+            id,
+            slot,
+            base: LinkList::new(),
+            upper: vec![LinkList::new(); level],
+        }
+    }
+
+    fn level(&self) -> usize {
+        self.upper.len()
+    }
+
+    fn layer(&self, layer: usize) -> Option<&LinkList> {
+        match layer {
+            0 => Some(&self.base),
+            _ => self.upper.get(layer - 1),
+        }
+    }
+
+    fn layer_mut(&mut self, layer: usize) -> Option<&mut LinkList> {
+        match layer {
+            0 => Some(&mut self.base),
+            _ => self.upper.get_mut(layer - 1),
+        }
+    }
+
+    /// Links on each layer from 0 up to the point's level
+    fn layers(&self) -> impl Iterator<Item = &LinkList> {
+        std::iter::once(&self.base).chain(&self.upper)
+    }
+
+    fn layers_mut(&mut self) -> impl Iterator<Item = &mut LinkList> {
+        std::iter::once(&mut self.base).chain(&mut self.upper)
+    }
+}
+
+/// A node as saved: its links as one list per layer
+#[derive(Deserialize)]
+struct StoredNode {
+    id: Id,
+    slot: u32,
+    links: Vec<LinkList>,
+}
+
+impl TryFrom<StoredNode> for Node {
+    type Error = String;
+
+    fn try_from(node: StoredNode) -> Result<Node, String> {
+        let mut layers = node.links.into_iter();
+        let base = layers
+            .next()
+            .ok_or_else(|| format!("point {} is on no layer", node.id))?;
+        Ok(Node {
+            id: node.id,
+            slot: node.slot,
+            base,
+            upper: layers.collect(),
+        })
+    }
+}
+
+impl Serialize for Node {
+    fn serialize<Z: serde::Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        use serde::ser::SerializeStruct;
+        /// Borrowing view of the layers, saved as one list per layer
+        struct Layers<'a>(&'a Node);
+        impl Serialize for Layers<'_> {
+            fn serialize<Z: serde::Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+                serializer.collect_seq(self.0.layers())
+            }
+        }
+        let mut node = serializer.serialize_struct("Node", 3)?;
+        node.serialize_field("id", &self.id)?;
+        node.serialize_field("slot", &self.slot)?;
+        node.serialize_field("links", &Layers(self))?;
+        node.end()
+    }
+}
+
+/// One page of ids from [`Hnsw::list_ids`] or [`Hnsw::scroll`]
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct IdPage {
+    pub ids: Vec<String>,
+    /// Pass back to get the next page; `None` on the last page
+    pub cursor: Option<String>,
+}
+
+/// Number of points carrying one payload value, from [`Hnsw::facet`]
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FacetCount {
+    pub value: serde_json::Value,
+    pub count: usize,
+}
+
+/// HNSW graph over vectors of scalar type `S`, held in store `V`
+///
+/// This is the native core behind the wasm index classes; it never
+/// constructs a `JsValue`, so it can be used from native code and binaries.
+#[derive(Serialize, Deserialize)]
+#[serde(bound(serialize = "V: Serialize", deserialize = "V: DeserializeOwned"))]
+pub struct Hnsw<S: Scalar = f32, V: VectorStore<S> = MemoryStore<S>> {
+    params: HNSWParams,
+    /// Node of each id, ordered by id so listing can resume from a cursor
+    points: BTreeMap<Id, u32>,
+    /// Graph nodes by internal id, `None` where a point was removed
+    nodes: Vec<Option<Node>>,
+    /// Node ids released by deletes, reused before growing
+    free_nodes: Vec<u32>,
+    entry_point: Option<u32>,
+    dimensions: usize,
+    #[serde(default)]
+    projection: Option<RandomProjection>,
+    store: V,
+    /// Slots released by deletes, reused before growing
+    free_slots: Vec<u32>,
+    next_slot: u32,
+    /// Number of points referencing each live slot
+    #[serde(default)]
+    slot_refs: HashMap<u32, u32>,
+    /// Content hash of stored vectors, for sharing identical vectors
+    #[serde(default)]
+    content_slots: HashMap<u64, Vec<u32>>,
+    /// JSON metadata attached to points, keyed by the id the point holds
+    #[serde(default)]
+    payloads: HashMap<Id, serde_json::Value>,
+    /// Secondary indexes over payload fields
+    #[serde(default)]
+    payload_indexes: PayloadIndexes,
+    /// Declared payload field types
+    #[serde(default)]
+    schema: PayloadSchema,
+    /// Sparse term-weight vectors attached to points
+    #[serde(default)]
+    sparse: SparseIndex,
+    /// BM25 term statistics of point text
+    #[serde(default)]
+    text: TextIndex,
+    /// Write counter, bumped by every change to a point
+    #[serde(default)]
+    generation: u64,
+    /// Generation of the last change to each id after `changes_floor`,
+    /// deleted ids included; not persisted
+    #[serde(skip)]
+    changes: HashMap<Id, u64>,
+    /// Generation up to which changes are no longer tracked
+    #[serde(skip)]
+    changes_floor: u64,
+    /// Generation up to which changes were written to the log
+    #[serde(default)]
+    logged_generation: u64,
+    /// Construction strategy; not persisted, reloaded indexes use the default
+    #[serde(skip, default = "default_builder")]
+    builder: Arc<dyn GraphBuilder<S, V>>,
+    /// Applied to query vectors before search; not persisted
+    #[serde(skip)]
+    query_transform: Option<Arc<dyn QueryTransform<S>>>,
+    /// Recent search results; not persisted, and off until sized
+    #[serde(skip)]
+    query_cache: QueryCache,
+    /// Walk buffers reused across searches
+    #[serde(skip)]
+    scratch: ScratchPool<S>,
+    /// 8-bit codes of the stored vectors when `params.quantize` is on;
+    /// rebuilt when loading
+    #[serde(skip)]
+    codes: Codes,
+    /// Id prefixes shared by the ids above; rebuilt when loading
+    #[serde(skip)]
+    ids: IdArena,
+    /// Draws point levels and the projection; not persisted
+    #[serde(skip)]
+    rng: IndexRng,
+    /// Number telling this index apart from every other in the process,
+    /// loaded copies included; not persisted
+    #[cfg(any(feature = "webgpu", feature = "sqlite"))]
+    #[serde(skip, default = "next_instance")]
+    instance: u64,
+    #[serde(skip)]
+    _scalar: std::marker::PhantomData<S>,
+}
+
+fn default_builder<S: Scalar, V: VectorStore<S>>() -> Arc<dyn GraphBuilder<S, V>> {
+    Arc::new(HnswBuilder)
+}
+
+/// Reject NaN and infinite components, which no distance orders
+fn check_finite<S: Scalar>(vector: &[S]) -> Result<(), HnswError> {
+    match vector.iter().find(|v| !v.to_f64().is_finite()) {
+        Some(value) => Err(HnswError::InvalidParams(format!(
+            "vector components must be finite, got {:?}",
+            value
+        ))),
+        None => Ok(()),
+    }
+}
+
+#[cfg(any(feature = "webgpu", feature = "sqlite"))]
+fn next_instance() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
+
+impl<S: Scalar> Hnsw<S> {
+    /// Create a new in-memory index with the given parameters
+    pub fn with_params(params: HNSWParams) -> Hnsw<S> {
+        Hnsw::with_store(params, MemoryStore::new())
+    }
+}
+
+impl<S: Scalar, V: VectorStore<S>> Hnsw<S, V> {
+    /// Create a new index keeping its vectors in `store`
+    pub fn with_store(params: HNSWParams, store: V) -> Hnsw<S, V> {
+        Hnsw {
+            params,
+            points: BTreeMap::new(),
+            nodes: Vec::new(),
+            free_nodes: Vec::new(),
+            entry_point: None,
+            dimensions: 0,
+            projection: None,
+            store,
+            free_slots: Vec::new(),
+            next_slot: 0,
+            slot_refs: HashMap::new(),
+            content_slots: HashMap::new(),
+            payloads: HashMap::new(),
+            payload_indexes: PayloadIndexes::default(),
+            schema: PayloadSchema::default(),
+            sparse: SparseIndex::default(),
+            text: TextIndex::default(),
+            generation: 0,
+            changes: HashMap::new(),
+            changes_floor: 0,
+            logged_generation: 0,
+            builder: default_builder(),
+            query_transform: None,
+            query_cache: QueryCache::default(),
+            scratch: ScratchPool::default(),
+            codes: Codes::default(),
+            ids: IdArena::default(),
+            rng: IndexRng::default(),
+            #[cfg(any(feature = "webgpu", feature = "sqlite"))]
+            instance: next_instance(),
+            _scalar: std::marker::PhantomData,
+        }
+    }
+
+    /// The vector store backing this index
+    pub fn store(&self) -> &V {
+        &self.store
+    }
+
+    /// Replace the graph construction strategy used by later inserts
+    pub fn set_builder(&mut self, builder: Arc<dyn GraphBuilder<S, V>>) {
+        self.builder = builder;
+    }
+
+    #[cfg(any(
+        all(feature = "parallel", not(target_arch = "wasm32")),
+        all(feature = "threads", target_arch = "wasm32")
+    ))]
+    pub(crate) fn builder(&self) -> &dyn GraphBuilder<S, V> {
+        &*self.builder
+    }
+
+    /// Rewrite every later query vector with `transform`, or stop with `None`
+    ///
+    /// Like the builder, the transform is not saved with the index and
+    /// must be set again after loading.
+    pub fn set_query_transform(&mut self, transform: Option<Arc<dyn QueryTransform<S>>>) {
+        self.query_transform = transform;
+    }
+
+    /// Keep the results of up to `capacity` recent searches, or none with 0
+    ///
+    /// Repeating a `search` with the same `k`, options and query direction
+    /// returns the cached results; any write to the index empties the
+    /// cache. Like the builder, the setting is not saved with the index.
+    pub fn set_query_cache(&mut self, capacity: usize) {
+        self.query_cache.set_capacity(capacity);
+    }
+
+    /// Hits and misses of the query cache since it was last sized
+    pub fn query_cache_stats(&self) -> CacheStats {
+        self.query_cache.stats()
+    }
+
+    pub(crate) fn query_cache(&self) -> &QueryCache {
+        &self.query_cache
+    }
+
+    /// Draw point levels and the projection matrix from a generator
+    /// seeded with `seed`, so the same inserts build the same graph
+    ///
+    /// Indexes are seeded from the OS otherwise, loaded ones included.
+    pub fn seed(&mut self, seed: u64) {
+        self.rng.seed(seed);
+    }
+
+    /// Current parameters of the index
+    pub fn params(&self) -> &HNSWParams {
+        &self.params
+    }
+
+    /// Replace the parameters of the index
+    ///
+    /// `ef_search`, `ef_construction` and `on_duplicate` can change at any
+    /// time and apply from the next search or insert; links already built
+    /// keep the `ef_construction` they were built with. `m`, `project_to`
+    /// and `projection` shape the graph and stored vectors, so they can
+    /// only change before the first insert or after [`Hnsw::clear`].
+    pub fn set_params(&mut self, params: HNSWParams) -> Result<(), HnswError> {
+        if params.m < 2 {
+            return Err(HnswError::InvalidParams(format!(
+                "m is {}, must be at least 2",
+                params.m
+            )));
+        }
+        if params.ef_construction == 0 || params.ef_search == 0 {
+            return Err(HnswError::InvalidParams(
+                "ef_construction and ef_search must be positive".to_string(),
+            ));
+        }
+        let shaped = self.dimensions != 0 || !self.is_empty();
+        let current = &self.params;
+        if shaped
+            && (params.m != current.m
+                || params.project_to != current.project_to
+                || params.projection != current.projection)
+        {
+            return Err(HnswError::InvalidParams(
+                "m, project_to and projection can only change before the first insert".to_string(),
+            ));
+        }
+        if params.ef_search != current.ef_search {
+            self.query_cache.invalidate();
+        }
+        let recode = params.quantize != current.quantize;
+        self.params = params;
+        if recode {
+            self.query_cache.invalidate();
+            self.recode();
+        }
+        Ok(())
+    }
+
+    /// Number of points in the index
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    /// Whether a point with this id is indexed
+    pub fn contains(&self, id: &str) -> bool {
+        self.points.contains_key(key(&id))
+    }
+
+    /// Up to `limit` (at least one) ids in ascending order, after `cursor`
+    ///
+    /// The cursor is the last id of the previous page, so pages stay
+    /// consistent across inserts and deletes between calls.
+    pub fn list_ids(&self, cursor: Option<&str>, limit: usize) -> IdPage {
+        let start = match &cursor {
+            Some(cursor) => Bound::Excluded(key(cursor)),
+            None => Bound::Unbounded,
+        };
+        let mut range = self
+            .points
+            .range::<dyn IdKey, _>((start, Bound::Unbounded))
+            .map(|(id, _)| id);
+        let ids: Vec<String> = range
+            .by_ref()
+            .take(limit.max(1))
+            .map(Id::to_string)
+            .collect();
+        let cursor = match range.next() {
+            Some(_) => ids.last().cloned(),
+            None => None,
+        };
+        IdPage { ids, cursor }
+    }
+
+    /// [`Hnsw::list_ids`] restricted to points whose payload matches `filter`
+    pub fn scroll(
+        &self,
+        filter: &Filter,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<IdPage, HnswError> {
+        self.schema.check_filter(filter)?;
+        let start = match &cursor {
+            Some(cursor) => Bound::Excluded(key(cursor)),
+            None => Bound::Unbounded,
+        };
+        let limit = limit.max(1);
+        // Candidates from payload indexes are sorted to keep id order
+        let ordered: Box<dyn Iterator<Item = &Id>> = match self.payload_indexes.candidates(filter) {
+            Some(candidates) => {
+                let mut candidates: Vec<&Id> = candidates
+                    .iter()
+                    .filter_map(|id| self.points.get_key_value(id).map(|(id, _)| id))
+                    .filter(|&id| cursor.iter().all(|c| key(c) < id as &dyn IdKey))
+                    .collect();
+                candidates.sort();
+                Box::new(candidates.into_iter())
+            }
+            None => Box::new(
+                self.points
+                    .range::<dyn IdKey, _>((start, Bound::Unbounded))
+                    .map(|(id, _)| id),
+            ),
+        };
+        let mut matching = ordered.filter(|id| filter.matches(self.payloads.get(*id)));
+        let ids: Vec<String> = matching.by_ref().take(limit).map(Id::to_string).collect();
+        let cursor = match matching.next() {
+            Some(_) => ids.last().cloned(),
+            None => None,
+        };
+        Ok(IdPage { ids, cursor })
+    }
+
+    /// Sparse vectors attached to points
+    pub(crate) fn sparse(&self) -> &SparseIndex {
+        &self.sparse
+    }
+
+    pub(crate) fn sparse_mut(&mut self) -> &mut SparseIndex {
+        &mut self.sparse
+    }
+
+    /// Term statistics of point text
+    pub(crate) fn text(&self) -> &TextIndex {
+        &self.text
+    }
+
+    pub(crate) fn text_mut(&mut self) -> &mut TextIndex {
+        &mut self.text
+    }
+
+    /// Metadata attached to a point
+    pub fn payload(&self, id: &str) -> Option<&serde_json::Value> {
+        self.payloads.get(key(&id))
+    }
+
+    /// Write counter of the index
+    ///
+    /// Every insert, update, delete, and payload, sparse vector or text
+    /// change bumps it; [`Hnsw::save_delta`] collects the points changed
+    /// after a given generation.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Number telling this index apart from every other in the process
+    ///
+    /// Together with [`Hnsw::generation`] it identifies the contents of an
+    /// index, for copies of them kept outside it.
+    #[cfg(any(feature = "webgpu", feature = "sqlite"))]
+    pub(crate) fn instance(&self) -> u64 {
+        self.instance
+    }
+
+    /// Record a change to `id` at a new generation
+    pub(crate) fn touch(&mut self, id: &str) {
+        let id = match self.points.get_key_value(key(&id)) {
+            Some((id, _)) => id.clone(),
+            None => self.ids.intern(id),
+        };
+        self.record_change(id);
+    }
+
+    fn record_change(&mut self, id: Id) {
+        self.generation += 1;
+        self.changes.insert(id, self.generation);
+    }
+
+    /// Generation up to which changes were written to the log
+    pub(crate) fn logged_generation(&self) -> u64 {
+        self.logged_generation
+    }
+
+    /// Record that every change so far is in the log, and stop tracking
+    /// them
+    pub(crate) fn mark_logged(&mut self) {
+        self.logged_generation = self.generation;
+        self.forget_changes(self.generation);
+    }
+
+    /// Stop tracking changes up to generation `through`, e.g. once a full
+    /// snapshot taken there is saved
+    ///
+    /// Deleted ids are remembered until then so deltas can carry them;
+    /// without this they pile up in a long-lived index. Afterwards
+    /// [`Hnsw::save_delta`] only accepts a generation of 0 or from
+    /// `through` on. Loading a snapshot, [`Hnsw::flush_log`] and
+    /// [`Hnsw::clear`] forget changes on their own.
+    pub fn forget_changes(&mut self, through: u64) {
+        let through = through.min(self.generation);
+        if through <= self.changes_floor {
+            return;
+        }
+        self.changes.retain(|_, changed| *changed > through);
+        self.changes_floor = through;
+    }
+
+    /// Generation before which changes are no longer tracked
+    pub(crate) fn changes_floor(&self) -> u64 {
+        self.changes_floor
+    }
+
+    /// Ids changed after `generation`, deleted ones included
+    pub(crate) fn changed_since(&self, generation: u64) -> impl Iterator<Item = &Id> {
+        self.changes
+            .iter()
+            .filter(move |(_, changed)| **changed > generation)
+            .map(|(id, _)| id)
+    }
+
+    pub(crate) fn payloads(&self) -> &HashMap<Id, serde_json::Value> {
+        &self.payloads
+    }
+
+    /// Fields with a secondary index and the kind of each
+    pub(crate) fn payload_index_kinds(&self) -> Vec<(&str, PayloadIndexKind)> {
+        self.payload_indexes.kinds()
+    }
+
+    /// Ids of all points in ascending order
+    pub(crate) fn ids(&self) -> impl Iterator<Item = &Id> {
+        self.points.keys()
+    }
+
+    /// Graph node of every point, in id order
+    pub(crate) fn point_nodes(&self) -> impl Iterator<Item = u32> + '_ {
+        self.points.values().copied()
+    }
+
+    /// Ids with the store slot of their vector
+    pub(crate) fn slots(&self) -> impl Iterator<Item = (&Id, u32)> {
+        self.points
+            .iter()
+            .filter_map(|(id, &node)| self.get_node(node).map(|n| (id, n.slot)))
+    }
+
+    pub(crate) fn store_mut(&mut self) -> &mut V {
+        &mut self.store
+    }
+
+    /// The same index over `store`, which must hold the vector of every
+    /// slot in use; the current store is dropped
+    ///
+    /// The construction strategy goes back to the default, since it is
+    /// specific to the store type.
+    pub(crate) fn into_store<W: VectorStore<S>>(self, store: W) -> Hnsw<S, W> {
+        Hnsw {
+            params: self.params,
+            points: self.points,
+            nodes: self.nodes,
+            free_nodes: self.free_nodes,
+            entry_point: self.entry_point,
+            dimensions: self.dimensions,
+            projection: self.projection,
+            store,
+            free_slots: self.free_slots,
+            next_slot: self.next_slot,
+            slot_refs: self.slot_refs,
+            content_slots: self.content_slots,
+            payloads: self.payloads,
+            payload_indexes: self.payload_indexes,
+            schema: self.schema,
+            sparse: self.sparse,
+            text: self.text,
+            generation: self.generation,
+            changes: self.changes,
+            changes_floor: self.changes_floor,
+            logged_generation: self.logged_generation,
+            builder: default_builder(),
+            query_transform: self.query_transform,
+            query_cache: self.query_cache,
+            scratch: self.scratch,
+            codes: self.codes,
+            ids: self.ids,
+            rng: self.rng,
+            #[cfg(any(feature = "webgpu", feature = "sqlite"))]
+            instance: self.instance,
+            _scalar: std::marker::PhantomData,
+        }
+    }
+
+    pub(crate) fn projection(&self) -> Option<&RandomProjection> {
+        self.projection.as_ref()
+    }
+
+    /// Fix the input dimensions and projection of an empty index
+    pub(crate) fn set_shape(&mut self, dimensions: usize, projection: Option<RandomProjection>) {
+        self.dimensions = dimensions;
+        self.projection = projection;
+    }
+
+    /// Payload schema checked on every metadata write
+    pub fn schema(&self) -> &PayloadSchema {
+        &self.schema
+    }
+
+    /// Declare payload field types, checking metadata already stored
+    pub fn set_schema(&mut self, schema: PayloadSchema) -> Result<(), HnswError> {
+        for payload in self.payloads.values() {
+            schema.check_payload(payload)?;
+        }
+        self.schema = schema;
+        Ok(())
+    }
+
+    /// Attach metadata to an existing point, returning false if it is missing
+    ///
+    /// A `null` payload detaches any existing metadata.
+    pub fn set_payload(&mut self, id: &str, payload: serde_json::Value) -> Result<bool, HnswError> {
+        let Some(id) = self.interned(id).cloned() else {
+            return Ok(false);
+        };
+        self.schema.check_payload(&payload)?;
+        self.query_cache.invalidate();
+        self.record_change(id.clone());
+        if let Some(old) = self.payloads.remove(&id) {
+            self.payload_indexes.remove(&id, &old);
+        }
+        if !payload.is_null() {
+            self.payload_indexes.insert(&id, &payload);
+            self.payloads.insert(id, payload);
+        }
+        Ok(true)
+    }
+
+    /// Build a secondary index on a payload field
+    ///
+    /// Filtered searches and deletes use it to narrow the points they look
+    /// at. Re-creating an index rebuilds it with the new kind.
+    pub fn create_payload_index(
+        &mut self,
+        field: &str,
+        kind: PayloadIndexKind,
+    ) -> Result<(), HnswError> {
+        self.schema.check_index(field, kind)?;
+        self.payload_indexes
+            .create(field, kind, self.payloads.iter());
+        Ok(())
+    }
+
+    /// Whether the index holds no points
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// Vector dimensions, or 0 before the first insert
+    pub fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    /// Dimensions of the stored vectors, after any random projection
+    pub fn stored_dimensions(&self) -> usize {
+        self.projection
+            .as_ref()
+            .map_or(self.dimensions, |p| p.output_dim())
+    }
+
+    /// Approximate bytes used by stored vectors
+    pub fn vector_bytes(&self) -> usize {
+        self.unique_vectors() * self.stored_dimensions() * S::BYTES
+    }
+
+    /// Number of distinct stored vectors
+    pub fn unique_vectors(&self) -> usize {
+        self.slot_refs.len()
+    }
+
+    /// Bytes saved by sharing identical vectors between ids
+    pub fn dedup_saved_bytes(&self) -> usize {
+        (self.points.len() - self.unique_vectors()) * self.stored_dimensions() * S::BYTES
+    }
+
+    /// Make room for `additional` more points ahead of a bulk insert
+    ///
+    /// Sizes the node table, the bookkeeping maps and the vector store so
+    /// the inserts do not reallocate and rehash as they go. Slots and nodes
+    /// freed by deletes count toward the room.
+    pub fn reserve(&mut self, additional: usize) {
+        self.nodes
+            .reserve(additional.saturating_sub(self.free_nodes.len()));
+        self.slot_refs.reserve(additional);
+        self.content_slots.reserve(additional);
+        self.changes.reserve(additional);
+        self.store
+            .reserve(additional.saturating_sub(self.free_slots.len()));
+    }
+
+    /// Renumber nodes and vector slots densely after deletes and release
+    /// the memory they left behind, returning the bytes reclaimed
+    ///
+    /// Ids, vectors, links and payloads are unchanged; only the internal
+    /// numbering moves, into the order of [`Hnsw::optimize_layout`].
+    /// Vectors are moved through the store, so a store that fetches on
+    /// demand keeps moved vectors resident.
+    pub fn compact(&mut self) -> Result<usize, HnswError> {
+        let before = self.graph_bytes();
+        self.optimize_layout()?;
+        self.payloads.shrink_to_fit();
+        self.changes.shrink_to_fit();
+        self.ids.shrink();
+        self.content_slots.shrink_to_fit();
+        let shrunk = self.store.shrink_to_fit();
+        Ok(before.saturating_sub(self.graph_bytes()) + shrunk)
+    }
+
+    /// Renumber nodes in breadth-first order from the entry point
+    ///
+    /// Neighbors on the base layer then sit next to each other in the node
+    /// table and, with their vector slots renumbered to match, in the
+    /// vector store, so a search touches fewer cache lines and pages.
+    /// Nodes the walk does not reach keep their relative order after it.
+    /// Numbering is dense afterwards, as after [`Hnsw::compact`].
+    pub fn optimize_layout(&mut self) -> Result<(), HnswError> {
+        let mut order = Vec::with_capacity(self.points.len());
+        let mut seen = vec![false; self.nodes.len()];
+        if let Some(entry) = self.entry_point {
+            seen[entry as usize] = true;
+            order.push(entry);
+            let mut next = 0;
+            while let Some(&node) = order.get(next) {
+                next += 1;
+                for &link in self.links(node, 0).unwrap_or(&[]) {
+                    if !std::mem::replace(&mut seen[link as usize], true) {
+                        order.push(link);
+                    }
+                }
+            }
+        }
+        for (node, point) in self.nodes.iter().enumerate() {
+            if point.is_some() && !seen[node] {
+                order.push(node as u32);
+            }
+        }
+        self.renumber(&order)
+    }
+
+    /// Give the live nodes in `order` the numbers 0, 1, ... and their
+    /// vector slots numbers in the order the nodes first use them
+    fn renumber(&mut self, order: &[u32]) -> Result<(), HnswError> {
+        if let Some(slot) = self
+            .slot_refs
+            .keys()
+            .find(|&&slot| self.store.get(slot).is_none())
+        {
+            return Err(HnswError::Storage(format!(
+                "vector in slot {} is missing",
+                slot
+            )));
+        }
+        let mut slot_of: HashMap<u32, u32> = HashMap::with_capacity(self.slot_refs.len());
+        for &node in order {
+            if let Some(point) = self.get_node(node) {
+                let next = slot_of.len() as u32;
+                slot_of.entry(point.slot).or_insert(next);
+            }
+        }
+
+        // Move vectors along the cycles of the permutation, carrying one
+        // at a time; a chain ends at a slot that held no vector
+        let mut moved: HashSet<u32> = HashSet::with_capacity(slot_of.len());
+        for (&start, &target) in &slot_of {
+            if start == target || !moved.insert(start) {
+                continue;
+            }
+            let mut carry = self.store.get(start).map(Cow::into_owned);
+            let mut pos = target;
+            while slot_of.contains_key(&pos) && moved.insert(pos) {
+                let next = self.store.get(pos).map(Cow::into_owned);
+                if let Some(vector) = std::mem::replace(&mut carry, next) {
+                    self.store.put(pos, vector)?;
+                }
+                pos = slot_of[&pos];
+            }
+            if let Some(vector) = carry {
+                self.store.put(pos, vector)?;
+            }
+        }
+        let live = slot_of.len() as u32;
+        for &old in slot_of.keys().filter(|&&old| old >= live) {
+            self.store.remove(old);
+        }
+        self.slot_refs = self
+            .slot_refs
+            .iter()
+            .map(|(slot, &refs)| (slot_of[slot], refs))
+            .collect();
+        for slots in self.content_slots.values_mut() {
+            for slot in slots.iter_mut() {
+                *slot = slot_of[slot];
+            }
+        }
+        self.free_slots = Vec::new();
+        self.next_slot = live;
+
+        let mut node_of = vec![u32::MAX; self.nodes.len()];
+        let mut nodes = Vec::with_capacity(order.len());
+        for &old in order {
+            if let Some(mut node) = self.nodes.get_mut(old as usize).and_then(Option::take) {
+                node_of[old as usize] = nodes.len() as u32;
+                node.slot = slot_of[&node.slot];
+                nodes.push(Some(node));
+            }
+        }
+        for node in nodes.iter_mut().flatten() {
+            for links in node.layers_mut() {
+                links.update(|link| node_of[link as usize]);
+                links.shrink_to_fit();
+            }
+        }
+        self.nodes = nodes;
+        self.free_nodes = Vec::new();
+        for node in self.points.values_mut() {
+            *node = node_of[*node as usize];
+        }
+        self.entry_point = self.entry_point.map(|node| node_of[node as usize]);
+        self.scratch.clear();
+        self.query_cache.invalidate();
+        self.recode();
+        Ok(())
+    }
+
+    /// Rebuild the vector codes from the store, or drop them when
+    /// `params.quantize` is off
+    fn recode(&mut self) {
+        self.codes.clear();
+        if !self.params.quantize {
+            return;
+        }
+        for &slot in self.slot_refs.keys() {
+            if let Some(vector) = self.store.get(slot) {
+                self.codes.set(slot, &vector);
+            }
+        }
+    }
+
+    /// Approximate heap bytes of the node table, links, slot maps, id
+    /// prefixes and vector codes
+    fn graph_bytes(&self) -> usize {
+        let links: usize = self
+            .nodes
+            .iter()
+            .flatten()
+            .map(|node| {
+                node.upper.capacity() * std::mem::size_of::<LinkList>()
+                    + node.layers().map(LinkList::heap_bytes).sum::<usize>()
+            })
+            .sum();
+        self.nodes.capacity() * std::mem::size_of::<Option<Node>>()
+            + links
+            + (self.free_nodes.capacity() + self.free_slots.capacity()) * 4
+            + self.slot_refs.capacity() * 8
+            + self.payloads.capacity() * std::mem::size_of::<(Id, serde_json::Value)>()
+            + self.changes.capacity() * std::mem::size_of::<(Id, u64)>()
+            + self.ids.heap_bytes()
+            + self.codes.heap_bytes()
+    }
+
+    /// Remove every point and forget the vector dimensions
+    ///
+    /// Counts as one change, after which deltas can only be taken from the
+    /// new generation or 0.
+    pub fn clear(&mut self) {
+        self.generation += 1;
+        self.changes.clear();
+        self.changes_floor = self.generation;
+        self.points.clear();
+        self.nodes.clear();
+        self.free_nodes.clear();
+        self.entry_point = None;
+        self.dimensions = 0;
+        self.projection = None;
+        self.store.clear();
+        self.free_slots.clear();
+        self.next_slot = 0;
+        self.slot_refs.clear();
+        self.content_slots.clear();
+        self.codes.clear();
+        self.payloads.clear();
+        self.payload_indexes.clear_entries();
+        self.sparse.clear();
+        self.text.clear();
+        self.query_cache.invalidate();
+        self.scratch.clear();
+    }
+
+    /// Insert a vector under the given id
+    ///
+    /// An id that is already indexed is handled according to
+    /// [`HNSWParams::on_duplicate`].
+    pub fn insert(&mut self, id: String, vector: Vec<S>) -> Result<(), HnswError> {
+        self.insert_with_payload(id, vector, None)
+    }
+
+    /// Insert a vector with optional metadata under the given id
+    ///
+    /// A duplicate id ignored by the policy keeps its existing metadata.
+    pub fn insert_with_payload(
+        &mut self,
+        id: String,
+        vector: Vec<S>,
+        payload: Option<serde_json::Value>,
+    ) -> Result<(), HnswError> {
+        if let Some(payload) = &payload {
+            self.schema.check_payload(payload)?;
+        }
+        if self.points.contains_key(key(&id.as_str())) {
+            return match self.params.on_duplicate {
+                DuplicatePolicy::Overwrite => {
+                    self.upsert_with_payload(id, vector, payload).map(|_| ())
+                }
+                DuplicatePolicy::Ignore => Ok(()),
+                DuplicatePolicy::Error => Err(HnswError::DuplicateId(id)),
+            };
+        }
+        self.insert_new(id.clone(), vector)?;
+        if let Some(payload) = payload {
+            self.set_payload(&id, payload)?;
+        }
+        Ok(())
+    }
+
+    /// Insert a vector, replacing and re-linking any point with the same id
+    ///
+    /// A replaced point keeps its metadata, sparse vector and text. Returns
+    /// whether an existing point was replaced.
+    pub fn upsert(&mut self, id: String, vector: Vec<S>) -> Result<bool, HnswError> {
+        self.upsert_with_payload(id, vector, None)
+    }
+
+    /// Upsert a vector, replacing the point's metadata with `payload` if
+    /// one is given
+    pub fn upsert_with_payload(
+        &mut self,
+        id: String,
+        vector: Vec<S>,
+        payload: Option<serde_json::Value>,
+    ) -> Result<bool, HnswError> {
+        if let Some(payload) = &payload {
+            self.schema.check_payload(payload)?;
+        }
+        let existed = self.points.contains_key(key(&id.as_str()));
+        if existed {
+            self.update_vector(&id, vector)?;
+        } else {
+            self.insert_new(id.clone(), vector)?;
+        }
+        if let Some(payload) = payload {
+            self.set_payload(&id, payload)?;
+        }
+        Ok(existed)
+    }
+
+    /// Replace the vector of an existing point and re-link it in place
+    ///
+    /// The point keeps its level; its links are dropped on every layer and
+    /// rebuilt by the graph builder against the new vector. Returns false
+    /// when the id is not in the index.
+    pub fn update_vector(&mut self, id: &str, vector: Vec<S>) -> Result<bool, HnswError> {
+        let Some(node) = self.node(id) else {
+            return Ok(false);
+        };
+        let (old_slot, level) = match self.get_node(node) {
+            Some(point) => (point.slot, point.level()),
+            None => return Ok(false),
+        };
+        if vector.len() != self.dimensions {
+            return Err(HnswError::DimensionMismatch {
+                expected: self.dimensions,
+                got: vector.len(),
+            });
+        }
+        check_finite(&vector)?;
+        let vector = self.project(vector);
+        self.query_cache.invalidate();
+        self.touch(id);
+
+        let slot = self.acquire_slot(vector)?;
+        self.release_slot(old_slot);
+        let mut neighbors = Vec::with_capacity(level + 1);
+        if let Some(point) = self.get_node_mut(node) {
+            point.slot = slot;
+            for links in point.layers_mut() {
+                neighbors.push(links.to_vec());
+                links.clear();
+            }
+        }
+
+        // Detach from the old neighborhood. Only the point's own neighbors
+        // are visited; a one-way link from elsewhere stays, and still
+        // leads to the point at its new position.
+        for (layer, neighbors) in neighbors.into_iter().enumerate() {
+            for neighbor in neighbors {
+                let links = self.get_node_mut(neighbor).and_then(|n| n.layer_mut(layer));
+                if let Some(links) = links {
+                    links.retain(|link| link != node);
+                }
+            }
+        }
+
+        // The builder descends from the entry point, so it must not be the
+        // point being re-linked
+        let was_entry = self.entry_point == Some(node);
+        if was_entry {
+            self.entry_point = self.highest_point(Some(node));
+        }
+
+        let builder = Arc::clone(&self.builder);
+        builder.insert(self, node, level);
+
+        if was_entry {
+            self.entry_point = Some(node);
+        }
+        Ok(true)
+    }
+
+    /// Insert a vector under an id that is not in the index
+    fn insert_new(&mut self, id: String, vector: Vec<S>) -> Result<(), HnswError> {
+        let vector = self.prepare_vector(vector)?;
+        self.insert_stored(id, vector)
+    }
+
+    /// Check an input vector's width, fixing it on the first insert, and
+    /// its values, then project it into stored space
+    pub(crate) fn prepare_vector(&mut self, vector: Vec<S>) -> Result<Vec<S>, HnswError> {
+        check_finite(&vector)?;
+        if self.dimensions == 0 {
+            self.init_projection(vector.len())?;
+            self.dimensions = vector.len();
+        } else if vector.len() != self.dimensions {
+            return Err(HnswError::DimensionMismatch {
+                expected: self.dimensions,
+                got: vector.len(),
+            });
+        }
+        Ok(self.project(vector))
+    }
+
+    /// Insert a vector already in stored space under an id that is not in
+    /// the index
+    pub(crate) fn insert_stored(&mut self, id: String, vector: Vec<S>) -> Result<(), HnswError> {
+        let level = self.random_level();
+        self.insert_at(id, vector, level)
+    }
+
+    /// [`Hnsw::insert_stored`] at a level drawn by the caller
+    pub(crate) fn insert_at(
+        &mut self,
+        id: String,
+        vector: Vec<S>,
+        level: usize,
+    ) -> Result<(), HnswError> {
+        let node = self.place(id, vector, level)?;
+
+        // Link into the graph while the old entry point is still in place
+        let builder = Arc::clone(&self.builder);
+        builder.insert(self, node, level);
+
+        self.raise_entry_point(node, level);
+        Ok(())
+    }
+
+    /// Store a point at `level` without linking it, for importers that
+    /// bring their own graph and wire it with [`Hnsw::set_links`]
+    ///
+    /// The index must not project, so `vector` is stored as given. Returns
+    /// the node of the new point.
+    pub(crate) fn insert_unlinked(
+        &mut self,
+        id: String,
+        vector: Vec<S>,
+        level: usize,
+    ) -> Result<u32, HnswError> {
+        if self.params.project_to != 0 {
+            return Err(HnswError::InvalidParams(
+                "imported graphs cannot be projected".to_string(),
+            ));
+        }
+        if self.points.contains_key(key(&id.as_str())) {
+            return Err(HnswError::DuplicateId(id));
+        }
+        if self.dimensions == 0 {
+            self.dimensions = vector.len();
+        } else if vector.len() != self.dimensions {
+            return Err(HnswError::DimensionMismatch {
+                expected: self.dimensions,
+                got: vector.len(),
+            });
+        }
+        let node = self.place(id, vector, level)?;
+        self.raise_entry_point(node, level);
+        Ok(node)
+    }
+
+    /// Store a point on layers `0..=level` with no links, returning its node
+    pub(crate) fn place(
+        &mut self,
+        id: String,
+        vector: Vec<S>,
+        level: usize,
+    ) -> Result<u32, HnswError> {
+        self.query_cache.invalidate();
+        let id = self.ids.intern(&id);
+        self.record_change(id.clone());
+
+        let slot = self.acquire_slot(vector)?;
+        let point = Node::new(id.clone(), slot, level);
+
+        let node = match self.free_nodes.pop() {
+            Some(node) => {
+                self.nodes[node as usize] = Some(point);
+                node
+            }
+            None => {
+                self.nodes.push(Some(point));
+                (self.nodes.len() - 1) as u32
+            }
+        };
+        self.points.insert(id, node);
+        Ok(node)
+    }
+
+    /// Make a node just placed at `level` the entry point if it is the
+    /// first or tops the graph
+    pub(crate) fn raise_entry_point(&mut self, node: u32, level: usize) {
+        if self.entry_point.is_none() || level > self.get_entry_level() {
+            self.entry_point = Some(node);
+        }
+    }
+
+    /// Store a point on layers `0..=level` without linking it, for a
+    /// loader that restores saved links with [`Hnsw::set_neighbors`]
+    #[cfg(all(feature = "sqlite", not(target_arch = "wasm32")))]
+    pub(crate) fn restore_point(
+        &mut self,
+        id: String,
+        vector: Vec<S>,
+        level: usize,
+    ) -> Result<(), HnswError> {
+        self.place(id, vector, level)?;
+        Ok(())
+    }
+
+    /// Restore the entry point and generation of an index rebuilt from
+    /// saved points, before [`Hnsw::check_loaded`]
+    ///
+    /// Everything up to `generation` counts as logged.
+    #[cfg(all(feature = "sqlite", not(target_arch = "wasm32")))]
+    pub(crate) fn resume(&mut self, entry_point: Option<String>, generation: u64) {
+        self.entry_point = entry_point.and_then(|id| self.node(&id));
+        self.generation = generation;
+        self.logged_generation = generation;
+    }
+
+    /// Insert `ids.len()` vectors packed back to back in `vectors`
+    ///
+    /// The shape is checked before anything is inserted, so a malformed
+    /// batch leaves the index untouched.
+    pub fn insert_batch(
+        &mut self,
+        ids: Vec<String>,
+        vectors: &[S],
+        dim: usize,
+    ) -> Result<(), HnswError> {
+        self.check_batch(ids.len(), vectors, dim)?;
+        self.reserve(ids.len());
+        for (id, vector) in ids.into_iter().zip(vectors.chunks_exact(dim)) {
+            self.insert(id, vector.to_vec())?;
+        }
+        Ok(())
+    }
+
+    /// Check that `vectors` packs `count` vectors of the index's width
+    pub(crate) fn check_batch(
+        &self,
+        count: usize,
+        vectors: &[S],
+        dim: usize,
+    ) -> Result<(), HnswError> {
+        if dim == 0 || vectors.len() != count * dim {
+            return Err(HnswError::InvalidParams(format!(
+                "batch of {} ids needs {} x {} values, got {}",
+                count,
+                count,
+                dim,
+                vectors.len()
+            )));
+        }
+        if self.dimensions != 0 && dim != self.dimensions {
+            return Err(HnswError::DimensionMismatch {
+                expected: self.dimensions,
+                got: dim,
+            });
+        }
+        Ok(())
+    }
+
+    /// Find the `k` nearest neighbors as `(id, similarity)` pairs, best first
+    pub fn nearest(&self, vector: &[S], k: usize) -> Result<Vec<(String, f32)>, HnswError> {
+        let query = self.prepare_query(vector)?;
+        let beam = Beam::Fixed(self.params.ef_search);
+        self.nearest_where(&query, k, None, beam, &Meter::unlimited())
+    }
+
+    /// Find the `k` nearest neighbors whose payload matches `filter`
+    ///
+    /// The filter is applied inside the search, so up to `k` matching
+    /// points are returned even when most neighbors are excluded. See
+    /// [`Hnsw::explain_filtered`] for how the search is carried out.
+    pub fn nearest_filtered(
+        &self,
+        vector: &[S],
+        k: usize,
+        filter: &Filter,
+    ) -> Result<Vec<(String, f32)>, HnswError> {
+        self.explain_filtered(vector, k, filter)
+            .map(|(results, _)| results)
+    }
+
+    /// [`Hnsw::nearest_filtered`], also returning the chosen [`QueryPlan`]
+    ///
+    /// Selectivity is estimated from payload indexes when they cover the
+    /// filter and from a fixed sample of points otherwise. Small match sets
+    /// are scored directly; permissive filters post-filter a widened
+    /// unfiltered search; the rest walk the graph ACORN style, skipping
+    /// over excluded neighbors to their own neighbors.
+    pub fn explain_filtered(
+        &self,
+        vector: &[S],
+        k: usize,
+        filter: &Filter,
+    ) -> Result<(Vec<(String, f32)>, QueryPlan), HnswError> {
+        let query = self.prepare_query(vector)?;
+        let beam = Beam::Fixed(self.params.ef_search);
+        self.explain_filtered_excluding(&query, k, filter, &|_| false, beam, &Meter::unlimited())
+    }
+
+    /// [`Hnsw::explain_filtered`] for a query already in stored space, also
+    /// skipping ids for which `exclude` holds
+    pub(crate) fn explain_filtered_excluding(
+        &self,
+        query: &[S],
+        k: usize,
+        filter: &Filter,
+        exclude: &dyn Fn(&Id) -> bool,
+        beam: Beam,
+        meter: &Meter,
+    ) -> Result<(Vec<(String, f32)>, QueryPlan), HnswError> {
+        self.schema.check_filter(filter)?;
+        let candidates = self.payload_indexes.candidates(filter);
+        let (estimated_matches, from_index) = match &candidates {
+            Some(candidates) => (candidates.len(), true),
+            None => (self.estimate_matches(filter), false),
+        };
+
+        let ef = beam.widest(k);
+        let plan = QueryPlan {
+            strategy: planner::choose(estimated_matches, self.points.len(), ef, max_links(self, 0)),
+            estimated_matches,
+            from_index,
+        };
+
+        let accept = |id: &Id| {
+            candidates.iter().all(|c| c.contains(id))
+                && !exclude(id)
+                && filter.matches(self.payloads.get(id))
+        };
+        let results = match plan.strategy {
+            FilterStrategy::BruteForce => match &candidates {
+                Some(candidates) => {
+                    let points = candidates
+                        .iter()
+                        .filter_map(|id| self.points.get_key_value(id));
+                    self.brute_force(query, k, points, &accept, meter)
+                }
+                None => self.brute_force(query, k, self.points.iter(), &accept, meter),
+            },
+            FilterStrategy::PostFilter => {
+                // Widen the beam by the expected share of rejected points
+                let wide = ef.saturating_mul(self.points.len()) / estimated_matches.max(1);
+                let mut results = self.nearest_where(query, wide.max(ef), None, beam, meter)?;
+                results.retain(|(id, _)| self.interned(id).is_some_and(accept));
+                if results.len() >= k.min(estimated_matches) || meter.exhausted() {
+                    results.truncate(k);
+                    results
+                } else {
+                    self.nearest_where(query, k, Some(&accept), beam, meter)?
+                }
+            }
+            FilterStrategy::Acorn => self.nearest_where(query, k, Some(&accept), beam, meter)?,
+        };
+        Ok((results, plan))
+    }
+
+    /// `k` nearest neighbors of a stored-space query, skipping ids for which
+    /// `exclude` holds
+    pub(crate) fn nearest_excluding(
+        &self,
+        query: &[S],
+        k: usize,
+        exclude: &dyn Fn(&Id) -> bool,
+        beam: Beam,
+        meter: &Meter,
+    ) -> Result<Vec<(String, f32)>, HnswError> {
+        self.nearest_where(query, k, Some(&|id| !exclude(id)), beam, meter)
+    }
+
+    /// Estimate how many points match `filter` from an evenly spaced sample
+    fn estimate_matches(&self, filter: &Filter) -> usize {
+        let total = self.points.len();
+        let stride = (total / planner::SELECTIVITY_SAMPLE).max(1);
+        let (sampled, hits) = self
+            .points
+            .keys()
+            .step_by(stride)
+            .take(planner::SELECTIVITY_SAMPLE)
+            .fold((0, 0), |(sampled, hits), id| {
+                (
+                    sampled + 1,
+                    hits + filter.matches(self.payloads.get(id)) as usize,
+                )
+            });
+        (hits * total).checked_div(sampled).unwrap_or(0)
+    }
+
+    /// Exact `k` nearest among `points` accepted by `accept`
+    fn brute_force<'a>(
+        &self,
+        query: &[S],
+        k: usize,
+        points: impl Iterator<Item = (&'a Id, &'a u32)>,
+        accept: &dyn Fn(&Id) -> bool,
+        meter: &Meter,
+    ) -> Vec<(String, f32)> {
+        let mut scored: Vec<(&Id, f32)> = points
+            .filter(|(id, _)| accept(id))
+            .take_while(|_| !meter.exhausted())
+            .filter_map(|(id, &node)| {
+                meter.visit();
+                Some((id, self.node_distance(query, node)?))
+            })
+            .collect();
+        // Select the k nearest before ordering them, ties by id
+        let by_distance =
+            |a: &(&Id, f32), b: &(&Id, f32)| a.1.total_cmp(&b.1).then_with(|| a.0.cmp(b.0));
+        if scored.len() > k && k > 0 {
+            scored.select_nth_unstable_by(k - 1, by_distance);
+        }
+        scored.truncate(k);
+        scored.sort_unstable_by(by_distance);
+        scored
+            .into_iter()
+            .map(|(id, dist)| (id.to_string(), 1.0 - dist))
+            .collect()
+    }
+
+    /// Transform a query, check its dimensions and map it into stored space
+    ///
+    /// An empty index accepts any query, as there is nothing to compare it to.
+    pub(crate) fn prepare_query(&self, vector: &[S]) -> Result<Vec<S>, HnswError> {
+        if self.points.is_empty() {
+            return Ok(Vec::new());
+        }
+        let vector = match &self.query_transform {
+            Some(transform) => transform.transform(vector)?,
+            None => vector.to_vec(),
+        };
+        if vector.len() != self.dimensions {
+            return Err(HnswError::DimensionMismatch {
+                expected: self.dimensions,
+                got: vector.len(),
+            });
+        }
+        Ok(self.project(vector))
+    }
+
+    /// `k` nearest neighbors of a stored-space query among accepted ids
+    pub(crate) fn nearest_where(
+        &self,
+        query: &[S],
+        k: usize,
+        accept: Option<&dyn Fn(&Id) -> bool>,
+        beam: Beam,
+        meter: &Meter,
+    ) -> Result<Vec<(String, f32)>, HnswError> {
+        if self.points.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut scratch = self.scratch.take();
+        if self.params.quantize {
+            Codes::encode(query, &mut scratch.coded);
+        }
+        self.descend(&mut scratch, query, 0, meter);
+        // Predicates see the ids held by the graph; the walk sees nodes
+        let accept = accept.map(|accept| move |node| self.node_id(node).is_some_and(accept));
+        match (beam, &accept) {
+            (Beam::Fixed(ef), Some(accept)) => {
+                let ef = ef.max(k);
+                self.walk_acorn(&mut scratch, query, ef, accept, meter);
+                if scratch.results.len() < k.min(self.points.len()) && !meter.exhausted() {
+                    // The matching points reachable within two hops ran out;
+                    // walk the whole neighborhood instead
+                    self.walk_layer(&mut scratch, query, ef, 0, accept, meter);
+                }
+            }
+            (Beam::Fixed(ef), None) => {
+                self.walk_layer(&mut scratch, query, ef.max(k), 0, &|_| true, meter)
+            }
+            (Beam::Adaptive(cap), Some(accept)) => {
+                self.walk_widening(&mut scratch, query, k, cap, accept, meter)
+            }
+            (Beam::Adaptive(cap), None) => {
+                self.walk_widening(&mut scratch, query, k, cap, &|_| true, meter)
+            }
+        }
+
+        if !scratch.coded.is_empty() {
+            // The walk ranked codes; rescore the best k with the vectors
+            scratch.results.truncate(k);
+            scratch.results.retain_mut(|(node, dist)| {
+                self.node_distance(query, *node)
+                    .map(|exact| *dist = exact)
+                    .is_some()
+            });
+            scratch
+                .results
+                .sort_unstable_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+        }
+
+        // The walk leaves its results nearest first; keep the top k
+        let results: Vec<(String, f32)> = scratch
+            .results
+            .iter()
+            .take(k)
+            .filter_map(|&(node, dist)| {
+                self.node_id(node).map(|id| (id.to_string(), 1.0 - dist)) // Convert to similarity
+            })
+            .collect();
+        self.scratch.give(scratch);
+
+        Ok(results)
+    }
+
+    /// Points within cosine distance `max_distance`, at most `limit` of them
+    ///
+    /// Returned as `(id, similarity)` pairs, best first. The beam starts at
+    /// `ef_search` and doubles while every point it returns is still inside
+    /// the radius, so dense neighborhoods are not cut off at a fixed k.
+    pub fn within_distance(
+        &self,
+        vector: &[S],
+        max_distance: f32,
+        limit: usize,
+    ) -> Result<Vec<(String, f32)>, HnswError> {
+        if self.points.is_empty() || limit == 0 {
+            return Ok(Vec::new());
+        }
+        let query = self.prepare_query(vector)?;
+        let mut scratch = self.scratch.take();
+        let meter = Meter::unlimited();
+        self.descend(&mut scratch, &query, 0, &meter);
+
+        let mut ef = self.params.ef_search.max(1).min(limit);
+        loop {
+            self.walk_layer(&mut scratch, &query, ef, 0, &|_| true, &meter);
+            let exhausted = scratch.results.len() < ef;
+            let inside: Vec<(String, f32)> = scratch
+                .results
+                .iter()
+                .filter(|(_, dist)| *dist <= max_distance)
+                .filter_map(|&(node, dist)| Some((self.node_id(node)?.to_string(), 1.0 - dist)))
+                .collect();
+
+            if exhausted || inside.len() < ef || ef >= limit {
+                self.scratch.give(scratch);
+                let mut results = inside;
+                results.truncate(limit);
+                return Ok(results);
+            }
+            ef = ef.saturating_mul(2).min(limit);
+        }
+    }
+
+    /// Answer `num_queries` queries packed back to back in `queries`
+    pub fn nearest_batch(
+        &self,
+        queries: &[S],
+        num_queries: usize,
+        k: usize,
+    ) -> Result<Vec<Vec<(String, f32)>>, HnswError> {
+        match batch_dim(queries.len(), num_queries)? {
+            0 => Ok(vec![Vec::new(); num_queries]),
+            dim => queries
+                .chunks_exact(dim)
+                .map(|query| self.nearest(query, k))
+                .collect(),
+        }
+    }
+
+    /// Remove a point and every link to it, returning whether it existed
+    pub fn remove(&mut self, id: &str) -> bool {
+        self.remove_many([id]) == 1
+    }
+
+    /// Remove many points in one pass over the layers
+    ///
+    /// Returns how many of the ids were in the index.
+    pub fn remove_many<I, T>(&mut self, ids: I) -> usize
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<str>,
+    {
+        let mut removed = HashSet::new();
+        for id in ids {
+            let id = id.as_ref();
+            let Some(node) = self.points.remove(key(&id)) else {
+                continue;
+            };
+            let Some(point) = self.nodes.get_mut(node as usize).and_then(Option::take) else {
+                continue;
+            };
+            self.release_slot(point.slot);
+            if let Some(payload) = self.payloads.remove(&point.id) {
+                self.payload_indexes.remove(&point.id, &payload);
+            }
+            self.sparse.remove(id);
+            self.text.remove(id);
+            self.record_change(point.id);
+            self.free_nodes.push(node);
+            removed.insert(node);
+        }
+        if removed.is_empty() {
+            return 0;
+        }
+        self.query_cache.invalidate();
+
+        for point in self.nodes.iter_mut().flatten() {
+            for links in point.layers_mut() {
+                links.retain(|link| !removed.contains(&link));
+            }
+        }
+
+        // Update entry point if needed, keeping it on the top layer
+        if self.entry_point.is_some_and(|node| removed.contains(&node)) {
+            self.entry_point = self.highest_point(None);
+        }
+
+        removed.len()
+    }
+
+    /// Remove every point whose payload matches `filter`
+    ///
+    /// Returns how many points were removed.
+    pub fn remove_by_filter(&mut self, filter: &Filter) -> Result<usize, HnswError> {
+        let matching = self.matching_ids(filter)?;
+        Ok(self.remove_many(matching.iter().map(Id::to_string)))
+    }
+
+    /// Number of points whose payload matches `filter`
+    pub fn count_matching(&self, filter: &Filter) -> Result<usize, HnswError> {
+        self.schema.check_filter(filter)?;
+        let matches = |id: &Id| filter.matches(self.payloads.get(id));
+        Ok(match self.payload_indexes.candidates(filter) {
+            Some(candidates) => candidates.iter().filter(|id| matches(id)).count(),
+            None => self.points.keys().filter(|id| matches(id)).count(),
+        })
+    }
+
+    /// Count values of a payload field across points matching `filter`
+    ///
+    /// Elements of array fields are counted individually. Results are
+    /// ordered by count, highest first, then by value.
+    pub fn facet(
+        &self,
+        field: &str,
+        filter: Option<&Filter>,
+    ) -> Result<Vec<FacetCount>, HnswError> {
+        let ids = match filter {
+            Some(filter) => self.matching_ids(filter)?,
+            None => self.payloads.keys().cloned().collect(),
+        };
+
+        let mut counts: HashMap<String, FacetCount> = HashMap::new();
+        for id in &ids {
+            let mut seen = HashSet::new();
+            for value in lookup_values(self.payloads.get(id), field) {
+                let key = value.to_string();
+                if seen.insert(key.clone()) {
+                    counts
+                        .entry(key)
+                        .or_insert_with(|| FacetCount {
+                            value: value.clone(),
+                            count: 0,
+                        })
+                        .count += 1;
+                }
+            }
+        }
+
+        let mut facets: Vec<(String, FacetCount)> = counts.into_iter().collect();
+        facets.sort_by(|a, b| b.1.count.cmp(&a.1.count).then_with(|| a.0.cmp(&b.0)));
+        Ok(facets.into_iter().map(|(_, facet)| facet).collect())
+    }
+
+    /// Ids of points whose payload matches `filter`
+    fn matching_ids(&self, filter: &Filter) -> Result<Vec<Id>, HnswError> {
+        self.schema.check_filter(filter)?;
+        Ok(match self.payload_indexes.candidates(filter) {
+            Some(candidates) => candidates
+                .into_iter()
+                .filter(|id| filter.matches(self.payloads.get(id)))
+                .collect(),
+            None => self
+                .points
+                .keys()
+                .filter(|id| filter.matches(self.payloads.get(*id)))
+                .cloned()
+                .collect(),
+        })
+    }
+
+    /// Vet an index just decoded from a snapshot
+    ///
+    /// Payload index entries are derived data and are rebuilt rather than
+    /// trusted; everything else must pass [`Hnsw::validate`].
+    pub(crate) fn check_loaded(&mut self) -> Result<(), HnswError> {
+        self.check_decoded(true)
+    }
+
+    /// [`Hnsw::check_loaded`] for a store that fetches vectors on demand,
+    /// leaving them unread
+    pub(crate) fn check_loaded_graph(&mut self) -> Result<(), HnswError> {
+        self.check_decoded(false)
+    }
+
+    fn check_decoded(&mut self, vectors: bool) -> Result<(), HnswError> {
+        if self.params.m < 2 {
+            return Err(HnswError::CorruptSnapshot(format!(
+                "m is {}, must be at least 2",
+                self.params.m
+            )));
+        }
+        self.check_invariants(vectors).map_err(|e| match e {
+            HnswError::Invariant(msg) => HnswError::CorruptSnapshot(msg),
+            other => other,
+        })?;
+        self.share_ids();
+        self.payload_indexes.clear_entries();
+        for (id, payload) in &self.payloads {
+            self.payload_indexes.insert(id, payload);
+        }
+        self.recode();
+        // Changes are not saved; everything up to here is in the snapshot
+        self.mark_logged();
+        Ok(())
+    }
+
+    /// Share the prefixes of decoded ids, and each id between the id map
+    /// and its node, as inserting them would have
+    fn share_ids(&mut self) {
+        let mut arena = IdArena::default();
+        let points = std::mem::take(&mut self.points);
+        self.points = points
+            .into_iter()
+            .map(|(id, node)| {
+                let id = arena.share(&id);
+                if let Some(Some(point)) = self.nodes.get_mut(node as usize) {
+                    point.id = id.clone();
+                }
+                (id, node)
+            })
+            .collect();
+        self.ids = arena;
+
+        // Point data then takes its keys from the id map
+        let points = &self.points;
+        let share = |id: &Id| {
+            points
+                .get_key_value(id)
+                .map_or_else(|| id.clone(), |(id, _)| id.clone())
+        };
+        self.payloads = std::mem::take(&mut self.payloads)
+            .into_iter()
+            .map(|(id, payload)| (share(&id), payload))
+            .collect();
+        self.sparse.share(share);
+        self.text.share(share);
+    }
+
+    /// Check the structural invariants of the graph
+    ///
+    /// Verifies that every point is linked on each of its layers, that no
+    /// layer references a missing point, that vectors share one dimension,
+    /// and that the entry point sits on the top layer.
+    pub fn validate(&self) -> Result<(), HnswError> {
+        self.check_invariants(true)
+    }
+
+    /// [`Hnsw::validate`], reading vectors only when `vectors` is set
+    fn check_invariants(&self, vectors: bool) -> Result<(), HnswError> {
+        let invariant = |msg: String| Err(HnswError::Invariant(msg));
+
+        let free: HashSet<u32> = self.free_slots.iter().copied().collect();
+        if free.len() != self.free_slots.len() || free.iter().any(|&s| s >= self.next_slot) {
+            return invariant("free slot list has duplicates or unallocated slots".to_string());
+        }
+        let free_nodes: HashSet<u32> = self.free_nodes.iter().copied().collect();
+        let vacant = self.nodes.iter().filter(|n| n.is_none()).count();
+        if free_nodes.len() != self.free_nodes.len()
+            || free_nodes.len() != vacant
+            || free_nodes.iter().any(|&n| self.get_node(n).is_some())
+        {
+            return invariant("free node list does not match removed nodes".to_string());
+        }
+        if self.points.len() + vacant != self.nodes.len() {
+            return invariant("nodes do not match point ids".to_string());
+        }
+        let mut slots: HashMap<u32, u32> = HashMap::new();
+        for (id, &node) in &self.points {
+            let Some(point) = self.get_node(node) else {
+                return invariant(format!("point {} maps to missing node {}", id, node));
+            };
+            if point.id != *id {
+                return invariant(format!("point keyed as {} carries id {}", id, point.id));
+            }
+            if point.slot >= self.next_slot || free.contains(&point.slot) {
+                return invariant(format!("point {} uses unallocated slot {}", id, point.slot));
+            }
+            *slots.entry(point.slot).or_default() += 1;
+            let stored = if vectors {
+                self.store.get(point.slot).map(|v| v.len())
+            } else {
+                Some(self.stored_dimensions())
+            };
+            if stored != Some(self.stored_dimensions()) {
+                return invariant(format!(
+                    "point {} has {:?} stored dimensions, index stores {}",
+                    id,
+                    stored,
+                    self.stored_dimensions()
+                ));
+            }
+            for (layer_idx, links) in point.layers().enumerate() {
+                let outside = links
+                    .iter()
+                    .find(|&&t| self.get_node(t).map_or(true, |t| t.level() < layer_idx));
+                if let Some(target) = outside {
+                    return invariant(format!(
+                        "layer {} link {} -> node {} points outside the layer",
+                        layer_idx, id, target
+                    ));
+                }
+            }
+        }
+
+        if slots != self.slot_refs {
+            return invariant("slot reference counts do not match points".to_string());
+        }
+        let hashed: usize = self.content_slots.values().map(|s| s.len()).sum();
+        if hashed != self.slot_refs.len()
+            || self
+                .content_slots
+                .values()
+                .flatten()
+                .any(|slot| !self.slot_refs.contains_key(slot))
+        {
+            return invariant("content hashes do not match live slots".to_string());
+        }
+
+        if let Some(id) = self
+            .payloads
+            .keys()
+            .find(|id| !self.points.contains_key(*id))
+        {
+            return invariant(format!("payload kept for missing point {}", id));
+        }
+        if let Some(id) = self.sparse.ids().find(|id| !self.points.contains_key(*id)) {
+            return invariant(format!("sparse vector kept for missing point {}", id));
+        }
+        if let Some(id) = self.text.ids().find(|id| !self.points.contains_key(*id)) {
+            return invariant(format!("text kept for missing point {}", id));
+        }
+
+        if let Some(projection) = &self.projection {
+            if !projection.is_well_formed() || projection.input_dim() != self.dimensions {
+                return invariant(format!(
+                    "projection expects {} dimensions, index has {}",
+                    projection.input_dim(),
+                    self.dimensions
+                ));
+            }
+        }
+
+        let top = self.nodes.iter().flatten().map(Node::level).max();
+        match self.entry_point {
+            None if !self.points.is_empty() => invariant("missing entry point".to_string()),
+            Some(node) if self.get_node(node).is_none() => {
+                invariant(format!("entry point node {} is not in the index", node))
+            }
+            Some(_) if Some(self.get_entry_level()) != top => invariant(format!(
+                "entry point level {} below top layer {}",
+                self.get_entry_level(),
+                top.unwrap_or(0)
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Graph access for [`GraphBuilder`] implementations
+///
+/// Points are addressed by id at the API surface and by a dense `u32`
+/// node inside the graph, so traversal never hashes or clones strings.
+/// A node stays with its point until the point is removed, after which
+/// the number may be reused.
+impl<S: Scalar, V: VectorStore<S>> Hnsw<S, V> {
+    /// Stored (projected) vector of a point
+    pub fn vector(&self, id: &str) -> Option<Cow<'_, [S]>> {
+        self.node(id).and_then(|node| self.node_vector(node))
+    }
+
+    /// Distance from a stored-space vector to a point
+    pub fn distance(&self, query: &[S], id: &str) -> Option<f32> {
+        self.node(id)
+            .and_then(|node| self.node_distance(query, node))
+    }
+
+    /// Current entry point of the graph
+    pub fn entry_point(&self) -> Option<&Id> {
+        self.entry_point.and_then(|node| self.node_id(node))
+    }
+
+    /// Top layer of a point
+    pub fn level(&self, id: &str) -> Option<usize> {
+        self.node(id).and_then(|node| self.node_level(node))
+    }
+
+    /// Outgoing links of `id` on `layer`, by id
+    pub fn neighbors(&self, id: &str, layer: usize) -> Option<Vec<&Id>> {
+        let links = self.node(id).and_then(|node| self.links(node, layer))?;
+        Some(
+            links
+                .iter()
+                .filter_map(|&link| self.node_id(link))
+                .collect(),
+        )
+    }
+
+    /// Replace the links of `id` on `layer` by id, see [`Hnsw::set_links`]
+    pub fn set_neighbors(&mut self, id: &str, layer: usize, links: Vec<String>) {
+        if let Some(node) = self.node(id) {
+            let links = links.iter().filter_map(|link| self.node(link)).collect();
+            self.set_links(node, layer, links);
+        }
+    }
+
+    /// Graph node of a point
+    pub fn node(&self, id: &str) -> Option<u32> {
+        self.points.get(key(&id)).copied()
+    }
+
+    /// The id held by the point `id`, shared with its point data
+    pub(crate) fn interned(&self, id: &str) -> Option<&Id> {
+        self.points.get_key_value(key(&id)).map(|(id, _)| id)
+    }
+
+    /// Id of the point at a graph node
+    pub fn node_id(&self, node: u32) -> Option<&Id> {
+        self.get_node(node).map(|n| &n.id)
+    }
+
+    /// Current entry node of the graph
+    pub fn entry_node(&self) -> Option<u32> {
+        self.entry_point
+    }
+
+    /// Top layer of a node
+    pub fn node_level(&self, node: u32) -> Option<usize> {
+        self.get_node(node).map(Node::level)
+    }
+
+    /// Stored (projected) vector of a node
+    pub fn node_vector(&self, node: u32) -> Option<Cow<'_, [S]>> {
+        self.get_node(node).and_then(|n| self.store.get(n.slot))
+    }
+
+    /// Distance from a stored-space vector to a node
+    pub fn node_distance(&self, query: &[S], node: u32) -> Option<f32> {
+        self.get_node(node).and_then(|n| self.distance_to(query, n))
+    }
+
+    /// Outgoing links of `node` on `layer`
+    pub fn links(&self, node: u32, layer: usize) -> Option<&[u32]> {
+        self.get_node(node)
+            .and_then(|n| n.layer(layer))
+            .map(|links| &links[..])
+    }
+
+    /// Replace the links of `node` on `layer`
+    ///
+    /// Ignored when `node` is not on that layer; links to nodes outside the
+    /// layer and self-links are dropped so the graph stays consistent.
+    pub fn set_links(&mut self, node: u32, layer: usize, mut links: Vec<u32>) {
+        if self.links(node, layer).is_none() {
+            return;
+        }
+        links.retain(|&link| {
+            link != node && self.node_level(link).is_some_and(|level| level >= layer)
+        });
+        if let Some(list) = self.get_node_mut(node).and_then(|n| n.layer_mut(layer)) {
+            list.set(&links);
+        }
+        self.query_cache.invalidate();
+    }
+
+    /// Best-first search of one layer from the given entry nodes
+    ///
+    /// `query` must already be in stored space (projected). Returns up to
+    /// `ef` `(node, distance)` pairs sorted nearest first.
+    pub fn search_layer(
+        &self,
+        query: &[S],
+        entry_points: &[u32],
+        ef: usize,
+        layer: usize,
+    ) -> Vec<(u32, f32)> {
+        let mut scratch = self.scratch.take();
+        scratch.entries.extend_from_slice(entry_points);
+        self.walk_layer(
+            &mut scratch,
+            query,
+            ef,
+            layer,
+            &|_| true,
+            &Meter::unlimited(),
+        );
+        let found = scratch.results.clone();
+        self.scratch.give(scratch);
+        found
+    }
+
+    /// Base-layer walk from `scratch.entries` that only scores nodes
+    /// accepted by `accept`, leaving up to `ef` of them in `scratch.results`
+    ///
+    /// A rejected neighbor is not scored; its own neighbors are considered
+    /// instead, widening each expansion to two hops up to twice the usual
+    /// link budget. This keeps the walk inside the matching subgraph even
+    /// when the filter is selective. Entry points seed the walk whether or
+    /// not they match.
+    fn walk_acorn(
+        &self,
+        scratch: &mut Scratch<S>,
+        query: &[S],
+        ef: usize,
+        accept: &dyn Fn(u32) -> bool,
+        meter: &Meter,
+    ) {
+        let budget = 2 * max_links(self, 0);
+        self.seed_walk(scratch, query, ef, accept, meter);
+        let Scratch {
+            visited,
+            candidates,
+            best,
+            expansion,
+            block,
+            distances,
+            coded,
+            ..
+        } = scratch;
+
+        // The nearest seed is always expanded, so a spent budget still
+        // leaves its neighborhood to choose from
+        let mut expanded = false;
+        while let Some(Reverse(Scored(current_dist, current))) = candidates.pop() {
+            if best.len() >= ef && best.peek().is_some_and(|far| current_dist > far.0)
+                || expanded && meter.exhausted()
+            {
+                break;
+            }
+            expanded = true;
+
+            expansion.clear();
+            for &neighbor in self.links(current, 0).unwrap_or(&[]) {
+                if expansion.len() >= budget {
+                    break;
+                }
+                if accept(neighbor) {
+                    if visited.insert(neighbor) {
+                        expansion.push(neighbor);
+                    }
+                    continue;
+                }
+                for &hop in self.links(neighbor, 0).unwrap_or(&[]) {
+                    if expansion.len() >= budget {
+                        break;
+                    }
+                    if accept(hop) && visited.insert(hop) {
+                        expansion.push(hop);
+                    }
+                }
+            }
+
+            self.score_block(query, coded, expansion, block, distances, meter);
+            for (&neighbor, &dist) in expansion.iter().zip(distances.iter()) {
+                if best.len() < ef || best.peek().is_some_and(|far| dist < far.0) {
+                    candidates.push(Reverse(Scored(dist, neighbor)));
+                    best.push(Scored(dist, neighbor));
+                    if best.len() > ef {
+                        best.pop();
+                    }
+                }
+            }
+        }
+        scratch.finish_walk();
+    }
+
+    /// Best-first walk of one layer from `scratch.entries`, leaving up to
+    /// `ef` nodes accepted by `accept` in `scratch.results` nearest first
+    ///
+    /// Rejected nodes are still expanded so the walk can cross regions of
+    /// the graph the predicate excludes.
+    fn walk_layer(
+        &self,
+        scratch: &mut Scratch<S>,
+        query: &[S],
+        ef: usize,
+        layer: usize,
+        accept: &dyn Fn(u32) -> bool,
+        meter: &Meter,
+    ) {
+        self.seed_walk(scratch, query, ef, accept, meter);
+        let Scratch {
+            visited,
+            candidates,
+            best,
+            expansion,
+            block,
+            distances,
+            coded,
+            ..
+        } = scratch;
+
+        // The nearest seed is always expanded, so a spent budget still
+        // leaves its neighborhood to choose from
+        let mut expanded = false;
+        while let Some(Reverse(Scored(current_dist, current))) = candidates.pop() {
+            if best.len() >= ef && best.peek().is_some_and(|far| current_dist > far.0)
+                || expanded && meter.exhausted()
+            {
+                break;
+            }
+            expanded = true;
+
+            let links = match self.links(current, layer) {
+                Some(links) => links,
+                None => continue,
+            };
+            expansion.clear();
+            expansion.extend(links.iter().filter(|&&neighbor| visited.insert(neighbor)));
+
+            self.score_block(query, coded, expansion, block, distances, meter);
+            for (&neighbor, &dist) in expansion.iter().zip(distances.iter()) {
+                if best.len() < ef || best.peek().is_some_and(|far| dist < far.0) {
+                    candidates.push(Reverse(Scored(dist, neighbor)));
+                    if accept(neighbor) {
+                        best.push(Scored(dist, neighbor));
+                        if best.len() > ef {
+                            best.pop();
+                        }
+                    }
+                }
+            }
+        }
+        scratch.finish_walk();
+    }
+
+    /// [`Hnsw::walk_layer`] on the base layer with a beam that starts at
+    /// `k` and doubles up to `cap`, stopping once [`SETTLED_ROUNDS`]
+    /// doublings in a row leave the top `k` unchanged
+    ///
+    /// Each wider round resumes the walk instead of starting over: nodes
+    /// the narrower beam scored but set aside rejoin the frontier and the
+    /// best set, so no node is scored twice.
+    fn walk_widening(
+        &self,
+        scratch: &mut Scratch<S>,
+        query: &[S],
+        k: usize,
+        cap: usize,
+        accept: &dyn Fn(u32) -> bool,
+        meter: &Meter,
+    ) {
+        let cap = cap.max(k).max(1);
+        let mut ef = k.clamp(1, cap);
+        self.seed_walk(scratch, query, ef, accept, meter);
+        let Scratch {
+            visited,
+            candidates,
+            best,
+            expansion,
+            block,
+            distances,
+            coded,
+            deferred,
+            evicted,
+            ..
+        } = scratch;
+        deferred.clear();
+        evicted.clear();
+
+        let mut settled: Vec<u32> = Vec::new();
+        let mut unchanged = 0;
+        let mut expanded = false;
+        loop {
+            if expanded && meter.exhausted() {
+                break;
+            }
+            let next = candidates.pop();
+            let round_over = match &next {
+                Some(Reverse(Scored(dist, _))) => {
+                    best.len() >= ef && best.peek().is_some_and(|far| *dist > far.0)
+                }
+                None => true,
+            };
+            if round_over {
+                candidates.extend(next);
+                let mut top: Vec<&Scored> = best.iter().collect();
+                top.sort_unstable();
+                let mut top: Vec<u32> = top.iter().take(k).map(|scored| scored.1).collect();
+                top.sort_unstable();
+                if top == settled {
+                    unchanged += 1;
+                } else {
+                    unchanged = 0;
+                    settled = top;
+                }
+                if ef >= cap
+                    || unchanged >= SETTLED_ROUNDS
+                    || candidates.is_empty() && deferred.is_empty()
+                {
+                    break;
+                }
+                ef = ef.saturating_mul(2).min(cap);
+                for Scored(dist, node) in deferred.drain(..) {
+                    candidates.push(Reverse(Scored(dist, node)));
+                    if accept(node) {
+                        best.push(Scored(dist, node));
+                    }
+                }
+                best.extend(evicted.drain(..));
+                while best.len() > ef {
+                    evicted.extend(best.pop());
+                }
+                continue;
+            }
+            let Some(Reverse(Scored(_, current))) = next else {
+                break;
+            };
+            expanded = true;
+
+            let links = match self.links(current, 0) {
+                Some(links) => links,
+                None => continue,
+            };
+            expansion.clear();
+            expansion.extend(links.iter().filter(|&&neighbor| visited.insert(neighbor)));
+
+            self.score_block(query, coded, expansion, block, distances, meter);
+            for (&neighbor, &dist) in expansion.iter().zip(distances.iter()) {
+                if best.len() < ef || best.peek().is_some_and(|far| dist < far.0) {
+                    candidates.push(Reverse(Scored(dist, neighbor)));
+                    if accept(neighbor) {
+                        best.push(Scored(dist, neighbor));
+                        if best.len() > ef {
+                            evicted.extend(best.pop());
+                        }
+                    }
+                } else {
+                    deferred.push(Scored(dist, neighbor));
+                }
+            }
+        }
+        scratch.finish_walk();
+    }
+
+    /// Start a walk from `scratch.entries`: every entry joins the frontier,
+    /// and the nearest `ef` (at least one) accepted ones seed the best set
+    fn seed_walk(
+        &self,
+        scratch: &mut Scratch<S>,
+        query: &[S],
+        ef: usize,
+        accept: &dyn Fn(u32) -> bool,
+        meter: &Meter,
+    ) {
+        let Scratch {
+            visited,
+            entries,
+            candidates,
+            best,
+            coded,
+            ..
+        } = scratch;
+        visited.start(self.nodes.len());
+        candidates.clear();
+        best.clear();
+        for &entry in entries.iter() {
+            if !visited.insert(entry) {
+                continue;
+            }
+            meter.visit();
+            if let Some(dist) = self.seed_distance(query, coded, entry) {
+                candidates.push(Reverse(Scored(dist, entry)));
+                if accept(entry) {
+                    best.push(Scored(dist, entry));
+                }
+            }
+        }
+        while best.len() > ef.max(1) {
+            best.pop();
+        }
+    }
+
+    /// Distance to a walk's entry node, from codes if the query is coded
+    fn seed_distance(&self, query: &[S], coded: &[i8], node: u32) -> Option<f32> {
+        if coded.is_empty() {
+            return self.node_distance(query, node);
+        }
+        let slot = self.get_node(node)?.slot;
+        if !self.codes.has(slot) {
+            return None;
+        }
+        let mut distance = Vec::with_capacity(1);
+        self.codes
+            .distances(coded, std::iter::once(slot), &mut distance);
+        distance.pop()
+    }
+
+    fn get_node(&self, node: u32) -> Option<&Node> {
+        self.nodes.get(node as usize).and_then(Option::as_ref)
+    }
+
+    fn get_node_mut(&mut self, node: u32) -> Option<&mut Node> {
+        self.nodes.get_mut(node as usize).and_then(Option::as_mut)
+    }
+}
+
+impl<S: Scalar, V: VectorStore<S> + Serialize> Hnsw<S, V> {
+    /// Serialize the index to bytes in the compact binary format
+    pub fn to_bytes(&self) -> Result<Vec<u8>, HnswError> {
+        snapshot::encode(self, S::NAME, self.dimensions, 0)
+    }
+
+    /// Serialize the index like [`Hnsw::to_bytes`], then deflate it
+    ///
+    /// The output is a standard zlib stream, so it can also be inflated
+    /// outside this crate before decoding.
+    pub fn to_compressed_bytes(&self) -> Result<Vec<u8>, HnswError> {
+        snapshot::encode(self, S::NAME, self.dimensions, snapshot::FLAG_COMPRESSED)
+    }
+
+    /// Serialize like [`Hnsw::to_bytes`], handing the bytes to `sink` in
+    /// pieces of at most `chunk_size`
+    ///
+    /// This does not stream: the snapshot is encoded into one buffer first,
+    /// so peak memory is that of `to_bytes`. It only spares the caller a
+    /// second copy of the whole snapshot, e.g. on the JS side of wasm.
+    pub fn write_chunks(
+        &self,
+        chunk_size: usize,
+        mut sink: impl FnMut(&[u8]) -> Result<(), HnswError>,
+    ) -> Result<(), HnswError> {
+        if chunk_size == 0 {
+            return Err(HnswError::InvalidParams(
+                "chunk size must be at least 1".to_string(),
+            ));
+        }
+        for chunk in self.to_bytes()?.chunks(chunk_size) {
+            sink(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+impl<S: Scalar, V: VectorStore<S> + DeserializeOwned> Hnsw<S, V> {
+    /// Deserialize an index from bytes produced by [`Hnsw::to_bytes`] or
+    /// [`Hnsw::to_compressed_bytes`]
+    ///
+    /// Older format versions are migrated, and JSON snapshots from before
+    /// the binary format are still accepted.
+    ///
+    /// The decoded graph is validated before it is returned, so hostile or
+    /// damaged input fails with [`HnswError::CorruptSnapshot`] instead of
+    /// yielding an index that misbehaves later.
+    pub fn deserialize(data: &[u8]) -> Result<Hnsw<S, V>, HnswError> {
+        let mut index: Hnsw<S, V> =
+            snapshot::decode(data, S::NAME, Kind::Full, |index: &Hnsw<S, V>| {
+                index.dimensions
+            })?;
+        index.check_loaded()?;
+        Ok(index)
+    }
+}
+
+impl<S: Scalar, V: VectorStore<S>> Hnsw<S, V> {
+    /// Find or create a slot holding `vector` and take a reference to it
+    ///
+    /// Identical vectors are stored once: the content hash narrows the
+    /// search and an exact comparison confirms the match.
+    fn acquire_slot(&mut self, vector: Vec<S>) -> Result<u32, HnswError> {
+        let hash = content_hash(&vector);
+        let existing = self.content_slots.get(&hash).and_then(|slots| {
+            slots
+                .iter()
+                .copied()
+                .find(|&slot| self.store.get(slot).is_some_and(|v| *v == vector[..]))
+        });
+
+        let slot = match existing {
+            Some(slot) => slot,
+            None => {
+                let slot = self.allocate_slot();
+                if self.params.quantize {
+                    self.codes.set(slot, &vector);
+                }
+                if let Err(e) = self.store.put(slot, vector) {
+                    self.free_slots.push(slot);
+                    return Err(e);
+                }
+                self.content_slots.entry(hash).or_default().push(slot);
+                slot
+            }
+        };
+
+        *self.slot_refs.entry(slot).or_default() += 1;
+        Ok(slot)
+    }
+
+    /// Drop one reference to `slot`, freeing it when no point uses it
+    fn release_slot(&mut self, slot: u32) {
+        let remaining = match self.slot_refs.get_mut(&slot) {
+            Some(refs) => {
+                *refs -= 1;
+                *refs
+            }
+            None => return,
+        };
+        if remaining > 0 {
+            return;
+        }
+
+        self.slot_refs.remove(&slot);
+        if let Some(hash) = self.store.get(slot).map(|v| content_hash(&v)) {
+            if let Some(slots) = self.content_slots.get_mut(&hash) {
+                slots.retain(|&s| s != slot);
+                if slots.is_empty() {
+                    self.content_slots.remove(&hash);
+                }
+            }
+        }
+        self.store.remove(slot);
+        self.free_slots.push(slot);
+    }
+
+    /// Take a free slot, or grow the slot range by one
+    fn allocate_slot(&mut self) -> u32 {
+        self.free_slots.pop().unwrap_or_else(|| {
+            self.next_slot += 1;
+            self.next_slot - 1
+        })
+    }
+
+    /// Distance from `query` to a stored point, if its vector is available
+    fn distance_to(&self, query: &[S], point: &Node) -> Option<f32> {
+        self.store
+            .get(point.slot)
+            .map(|vector| S::cosine_distance(query, &vector))
+    }
+
+    /// Draw the projection matrix once the input dimension is known
+    fn init_projection(&mut self, input_dim: usize) -> Result<(), HnswError> {
+        let output_dim = self.params.project_to;
+        if output_dim == 0 {
+            return Ok(());
+        }
+        if !S::IS_FLOAT {
+            return Err(HnswError::InvalidParams(format!(
+                "random projection is not supported for {} vectors",
+                S::NAME
+            )));
+        }
+        if output_dim >= input_dim {
+            return Err(HnswError::InvalidParams(format!(
+                "project_to {} must be smaller than the vector dimensions {}",
+                output_dim, input_dim
+            )));
+        }
+
+        self.projection = Some(RandomProjection::new(
+            self.params.projection,
+            input_dim,
+            output_dim,
+            self.rng.get_mut(),
+        ));
+        Ok(())
+    }
+
+    /// Apply the index projection, if any, to an input vector
+    fn project(&self, vector: Vec<S>) -> Vec<S> {
+        match &self.projection {
+            Some(projection) => projection.project(&vector),
+            None => vector,
+        }
+    }
+
+    /// Generate random level for new point
+    pub(crate) fn random_level(&self) -> usize {
+        self.rng.level(self.params.m)
+    }
+
+    /// Get entry point level
+    fn get_entry_level(&self) -> usize {
+        self.entry_point
+            .and_then(|node| self.node_level(node))
+            .unwrap_or(0)
+    }
+
+    /// Node on the highest layer, ties broken by smallest id
+    fn highest_point(&self, exclude: Option<u32>) -> Option<u32> {
+        self.points
+            .values()
+            .filter(|&&node| Some(node) != exclude)
+            .filter_map(|&node| Some((node, self.get_node(node)?)))
+            .max_by(|(_, a), (_, b)| a.level().cmp(&b.level()).then_with(|| b.id.cmp(&a.id)))
+            .map(|(node, _)| node)
+    }
+
+    /// Score the neighbors gathered for one expansion in a single pass
+    ///
+    /// Their vectors are copied back to back into `block` and handed to
+    /// [`Scalar::cosine_distances`] together, so the kernel streams one
+    /// contiguous buffer and computes the query's norm once. With a coded
+    /// query the nodes' codes are scored instead. Nodes without a vector
+    /// are dropped from `nodes`; `distances` ends up parallel to what
+    /// remains.
+    fn score_block(
+        &self,
+        query: &[S],
+        coded: &[i8],
+        nodes: &mut Vec<u32>,
+        block: &mut Vec<S>,
+        distances: &mut Vec<f32>,
+        meter: &Meter,
+    ) {
+        block.clear();
+        distances.clear();
+        if !coded.is_empty() {
+            nodes.retain(|&node| {
+                meter.visit();
+                self.get_node(node)
+                    .is_some_and(|point| self.codes.has(point.slot))
+            });
+            let slots = nodes.iter().filter_map(|&node| self.get_node(node));
+            self.codes
+                .distances(coded, slots.map(|point| point.slot), distances);
+            return;
+        }
+        nodes.retain(|&node| {
+            meter.visit();
+            match self.node_vector(node) {
+                Some(vector) if vector.len() == query.len() => {
+                    block.extend_from_slice(&vector);
+                    true
+                }
+                _ => false,
+            }
+        });
+        S::cosine_distances(query, block, distances);
+    }
+
+    /// Greedily walk down from the entry point to `target_layer`, leaving
+    /// the seeds for that layer in `scratch.entries`
+    fn descend(&self, scratch: &mut Scratch<S>, query: &[S], target_layer: usize, meter: &Meter) {
+        scratch.entries.clear();
+        let entry = match self.entry_point {
+            Some(entry) => entry,
+            None => return,
+        };
+
+        scratch.entries.push(entry);
+        for layer in (target_layer + 1..=self.get_entry_level()).rev() {
+            self.walk_layer(scratch, query, 1, layer, &|_| true, meter);
+            if let Some(&(closest, _)) = scratch.results.first() {
+                scratch.entries.clear();
+                scratch.entries.push(closest);
+            }
+        }
+    }
+}
+
+/// Content hash of a vector's little-endian bytes
+fn content_hash<S: Scalar>(vector: &[S]) -> u64 {
+    let mut bytes = Vec::with_capacity(vector.len() * S::BYTES);
+    for &value in vector {
+        value.write_le(&mut bytes);
+    }
+    fnv1a(&bytes)
+}
+
+/// Dimensions of each of `num_queries` queries packed into `len` values
+pub(crate) fn batch_dim(len: usize, num_queries: usize) -> Result<usize, HnswError> {
+    if num_queries == 0 {
+        return Ok(0);
+    }
+    let dim = len / num_queries;
+    if dim * num_queries != len {
+        return Err(HnswError::InvalidParams(format!(
+            "{} values do not split into {} queries",
+            len, num_queries
+        )));
+    }
+    Ok(dim)
+}
@@ -0,0 +1,397 @@
+//! Reference repository indexer
+//!
+//! Ties the pieces a code-search app needs into one small, working
+//! pipeline: chunk files by lines, embed chunks through an
+//! [`EmbeddingProvider`], keep them in an [`Hnsw`] collection with a
+//! per-file manifest, reconcile against the current file set, and
+//! persist everything as one snapshot. It is deliberately simple and
+//! meant to be read as a blueprint as much as used.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::hash::fnv1a;
+use crate::scalar::Scalar;
+use crate::snapshot::{self, Kind};
+use crate::{HNSWParams, Hnsw, HnswError};
+
+/// Turns chunk text into vectors
+pub trait EmbeddingProvider {
+    /// Embed a batch of texts, returning one vector per text
+    fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, HnswError>;
+}
+
+/// Training-free embedder hashing identifier tokens into buckets
+///
+/// Splits camelCase and snake_case identifiers, hashes each lowercased
+/// token into one of `dimensions` signed buckets, and L2-normalizes. Good
+/// enough for examples and integration checks; not a semantic model.
+#[derive(Clone, Copy, Debug)]
+pub struct HashingEmbedder {
+    dimensions: usize,
+}
+
+impl HashingEmbedder {
+    pub fn new(dimensions: usize) -> HashingEmbedder {
+        HashingEmbedder {
+            dimensions: dimensions.max(1),
+        }
+    }
+}
+
+impl EmbeddingProvider for HashingEmbedder {
+    fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, HnswError> {
+        Ok(texts
+            .iter()
+            .map(|text| {
+                let mut vector = vec![0.0f32; self.dimensions];
+                for token in tokenize(text) {
+                    let hash = fnv1a(token.as_bytes());
+                    let bucket = (hash % self.dimensions as u64) as usize;
+                    vector[bucket] += if hash >> 63 == 0 { 1.0 } else { -1.0 };
+                }
+                let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+                if norm > 0.0 {
+                    vector.iter_mut().for_each(|x| *x /= norm);
+                }
+                vector
+            })
+            .collect())
+    }
+}
+
+/// Split text into lowercased identifier parts
+///
+/// `parseHttpRequest` and `parse_http_request` both yield
+/// `parse`, `http`, `request`.
+pub fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    for word in text.split(|c: char| !c.is_alphanumeric()) {
+        let mut current = String::new();
+        let mut prev_lower = false;
+        for c in word.chars() {
+            if c.is_uppercase() && prev_lower && !current.is_empty() {
+                tokens.push(std::mem::take(&mut current));
+            }
+            prev_lower = c.is_lowercase() || c.is_ascii_digit();
+            current.extend(c.to_lowercase());
+        }
+        if !current.is_empty() {
+            tokens.push(current);
+        }
+    }
+    tokens
+}
+
+/// Fixed-size line windows with overlap
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct LineChunker {
+    pub max_lines: usize,
+    pub overlap: usize,
+}
+
+impl Default for LineChunker {
+    fn default() -> Self {
+        LineChunker {
+            max_lines: 40,
+            overlap: 5,
+        }
+    }
+}
+
+/// A chunk of a file, with 1-based inclusive line numbers
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Chunk {
+    pub id: String,
+    pub path: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    #[serde(skip)]
+    pub text: String,
+}
+
+impl LineChunker {
+    /// Split `content` into chunks with ids `path#start-end`
+    pub fn chunk(&self, path: &str, content: &str) -> Vec<Chunk> {
+        let lines: Vec<&str> = content.lines().collect();
+        let step = self.max_lines.max(1).saturating_sub(self.overlap).max(1);
+        let mut chunks = Vec::new();
+
+        let mut start = 0;
+        while start < lines.len() {
+            let end = (start + self.max_lines.max(1)).min(lines.len());
+            let text = lines[start..end].join("\n");
+            if !text.trim().is_empty() {
+                chunks.push(Chunk {
+                    id: format!("{}#{}-{}", path, start + 1, end),
+                    path: path.to_string(),
+                    start_line: start + 1,
+                    end_line: end,
+                    text,
+                });
+            }
+            if end == lines.len() {
+                break;
+            }
+            start += step;
+        }
+        chunks
+    }
+}
+
+/// What happened to a file during indexing
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileChange {
+    Added,
+    Updated,
+    Unchanged,
+}
+
+/// Counts from a [`RepoIndexer::reconcile`] pass
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReconcileReport {
+    pub added: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub removed: usize,
+}
+
+/// A search hit resolved back to its file location
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CodeHit {
+    pub id: String,
+    pub path: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    pub score: f32,
+}
+
+/// Manifest entry for one indexed file
+#[derive(Clone, Serialize, Deserialize)]
+struct FileEntry {
+    content_hash: u64,
+    chunks: Vec<Chunk>,
+}
+
+/// Persisted state of a [`RepoIndexer`]
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    chunker: LineChunker,
+    files: BTreeMap<String, FileEntry>,
+    index: Hnsw<f32>,
+}
+
+/// Chunk, embed, index, reconcile, and persist a set of source files
+pub struct RepoIndexer<E: EmbeddingProvider> {
+    embedder: E,
+    chunker: LineChunker,
+    files: BTreeMap<String, FileEntry>,
+    chunk_paths: HashMap<String, String>,
+    index: Hnsw<f32>,
+}
+
+impl<E: EmbeddingProvider> RepoIndexer<E> {
+    pub fn new(embedder: E, params: HNSWParams, chunker: LineChunker) -> RepoIndexer<E> {
+        RepoIndexer {
+            embedder,
+            chunker,
+            files: BTreeMap::new(),
+            chunk_paths: HashMap::new(),
+            index: Hnsw::with_params(params),
+        }
+    }
+
+    /// The underlying vector collection
+    pub fn index(&self) -> &Hnsw<f32> {
+        &self.index
+    }
+
+    /// Number of indexed files
+    pub fn file_count(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Index or re-index a single file, skipping it when unchanged
+    pub fn index_file(&mut self, path: &str, content: &str) -> Result<FileChange, HnswError> {
+        let content_hash = fnv1a(content.as_bytes());
+        let change = match self.files.get(path) {
+            Some(entry) if entry.content_hash == content_hash => return Ok(FileChange::Unchanged),
+            Some(_) => FileChange::Updated,
+            None => FileChange::Added,
+        };
+
+        let chunks = self.chunker.chunk(path, content);
+        let texts: Vec<String> = chunks.iter().map(|c| c.text.clone()).collect();
+        let vectors = self.embedder.embed(&texts)?;
+        if vectors.len() != chunks.len() {
+            return Err(HnswError::Embedding(format!(
+                "provider returned {} vectors for {} chunks",
+                vectors.len(),
+                chunks.len()
+            )));
+        }
+
+        // Only touch the index once embedding succeeded
+        self.remove_file(path);
+        for (chunk, vector) in chunks.iter().zip(vectors) {
+            self.index.insert(chunk.id.clone(), vector)?;
+            self.chunk_paths.insert(chunk.id.clone(), path.to_string());
+        }
+        // Chunk text is only needed for embedding; keep the manifest small
+        let chunks = chunks
+            .into_iter()
+            .map(|chunk| Chunk {
+                text: String::new(),
+                ..chunk
+            })
+            .collect();
+        self.files.insert(
+            path.to_string(),
+            FileEntry {
+                content_hash,
+                chunks,
+            },
+        );
+
+        Ok(change)
+    }
+
+    /// Drop a file and all of its chunks
+    pub fn remove_file(&mut self, path: &str) -> bool {
+        match self.files.remove(path) {
+            Some(entry) => {
+                for chunk in &entry.chunks {
+                    self.chunk_paths.remove(&chunk.id);
+                }
+                self.index.remove_many(entry.chunks.iter().map(|c| &c.id));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Bring the index in line with `files` (path, content)
+    ///
+    /// New and changed files are (re-)indexed, unchanged files are left
+    /// alone, and indexed files missing from `files` are removed.
+    pub fn reconcile<I>(&mut self, files: I) -> Result<ReconcileReport, HnswError>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let mut report = ReconcileReport::default();
+        let mut seen = HashSet::new();
+
+        for (path, content) in files {
+            match self.index_file(&path, &content)? {
+                FileChange::Added => report.added += 1,
+                FileChange::Updated => report.updated += 1,
+                FileChange::Unchanged => report.unchanged += 1,
+            }
+            seen.insert(path);
+        }
+
+        let stale: Vec<String> = self
+            .files
+            .keys()
+            .filter(|path| !seen.contains(*path))
+            .cloned()
+            .collect();
+        for path in stale {
+            self.remove_file(&path);
+            report.removed += 1;
+        }
+
+        Ok(report)
+    }
+
+    /// Embed `query` and return the `k` best chunks
+    pub fn search(&self, query: &str, k: usize) -> Result<Vec<CodeHit>, HnswError> {
+        let vector = match self.embedder.embed(&[query.to_string()])?.pop() {
+            Some(vector) => vector,
+            None => return Ok(Vec::new()),
+        };
+
+        Ok(self
+            .index
+            .nearest(&vector, k)?
+            .into_iter()
+            .filter_map(|(id, score)| {
+                let path = self.chunk_paths.get(&id)?;
+                let chunk = self.files.get(path)?.chunks.iter().find(|c| c.id == id)?;
+                Some(CodeHit {
+                    id,
+                    path: path.clone(),
+                    start_line: chunk.start_line,
+                    end_line: chunk.end_line,
+                    score,
+                })
+            })
+            .collect())
+    }
+
+    /// Serialize the manifest and index into one snapshot
+    pub fn save(&self) -> Result<Vec<u8>, HnswError> {
+        let snapshot = SnapshotRef {
+            chunker: &self.chunker,
+            files: &self.files,
+            index: &self.index,
+        };
+        snapshot::encode(&snapshot, f32::NAME, self.index.dimensions(), 0)
+    }
+
+    /// Like [`RepoIndexer::save`], deflated
+    pub fn save_compressed(&self) -> Result<Vec<u8>, HnswError> {
+        let snapshot = SnapshotRef {
+            chunker: &self.chunker,
+            files: &self.files,
+            index: &self.index,
+        };
+        snapshot::encode(
+            &snapshot,
+            f32::NAME,
+            self.index.dimensions(),
+            snapshot::FLAG_COMPRESSED,
+        )
+    }
+
+    /// Restore an indexer saved with [`RepoIndexer::save`] or
+    /// [`RepoIndexer::save_compressed`]
+    pub fn load(embedder: E, data: &[u8]) -> Result<RepoIndexer<E>, HnswError> {
+        let mut snapshot: Snapshot =
+            snapshot::decode(data, f32::NAME, Kind::Full, |s: &Snapshot| {
+                s.index.dimensions()
+            })?;
+        snapshot.index.check_loaded()?;
+
+        let chunk_paths = snapshot
+            .files
+            .iter()
+            .flat_map(|(path, entry)| {
+                entry
+                    .chunks
+                    .iter()
+                    .map(move |c| (c.id.clone(), path.clone()))
+            })
+            .collect();
+
+        Ok(RepoIndexer {
+            embedder,
+            chunker: snapshot.chunker,
+            files: snapshot.files,
+            chunk_paths,
+            index: snapshot.index,
+        })
+    }
+}
+
+/// Borrowing twin of [`Snapshot`] so saving does not clone the index
+#[derive(Serialize)]
+struct SnapshotRef<'a> {
+    chunker: &'a LineChunker,
+    files: &'a BTreeMap<String, FileEntry>,
+    index: &'a Hnsw<f32>,
+}
@@ -0,0 +1,70 @@
+//! JSON Lines dump for inspecting index contents
+//!
+//! One object per point in id order, with keys in a fixed order and
+//! payload keys sorted, so dumps of two snapshots diff line by line.
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::index::Hnsw;
+use crate::scalar::Scalar;
+use crate::store::VectorStore;
+use crate::HnswError;
+
+#[derive(Serialize)]
+struct PointRecord<'a> {
+    id: &'a str,
+    level: usize,
+    /// Link count on each layer from 0 up to `level`
+    neighbors: Vec<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    payload: Option<&'a Value>,
+    /// Stored-space vector
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vector: Option<Vec<f32>>,
+}
+
+impl<S: Scalar, V: VectorStore<S>> Hnsw<S, V> {
+    /// Pass one JSON line per point, newline included, to `sink`
+    pub fn write_jsonl<F>(&self, include_vectors: bool, mut sink: F) -> Result<(), HnswError>
+    where
+        F: FnMut(&str) -> Result<(), HnswError>,
+    {
+        for id in self.ids() {
+            let id = &id.to_string();
+            let level = self.level(id).unwrap_or(0);
+            let vector = if include_vectors {
+                let vector = self
+                    .vector(id)
+                    .ok_or_else(|| HnswError::Storage(format!("vector of {} is missing", id)))?;
+                Some(vector.iter().map(|x| x.to_f64() as f32).collect())
+            } else {
+                None
+            };
+            let record = PointRecord {
+                id,
+                level,
+                neighbors: (0..=level)
+                    .map(|layer| self.neighbors(id, layer).map_or(0, |links| links.len()))
+                    .collect(),
+                payload: self.payload(id),
+                vector,
+            };
+            let mut line = serde_json::to_string(&record)
+                .map_err(|e| HnswError::Serialization(e.to_string()))?;
+            line.push('\n');
+            sink(&line)?;
+        }
+        Ok(())
+    }
+
+    /// The whole JSON Lines dump as one string
+    pub fn to_jsonl(&self, include_vectors: bool) -> Result<String, HnswError> {
+        let mut out = String::new();
+        self.write_jsonl(include_vectors, |line| {
+            out.push_str(line);
+            Ok(())
+        })?;
+        Ok(out)
+    }
+}
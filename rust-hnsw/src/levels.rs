@@ -0,0 +1,46 @@
+//! Random source of an index
+//!
+//! Point levels and the projection matrix are drawn from a generator the
+//! index owns. It is seeded from the OS by default; [`Hnsw::seed`] fixes
+//! the seed so a build, such as a failing soak run, can be replayed. The
+//! generator sits behind a lock because parallel builds draw levels from
+//! several threads.
+//!
+//! [`Hnsw::seed`]: crate::Hnsw::seed
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::sync::{Mutex, PoisonError};
+
+pub(crate) struct IndexRng {
+    rng: Mutex<StdRng>,
+}
+
+impl Default for IndexRng {
+    fn default() -> Self {
+        IndexRng {
+            rng: Mutex::new(StdRng::from_entropy()),
+        }
+    }
+}
+
+impl IndexRng {
+    /// Restart the sequence from `seed`
+    pub fn seed(&mut self, seed: u64) {
+        *self.get_mut() = StdRng::seed_from_u64(seed);
+    }
+
+    /// Level of a new point in a graph with `m` links per node
+    pub fn level(&self, m: usize) -> usize {
+        let mut rng = self.rng.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut level = 0;
+        while rng.gen::<f32>() < 1.0 / m as f32 && level < 32 {
+            level += 1;
+        }
+        level
+    }
+
+    pub fn get_mut(&mut self) -> &mut StdRng {
+        self.rng.get_mut().unwrap_or_else(PoisonError::into_inner)
+    }
+}
@@ -1,7 +1,123 @@
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
 use wasm_bindgen::prelude::*;
 
+mod annoy;
+mod arrow;
+mod async_batch;
+mod autosave;
+mod beam;
+mod budget;
+pub mod builder;
+mod bulk;
+mod cache;
+mod codec;
+mod decay;
+mod deflate;
+mod delta;
+mod error;
+mod faiss;
+#[cfg(feature = "web")]
+mod fetch;
+mod filter;
+mod formula;
+mod fusion;
+#[cfg(feature = "webgpu")]
+mod gpu;
+mod hash;
+#[cfg(all(feature = "hdf5", not(target_arch = "wasm32")))]
+mod hdf5;
+mod hnswlib;
+#[cfg(feature = "web")]
+mod idb;
+mod ids;
+mod import;
+mod index;
+pub mod indexer;
+mod jsonl;
+mod levels;
+mod links;
+#[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+mod mapped;
+mod msgpack;
+mod npy;
+mod optimize;
+#[cfg(any(
+    all(feature = "parallel", not(target_arch = "wasm32")),
+    all(feature = "threads", target_arch = "wasm32")
+))]
+mod parallel;
+mod parquet;
+mod partial;
+mod payload_index;
+mod planner;
+mod progressive;
+mod projection;
+mod protobuf;
+mod quantize;
+mod rebuild;
+mod recommend;
+mod rerank;
+mod scalar;
+mod schema;
+mod scratch;
+mod search;
+mod snapshot;
+mod sparse;
+#[cfg(all(feature = "sqlite", not(target_arch = "wasm32")))]
+mod sqlite;
+pub mod store;
+mod text;
+#[cfg(all(feature = "threads", target_arch = "wasm32"))]
+mod threads;
+pub mod transform;
+mod turns;
+mod usearch;
+mod wal;
+
+pub use cache::CacheStats;
+pub use decay::{Decay, DecayFunction};
+pub use error::HnswError;
+pub use filter::{Condition, Filter, Range};
+pub use formula::ScoreFormula;
+pub use fusion::{Fusion, HybridFusion, Normalization};
+#[cfg(feature = "webgpu")]
+pub use gpu::GpuScorer;
+#[cfg(all(feature = "hdf5", not(target_arch = "wasm32")))]
+pub use hdf5::{AnnDataset, Evaluation};
+#[cfg(feature = "web")]
+pub use idb::IndexedDbStore;
+pub use ids::Id;
+pub use index::{FacetCount, Hnsw, IdPage};
+#[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+pub use mapped::MappedIndex;
+pub use optimize::GraphReport;
+pub use payload_index::PayloadIndexKind;
+pub use planner::{FilterStrategy, QueryPlan};
+pub use progressive::ProgressiveSearch;
+pub use projection::ProjectionKind;
+pub use rebuild::GraphRebuild;
+pub use recommend::{Example, RecommendStrategy};
+pub use rerank::{RerankCandidate, Reranker};
+pub use scalar::Scalar;
+pub use schema::{FieldType, PayloadSchema};
+pub use search::{best_first, PayloadSelector, SearchGroup, SearchOptions, SearchPage};
+pub use snapshot::{snapshot_info, SnapshotInfo, FORMAT_VERSION};
+pub use sparse::SparseVector;
+#[cfg(all(feature = "sqlite", not(target_arch = "wasm32")))]
+pub use sqlite::SqliteStore;
+pub use text::tokenize;
+
+use async_batch::AsyncBatch;
+use autosave::AutoSave;
+use indexer::{EmbeddingProvider, FileChange, LineChunker, RepoIndexer};
+use store::VectorStore;
+#[cfg(feature = "web")]
+use store::{CachedStore, OpfsStore, TieredStore};
+use transform::LinearTransform;
+use turns::Turns;
+
 /// HNSW parameters
 #[wasm_bindgen]
 #[derive(Clone, Copy, Serialize, Deserialize)]
@@ -9,6 +125,36 @@ pub struct HNSWParams {
     pub m: usize,
     pub ef_construction: usize,
     pub ef_search: usize,
+    /// Randomly project vectors down to this many dimensions (0 = off)
+    #[serde(default)]
+    pub project_to: usize,
+    /// Distribution of the projection matrix when `project_to` is set
+    #[serde(default)]
+    pub projection: ProjectionKind,
+    /// What `add` does when the id is already in the index
+    #[serde(default)]
+    pub on_duplicate: DuplicatePolicy,
+    /// Keep 8-bit codes of the vectors and walk the graph with them,
+    /// rescoring the candidates kept with the full vectors
+    ///
+    /// Codes are rebuilt from the stored vectors when loading, so a lazily
+    /// loaded index reads every vector once.
+    #[serde(default)]
+    pub quantize: bool,
+}
+
+/// How `add` treats an id that is already in the index
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicatePolicy {
+    /// Replace the existing vector, re-linking the point
+    #[default]
+    Overwrite,
+    /// Keep the existing vector and drop the new one
+    Ignore,
+    /// Reject the insert with an error
+    Error,
 }
 
 impl Default for HNSWParams {
@@ -17,286 +163,1898 @@ impl Default for HNSWParams {
             m: 16,
             ef_construction: 200,
             ef_search: 64,
+            project_to: 0,
+            projection: ProjectionKind::Gaussian,
+            on_duplicate: DuplicatePolicy::Overwrite,
+            quantize: false,
         }
     }
 }
 
-/// A single point in the HNSW graph
-#[derive(Clone, Serialize, Deserialize)]
-struct Point {
-    id: String,
-    vector: Vec<f32>,
-    level: usize,
+/// Parse constructor params, falling back to defaults when omitted
+fn parse_params(params: JsValue) -> Result<HNSWParams, HnswError> {
+    if params.is_undefined() {
+        Ok(HNSWParams::default())
+    } else {
+        serde_wasm_bindgen::from_value(params).map_err(|e| HnswError::InvalidParams(e.to_string()))
+    }
 }
 
-/// Layer in the HNSW graph
-#[derive(Clone, Serialize, Deserialize)]
-struct Layer {
-    links: HashMap<String, Vec<String>>,
+/// Apply the fields set in `update` over `current`
+fn merge_params(current: &HNSWParams, update: JsValue) -> Result<HNSWParams, HnswError> {
+    let invalid = |e: serde_json::Error| HnswError::InvalidParams(e.to_string());
+    let update: serde_json::Map<String, serde_json::Value> = serde_wasm_bindgen::from_value(update)
+        .map_err(|e| HnswError::InvalidParams(e.to_string()))?;
+    let mut merged = serde_json::to_value(current).map_err(invalid)?;
+    if let serde_json::Value::Object(fields) = &mut merged {
+        fields.extend(update);
+    }
+    serde_json::from_value(merged).map_err(invalid)
 }
 
-/// HNSW Vector Index
-#[wasm_bindgen]
-#[derive(Serialize, Deserialize)]
-pub struct HNSWIndex {
-    params: HNSWParams,
-    points: HashMap<String, Point>,
-    layers: Vec<Layer>,
-    entry_point: Option<String>,
-    dimensions: usize,
+/// Parse the optional `schema` entry of constructor params
+fn parse_schema(params: &JsValue) -> Result<Option<PayloadSchema>, HnswError> {
+    if !params.is_object() {
+        return Ok(None);
+    }
+    let schema = js_sys::Reflect::get(params, &JsValue::from_str("schema"))
+        .map_err(|e| HnswError::InvalidParams(format!("{:?}", e)))?;
+    if schema.is_undefined() || schema.is_null() {
+        return Ok(None);
+    }
+    let value = serde_wasm_bindgen::from_value(schema)
+        .map_err(|e| HnswError::InvalidParams(e.to_string()))?;
+    PayloadSchema::parse(value).map(Some)
 }
 
-#[wasm_bindgen]
-impl HNSWIndex {
-    /// Create a new HNSW index
-    #[wasm_bindgen(constructor)]
-    pub fn new(params: JsValue) -> Result<HNSWIndex, JsValue> {
-        let params: HNSWParams = if params.is_undefined() {
-            HNSWParams::default()
-        } else {
-            serde_wasm_bindgen::from_value(params)
-                .map_err(|e| JsValue::from_str(&format!("Invalid params: {}", e)))?
-        };
+/// Parse a payload filter object
+fn parse_filter(filter: JsValue) -> Result<Filter, HnswError> {
+    let value = serde_wasm_bindgen::from_value(filter)
+        .map_err(|e| HnswError::InvalidFilter(e.to_string()))?;
+    Filter::parse(value)
+}
 
-        Ok(HNSWIndex {
-            params,
-            points: HashMap::new(),
-            layers: Vec::new(),
-            entry_point: None,
-            dimensions: 0,
-        })
+/// Parse search options, treating `undefined` and `null` as defaults
+fn parse_search_options(options: JsValue) -> Result<SearchOptions, HnswError> {
+    if options.is_undefined() || options.is_null() {
+        return Ok(SearchOptions::default());
+    }
+    let value = serde_wasm_bindgen::from_value(options)
+        .map_err(|e| HnswError::InvalidParams(e.to_string()))?;
+    SearchOptions::parse(value)
+}
+
+/// Parse search options carrying extra entries `keys`, such as the
+/// `strategy` of `recommend`, returned separately
+fn parse_options_with(
+    options: JsValue,
+    keys: &[&str],
+) -> Result<(serde_json::Map<String, serde_json::Value>, SearchOptions), HnswError> {
+    let mut extras = serde_json::Map::new();
+    if options.is_undefined() || options.is_null() {
+        return Ok((extras, SearchOptions::default()));
     }
+    let mut value: serde_json::Value = serde_wasm_bindgen::from_value(options)
+        .map_err(|e| HnswError::InvalidParams(e.to_string()))?;
+    if let Some(map) = value.as_object_mut() {
+        for key in keys {
+            if let Some(extra) = map.remove(*key).filter(|v| !v.is_null()) {
+                extras.insert(key.to_string(), extra);
+            }
+        }
+    }
+    Ok((extras, SearchOptions::parse(value)?))
+}
+
+/// A string entry of [`parse_options_with`] extras
+fn extra_str<'a>(
+    extras: &'a serde_json::Map<String, serde_json::Value>,
+    key: &str,
+) -> Result<Option<&'a str>, HnswError> {
+    match extras.get(key) {
+        None => Ok(None),
+        Some(serde_json::Value::String(s)) => Ok(Some(s)),
+        Some(other) => Err(HnswError::InvalidParams(format!(
+            "{} must be a string, got {}",
+            key, other
+        ))),
+    }
+}
 
-    /// Add a vector to the index
-    pub fn add(&mut self, id: String, vector: Vec<f32>) -> Result<(), JsValue> {
-        if self.dimensions == 0 {
-            self.dimensions = vector.len();
-        } else if vector.len() != self.dimensions {
-            return Err(JsValue::from_str(&format!(
-                "Vector dimension mismatch: expected {}, got {}",
-                self.dimensions,
-                vector.len()
-            )));
+/// Search options plus the `fusion`, `denseWeight` and `normalization` of
+/// hybrid searches
+fn parse_hybrid_options(options: JsValue) -> Result<(HybridFusion, SearchOptions), HnswError> {
+    let (extras, options) =
+        parse_options_with(options, &["fusion", "denseWeight", "normalization"])?;
+    let dense_weight = match extras.get("denseWeight") {
+        None => None,
+        Some(w) => Some(w.as_f64().ok_or_else(|| {
+            HnswError::InvalidParams(format!("denseWeight must be a number, got {}", w))
+        })?),
+    };
+    let normalization = extra_str(&extras, "normalization")?
+        .map(Normalization::parse)
+        .transpose()?;
+    // Weighted-only settings alone ask for weighted fusion
+    let fusion = match extra_str(&extras, "fusion")? {
+        Some(fusion) => HybridFusion::parse(fusion, dense_weight, normalization)?,
+        None if dense_weight.is_some() || normalization.is_some() => {
+            HybridFusion::parse("weighted", dense_weight, normalization)?
         }
+        None => HybridFusion::default(),
+    };
+    Ok((fusion, options))
+}
 
-        let level = self.random_level();
-        let point = Point {
-            id: id.clone(),
-            vector,
-            level,
-        };
+/// Parse an array of example ids and vectors, treating `undefined` as empty
+fn parse_examples<S: Scalar>(examples: JsValue) -> Result<Vec<Example<S>>, HnswError> {
+    if examples.is_undefined() {
+        return Ok(Vec::new());
+    }
+    let value = serde_wasm_bindgen::from_value(examples)
+        .map_err(|e| HnswError::InvalidParams(e.to_string()))?;
+    Example::parse_list(value)
+}
 
-        self.points.insert(id.clone(), point);
+/// Parse an optional filter, treating `undefined` and `null` as none
+fn parse_optional_filter(filter: JsValue) -> Result<Option<Filter>, HnswError> {
+    if filter.is_undefined() || filter.is_null() {
+        Ok(None)
+    } else {
+        parse_filter(filter).map(Some)
+    }
+}
 
-        // Ensure enough layers exist
-        while self.layers.len() <= level {
-            self.layers.push(Layer {
-                links: HashMap::new(),
-            });
-        }
+/// Convert a value into plain JS objects and arrays, as JSON would
+fn json_to_js<T: Serialize + ?Sized>(value: &T) -> Result<JsValue, JsValue> {
+    let serializer = serde_wasm_bindgen::Serializer::json_compatible();
+    Ok(value.serialize(&serializer)?)
+}
 
-        // Insert into layers
-        for layer_idx in 0..=level {
-            let layer = &mut self.layers[layer_idx];
-            layer.links.entry(id.clone()).or_insert_with(Vec::new);
-        }
+/// Parse optional metadata, treating `undefined` and `null` as none
+fn parse_payload(metadata: JsValue) -> Result<Option<serde_json::Value>, HnswError> {
+    if metadata.is_undefined() || metadata.is_null() {
+        return Ok(None);
+    }
+    serde_wasm_bindgen::from_value(metadata)
+        .map(Some)
+        .map_err(|e| HnswError::InvalidParams(e.to_string()))
+}
 
-        // Update entry point
-        if self.entry_point.is_none() || level > self.get_entry_level() {
-            self.entry_point = Some(id);
+/// Convert `(id, score)` pairs into a JS array of `{ id, score, metadata?, vector? }`
+/// objects, attaching what `options` asks for
+fn results_to_js<S: Scalar, V: VectorStore<S>>(
+    index: &Hnsw<S, V>,
+    results: Vec<(String, f32)>,
+    options: &SearchOptions,
+) -> Result<JsValue, JsValue> {
+    let results_js = js_sys::Array::new();
+    for (id, score) in results {
+        let obj = js_sys::Object::new();
+        js_sys::Reflect::set(&obj, &JsValue::from_str("id"), &JsValue::from_str(&id))?;
+        js_sys::Reflect::set(
+            &obj,
+            &JsValue::from_str("score"),
+            &JsValue::from_f64(score as f64),
+        )?;
+        if let Some(payload) = index
+            .payload(&id)
+            .and_then(|p| options.with_payload.select(p))
+        {
+            js_sys::Reflect::set(&obj, &JsValue::from_str("metadata"), &json_to_js(&payload)?)?;
+        }
+        if options.with_vector {
+            if let Some(vector) = index.vector(&id) {
+                js_sys::Reflect::set(&obj, &JsValue::from_str("vector"), &json_to_js(&*vector)?)?;
+            }
         }
+        results_js.push(&obj);
+    }
+
+    Ok(results_js.into())
+}
+
+/// Order the `results_to_js` objects of `hits` by the reranker's `scores`,
+/// keeping the best `k`
+fn finish_rerank(
+    hits: Vec<(String, f32)>,
+    objects: js_sys::Array,
+    scores: JsValue,
+    k: usize,
+    options: &SearchOptions,
+) -> Result<JsValue, JsValue> {
+    let scores: Vec<f32> = serde_wasm_bindgen::from_value(scores).map_err(|e| {
+        HnswError::InvalidParams(format!("reranker must return an array of numbers: {}", e))
+    })?;
+    order_by_scores(hits, objects, scores, k, options)
+}
+
+/// Order the `results_to_js` objects of `hits` by new `scores`, one per
+/// hit, keeping the best `k`
+fn order_by_scores(
+    hits: Vec<(String, f32)>,
+    objects: js_sys::Array,
+    scores: Vec<f32>,
+    k: usize,
+    options: &SearchOptions,
+) -> Result<JsValue, JsValue> {
+    let positions: HashMap<String, u32> = hits
+        .iter()
+        .enumerate()
+        .map(|(i, (id, _))| (id.clone(), i as u32))
+        .collect();
+    let reranked = js_sys::Array::new();
+    for (id, score) in rerank::apply_rerank(hits, scores, k, options)? {
+        let obj = objects.get(positions[&id]);
+        js_sys::Reflect::set(
+            &obj,
+            &JsValue::from_str("score"),
+            &JsValue::from_f64(score as f64),
+        )?;
+        reranked.push(&obj);
+    }
+    Ok(reranked.into())
+}
+
+/// Normalize a list of scores with "minMax", "zScore" or "softmax"
+///
+/// Puts results from different searches, e.g. cosine and BM25, on one
+/// scale before fusing them or applying a threshold in JS.
+#[wasm_bindgen(js_name = normalizeScores)]
+pub fn normalize_scores(mut scores: Vec<f32>, method: &str) -> Result<Vec<f32>, JsValue> {
+    Normalization::parse(method)?.apply(&mut scores);
+    Ok(scores)
+}
 
-        Ok(())
+/// Read a snapshot's header without loading it
+///
+/// Returns `{ version, scalar, metric, dimensions, compressed }`, or
+/// `null` for snapshots saved before the header existed.
+#[wasm_bindgen(js_name = snapshotInfo)]
+pub fn snapshot_info_js(data: &[u8]) -> Result<JsValue, JsValue> {
+    json_to_js(&snapshot_info(data)?)
+}
+
+/// Load a snapshot of either kind, rebuilding the graph of vectors-only ones
+fn load_index<S: Scalar>(
+    data: &[u8],
+    progress: impl FnMut(usize, usize),
+) -> Result<Hnsw<S>, HnswError> {
+    match snapshot_info(data)? {
+        Some(info) if info.vectors_only => Hnsw::rebuild_from_bytes(data, progress),
+        _ => Hnsw::deserialize(data),
     }
+}
+
+/// Generate a wasm index class wrapping `Hnsw<$scalar>`
+///
+/// Vector arguments of type `Vec<$scalar>` map to the matching typed array
+/// in JS (`Float32Array`, `Uint8Array`, ...).
+macro_rules! wasm_index {
+    ($(#[$doc:meta])* $name:ident, $scalar:ty) => {
+        $(#[$doc])*
+        #[wasm_bindgen]
+        pub struct $name {
+            inner: Hnsw<$scalar>,
+            /// Snapshot bytes received by `loadChunk`, loaded by `finishLoad`
+            pending_load: Vec<u8>,
+            /// Timer armed by `enableAutoSave`
+            auto_save: Option<AutoSave>,
+            /// Batch started by `addBatchAsync`
+            async_batch: Option<AsyncBatch>,
+            /// Searches started by `searchProgressive`, until they finish
+            searches: Vec<Turns>,
+        }
 
-    /// Search for nearest neighbors
-    pub fn search(&self, vector: Vec<f32>, k: usize) -> Result<JsValue, JsValue> {
-        if vector.len() != self.dimensions {
-            return Err(JsValue::from_str("Vector dimension mismatch"));
+        impl $name {
+            /// Wrap an index with nothing pending or running
+            fn wrap(inner: Hnsw<$scalar>) -> $name {
+                $name {
+                    inner,
+                    pending_load: Vec::new(),
+                    auto_save: None,
+                    async_batch: None,
+                    searches: Vec::new(),
+                }
+            }
+
+            /// Fail while an `addBatchAsync` batch or a `searchProgressive`
+            /// search still runs on the index between turns
+            ///
+            /// Those hold a pointer to `inner` and would carry on against
+            /// whatever replaced it.
+            fn check_idle(&mut self, action: &str) -> Result<(), JsValue> {
+                self.searches.retain(Turns::is_running);
+                let running = if self.async_batch.as_ref().is_some_and(AsyncBatch::is_running) {
+                    "a batch is being added"
+                } else if !self.searches.is_empty() {
+                    "a progressive search is running"
+                } else {
+                    return Ok(());
+                };
+                Err(JsValue::from_str(&format!("cannot {} while {}", action, running)))
+            }
+
+            /// Swap in a loaded index, which auto-save counts as saved
+            fn replace(&mut self, inner: Hnsw<$scalar>) {
+                self.inner = inner;
+                if let Some(auto_save) = &self.auto_save {
+                    auto_save.rebase(self.inner.generation());
+                }
+            }
         }
 
-        let ef = self.params.ef_search.max(k);
-        let candidates = self.search_layer(&vector, ef, 0);
+        #[wasm_bindgen]
+        impl $name {
+            /// Create a new HNSW index
+            ///
+            /// `params.schema` optionally declares metadata field types as
+            /// `{ field: "keyword" | "number" | "bool" | "text" }`.
+            #[wasm_bindgen(constructor)]
+            pub fn new(params: JsValue) -> Result<$name, JsValue> {
+                let schema = parse_schema(&params)?;
+                let mut inner = Hnsw::with_params(parse_params(params)?);
+                if let Some(schema) = schema {
+                    inner.set_schema(schema)?;
+                }
+                Ok($name::wrap(inner))
+            }
+
+            /// Build an index over many vectors packed back to back, `dim`
+            /// values each
+            ///
+            /// Prefer it to `addBatch` on an empty index: levels are drawn
+            /// up front, the tallest points linked first, and the graph is
+            /// laid out for search once built. `params` are as for the
+            /// constructor.
+            #[wasm_bindgen(js_name = fromVectors)]
+            pub fn from_vectors(
+                ids: Vec<String>,
+                vectors: &[$scalar],
+                dim: usize,
+                params: JsValue,
+            ) -> Result<$name, JsValue> {
+                let schema = parse_schema(&params)?;
+                let mut inner = Hnsw::from_vectors(ids, vectors, dim, parse_params(params)?)?;
+                if let Some(schema) = schema {
+                    inner.set_schema(schema)?;
+                }
+                Ok($name::wrap(inner))
+            }
+
+            /// Add a vector to the index, optionally with a JSON metadata object
+            ///
+            /// An id already indexed is handled by `on_duplicate`. Overwriting
+            /// keeps its metadata unless new metadata is given, and keeps its
+            /// sparse vector and text.
+            pub fn add(
+                &mut self,
+                id: String,
+                vector: Vec<$scalar>,
+                metadata: JsValue,
+            ) -> Result<(), JsValue> {
+                Ok(self
+                    .inner
+                    .insert_with_payload(id, vector, parse_payload(metadata)?)?)
+            }
+
+            /// Add many vectors packed back to back, `dim` values each
+            #[wasm_bindgen(js_name = addBatch)]
+            pub fn add_batch(
+                &mut self,
+                ids: Vec<String>,
+                vectors: &[$scalar],
+                dim: usize,
+            ) -> Result<(), JsValue> {
+                Ok(self.inner.insert_batch(ids, vectors, dim)?)
+            }
 
-        // Get top k results
-        let mut results: Vec<(String, f32)> = candidates
-            .into_iter()
-            .filter_map(|(id, dist)| {
-                self.points.get(&id).map(|_| (id, 1.0 - dist)) // Convert to similarity
-            })
-            .collect();
+            /// `addBatch` spread over the `initThreadPool` workers
+            ///
+            /// Blocks until they finish, so call it from a worker.
+            #[cfg(all(feature = "threads", target_arch = "wasm32"))]
+            #[wasm_bindgen(js_name = addBatchParallel)]
+            pub fn add_batch_parallel(
+                &mut self,
+                ids: Vec<String>,
+                vectors: &[$scalar],
+                dim: usize,
+            ) -> Result<(), JsValue> {
+                Ok(self.inner.insert_batch_parallel(ids, vectors, dim, 0)?)
+            }
 
-        results.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
-        results.truncate(k);
+            /// `addBatch` in slices that yield to the event loop between
+            /// them, resolving to the number of vectors added
+            ///
+            /// Keeps the page responsive during a long build.
+            /// `progress(done, total)` is called after each slice. Other
+            /// calls may run between slices and see the vectors added so
+            /// far, except `load` and `clear`, which throw; freeing the
+            /// index rejects the promise. Only one batch runs at a time.
+            #[wasm_bindgen(js_name = addBatchAsync)]
+            pub fn add_batch_async(
+                &mut self,
+                ids: Vec<String>,
+                vectors: &[$scalar],
+                dim: usize,
+                progress: Option<js_sys::Function>,
+            ) -> Result<js_sys::Promise, JsValue> {
+                if self.async_batch.as_ref().is_some_and(AsyncBatch::is_running) {
+                    return Err(JsValue::from_str("a batch is already being added"));
+                }
+                self.inner.check_batch(ids.len(), vectors, dim)?;
+                self.inner.reserve(ids.len());
+                // SAFETY: `inner` lives in the boxed class instance next to
+                // the batch, which is cancelled when the instance is freed
+                let (batch, promise) = unsafe {
+                    AsyncBatch::start(&mut self.inner, ids, vectors.to_vec(), dim, progress)?
+                };
+                self.async_batch = Some(batch);
+                Ok(promise)
+            }
 
-        // Convert to JavaScript array
-        let results_js: Vec<JsValue> = results
-            .into_iter()
-            .map(|(id, score)| {
+            /// Add every row of a float `.npy` matrix or `.npz` archive,
+            /// returning how many were added
+            ///
+            /// Rows are named by `ids`, else by the archive's `ids` array,
+            /// else by row number.
+            #[wasm_bindgen(js_name = importNpy)]
+            pub fn import_npy(
+                &mut self,
+                bytes: &[u8],
+                ids: Option<Vec<String>>,
+            ) -> Result<usize, JsValue> {
+                Ok(self.inner.import_npy(bytes, ids)?)
+            }
+
+            /// Insert or replace a vector regardless of the duplicate policy
+            ///
+            /// Returns true when an existing vector was replaced. `metadata`,
+            /// if given, replaces the previous metadata; otherwise the point
+            /// keeps it, along with its sparse vector and text.
+            pub fn upsert(
+                &mut self,
+                id: String,
+                vector: Vec<$scalar>,
+                metadata: JsValue,
+            ) -> Result<bool, JsValue> {
+                Ok(self
+                    .inner
+                    .upsert_with_payload(id, vector, parse_payload(metadata)?)?)
+            }
+
+            /// Replace the vector of an existing id and re-link it in the graph
+            ///
+            /// Returns false when the id is not in the index.
+            #[wasm_bindgen(js_name = updateVector)]
+            pub fn update_vector(&mut self, id: &str, vector: Vec<$scalar>) -> Result<bool, JsValue> {
+                Ok(self.inner.update_vector(id, vector)?)
+            }
+
+            /// Stored vector of an id, or undefined when it is not indexed
+            ///
+            /// With `project_to` set this is the projected vector, since the
+            /// original is not kept.
+            #[wasm_bindgen(js_name = getVector)]
+            pub fn get_vector(&self, id: &str) -> Option<Vec<$scalar>> {
+                self.inner.vector(id).map(|v| v.into_owned())
+            }
+
+            /// Rewrite every query vector before search, e.g. to adapt a
+            /// query embedding model to the document one
+            ///
+            /// `transform` is `{ matrix?, bias?, normalize? }` computing
+            /// `normalize(matrix * query + bias)`, with `matrix` an array of
+            /// rows; `undefined` or `null` removes it. The transform is not
+            /// saved with the index.
+            #[wasm_bindgen(js_name = setQueryTransform)]
+            pub fn set_query_transform(&mut self, transform: JsValue) -> Result<(), JsValue> {
+                if transform.is_undefined() || transform.is_null() {
+                    self.inner.set_query_transform(None);
+                    return Ok(());
+                }
+                let transform: LinearTransform = serde_wasm_bindgen::from_value(transform)
+                    .map_err(|e| HnswError::InvalidParams(e.to_string()))?;
+                transform.validate()?;
+                self.inner.set_query_transform(Some(Arc::new(transform)));
+                Ok(())
+            }
+
+            /// Cache the results of up to `capacity` recent searches, or
+            /// none with 0
+            ///
+            /// Repeated `search` calls with the same query, `k` and options
+            /// skip the graph, e.g. while an editor re-runs a query on each
+            /// keystroke. Any write empties the cache. The setting is not
+            /// saved with the index.
+            #[wasm_bindgen(js_name = setQueryCache)]
+            pub fn set_query_cache(&mut self, capacity: usize) {
+                self.inner.set_query_cache(capacity);
+            }
+
+            /// Current parameters as `{ m, ef_construction, ef_search,
+            /// project_to, projection, on_duplicate }`
+            #[wasm_bindgen(js_name = getParams)]
+            pub fn get_params(&self) -> Result<JsValue, JsValue> {
+                json_to_js(self.inner.params())
+            }
+
+            /// Change parameters, keeping any not named in `params`
+            ///
+            /// `ef_search`, `ef_construction` and `on_duplicate` can change
+            /// at any time. `m`, `project_to` and `projection` can only
+            /// change before the first `add` or after `clear`; otherwise,
+            /// or for an `m` below 2 or a zero `ef`, this throws and
+            /// nothing changes.
+            #[wasm_bindgen(js_name = setParams)]
+            pub fn set_params(&mut self, params: JsValue) -> Result<(), JsValue> {
+                let params = merge_params(self.inner.params(), params)?;
+                self.inner.set_params(params)?;
+                Ok(())
+            }
+
+            /// Query cache counters as `{ hits, misses, entries }`
+            #[wasm_bindgen(js_name = queryCacheStats)]
+            pub fn query_cache_stats(&self) -> Result<JsValue, JsValue> {
+                json_to_js(&self.inner.query_cache_stats())
+            }
+
+            /// Attach a sparse term-weight vector to an indexed point
+            ///
+            /// `indices` are term ids and `values` their weights, e.g. from
+            /// BM25 or SPLADE. Empty arrays detach it. Returns false if `id`
+            /// is not indexed.
+            #[wasm_bindgen(js_name = setSparseVector)]
+            pub fn set_sparse_vector(
+                &mut self,
+                id: &str,
+                indices: Vec<u32>,
+                values: Vec<f32>,
+            ) -> Result<bool, JsValue> {
+                let vector = SparseVector::new(indices, values)?;
+                Ok(self.inner.set_sparse_vector(id, vector))
+            }
+
+            /// Sparse vector of a point as `{ indices, values }`, if any
+            #[wasm_bindgen(js_name = getSparseVector)]
+            pub fn get_sparse_vector(&self, id: &str) -> Result<JsValue, JsValue> {
+                match self.inner.sparse_vector(id) {
+                    Some(vector) => json_to_js(vector),
+                    None => Ok(JsValue::UNDEFINED),
+                }
+            }
+
+            /// Whether an id is in the index
+            pub fn contains(&self, id: &str) -> bool {
+                self.inner.contains(id)
+            }
+
+            /// Number of ids in the index
+            pub fn count(&self) -> usize {
+                self.inner.len()
+            }
+
+            /// Page through ids in ascending order
+            ///
+            /// Returns `{ ids, cursor }`; pass `cursor` back for the next
+            /// page. It is undefined once the last page has been returned.
+            #[wasm_bindgen(js_name = listIds)]
+            pub fn list_ids(&self, cursor: Option<String>, limit: usize) -> Result<JsValue, JsValue> {
+                let page = self.inner.list_ids(cursor.as_deref(), limit);
+                Ok(serde_wasm_bindgen::to_value(&page)?)
+            }
+
+            /// Page through ids whose metadata matches `filter`, in ascending order
+            ///
+            /// Returns `{ points, cursor }` with `points` shaped like `{ id,
+            /// metadata }`; pass `cursor` back for the next page. It is
+            /// undefined once the last page has been returned.
+            pub fn scroll(
+                &self,
+                filter: JsValue,
+                cursor: Option<String>,
+                limit: usize,
+            ) -> Result<JsValue, JsValue> {
+                let filter = parse_filter(filter)?;
+                let page = self.inner.scroll(&filter, cursor.as_deref(), limit)?;
+                let points = js_sys::Array::new();
+                for id in &page.ids {
+                    let obj = js_sys::Object::new();
+                    js_sys::Reflect::set(&obj, &JsValue::from_str("id"), &JsValue::from_str(id))?;
+                    if let Some(payload) = self.inner.payload(id) {
+                        js_sys::Reflect::set(&obj, &JsValue::from_str("metadata"), &json_to_js(payload)?)?;
+                    }
+                    points.push(&obj);
+                }
+                let obj = js_sys::Object::new();
+                js_sys::Reflect::set(&obj, &JsValue::from_str("points"), &points)?;
+                if let Some(cursor) = page.cursor {
+                    js_sys::Reflect::set(&obj, &JsValue::from_str("cursor"), &JsValue::from_str(&cursor))?;
+                }
+                Ok(obj.into())
+            }
+
+            /// Search for nearest neighbors
+            ///
+            /// `options` takes the fields of `SearchOptions` in camelCase,
+            /// e.g. `{ filter, minScore, exclude, withPayload, prefetch }`.
+            /// Hits are `{ id, score, metadata }` objects, best first. When
+            /// `maxVisits` or `timeoutMs` cut the search short, the array
+            /// gets `truncated: true`.
+            pub fn search(
+                &self,
+                vector: Vec<$scalar>,
+                k: usize,
+                options: JsValue,
+            ) -> Result<JsValue, JsValue> {
+                let options = parse_search_options(options)?;
+                let (results, truncated) = self.inner.search_within_budget(&vector, k, &options)?;
+                let results = results_to_js(&self.inner, results, &options)?;
+                if truncated {
+                    js_sys::Reflect::set(&results, &JsValue::from_str("truncated"), &JsValue::TRUE)?;
+                }
+                Ok(results)
+            }
+
+            /// `search` in rounds of widening beam, one per turn of the
+            /// event loop, resolving to the same results as `search`
+            ///
+            /// `onUpdate(results)` is called whenever a round changes the
+            /// top `k`, so early hits can be shown while the search refines
+            /// them. Other calls may run between rounds, except `load` and
+            /// `clear`, which throw; freeing the index rejects the promise.
+            /// `maxVisits` and `timeoutMs` bound each round on its own.
+            #[wasm_bindgen(js_name = searchProgressive)]
+            pub fn search_progressive(
+                &mut self,
+                vector: Vec<$scalar>,
+                k: usize,
+                on_update: js_sys::Function,
+                options: JsValue,
+            ) -> Result<js_sys::Promise, JsValue> {
+                let options = parse_search_options(options)?;
+                let search = self.inner.search_progressive(&vector, k, &options)?;
+                // SAFETY: `inner` lives in the boxed class instance next to
+                // the search, which is cancelled when the instance is freed
+                let (turns, promise) =
+                    unsafe { progressive::run(&self.inner, search, on_update, results_to_js)? };
+                self.searches.retain(Turns::is_running);
+                self.searches.push(turns);
+                Ok(promise)
+            }
+
+            /// Search one page at a time for "more results" beyond a fixed k
+            ///
+            /// Returns `{ results, cursor }`; pass `cursor` back for the next
+            /// `limit` results. It is undefined once results run out. Takes
+            /// the same `options` as `search`, except `diversity`.
+            #[wasm_bindgen(js_name = searchPage)]
+            pub fn search_page(
+                &self,
+                vector: Vec<$scalar>,
+                limit: usize,
+                cursor: Option<String>,
+                options: JsValue,
+            ) -> Result<JsValue, JsValue> {
+                let options = parse_search_options(options)?;
+                let page = self
+                    .inner
+                    .search_page(&vector, limit, cursor.as_deref(), &options)?;
                 let obj = js_sys::Object::new();
-                js_sys::Reflect::set(&obj, &JsValue::from_str("id"), &JsValue::from_str(&id))
-                    .unwrap();
-                js_sys::Reflect::set(&obj, &JsValue::from_str("score"), &JsValue::from_f64(score as f64))
-                    .unwrap();
+                js_sys::Reflect::set(
+                    &obj,
+                    &JsValue::from_str("results"),
+                    &results_to_js(&self.inner, page.results, &options)?,
+                )?;
+                if let Some(cursor) = page.cursor {
+                    js_sys::Reflect::set(&obj, &JsValue::from_str("cursor"), &JsValue::from_str(&cursor))?;
+                }
+                Ok(obj.into())
+            }
+
+            /// Search sparse vectors by dot product with the query terms
+            ///
+            /// Only points with a sparse vector sharing a term can match;
+            /// scores are raw dot products. Takes the same `options` as
+            /// `search`, except `diversity`.
+            #[wasm_bindgen(js_name = searchSparse)]
+            pub fn search_sparse(
+                &self,
+                indices: Vec<u32>,
+                values: Vec<f32>,
+                k: usize,
+                options: JsValue,
+            ) -> Result<JsValue, JsValue> {
+                let options = parse_search_options(options)?;
+                let query = SparseVector::new(indices, values)?;
+                let results = self.inner.search_sparse(&query, k, &options)?;
+                results_to_js(&self.inner, results, &options)
+            }
+
+            /// Search a dense and a sparse query together, merging both rankings
+            ///
+            /// `options` takes the `search` options, except `diversity`,
+            /// plus `fusion`: "rrf" (default) for reciprocal rank fusion, or
+            /// "weighted" to mix normalized scores, giving the dense list
+            /// `denseWeight` (default 0.5) of the total. `normalization` is
+            /// "minMax" (default), "zScore" or "softmax".
+            #[wasm_bindgen(js_name = hybridSearch)]
+            pub fn hybrid_search(
+                &self,
+                dense: Vec<$scalar>,
+                sparse_indices: Vec<u32>,
+                sparse_values: Vec<f32>,
+                k: usize,
+                options: JsValue,
+            ) -> Result<JsValue, JsValue> {
+                let (fusion, options) = parse_hybrid_options(options)?;
+                let sparse = SparseVector::new(sparse_indices, sparse_values)?;
+                let results = self
+                    .inner
+                    .search_hybrid(&dense, &sparse, k, fusion, &options)?;
+                results_to_js(&self.inner, results, &options)
+            }
+
+            /// Index the text of a point for `searchText` and `hybridTextSearch`
+            ///
+            /// Identifiers are split on case and underscores, so `parseFilter`
+            /// matches "parse filter". Only term counts are kept; text
+            /// without terms detaches it. Returns false if `id` is not
+            /// indexed.
+            #[wasm_bindgen(js_name = setText)]
+            pub fn set_text(&mut self, id: &str, text: &str) -> bool {
+                self.inner.set_text(id, text)
+            }
+
+            /// Whether a point has indexed text
+            #[wasm_bindgen(js_name = hasText)]
+            pub fn has_text(&self, id: &str) -> bool {
+                self.inner.has_text(id)
+            }
+
+            /// Search point text with BM25 keyword scoring
+            ///
+            /// Takes the same `options` as `search`, except `diversity`.
+            #[wasm_bindgen(js_name = searchText)]
+            pub fn search_text(
+                &self,
+                query: &str,
+                k: usize,
+                options: JsValue,
+            ) -> Result<JsValue, JsValue> {
+                let options = parse_search_options(options)?;
+                let results = self.inner.search_text(query, k, &options)?;
+                results_to_js(&self.inner, results, &options)
+            }
+
+            /// Search a dense query and BM25 over point text together
+            ///
+            /// Takes the same `options` as `hybridSearch`.
+            #[wasm_bindgen(js_name = hybridTextSearch)]
+            pub fn hybrid_text_search(
+                &self,
+                dense: Vec<$scalar>,
+                text: &str,
+                k: usize,
+                options: JsValue,
+            ) -> Result<JsValue, JsValue> {
+                let (fusion, options) = parse_hybrid_options(options)?;
+                let results = self
+                    .inner
+                    .search_hybrid_text(&dense, text, k, fusion, &options)?;
+                results_to_js(&self.inner, results, &options)
+            }
+
+            /// Search, then rescore the top `candidates` hits with `rerank`
+            ///
+            /// `rerank` is called with the candidates as `{ id, score,
+            /// metadata }` objects, always with their full metadata, and
+            /// returns one score per candidate in the same order, e.g. from
+            /// a cross-encoder given the query text and each chunk. The best
+            /// `k` by those scores are returned, and `minScore` applies to
+            /// them. If `rerank` returns a promise, so does this method.
+            /// Takes the same `options` as `search`, except `diversity`.
+            #[wasm_bindgen(js_name = searchReranked)]
+            pub fn search_reranked(
+                &self,
+                vector: Vec<$scalar>,
+                k: usize,
+                candidates: usize,
+                rerank: &js_sys::Function,
+                options: JsValue,
+            ) -> Result<JsValue, JsValue> {
+                let options = parse_search_options(options)?;
+                let hits = self
+                    .inner
+                    .rerank_candidates(&vector, k, candidates, &options)?;
+                let mut full = options.clone();
+                full.with_payload = PayloadSelector::All;
+                full.with_vector = false;
+                let arg = results_to_js(&self.inner, hits.clone(), &full)?;
+                let objects: js_sys::Array =
+                    results_to_js(&self.inner, hits.clone(), &options)?.into();
+
+                let scores = rerank.call1(&JsValue::NULL, &arg)?;
+                let then = js_sys::Reflect::get(&scores, &JsValue::from_str("then"))
+                    .unwrap_or(JsValue::UNDEFINED);
+                if !then.is_function() {
+                    return finish_rerank(hits, objects, scores, k, &options);
+                }
+                let finish = Closure::once_into_js(move |scores: JsValue| {
+                    finish_rerank(hits, objects, scores, k, &options)
+                });
+                js_sys::Function::from(then).call1(&scores, &finish)
+            }
+
+            /// Search ranked by a scoring `formula` over the similarity and
+            /// metadata, e.g. `score + 0.1 * ln(1 + payload.stars)`
+            ///
+            /// A few times `k` candidates are rescored, and results carry the
+            /// formula scores. `minScore` applies to them. Takes the same
+            /// `options` as `search`, except `diversity`.
+            #[wasm_bindgen(js_name = searchScored)]
+            pub fn search_scored(
+                &self,
+                vector: Vec<$scalar>,
+                k: usize,
+                formula: &str,
+                options: JsValue,
+            ) -> Result<JsValue, JsValue> {
+                let options = parse_search_options(options)?;
+                let formula = ScoreFormula::parse(formula)?;
+                let results = self.inner.search_scored(&vector, k, &formula, &options)?;
+                results_to_js(&self.inner, results, &options)
+            }
+
+            /// Search with the stored vector of `id`, e.g. "more like this chunk"
+            ///
+            /// The point itself is left out of the results. Takes the same
+            /// `options` as `search`; throws if `id` is not indexed.
+            #[wasm_bindgen(js_name = searchById)]
+            pub fn search_by_id(
+                &self,
+                id: &str,
+                k: usize,
+                options: JsValue,
+            ) -> Result<JsValue, JsValue> {
+                let options = parse_search_options(options)?;
+                results_to_js(&self.inner, self.inner.search_by_id(id, k, &options)?, &options)
+            }
+
+            /// Search grouped by metadata `field`, e.g. the best files
+            ///
+            /// Returns up to `groups` objects `{ value, hits }`, ordered by
+            /// their best hit, each with up to `per_group` hits shaped like
+            /// `search` results. Takes the same `options` as `search`.
+            #[wasm_bindgen(js_name = searchGrouped)]
+            pub fn search_grouped(
+                &self,
+                vector: Vec<$scalar>,
+                field: &str,
+                groups: usize,
+                per_group: usize,
+                options: JsValue,
+            ) -> Result<JsValue, JsValue> {
+                let options = parse_search_options(options)?;
+                let grouped = self
+                    .inner
+                    .search_grouped(&vector, field, groups, per_group, &options)?;
+                let groups_js = js_sys::Array::new();
+                for group in grouped {
+                    let obj = js_sys::Object::new();
+                    js_sys::Reflect::set(&obj, &JsValue::from_str("value"), &json_to_js(&group.value)?)?;
+                    js_sys::Reflect::set(
+                        &obj,
+                        &JsValue::from_str("hits"),
+                        &results_to_js(&self.inner, group.hits, &options)?,
+                    )?;
+                    groups_js.push(&obj);
+                }
+                Ok(groups_js.into())
+            }
+
+            /// Search with several queries packed back to back in `queries`,
+            /// one per entry of `weights`
+            ///
+            /// Scores against every query are fused before the top `k` are
+            /// kept. `options` takes the `search` options plus `fusion`:
+            /// "sum" (default) for the weighted mean of similarities, "max"
+            /// for the best weighted similarity.
+            #[wasm_bindgen(js_name = searchFused)]
+            pub fn search_fused(
+                &self,
+                queries: &[$scalar],
+                weights: Vec<f32>,
+                k: usize,
+                options: JsValue,
+            ) -> Result<JsValue, JsValue> {
+                let (extras, options) = parse_options_with(options, &["fusion"])?;
+                let fusion = extra_str(&extras, "fusion")?
+                    .map(Fusion::parse)
+                    .transpose()?
+                    .unwrap_or_default();
+                let dim = queries.len().checked_div(weights.len()).unwrap_or(0);
+                if dim * weights.len() != queries.len() {
+                    return Err(HnswError::InvalidParams(format!(
+                        "{} values do not split into {} queries",
+                        queries.len(),
+                        weights.len()
+                    ))
+                    .into());
+                }
+                let weighted: Vec<(&[$scalar], f32)> = if dim == 0 {
+                    weights.iter().map(|&w| (&[][..], w)).collect()
+                } else {
+                    queries.chunks_exact(dim).zip(weights).collect()
+                };
+                let results = self.inner.search_fused(&weighted, k, fusion, &options)?;
+                results_to_js(&self.inner, results, &options)
+            }
+
+            /// Recommend points like the `positive` examples and unlike the
+            /// `negative` ones
+            ///
+            /// Examples are ids of indexed points or vectors given as arrays;
+            /// example ids are left out of the results. `options` takes the
+            /// `search` options plus `strategy`: "averageVector" (default)
+            /// searches once around the combined examples, "bestScore" ranks
+            /// each hit by its closest example.
+            pub fn recommend(
+                &self,
+                positive: JsValue,
+                negative: JsValue,
+                k: usize,
+                options: JsValue,
+            ) -> Result<JsValue, JsValue> {
+                let (extras, options) = parse_options_with(options, &["strategy"])?;
+                let strategy = extra_str(&extras, "strategy")?
+                    .map(RecommendStrategy::parse)
+                    .transpose()?
+                    .unwrap_or_default();
+                let results = self.inner.recommend(
+                    &parse_examples(positive)?,
+                    &parse_examples(negative)?,
+                    k,
+                    strategy,
+                    &options,
+                )?;
+                results_to_js(&self.inner, results, &options)
+            }
+
+            /// All ids within cosine distance `max_distance` (score >= 1 - max_distance),
+            /// best first, at most `limit` of them
+            #[wasm_bindgen(js_name = searchRadius)]
+            pub fn search_radius(
+                &self,
+                vector: Vec<$scalar>,
+                max_distance: f32,
+                limit: usize,
+            ) -> Result<JsValue, JsValue> {
+                results_to_js(
+                    &self.inner,
+                    self.inner.within_distance(&vector, max_distance, limit)?,
+                    &SearchOptions::default(),
+                )
+            }
+
+            /// `search` that also reports how a filter was applied
+            ///
+            /// Returns `{ results, plan }`. With a filter, `plan` holds the
+            /// chosen `strategy` ("bruteForce", "acorn", or "postFilter"),
+            /// `estimatedMatches`, and whether the estimate came `fromIndex`;
+            /// without one it is null.
+            #[wasm_bindgen(js_name = explainSearch)]
+            pub fn explain_search(
+                &self,
+                vector: Vec<$scalar>,
+                k: usize,
+                options: JsValue,
+            ) -> Result<JsValue, JsValue> {
+                let options = parse_search_options(options)?;
+                let (results, plan) = self.inner.explain(&vector, k, &options)?;
+                let obj = js_sys::Object::new();
+                js_sys::Reflect::set(
+                    &obj,
+                    &JsValue::from_str("results"),
+                    &results_to_js(&self.inner, results, &options)?,
+                )?;
+                js_sys::Reflect::set(&obj, &JsValue::from_str("plan"), &json_to_js(&plan)?)?;
+                Ok(obj.into())
+            }
+
+            /// Search many queries packed back to back; returns one result array per query
+            #[wasm_bindgen(js_name = searchBatch)]
+            pub fn search_batch(
+                &self,
+                queries: &[$scalar],
+                num_queries: usize,
+                k: usize,
+            ) -> Result<JsValue, JsValue> {
+                let batch = js_sys::Array::new();
+                for results in self.inner.nearest_batch(queries, num_queries, k)? {
+                    batch.push(&results_to_js(&self.inner, results, &SearchOptions::default())?);
+                }
+                Ok(batch.into())
+            }
+
+            /// `searchBatch` spread over the `initThreadPool` workers
+            ///
+            /// Blocks until they finish, so call it from a worker.
+            #[cfg(all(feature = "threads", target_arch = "wasm32"))]
+            #[wasm_bindgen(js_name = searchBatchParallel)]
+            pub fn search_batch_parallel(
+                &self,
+                queries: &[$scalar],
+                num_queries: usize,
+                k: usize,
+            ) -> Result<JsValue, JsValue> {
+                let batch = js_sys::Array::new();
+                for results in self
+                    .inner
+                    .nearest_batch_parallel(queries, num_queries, k, 0)?
+                {
+                    batch.push(&results_to_js(&self.inner, results, &SearchOptions::default())?);
+                }
+                Ok(batch.into())
+            }
+
+            /// Delete a vector from the index
+            pub fn delete(&mut self, id: &str) -> Result<(), JsValue> {
+                self.inner.remove(id);
+                Ok(())
+            }
+
+            /// Delete many ids in one pass; returns how many were present
+            #[wasm_bindgen(js_name = deleteMany)]
+            pub fn delete_many(&mut self, ids: Vec<String>) -> usize {
+                self.inner.remove_many(ids)
+            }
+
+            /// Delete every id whose metadata matches `filter`
+            ///
+            /// `filter` maps fields to expected values, e.g. `{ path: "src/old.rs" }`.
+            /// Returns how many ids were deleted.
+            #[wasm_bindgen(js_name = deleteByFilter)]
+            pub fn delete_by_filter(&mut self, filter: JsValue) -> Result<usize, JsValue> {
+                Ok(self.inner.remove_by_filter(&parse_filter(filter)?)?)
+            }
+
+            /// Attach metadata to an existing id; returns false if the id is missing
+            #[wasm_bindgen(js_name = setMetadata)]
+            pub fn set_metadata(&mut self, id: &str, metadata: JsValue) -> Result<bool, JsValue> {
+                let payload = serde_wasm_bindgen::from_value(metadata)
+                    .map_err(|e| HnswError::InvalidParams(e.to_string()))?;
+                Ok(self.inner.set_payload(id, payload)?)
+            }
+
+            /// Metadata of an id, or undefined when none is attached
+            #[wasm_bindgen(js_name = getMetadata)]
+            pub fn get_metadata(&self, id: &str) -> Result<JsValue, JsValue> {
+                match self.inner.payload(id) {
+                    Some(payload) => json_to_js(payload),
+                    None => Ok(JsValue::UNDEFINED),
+                }
+            }
+
+            /// Attach metadata encoded as MessagePack; returns false if the
+            /// id is missing
+            ///
+            /// Cheaper than `setMetadata` for large metadata, which crosses
+            /// as one byte array instead of field by field.
+            #[wasm_bindgen(js_name = setMetadataMsgpack)]
+            pub fn set_metadata_msgpack(
+                &mut self,
+                id: &str,
+                metadata: &[u8],
+            ) -> Result<bool, JsValue> {
+                Ok(self.inner.set_payload_msgpack(id, metadata)?)
+            }
+
+            /// Metadata of an id encoded as MessagePack, or undefined when
+            /// none is attached
+            #[wasm_bindgen(js_name = getMetadataMsgpack)]
+            pub fn get_metadata_msgpack(&self, id: &str) -> Option<Vec<u8>> {
+                self.inner.payload_msgpack(id)
+            }
+
+            /// Index a metadata field for faster filtering
+            ///
+            /// `kind` is `"keyword"` for string and string array fields or
+            /// `"number"` for range conditions. The index is saved with the
+            /// rest of the index.
+            #[wasm_bindgen(js_name = createPayloadIndex)]
+            pub fn create_payload_index(&mut self, field: &str, kind: &str) -> Result<(), JsValue> {
+                Ok(self
+                    .inner
+                    .create_payload_index(field, PayloadIndexKind::parse(kind)?)?)
+            }
+
+            /// Number of ids whose metadata matches `filter`, without a vector search
+            #[wasm_bindgen(js_name = countByFilter)]
+            pub fn count_by_filter(&self, filter: JsValue) -> Result<usize, JsValue> {
+                Ok(self.inner.count_matching(&parse_filter(filter)?)?)
+            }
+
+            /// Count metadata values of `field`, optionally among ids matching `filter`
+            ///
+            /// Returns `[{ value, count }]`, most frequent first.
+            pub fn facet(&self, field: &str, filter: JsValue) -> Result<JsValue, JsValue> {
+                let filter = parse_optional_filter(filter)?;
+                json_to_js(&self.inner.facet(field, filter.as_ref())?)
+            }
+
+            /// Save the index to bytes
+            pub fn save(&self) -> Result<Vec<u8>, JsValue> {
+                Ok(self.inner.to_bytes()?)
+            }
+
+            /// Save the index to zlib-compressed bytes
+            ///
+            /// `load` detects and inflates these transparently.
+            #[wasm_bindgen(js_name = saveCompressed)]
+            pub fn save_compressed(&self) -> Result<Vec<u8>, JsValue> {
+                Ok(self.inner.to_compressed_bytes()?)
+            }
+
+            /// Create an index from bytes produced by `save`
+            #[wasm_bindgen(js_name = fromBytes)]
+            pub fn from_bytes(data: &[u8]) -> Result<$name, JsValue> {
+                Ok($name::wrap(load_index(data, |_, _| {})?))
+            }
+
+            /// Save the index into a standalone `ArrayBuffer`
+            ///
+            /// The buffer holds exactly the snapshot, so a worker can hand
+            /// it over with `postMessage(buffer, [buffer])`, moving it
+            /// instead of copying; rebuild the index with `fromTransferable`.
+            #[wasm_bindgen(js_name = toTransferable)]
+            pub fn to_transferable(&self) -> Result<js_sys::ArrayBuffer, JsValue> {
+                let bytes = self.inner.to_bytes()?;
+                let array = js_sys::Uint8Array::new_with_length(bytes.len() as u32);
+                array.copy_from(&bytes);
+                Ok(array.buffer())
+            }
+
+            /// Create an index from a buffer made by `toTransferable`
+            #[wasm_bindgen(js_name = fromTransferable)]
+            pub fn from_transferable(buffer: &js_sys::ArrayBuffer) -> Result<$name, JsValue> {
+                $name::from_bytes(&js_sys::Uint8Array::new(buffer).to_vec())
+            }
+
+            /// Export as a protobuf `Snapshot` message, see
+            /// `proto/snapshot.proto`
+            #[wasm_bindgen(js_name = saveProtobuf)]
+            pub fn save_protobuf(&self) -> Result<Vec<u8>, JsValue> {
+                Ok(self.inner.to_protobuf()?)
+            }
+
+            /// Load a protobuf `Snapshot` message, building the graph when
+            /// its points have no layers
+            #[wasm_bindgen(js_name = fromProtobuf)]
+            pub fn from_protobuf(data: &[u8]) -> Result<$name, JsValue> {
+                Ok($name::wrap(Hnsw::from_protobuf(data)?))
+            }
+
+            /// Export as a usearch 2.x index file, readable by `Index.restore`
+            ///
+            /// Numeric ids become the usearch keys; otherwise keys are
+            /// positions in sorted id order.
+            #[wasm_bindgen(js_name = saveUsearch)]
+            pub fn save_usearch(&self) -> Result<Vec<u8>, JsValue> {
+                Ok(self.inner.to_usearch()?)
+            }
+
+            /// Load a usearch 2.x index file saved with its vectors, keeping
+            /// its graph; keys become decimal ids
+            #[wasm_bindgen(js_name = fromUsearch)]
+            pub fn from_usearch(data: &[u8], params: JsValue) -> Result<$name, JsValue> {
+                Ok($name::wrap(Hnsw::from_usearch(data, parse_params(params)?)?))
+            }
+
+            /// Load a file written by hnswlib's `save_index`, keeping its
+            /// graph; labels become decimal ids
+            #[wasm_bindgen(js_name = fromHnswlib)]
+            pub fn from_hnswlib(data: &[u8], params: JsValue) -> Result<$name, JsValue> {
+                Ok($name::wrap(Hnsw::from_hnswlib(data, parse_params(params)?)?))
+            }
+
+            /// Build an index from the items of an Annoy file, named by
+            /// number
+            ///
+            /// Annoy files do not record their `dimensions` or `metric`
+            /// (`"angular"`, `"euclidean"`, `"manhattan"` or `"dot"`).
+            #[wasm_bindgen(js_name = fromAnnoy)]
+            pub fn from_annoy(
+                data: &[u8],
+                dimensions: usize,
+                metric: &str,
+                params: JsValue,
+            ) -> Result<$name, JsValue> {
+                Ok($name::wrap(Hnsw::from_annoy(data, dimensions, metric, parse_params(params)?)?))
+            }
+
+            /// Export ids, stored vectors and payload fields as an Arrow IPC
+            /// stream, readable by DuckDB, Polars or `pyarrow.ipc`
+            #[wasm_bindgen(js_name = exportArrow)]
+            pub fn export_arrow(&self) -> Result<Vec<u8>, JsValue> {
+                Ok(self.inner.to_arrow()?)
+            }
+
+            /// Export ids, stored vectors, levels and payload fields as a
+            /// Parquet file
+            #[wasm_bindgen(js_name = exportParquet)]
+            pub fn export_parquet(&self) -> Result<Vec<u8>, JsValue> {
+                Ok(self.inner.to_parquet()?)
+            }
+
+            /// Dump one JSON line per point: id, level, link count per layer,
+            /// payload and, with `includeVectors`, the stored vector
+            ///
+            /// With `callback`, each line is passed to it in id order and
+            /// nothing is returned; otherwise the dump is returned whole.
+            #[wasm_bindgen(js_name = exportJsonl)]
+            pub fn export_jsonl(
+                &self,
+                include_vectors: bool,
+                callback: Option<js_sys::Function>,
+            ) -> Result<Option<String>, JsValue> {
+                let Some(callback) = callback else {
+                    return Ok(Some(self.inner.to_jsonl(include_vectors)?));
+                };
+                self.inner.write_jsonl(include_vectors, |line| {
+                    callback
+                        .call1(&JsValue::NULL, &JsValue::from_str(line))
+                        .map(|_| ())
+                        .map_err(|e| HnswError::Storage(format!("{:?}", e)))
+                })?;
+                Ok(None)
+            }
+
+            /// Export as a FAISS `IndexHNSWFlat` file, or `IndexFlatIP` when
+            /// `graph` is `false`
+            ///
+            /// FAISS point `i` is the `i`-th id in sorted order; vectors are
+            /// normalized so inner product ranks like cosine.
+            #[wasm_bindgen(js_name = saveFaiss)]
+            pub fn save_faiss(&self, graph: Option<bool>) -> Result<Vec<u8>, JsValue> {
+                Ok(self.inner.to_faiss(graph.unwrap_or(true))?)
+            }
+
+            /// Build an index from the vectors of a FAISS flat or HNSW-flat
+            /// file, naming them by `ids` or by position
+            #[wasm_bindgen(js_name = fromFaiss)]
+            pub fn from_faiss(
+                data: &[u8],
+                ids: Option<Vec<String>>,
+                params: JsValue,
+            ) -> Result<$name, JsValue> {
+                let schema = parse_schema(&params)?;
+                let mut inner = Hnsw::from_faiss(data, ids, parse_params(params)?)?;
+                if let Some(schema) = schema {
+                    inner.set_schema(schema)?;
+                }
+                Ok($name::wrap(inner))
+            }
+
+            /// Create an index from bytes produced by `saveCompressed`
+            ///
+            /// Same as `fromBytes`, which detects compression itself.
+            #[wasm_bindgen(js_name = fromBytesCompressed)]
+            pub fn from_bytes_compressed(data: &[u8]) -> Result<$name, JsValue> {
+                $name::from_bytes(data)
+            }
+
+            /// Call `sink(bytes)` with a `save()` snapshot every
+            /// `intervalMs` while the index has changed
+            ///
+            /// A save waits for a tick with no new writes, but is put off at
+            /// most a few ticks. `sink` runs synchronously; a promise it
+            /// returns is not awaited, and if it throws the save is retried on
+            /// the next tick. Pass e.g. `bytes => store.save("repo", bytes)`
+            /// with an `IndexedDbStore`. Replaces any earlier auto-save.
+            #[wasm_bindgen(js_name = enableAutoSave)]
+            pub fn enable_auto_save(
+                &mut self,
+                interval_ms: u32,
+                sink: js_sys::Function,
+            ) -> Result<(), JsValue> {
+                self.auto_save = None;
+                // SAFETY: `inner` lives in the boxed class instance next to
+                // the timer, which is cleared when the instance is freed
+                let timer = unsafe { AutoSave::start(&self.inner, interval_ms, sink)? };
+                self.auto_save = Some(timer);
+                Ok(())
+            }
+
+            /// Stop the timer armed by `enableAutoSave`
+            #[wasm_bindgen(js_name = disableAutoSave)]
+            pub fn disable_auto_save(&mut self) {
+                self.auto_save = None;
+            }
+
+            /// Save ids, vectors and metadata without the graph
+            ///
+            /// Much smaller than `save`; loading rebuilds the graph, which
+            /// takes about as long as adding the points again.
+            #[wasm_bindgen(js_name = saveVectorsOnly)]
+            pub fn save_vectors_only(&self, compressed: Option<bool>) -> Result<Vec<u8>, JsValue> {
+                Ok(self
+                    .inner
+                    .to_vectors_only_bytes(compressed.unwrap_or(false))?)
+            }
+
+            /// Write counter of the index, bumped by every change to a point
+            pub fn generation(&self) -> f64 {
+                self.inner.generation() as f64
+            }
+
+            /// Save only the points changed after generation `since`
+            ///
+            /// Pass the `generation()` seen at the previous save, or 0 for
+            /// every point. Throws for a generation before those the index
+            /// still tracks, see `forgetChanges`.
+            #[wasm_bindgen(js_name = saveDelta)]
+            pub fn save_delta(&self, since: f64, compressed: Option<bool>) -> Result<Vec<u8>, JsValue> {
+                Ok(self
+                    .inner
+                    .save_delta(since as u64, compressed.unwrap_or(false))?)
+            }
+
+            /// Stop tracking changes up to `generation`, e.g. once a full
+            /// snapshot taken there is saved
+            ///
+            /// Deleted ids are kept until then for `saveDelta`. Loading a
+            /// snapshot, `flushLog` and `clear` forget changes on their own.
+            #[wasm_bindgen(js_name = forgetChanges)]
+            pub fn forget_changes(&mut self, generation: f64) {
+                self.inner.forget_changes(generation as u64);
+            }
+
+            /// Apply a delta from `saveDelta` on the same source index
+            ///
+            /// Deltas must be applied in order. Returns the source generation
+            /// the delta brings this index up to.
+            #[wasm_bindgen(js_name = applyDelta)]
+            pub fn apply_delta(&mut self, data: &[u8]) -> Result<f64, JsValue> {
+                Ok(self.inner.apply_delta(data)? as f64)
+            }
+
+            /// Log record of the points changed since the previous flush
+            ///
+            /// Append it to durable storage; empty when nothing changed. On
+            /// startup, load the last full snapshot and `replayLog` the
+            /// records appended since.
+            #[wasm_bindgen(js_name = flushLog)]
+            pub fn flush_log(&mut self) -> Result<Vec<u8>, JsValue> {
+                Ok(self.inner.flush_log()?)
+            }
+
+            /// Apply concatenated `flushLog` records, returning how many were
+            /// applied; a torn final record is skipped
+            #[wasm_bindgen(js_name = replayLog)]
+            pub fn replay_log(&mut self, log: &[u8]) -> Result<usize, JsValue> {
+                Ok(self.inner.replay_log(log)?)
+            }
+
+            /// Replace the contents of this index with a snapshot of any kind
+            ///
+            /// Throws while `addBatchAsync` or `searchProgressive` is still
+            /// running. An auto-save counts the loaded snapshot as saved.
+            pub fn load(&mut self, data: &[u8]) -> Result<(), JsValue> {
+                self.check_idle("load")?;
+                let inner = load_index(data, |_, _| {})?;
+                self.replace(inner);
+                Ok(())
+            }
+
+            /// Like `load`, calling `progress(done, total)` while the graph
+            /// of a vectors-only snapshot is rebuilt
+            #[wasm_bindgen(js_name = loadWithProgress)]
+            pub fn load_with_progress(
+                &mut self,
+                data: &[u8],
+                progress: &js_sys::Function,
+            ) -> Result<(), JsValue> {
+                self.check_idle("load")?;
+                let inner = load_index(data, |done, total| {
+                    let _ = progress.call2(
+                        &JsValue::NULL,
+                        &JsValue::from_f64(done as f64),
+                        &JsValue::from_f64(total as f64),
+                    );
+                })?;
+                self.replace(inner);
+                Ok(())
+            }
+
+            /// Save the index by calling `callback(bytes)` once per chunk
+            ///
+            /// Chunks are `Uint8Array`s of at most `chunkSize` bytes, passed in
+            /// order and synchronously; concatenated they equal `save()`. The
+            /// snapshot is still encoded whole in wasm memory first; chunking
+            /// only avoids one JS array as large as the snapshot, e.g. when
+            /// writing it to a file stream.
+            #[wasm_bindgen(js_name = saveChunks)]
+            pub fn save_chunks(
+                &self,
+                callback: &js_sys::Function,
+                chunk_size: usize,
+            ) -> Result<(), JsValue> {
+                Ok(self.inner.write_chunks(chunk_size, |chunk| {
+                    callback
+                        .call1(&JsValue::NULL, &js_sys::Uint8Array::from(chunk))
+                        .map(|_| ())
+                        .map_err(|e| HnswError::Storage(format!("{:?}", e)))
+                })?)
+            }
+
+            /// Append the next chunk of a snapshot being loaded
+            ///
+            /// Chunks are copied into one buffer in wasm memory, decoded by
+            /// `finishLoad`, so peak memory is that of `load`; this only lets
+            /// JS hand the snapshot over without holding it all at once. The
+            /// index is unchanged until `finishLoad`.
+            #[wasm_bindgen(js_name = loadChunk)]
+            pub fn load_chunk(&mut self, bytes: &[u8]) {
+                self.pending_load.extend_from_slice(bytes);
+            }
+
+            /// Load the snapshot assembled by `loadChunk` calls, as `load`
+            ///
+            /// Pending chunks are discarded whether or not the load succeeds.
+            #[wasm_bindgen(js_name = finishLoad)]
+            pub fn finish_load(&mut self) -> Result<(), JsValue> {
+                let data = std::mem::take(&mut self.pending_load);
+                self.check_idle("load")?;
+                let inner = load_index(&data, |_, _| {})?;
+                self.replace(inner);
+                Ok(())
+            }
+
+            /// Get index statistics
+            pub fn get_stats(&self) -> JsValue {
+                let obj = js_sys::Object::new();
+                js_sys::Reflect::set(
+                    &obj,
+                    &JsValue::from_str("totalVectors"),
+                    &JsValue::from_f64(self.inner.len() as f64),
+                )
+                .unwrap();
+                js_sys::Reflect::set(
+                    &obj,
+                    &JsValue::from_str("dimensions"),
+                    &JsValue::from_f64(self.inner.dimensions() as f64),
+                )
+                .unwrap();
+                js_sys::Reflect::set(
+                    &obj,
+                    &JsValue::from_str("indexSize"),
+                    &JsValue::from_f64(self.inner.vector_bytes() as f64),
+                )
+                .unwrap();
+                js_sys::Reflect::set(
+                    &obj,
+                    &JsValue::from_str("uniqueVectors"),
+                    &JsValue::from_f64(self.inner.unique_vectors() as f64),
+                )
+                .unwrap();
+                js_sys::Reflect::set(
+                    &obj,
+                    &JsValue::from_str("dedupSavedBytes"),
+                    &JsValue::from_f64(self.inner.dedup_saved_bytes() as f64),
+                )
+                .unwrap();
                 JsValue::from(obj)
-            })
-            .collect();
+            }
 
-        Ok(serde_wasm_bindgen::to_value(&results_js)?)
-    }
+            /// Renumber internal storage densely after deletes; returns the
+            /// bytes reclaimed
+            pub fn compact(&mut self) -> Result<usize, JsValue> {
+                Ok(self.inner.compact()?)
+            }
 
-    /// Delete a vector from the index
-    pub fn delete(&mut self, id: &str) -> Result<(), JsValue> {
-        self.points.remove(id);
+            /// Renumber the graph breadth-first from the entry point so
+            /// neighbors sit together in memory; `compact` also does this
+            #[wasm_bindgen(js_name = optimizeLayout)]
+            pub fn optimize_layout(&mut self) -> Result<(), JsValue> {
+                Ok(self.inner.optimize_layout()?)
+            }
+
+            /// Drop links a closer neighbor makes redundant and reconnect
+            /// nodes left sparsely linked by deletes; returns
+            /// `{pruned, added}` link counts
+            #[wasm_bindgen(js_name = optimizeGraph)]
+            pub fn optimize_graph(&mut self) -> Result<JsValue, JsValue> {
+                json_to_js(&self.inner.optimize_graph())
+            }
+
+            /// Make room for `num_vectors` more points ahead of a bulk insert
+            pub fn reserve(&mut self, num_vectors: usize) {
+                self.inner.reserve(num_vectors);
+            }
 
-        // Remove from all layers
-        for layer in &mut self.layers {
-            layer.links.remove(id);
+            /// Clear the index
+            ///
+            /// Throws while `addBatchAsync` or `searchProgressive` is still
+            /// running.
+            pub fn clear(&mut self) -> Result<(), JsValue> {
+                self.check_idle("clear the index")?;
+                self.inner.clear();
+                Ok(())
+            }
+        }
 
-            // Remove links to this point from other points
-            for links in layer.links.values_mut() {
-                links.retain(|link_id| link_id != id);
+        #[cfg(feature = "web")]
+        #[wasm_bindgen]
+        impl $name {
+            /// Download a snapshot from `url` and load it
+            ///
+            /// The response body is streamed in, calling `progress(loaded,
+            /// total)` after each chunk; `total` is undefined when the
+            /// server sends no length. Resolves to the new index and
+            /// accepts every kind of snapshot `load` does.
+            #[wasm_bindgen(js_name = fromUrl)]
+            pub fn from_url(
+                url: &str,
+                progress: Option<js_sys::Function>,
+            ) -> Result<js_sys::Promise, JsValue> {
+                fetch::stream_url(url, progress, |data| {
+                    let mut index = $name::new(JsValue::UNDEFINED)?;
+                    index.load_chunk(&data);
+                    index.finish_load()?;
+                    Ok(index.into())
+                })
             }
         }
 
-        // Update entry point if needed
-        if self.entry_point.as_ref() == Some(&id.to_string()) {
-            self.entry_point = self.points.keys().next().cloned();
+        #[cfg(feature = "webgpu")]
+        #[wasm_bindgen]
+        impl $name {
+            /// Score the points among `ids` exactly against `vector` on
+            /// `scorer`, resolving to the best `k` as `search` returns them
+            ///
+            /// Meant for candidate sets too large to rescore one by one,
+            /// such as the shortlist of a coarse first stage. Ids not in
+            /// the index are skipped. Takes the `filter`, exclusions,
+            /// `minScore`, `withPayload` and `withVector` of `search`.
+            #[wasm_bindgen(js_name = rescoreGpu)]
+            pub fn rescore_gpu(
+                &self,
+                scorer: &GpuScorer,
+                vector: Vec<$scalar>,
+                ids: Vec<String>,
+                k: usize,
+                options: JsValue,
+            ) -> Result<js_sys::Promise, JsValue> {
+                let options = parse_search_options(options)?;
+                let query = gpu::widen(&self.inner.prepare_query(&vector)?);
+                let admitted = ids
+                    .into_iter()
+                    .filter(|id| self.inner.admits(id, &options));
+                let (ids, block) = self.inner.widened_rows(admitted);
+                let hits: Vec<(String, f32)> = ids.into_iter().map(|id| (id, 0.0)).collect();
+                let objects: js_sys::Array =
+                    results_to_js(&self.inner, hits.clone(), &options)?.into();
+                scorer.score(&query, block, move |distances| {
+                    let scores = distances.iter().map(|dist| 1.0 - dist).collect();
+                    order_by_scores(hits, objects, scores, k, &options)
+                })
+            }
+
+            /// Exact `k` nearest neighbors of `vector` by scoring every
+            /// point on `scorer`, resolving to `{ id, score }` objects best
+            /// first
+            ///
+            /// Suits indexes whose vectors fit in one GPU buffer, typically
+            /// 128 MiB. They are uploaded on the first call and stay on the
+            /// GPU until the index changes. Takes the `filter`, exclusions
+            /// and `minScore` of `search`; look metadata up with
+            /// `getMetadata`, since the index may change before the
+            /// results arrive.
+            #[wasm_bindgen(js_name = searchExactGpu)]
+            pub fn search_exact_gpu(
+                &self,
+                scorer: &GpuScorer,
+                vector: Vec<$scalar>,
+                k: usize,
+                options: JsValue,
+            ) -> Result<js_sys::Promise, JsValue> {
+                let options = parse_search_options(options)?;
+                let query = gpu::widen(&self.inner.prepare_query(&vector)?);
+                // Rows are every point; the ones options let through, if not all
+                let admitted: Option<std::collections::HashSet<String>> =
+                    (options.filter.is_some() || options.has_exclusions()).then(|| {
+                        self.inner
+                            .ids()
+                            .map(|id| id.to_string())
+                            .filter(|id| self.inner.admits(id, &options))
+                            .collect()
+                    });
+                let index = &self.inner;
+                scorer.score_resident(
+                    index.gpu_state(),
+                    &query,
+                    || index.widened_rows(index.ids().map(|id| id.to_string())),
+                    move |distances, ids| {
+                        let mut hits: Vec<(&String, f32)> = ids
+                            .iter()
+                            .zip(distances)
+                            .filter(|(id, _)| admitted.as_ref().map_or(true, |a| a.contains(*id)))
+                            .map(|(id, dist)| (id, 1.0 - dist))
+                            .collect();
+                        if hits.len() > k && k > 0 {
+                            hits.select_nth_unstable_by(k - 1, best_first);
+                        }
+                        hits.truncate(k);
+                        hits.sort_unstable_by(best_first);
+                        let results = hits.into_iter().map(|(id, score)| (id.clone(), score)).collect();
+                        let results: Vec<serde_json::Value> = options
+                            .finish(results)
+                            .into_iter()
+                            .map(|(id, score)| serde_json::json!({ "id": id, "score": score }))
+                            .collect();
+                        json_to_js(&results)
+                    },
+                )
+            }
         }
+    };
+}
+
+wasm_index!(
+    /// HNSW Vector Index
+    HNSWIndex,
+    f32
+);
+
+wasm_index!(
+    /// HNSW index over `Uint8Array` vectors scored with integer kernels
+    HNSWIndexU8,
+    u8
+);
+
+wasm_index!(
+    /// HNSW index over `Float64Array` vectors for double-precision data
+    HNSWIndexF64,
+    f64
+);
+
+/// HNSW index over `Float32Array` vectors kept in an OPFS file
+///
+/// The graph, ids and metadata stay in wasm memory while every vector is
+/// written to the file behind a `FileSystemSyncAccessHandle`, which
+/// browsers only hand out inside dedicated workers. Up to `memoryBudget`
+/// bytes of recently read vectors are cached in memory, the rest read back
+/// from the file. `save` writes everything but the vectors; `load` reopens
+/// it over the same file.
+#[cfg(feature = "web")]
+#[wasm_bindgen(js_name = OpfsIndex)]
+pub struct WasmOpfsIndex {
+    inner: Hnsw<f32, TieredStore<f32>>,
+}
 
-        Ok(())
+#[cfg(feature = "web")]
+#[wasm_bindgen(js_class = OpfsIndex)]
+impl WasmOpfsIndex {
+    /// Create an empty index over vectors of `dim` values, emptying the
+    /// file behind `handle`
+    ///
+    /// `params` are as for `HNSWIndex`. Without a `memoryBudget` every
+    /// read goes to the file.
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        handle: web_sys::FileSystemSyncAccessHandle,
+        dim: usize,
+        params: JsValue,
+        memory_budget: Option<usize>,
+    ) -> Result<WasmOpfsIndex, JsValue> {
+        let schema = parse_schema(&params)?;
+        let params = parse_params(params)?;
+        let stored = if params.project_to == 0 {
+            dim
+        } else {
+            params.project_to
+        };
+        let mut store = OpfsStore::open(handle, stored)?;
+        store.clear();
+        let store = CachedStore::with_budget(store, memory_budget.unwrap_or(0), stored);
+        let mut inner = Hnsw::with_store(params, store);
+        if let Some(schema) = schema {
+            inner.set_schema(schema)?;
+        }
+        Ok(WasmOpfsIndex { inner })
     }
 
-    /// Save the index to bytes
-    pub fn save(&self) -> Result<Vec<u8>, JsValue> {
-        let json = serde_json::to_vec(self)
-            .map_err(|e| JsValue::from_str(&format!("Serialization error: {}", e)))?;
-        Ok(json)
+    /// Reopen an index from bytes produced by `save` and the file behind
+    /// `handle` it was saved with
+    ///
+    /// Every vector is read back once and checked against the graph.
+    pub fn load(
+        handle: web_sys::FileSystemSyncAccessHandle,
+        data: &[u8],
+        memory_budget: Option<usize>,
+    ) -> Result<WasmOpfsIndex, JsValue> {
+        let inner = Hnsw::load_graph(data, |dim| {
+            let store = OpfsStore::open(handle, dim)?;
+            Ok(CachedStore::with_budget(
+                store,
+                memory_budget.unwrap_or(0),
+                dim,
+            ))
+        })?;
+        Ok(WasmOpfsIndex { inner })
     }
 
-    /// Load the index from bytes
-    pub fn load(&mut self, data: &[u8]) -> Result<(), JsValue> {
-        let loaded: HNSWIndex = serde_json::from_slice(data)
-            .map_err(|e| JsValue::from_str(&format!("Deserialization error: {}", e)))?;
+    /// Add a vector to the index, optionally with a JSON metadata object
+    pub fn add(&mut self, id: String, vector: Vec<f32>, metadata: JsValue) -> Result<(), JsValue> {
+        Ok(self
+            .inner
+            .insert_with_payload(id, vector, parse_payload(metadata)?)?)
+    }
 
-        *self = loaded;
-        Ok(())
+    /// Add many vectors packed back to back, `dim` values each
+    #[wasm_bindgen(js_name = addBatch)]
+    pub fn add_batch(
+        &mut self,
+        ids: Vec<String>,
+        vectors: &[f32],
+        dim: usize,
+    ) -> Result<(), JsValue> {
+        Ok(self.inner.insert_batch(ids, vectors, dim)?)
     }
 
-    /// Get index statistics
-    pub fn get_stats(&self) -> JsValue {
-        let obj = js_sys::Object::new();
-        js_sys::Reflect::set(
-            &obj,
-            &JsValue::from_str("totalVectors"),
-            &JsValue::from_f64(self.points.len() as f64),
-        )
-        .unwrap();
-        js_sys::Reflect::set(
-            &obj,
-            &JsValue::from_str("dimensions"),
-            &JsValue::from_f64(self.dimensions as f64),
-        )
-        .unwrap();
-        js_sys::Reflect::set(
-            &obj,
-            &JsValue::from_str("indexSize"),
-            &JsValue::from_f64((self.points.len() * self.dimensions * 4) as f64),
-        )
-        .unwrap();
-        JsValue::from(obj)
+    /// Delete a vector from the index
+    pub fn delete(&mut self, id: &str) {
+        self.inner.remove(id);
     }
 
-    /// Clear the index
-    pub fn clear(&mut self) {
-        self.points.clear();
-        self.layers.clear();
-        self.entry_point = None;
-        self.dimensions = 0;
+    /// Stored vector of an id, or undefined when it is not indexed
+    #[wasm_bindgen(js_name = getVector)]
+    pub fn get_vector(&self, id: &str) -> Option<Vec<f32>> {
+        self.inner.vector(id).map(|v| v.into_owned())
     }
-}
 
-impl HNSWIndex {
-    /// Generate random level for new point
-    fn random_level(&self) -> usize {
-        let mut level = 0;
-        let m = self.params.m as f32;
-        while rand::random::<f32>() < 1.0 / m && level < 32 {
-            level += 1;
-        }
-        level
+    /// Number of ids in the index
+    pub fn count(&self) -> usize {
+        self.inner.len()
     }
 
-    /// Get entry point level
-    fn get_entry_level(&self) -> usize {
-        if let Some(id) = &self.entry_point {
-            if let Some(point) = self.points.get(id) {
-                return point.level;
-            }
+    /// Search for nearest neighbors, with the options of `HNSWIndex.search`
+    pub fn search(&self, vector: Vec<f32>, k: usize, options: JsValue) -> Result<JsValue, JsValue> {
+        let options = parse_search_options(options)?;
+        let (results, truncated) = self.inner.search_within_budget(&vector, k, &options)?;
+        let results = results_to_js(&self.inner, results, &options)?;
+        if truncated {
+            js_sys::Reflect::set(&results, &JsValue::from_str("truncated"), &JsValue::TRUE)?;
         }
-        0
+        Ok(results)
     }
 
-    /// Search a single layer
-    fn search_layer(&self, vector: &[f32], ef: usize, layer: usize) -> Vec<(String, f32)> {
-        let mut visited = HashSet::new();
-        let mut candidates: Vec<(String, f32)> = Vec::new();
-        let mut results: Vec<(String, f32)> = Vec::new();
+    /// Save the graph, ids and metadata; the vectors stay in the file
+    pub fn save(&self) -> Result<Vec<u8>, JsValue> {
+        Ok(self.inner.to_bytes()?)
+    }
 
-        // Start from entry point
-        if let Some(entry_id) = &self.entry_point {
-            if let Some(entry_point) = self.points.get(entry_id) {
-                let dist = cosine_distance(vector, &entry_point.vector);
-                candidates.push((entry_id.clone(), dist));
-                visited.insert(entry_id.clone());
-            }
-        }
+    /// Number of vectors currently cached in memory
+    #[wasm_bindgen(js_name = cachedVectors)]
+    pub fn cached_vectors(&self) -> usize {
+        self.inner.store().cached()
+    }
 
-        // Greedy search
-        while let Some((current_id, _)) = candidates.pop() {
-            if let Some(links) = self.layers.get(layer).and_then(|l| l.links.get(&current_id)) {
-                for neighbor_id in links {
-                    if visited.contains(neighbor_id) {
-                        continue;
-                    }
-                    visited.insert(neighbor_id.clone());
+    /// Persist vectors written to the file so far
+    pub fn flush(&self) -> Result<(), JsValue> {
+        Ok(self.inner.store().inner().flush()?)
+    }
+}
 
-                    if let Some(neighbor) = self.points.get(neighbor_id) {
-                        let dist = cosine_distance(vector, &neighbor.vector);
+/// Embedding provider backed by a synchronous JS callback
+///
+/// The callback receives an array of strings and must return an array of
+/// `Float32Array`s (or number arrays), one per input.
+struct JsEmbedder {
+    callback: js_sys::Function,
+}
 
-                        if results.is_empty() || dist < results.last().unwrap().1 {
-                            candidates.push((neighbor_id.clone(), dist));
-                            results.push((neighbor_id.clone(), dist));
-                            results.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+impl EmbeddingProvider for JsEmbedder {
+    fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, HnswError> {
+        let input = js_sys::Array::new();
+        for text in texts {
+            input.push(&JsValue::from_str(text));
+        }
 
-                            if results.len() > ef {
-                                results.pop();
-                            }
-                        }
-                    }
-                }
-            }
+        let output = self
+            .callback
+            .call1(&JsValue::NULL, &input)
+            .map_err(|e| HnswError::Embedding(format!("{:?}", e)))?;
+        if !js_sys::Array::is_array(&output) {
+            return Err(HnswError::Embedding(
+                "embed callback must return an array".to_string(),
+            ));
         }
 
-        results
+        Ok(js_sys::Array::from(&output)
+            .iter()
+            .map(|vector| js_sys::Float32Array::new(&vector).to_vec())
+            .collect())
     }
 }
 
-/// Compute cosine distance between two vectors
-fn cosine_distance(a: &[f32], b: &[f32]) -> f32 {
-    let mut dot = 0.0;
-    let mut norm_a = 0.0;
-    let mut norm_b = 0.0;
+/// Reference indexer: chunk, embed, index, reconcile, and persist files
+#[wasm_bindgen(js_name = RepoIndexer)]
+pub struct WasmRepoIndexer {
+    inner: RepoIndexer<JsEmbedder>,
+}
+
+#[wasm_bindgen(js_class = RepoIndexer)]
+impl WasmRepoIndexer {
+    /// Create an indexer around an `embed(texts) => vectors` callback
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        embed: js_sys::Function,
+        params: JsValue,
+        max_lines: Option<usize>,
+        overlap: Option<usize>,
+    ) -> Result<WasmRepoIndexer, JsValue> {
+        let defaults = LineChunker::default();
+        let chunker = LineChunker {
+            max_lines: max_lines.unwrap_or(defaults.max_lines),
+            overlap: overlap.unwrap_or(defaults.overlap),
+        };
+        Ok(WasmRepoIndexer {
+            inner: RepoIndexer::new(
+                JsEmbedder { callback: embed },
+                parse_params(params)?,
+                chunker,
+            ),
+        })
+    }
+
+    /// Index one file; returns "added", "updated", or "unchanged"
+    #[wasm_bindgen(js_name = indexFile)]
+    pub fn index_file(&mut self, path: &str, content: &str) -> Result<String, JsValue> {
+        let change = match self.inner.index_file(path, content)? {
+            FileChange::Added => "added",
+            FileChange::Updated => "updated",
+            FileChange::Unchanged => "unchanged",
+        };
+        Ok(change.to_string())
+    }
 
-    for i in 0..a.len() {
-        dot += a[i] * b[i];
-        norm_a += a[i] * a[i];
-        norm_b += b[i] * b[i];
+    /// Drop a file and its chunks
+    #[wasm_bindgen(js_name = removeFile)]
+    pub fn remove_file(&mut self, path: &str) -> bool {
+        self.inner.remove_file(path)
     }
 
-    if norm_a == 0.0 || norm_b == 0.0 {
-        return 1.0;
+    /// Reconcile against a `{ [path]: content }` object of current files
+    pub fn reconcile(&mut self, files: JsValue) -> Result<JsValue, JsValue> {
+        let files: BTreeMap<String, String> = serde_wasm_bindgen::from_value(files)
+            .map_err(|e| HnswError::InvalidParams(e.to_string()))?;
+        let report = self.inner.reconcile(files)?;
+        Ok(serde_wasm_bindgen::to_value(&report)?)
     }
 
-    1.0 - (dot / (norm_a.sqrt() * norm_b.sqrt()))
+    /// Search chunks by text; hits carry path and line range
+    pub fn search(&self, query: &str, k: usize) -> Result<JsValue, JsValue> {
+        let hits = self.inner.search(query, k)?;
+        Ok(serde_wasm_bindgen::to_value(&hits)?)
+    }
+
+    /// Save manifest and index as one snapshot
+    pub fn save(&self) -> Result<Vec<u8>, JsValue> {
+        Ok(self.inner.save()?)
+    }
+
+    /// Save a zlib-compressed snapshot; `load` accepts either kind
+    #[wasm_bindgen(js_name = saveCompressed)]
+    pub fn save_compressed(&self) -> Result<Vec<u8>, JsValue> {
+        Ok(self.inner.save_compressed()?)
+    }
+
+    /// Restore an indexer from a snapshot
+    pub fn load(embed: js_sys::Function, data: &[u8]) -> Result<WasmRepoIndexer, JsValue> {
+        Ok(WasmRepoIndexer {
+            inner: RepoIndexer::load(JsEmbedder { callback: embed }, data)?,
+        })
+    }
 }
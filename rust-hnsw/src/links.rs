@@ -0,0 +1,170 @@
+//! Neighbor lists stored inline
+//!
+//! Pruning keeps a list within `2 * m` links on layer 0 and `m` above, so
+//! with the default `m` every list fits in a fixed array inside its node.
+//! A [`LinkList`] holds up to [`INLINE`] links in place and moves to the
+//! heap only past that, for a larger `m` or a foreign graph imported as
+//! given; a search then reads a node's links from the node table itself
+//! instead of following a pointer per node per layer.
+
+use serde::de::{SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::ops::Deref;
+
+/// Links held in place, the layer 0 bound of the default `m`
+pub(crate) const INLINE: usize = 32;
+
+/// Outgoing links of one node on one layer
+#[derive(Clone)]
+pub(crate) enum LinkList {
+    Inline { len: u8, links: [u32; INLINE] },
+    Heap(Vec<u32>),
+}
+
+impl LinkList {
+    pub fn new() -> LinkList {
+        LinkList::Inline {
+            len: 0,
+            links: [0; INLINE],
+        }
+    }
+
+    /// Replace the links, keeping them in place when they fit
+    pub fn set(&mut self, links: &[u32]) {
+        match self {
+            LinkList::Heap(heap) if links.len() > INLINE => {
+                heap.clear();
+                heap.extend_from_slice(links);
+            }
+            _ => *self = LinkList::from(links),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.set(&[]);
+    }
+
+    /// Keep only the links for which `keep` holds, in order
+    pub fn retain(&mut self, mut keep: impl FnMut(u32) -> bool) {
+        match self {
+            LinkList::Inline { len, links } => {
+                let mut kept = 0;
+                for i in 0..*len as usize {
+                    if keep(links[i]) {
+                        links[kept] = links[i];
+                        kept += 1;
+                    }
+                }
+                *len = kept as u8;
+            }
+            LinkList::Heap(heap) => {
+                heap.retain(|&link| keep(link));
+                self.shrink_to_fit();
+            }
+        }
+    }
+
+    /// Map every link through `f` in place
+    pub fn update(&mut self, mut f: impl FnMut(u32) -> u32) {
+        let links = match self {
+            LinkList::Inline { len, links } => &mut links[..*len as usize],
+            LinkList::Heap(heap) => heap.as_mut_slice(),
+        };
+        for link in links {
+            *link = f(*link);
+        }
+    }
+
+    /// Move a spilled list back in place if it fits, else trim its heap
+    pub fn shrink_to_fit(&mut self) {
+        if let LinkList::Heap(heap) = self {
+            if heap.len() <= INLINE {
+                *self = LinkList::from(heap.as_slice());
+            } else {
+                heap.shrink_to_fit();
+            }
+        }
+    }
+
+    /// Heap bytes held beyond the list itself
+    pub fn heap_bytes(&self) -> usize {
+        match self {
+            LinkList::Inline { .. } => 0,
+            LinkList::Heap(heap) => heap.capacity() * 4,
+        }
+    }
+}
+
+impl Default for LinkList {
+    fn default() -> LinkList {
+        LinkList::new()
+    }
+}
+
+impl From<&[u32]> for LinkList {
+    fn from(slice: &[u32]) -> LinkList {
+        if slice.len() > INLINE {
+            return LinkList::Heap(slice.to_vec());
+        }
+        let mut links = [0; INLINE];
+        links[..slice.len()].copy_from_slice(slice);
+        LinkList::Inline {
+            len: slice.len() as u8,
+            links,
+        }
+    }
+}
+
+impl Deref for LinkList {
+    type Target = [u32];
+
+    fn deref(&self) -> &[u32] {
+        match self {
+            LinkList::Inline { len, links } => &links[..*len as usize],
+            LinkList::Heap(heap) => heap,
+        }
+    }
+}
+
+impl Serialize for LinkList {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de> Deserialize<'de> for LinkList {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<LinkList, D::Error> {
+        deserializer.deserialize_seq(LinkListVisitor)
+    }
+}
+
+/// Fills a list in place, spilling only once it outgrows [`INLINE`]
+struct LinkListVisitor;
+
+impl<'de> Visitor<'de> for LinkListVisitor {
+    type Value = LinkList;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a list of links")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<LinkList, A::Error> {
+        let mut list = LinkList::new();
+        while let Some(link) = seq.next_element::<u32>()? {
+            match &mut list {
+                LinkList::Inline { len, links } if (*len as usize) < INLINE => {
+                    links[*len as usize] = link;
+                    *len += 1;
+                }
+                LinkList::Inline { links, .. } => {
+                    let mut heap = links.to_vec();
+                    heap.push(link);
+                    list = LinkList::Heap(heap);
+                }
+                LinkList::Heap(heap) => heap.push(link),
+            }
+        }
+        Ok(list)
+    }
+}
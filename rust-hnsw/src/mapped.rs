@@ -0,0 +1,555 @@
+//! Memory-mapped read-only indexes
+//!
+//! [`Hnsw::save_mapped`] writes the graph as fixed-width neighbor records
+//! addressed by point number, next to a flat vector block, so
+//! [`MappedIndex::open`] only checks the header and section bounds before
+//! the file can be searched. Nothing is decoded up front; pages are read
+//! as the search touches them.
+//!
+//! | section    | contents                                                  |
+//! |------------|-----------------------------------------------------------|
+//! | header     | see `HEADER_LEN`, sizes and section offsets               |
+//! | ids        | `count + 1` u32 byte offsets, then the UTF-8 ids in order |
+//! | vectors    | `count` stored-space vectors, little-endian               |
+//! | layer 0    | per point: link count, then `degree0` u32 slots           |
+//! | upper      | per layer: node count, sorted point numbers, then records |
+//! |            | of a link count and `degree` u32 slots per node           |
+//! | projection | codec-encoded random projection, empty when unused        |
+//!
+//! Points are numbered in id order. Payloads, sparse vectors and text are
+//! not part of the layout; filter on the full index instead.
+
+use memmap2::Mmap;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+
+use crate::codec;
+use crate::ids::Id;
+use crate::index::Hnsw;
+use crate::projection::RandomProjection;
+use crate::scalar::Scalar;
+use crate::scratch::Scored;
+use crate::snapshot::SCALARS;
+use crate::store::VectorStore;
+use crate::HnswError;
+
+const MAGIC: [u8; 8] = *b"\x89HNSMAP\n";
+const VERSION: u16 = 1;
+const HEADER_LEN: usize = 96;
+/// Bound on the layer count, well above what random levels reach
+const MAX_LAYERS: usize = 64;
+/// Marks a missing entry point
+const NO_ENTRY: u32 = u32::MAX;
+
+impl<S: Scalar, V: VectorStore<S>> Hnsw<S, V> {
+    /// Write the index to `path` in the layout read by [`MappedIndex`]
+    pub fn save_mapped<P: AsRef<Path>>(&self, path: P) -> Result<(), HnswError> {
+        let ids: Vec<String> = self.ids().map(Id::to_string).collect();
+        let nodes: Vec<u32> = ids.iter().filter_map(|id| self.node(id)).collect();
+        let number: HashMap<u32, u32> = nodes
+            .iter()
+            .enumerate()
+            .map(|(i, &node)| (node, i as u32))
+            .collect();
+        let count = u32::try_from(ids.len())
+            .map_err(|_| HnswError::Serialization("too many points to map".to_string()))?;
+        let layers = self
+            .entry_node()
+            .and_then(|entry| self.node_level(entry))
+            .map_or(0, |level| level + 1);
+        let links = |node: u32, layer: usize| -> Vec<u32> {
+            self.links(node, layer)
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|link| number.get(link).copied())
+                .collect()
+        };
+        let degree_of = |layer: usize| {
+            nodes
+                .iter()
+                .filter_map(|&node| self.links(node, layer))
+                .map(|links| links.len())
+                .max()
+                .unwrap_or(0)
+        };
+        let degree0 = degree_of(0);
+        let degree = (1..layers).map(degree_of).max().unwrap_or(0);
+        let upper: Vec<Vec<u32>> = (1..layers)
+            .map(|layer| {
+                (0..count)
+                    .filter(|&i| {
+                        self.node_level(nodes[i as usize])
+                            .is_some_and(|l| l >= layer)
+                    })
+                    .collect()
+            })
+            .collect();
+        let projection = match self.projection() {
+            Some(projection) => codec::encode(projection)?,
+            None => Vec::new(),
+        };
+
+        let stored = self.stored_dimensions();
+        let id_bytes: usize = ids.iter().map(|id| id.len()).sum();
+        let ids_at = HEADER_LEN;
+        let vectors_at = ids_at + (ids.len() + 1) * 4 + id_bytes;
+        let layer0_at = vectors_at + ids.len() * stored * S::BYTES;
+        let upper_at = layer0_at + ids.len() * (1 + degree0) * 4;
+        let upper_len: usize = upper
+            .iter()
+            .map(|nodes| 4 + nodes.len() * 4 + nodes.len() * (1 + degree) * 4)
+            .sum();
+        let projection_at = upper_at + upper_len;
+        let end = projection_at + projection.len();
+
+        let as_u32 = |value: usize, what: &str| {
+            u32::try_from(value)
+                .map_err(|_| HnswError::Serialization(format!("{} exceeds u32", what)))
+        };
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.extend_from_slice(&MAGIC);
+        header.extend_from_slice(&VERSION.to_le_bytes());
+        let scalar = SCALARS
+            .iter()
+            .position(|s| *s == S::NAME)
+            .ok_or_else(|| HnswError::Serialization(format!("unsupported scalar {}", S::NAME)))?;
+        header.push(scalar as u8);
+        header.push(0);
+        for value in [
+            as_u32(stored, "stored dimensions")?,
+            as_u32(self.dimensions(), "dimensions")?,
+            count,
+            layers as u32,
+            self.entry_node()
+                .and_then(|entry| number.get(&entry))
+                .copied()
+                .unwrap_or(NO_ENTRY),
+            as_u32(degree0, "layer 0 degree")?,
+            as_u32(degree, "degree")?,
+            as_u32(self.params().ef_search, "ef_search")?,
+            0,
+        ] {
+            header.extend_from_slice(&value.to_le_bytes());
+        }
+        for offset in [ids_at, vectors_at, layer0_at, upper_at, projection_at, end] {
+            header.extend_from_slice(&(offset as u64).to_le_bytes());
+        }
+        debug_assert_eq!(header.len(), HEADER_LEN);
+
+        let file = File::create(path).map_err(storage_error)?;
+        let mut out = BufWriter::new(file);
+        let mut write = |bytes: &[u8]| out.write_all(bytes).map_err(storage_error);
+        write(&header)?;
+
+        let mut offset = 0u32;
+        for id in &ids {
+            write(&offset.to_le_bytes())?;
+            offset += id.len() as u32;
+        }
+        write(&offset.to_le_bytes())?;
+        for id in &ids {
+            write(id.as_bytes())?;
+        }
+
+        let mut encoded = Vec::with_capacity(stored * S::BYTES);
+        for id in &ids {
+            let vector = self
+                .vector(id)
+                .ok_or_else(|| HnswError::Storage(format!("vector of {} is missing", id)))?;
+            encoded.clear();
+            for &value in vector.iter() {
+                value.write_le(&mut encoded);
+            }
+            write(&encoded)?;
+        }
+
+        for &node in &nodes {
+            write(&record(links(node, 0), degree0))?;
+        }
+        for (layer, points) in upper.iter().enumerate() {
+            write(&(points.len() as u32).to_le_bytes())?;
+            for &point in points {
+                write(&point.to_le_bytes())?;
+            }
+            for &point in points {
+                write(&record(links(nodes[point as usize], layer + 1), degree))?;
+            }
+        }
+        write(&projection)?;
+        out.flush().map_err(storage_error)
+    }
+}
+
+/// Read-only index searched in place from a file written by
+/// [`Hnsw::save_mapped`]
+pub struct MappedIndex<S: Scalar> {
+    map: Mmap,
+    stored_dimensions: usize,
+    dimensions: usize,
+    count: usize,
+    entry: Option<u32>,
+    degree0: usize,
+    degree: usize,
+    ef_search: usize,
+    ids_at: usize,
+    vectors_at: usize,
+    layer0_at: usize,
+    /// Node list offset and node count of layers 1 and up
+    upper: Vec<(usize, usize)>,
+    projection: Option<RandomProjection>,
+    _scalar: PhantomData<S>,
+}
+
+impl<S: Scalar> MappedIndex<S> {
+    /// Map the file at `path`, checking its header and section bounds
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<MappedIndex<S>, HnswError> {
+        let file = File::open(path).map_err(storage_error)?;
+        // SAFETY: the map is read-only; other processes must not truncate
+        // or rewrite the file while it is open.
+        let map = unsafe { Mmap::map(&file) }.map_err(storage_error)?;
+        let corrupt = |msg: &str| HnswError::CorruptSnapshot(format!("mapped index: {}", msg));
+
+        let header = map
+            .get(..HEADER_LEN)
+            .ok_or_else(|| corrupt("truncated header"))?;
+        if header[..8] != MAGIC {
+            return Err(corrupt("not a mapped index file"));
+        }
+        let version = u16::from_le_bytes([header[8], header[9]]);
+        if version > VERSION {
+            return Err(HnswError::Deserialization(format!(
+                "mapped index version {} is newer than the supported version {}",
+                version, VERSION
+            )));
+        }
+        let scalar = SCALARS
+            .get(header[10] as usize)
+            .copied()
+            .unwrap_or("unknown");
+        if scalar != S::NAME {
+            return Err(HnswError::Deserialization(format!(
+                "mapped index holds {} vectors, expected {}",
+                scalar,
+                S::NAME
+            )));
+        }
+        let word = |i: usize| {
+            let at = 12 + i * 4;
+            u32::from_le_bytes([header[at], header[at + 1], header[at + 2], header[at + 3]])
+                as usize
+        };
+        let offset = |i: usize| {
+            let at = 48 + i * 8;
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&header[at..at + 8]);
+            usize::try_from(u64::from_le_bytes(bytes)).unwrap_or(usize::MAX)
+        };
+        let (stored_dimensions, dimensions, count, layers) = (word(0), word(1), word(2), word(3));
+        let (entry, degree0, degree, ef_search) = (word(4) as u32, word(5), word(6), word(7));
+        let sections: Vec<usize> = (0..6).map(offset).collect();
+        if sections[0] != HEADER_LEN
+            || sections.windows(2).any(|w| w[0] > w[1])
+            || sections[5] != map.len()
+        {
+            return Err(corrupt("section offsets do not match the file"));
+        }
+        let [ids_at, vectors_at, layer0_at, upper_at, projection_at, _] = sections[..] else {
+            unreachable!()
+        };
+
+        let sized = |at: usize, len: Option<usize>, what: &str| match len {
+            Some(len) if at.checked_add(len).is_some_and(|end| end <= map.len()) => Ok(()),
+            _ => Err(corrupt(&format!("{} section is truncated", what))),
+        };
+        sized(ids_at, (count + 1).checked_mul(4), "id")?;
+        let vector_bytes = count
+            .checked_mul(stored_dimensions)
+            .and_then(|n| n.checked_mul(S::BYTES));
+        if vector_bytes != Some(layer0_at - vectors_at) {
+            return Err(corrupt("vector section has the wrong size"));
+        }
+        if count
+            .checked_mul(1 + degree0)
+            .and_then(|n| n.checked_mul(4))
+            != Some(upper_at - layer0_at)
+        {
+            return Err(corrupt("layer 0 section has the wrong size"));
+        }
+        if (count == 0) != (entry == NO_ENTRY) || (entry != NO_ENTRY && entry as usize >= count) {
+            return Err(corrupt("entry point is out of range"));
+        }
+        if (count > 0 && layers == 0) || layers > MAX_LAYERS {
+            return Err(corrupt("layer count is out of range"));
+        }
+
+        let mut upper = Vec::new();
+        let mut at = upper_at;
+        for _ in 1..layers {
+            let nodes = map
+                .get(at..at + 4)
+                .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+                .ok_or_else(|| corrupt("upper layer section is truncated"))?;
+            let len = nodes
+                .checked_mul(4 + (1 + degree) * 4)
+                .and_then(|n| n.checked_add(4))
+                .filter(|&n| at + n <= projection_at)
+                .ok_or_else(|| corrupt("upper layer section is truncated"))?;
+            upper.push((at + 4, nodes));
+            at += len;
+        }
+        if at != projection_at {
+            return Err(corrupt("upper layer section has the wrong size"));
+        }
+
+        let projection = if projection_at == map.len() {
+            None
+        } else {
+            let projection: RandomProjection = codec::decode(&map[projection_at..])?;
+            if !projection.is_well_formed()
+                || projection.input_dim() != dimensions
+                || projection.output_dim() != stored_dimensions
+            {
+                return Err(corrupt("projection does not match the dimensions"));
+            }
+            Some(projection)
+        };
+        if projection.is_none() && dimensions != stored_dimensions {
+            return Err(corrupt("stored dimensions differ without a projection"));
+        }
+
+        Ok(MappedIndex {
+            map,
+            stored_dimensions,
+            dimensions,
+            count,
+            entry: (entry != NO_ENTRY).then_some(entry),
+            degree0,
+            degree,
+            ef_search: ef_search.max(1),
+            ids_at,
+            vectors_at,
+            layer0_at,
+            upper,
+            projection,
+            _scalar: PhantomData,
+        })
+    }
+
+    /// Number of points
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Dimensions of query vectors
+    pub fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    /// Beam width of searches, saved from the index parameters
+    pub fn ef_search(&self) -> usize {
+        self.ef_search
+    }
+
+    pub fn set_ef_search(&mut self, ef_search: usize) {
+        self.ef_search = ef_search.max(1);
+    }
+
+    /// Find the `k` nearest neighbors, as `(id, similarity)` best first
+    pub fn nearest(&self, vector: &[S], k: usize) -> Result<Vec<(String, f32)>, HnswError> {
+        let Some(mut entry) = self.entry else {
+            return Ok(Vec::new());
+        };
+        if vector.len() != self.dimensions {
+            return Err(HnswError::DimensionMismatch {
+                expected: self.dimensions,
+                got: vector.len(),
+            });
+        }
+        let query = match &self.projection {
+            Some(projection) => projection.project(vector),
+            None => vector.to_vec(),
+        };
+        for layer in (1..=self.upper.len()).rev() {
+            if let Some(Scored(_, closest)) = self.search_layer(&query, entry, 1, layer).first() {
+                entry = *closest;
+            }
+        }
+        let found = self.search_layer(&query, entry, self.ef_search.max(k), 0);
+        Ok(found
+            .into_iter()
+            .take(k)
+            .filter_map(|Scored(dist, point)| Some((self.id(point)?.to_string(), 1.0 - dist)))
+            .collect())
+    }
+
+    /// Stored (projected) vector of `id`
+    pub fn vector(&self, id: &str) -> Option<Vec<S>> {
+        let point = self.find(id)?;
+        self.vector_at(point)
+    }
+
+    fn u32_at(&self, at: usize) -> Option<u32> {
+        self.map
+            .get(at..at + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn id(&self, point: u32) -> Option<&str> {
+        let point = point as usize;
+        let start = self.u32_at(self.ids_at + point * 4)? as usize;
+        let end = self.u32_at(self.ids_at + point * 4 + 4)? as usize;
+        let blob = self.ids_at + (self.count + 1) * 4;
+        let bytes = self.map.get(blob + start..blob + end)?;
+        if blob + end > self.vectors_at {
+            return None;
+        }
+        std::str::from_utf8(bytes).ok()
+    }
+
+    /// Point number of `id`; points are numbered in id order
+    fn find(&self, id: &str) -> Option<u32> {
+        let (mut low, mut high) = (0u32, self.count as u32);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match self.id(mid)?.cmp(id) {
+                Ordering::Less => low = mid + 1,
+                Ordering::Greater => high = mid,
+                Ordering::Equal => return Some(mid),
+            }
+        }
+        None
+    }
+
+    fn vector_at(&self, point: u32) -> Option<Vec<S>> {
+        let mut vector = Vec::with_capacity(self.stored_dimensions);
+        self.decode_into(point, &mut vector).then_some(vector)
+    }
+
+    /// Append the vector of `point` to `out`, returning whether it exists
+    fn decode_into(&self, point: u32, out: &mut Vec<S>) -> bool {
+        let len = self.stored_dimensions * S::BYTES;
+        let at = self.vectors_at + point as usize * len;
+        match self.map.get(at..at + len) {
+            Some(bytes) => {
+                out.extend(bytes.chunks_exact(S::BYTES).map(S::read_le));
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn distance(&self, query: &[S], point: u32) -> Option<f32> {
+        if point as usize >= self.count {
+            return None;
+        }
+        self.vector_at(point)
+            .map(|vector| S::cosine_distance(query, &vector))
+    }
+
+    /// Links of `point` on `layer`; points off the layer have none
+    fn links(&self, point: u32, layer: usize) -> impl Iterator<Item = u32> + '_ {
+        let record = if layer == 0 {
+            Some((
+                self.layer0_at + point as usize * (1 + self.degree0) * 4,
+                self.degree0,
+            ))
+        } else {
+            self.upper.get(layer - 1).and_then(|&(at, nodes)| {
+                let position = self.upper_position(at, nodes, point)?;
+                let records = at + nodes * 4;
+                Some((records + position * (1 + self.degree) * 4, self.degree))
+            })
+        };
+        let (at, width) = record.unwrap_or((0, 0));
+        let len = if width == 0 {
+            0
+        } else {
+            self.u32_at(at).map_or(0, |n| (n as usize).min(width))
+        };
+        (0..len).filter_map(move |i| self.u32_at(at + 4 + i * 4))
+    }
+
+    /// Position of `point` in the sorted node list of an upper layer
+    fn upper_position(&self, at: usize, nodes: usize, point: u32) -> Option<usize> {
+        let (mut low, mut high) = (0, nodes);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match self.u32_at(at + mid * 4)?.cmp(&point) {
+                Ordering::Less => low = mid + 1,
+                Ordering::Greater => high = mid,
+                Ordering::Equal => return Some(mid),
+            }
+        }
+        None
+    }
+
+    /// Best-first search of one layer, up to `ef` points nearest first
+    fn search_layer(&self, query: &[S], entry: u32, ef: usize, layer: usize) -> Vec<Scored> {
+        let mut visited = HashSet::from([entry]);
+        let mut candidates = BinaryHeap::new();
+        let mut results = BinaryHeap::new();
+        // Neighbors of one expansion, decoded back to back and scored as
+        // one block
+        let mut expansion = Vec::new();
+        let mut block = Vec::new();
+        let mut distances = Vec::new();
+        if let Some(dist) = self.distance(query, entry) {
+            candidates.push(Reverse(Scored(dist, entry)));
+            results.push(Scored(dist, entry));
+        }
+
+        while let Some(Reverse(Scored(dist, point))) = candidates.pop() {
+            if results.len() >= ef && results.peek().is_some_and(|far: &Scored| dist > far.0) {
+                break;
+            }
+            expansion.clear();
+            block.clear();
+            distances.clear();
+            for neighbor in self.links(point, layer) {
+                if (neighbor as usize) < self.count
+                    && visited.insert(neighbor)
+                    && self.decode_into(neighbor, &mut block)
+                {
+                    expansion.push(neighbor);
+                }
+            }
+            S::cosine_distances(query, &block, &mut distances);
+            for (&neighbor, &dist) in expansion.iter().zip(&distances) {
+                if results.len() < ef || results.peek().is_some_and(|far| dist < far.0) {
+                    candidates.push(Reverse(Scored(dist, neighbor)));
+                    results.push(Scored(dist, neighbor));
+                    if results.len() > ef {
+                        results.pop();
+                    }
+                }
+            }
+        }
+        results.into_sorted_vec()
+    }
+}
+
+/// Link count followed by `width` link slots, padded with `NO_ENTRY`
+fn record(links: Vec<u32>, width: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity((1 + width) * 4);
+    out.extend_from_slice(&(links.len() as u32).to_le_bytes());
+    for link in links
+        .into_iter()
+        .chain(std::iter::repeat(NO_ENTRY))
+        .take(width)
+    {
+        out.extend_from_slice(&link.to_le_bytes());
+    }
+    out
+}
+
+fn storage_error(err: std::io::Error) -> HnswError {
+    HnswError::Storage(err.to_string())
+}
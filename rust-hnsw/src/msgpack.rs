@@ -0,0 +1,265 @@
+//! MessagePack payload encoding
+//!
+//! Converts payloads to and from MessagePack at the JS boundary, so
+//! callers holding large metadata can hand it over as one byte array
+//! instead of an object graph walked field by field. Only values JSON can
+//! hold are accepted: map keys must be strings or integers, which become
+//! decimal strings, and `bin` and extension types are rejected.
+
+use serde_json::{Map, Number, Value};
+
+use crate::index::Hnsw;
+use crate::scalar::Scalar;
+use crate::store::VectorStore;
+use crate::HnswError;
+
+/// Nesting allowed while decoding, so hostile input cannot exhaust the stack
+const MAX_DEPTH: usize = 128;
+
+impl<S: Scalar, V: VectorStore<S>> Hnsw<S, V> {
+    /// Payload of `id` encoded as MessagePack
+    pub fn payload_msgpack(&self, id: &str) -> Option<Vec<u8>> {
+        self.payload(id).map(encode)
+    }
+
+    /// Attach a MessagePack-encoded payload, see [`Hnsw::set_payload`]
+    pub fn set_payload_msgpack(&mut self, id: &str, payload: &[u8]) -> Result<bool, HnswError> {
+        self.set_payload(id, decode(payload)?)
+    }
+}
+
+/// Encode a JSON value as MessagePack
+pub(crate) fn encode(value: &Value) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_value(&mut out, value);
+    out
+}
+
+/// Decode one MessagePack value filling all of `data`
+pub(crate) fn decode(data: &[u8]) -> Result<Value, HnswError> {
+    let mut reader = Reader { data, pos: 0 };
+    let value = reader.value(0)?;
+    if reader.pos != data.len() {
+        return Err(msgpack_error("trailing bytes after the value"));
+    }
+    Ok(value)
+}
+
+fn write_value(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Null => out.push(0xC0),
+        Value::Bool(false) => out.push(0xC2),
+        Value::Bool(true) => out.push(0xC3),
+        Value::Number(n) => {
+            if let Some(n) = n.as_u64() {
+                write_uint(out, n);
+            } else if let Some(n) = n.as_i64() {
+                write_int(out, n);
+            } else {
+                out.push(0xCB);
+                out.extend_from_slice(&n.as_f64().unwrap_or(0.0).to_be_bytes());
+            }
+        }
+        Value::String(s) => write_str(out, s),
+        Value::Array(items) => {
+            write_len(out, items.len(), 0x90, 0xDC);
+            for item in items {
+                write_value(out, item);
+            }
+        }
+        Value::Object(fields) => {
+            write_len(out, fields.len(), 0x80, 0xDE);
+            for (key, item) in fields {
+                write_str(out, key);
+                write_value(out, item);
+            }
+        }
+    }
+}
+
+fn write_uint(out: &mut Vec<u8>, n: u64) {
+    match n {
+        0..=0x7F => out.push(n as u8),
+        0x80..=0xFF => out.extend_from_slice(&[0xCC, n as u8]),
+        0x100..=0xFFFF => {
+            out.push(0xCD);
+            out.extend_from_slice(&(n as u16).to_be_bytes());
+        }
+        0x1_0000..=0xFFFF_FFFF => {
+            out.push(0xCE);
+            out.extend_from_slice(&(n as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(0xCF);
+            out.extend_from_slice(&n.to_be_bytes());
+        }
+    }
+}
+
+/// A negative integer
+fn write_int(out: &mut Vec<u8>, n: i64) {
+    if n >= -32 {
+        out.push(n as u8);
+    } else if n >= i8::MIN as i64 {
+        out.extend_from_slice(&[0xD0, n as u8]);
+    } else if n >= i16::MIN as i64 {
+        out.push(0xD1);
+        out.extend_from_slice(&(n as i16).to_be_bytes());
+    } else if n >= i32::MIN as i64 {
+        out.push(0xD2);
+        out.extend_from_slice(&(n as i32).to_be_bytes());
+    } else {
+        out.push(0xD3);
+        out.extend_from_slice(&n.to_be_bytes());
+    }
+}
+
+fn write_str(out: &mut Vec<u8>, s: &str) {
+    let len = s.len();
+    if len < 32 {
+        out.push(0xA0 | len as u8);
+    } else if len <= 0xFF {
+        out.extend_from_slice(&[0xD9, len as u8]);
+    } else if len <= 0xFFFF {
+        out.push(0xDA);
+        out.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        out.push(0xDB);
+        out.extend_from_slice(&(len as u32).to_be_bytes());
+    }
+    out.extend_from_slice(s.as_bytes());
+}
+
+/// Array or map header: a fixed form under 16 entries, else 16 or 32 bits
+fn write_len(out: &mut Vec<u8>, len: usize, fixed: u8, wide: u8) {
+    if len < 16 {
+        out.push(fixed | len as u8);
+    } else if len <= 0xFFFF {
+        out.push(wide);
+        out.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        out.push(wide + 1);
+        out.extend_from_slice(&(len as u32).to_be_bytes());
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn value(&mut self, depth: usize) -> Result<Value, HnswError> {
+        if depth > MAX_DEPTH {
+            return Err(msgpack_error("nesting too deep"));
+        }
+        let tag = self.take(1)?[0];
+        Ok(match tag {
+            0x00..=0x7F => Value::from(tag),
+            0x80..=0x8F => self.map((tag & 0x0F) as usize, depth)?,
+            0x90..=0x9F => self.array((tag & 0x0F) as usize, depth)?,
+            0xA0..=0xBF => Value::String(self.str((tag & 0x1F) as usize)?),
+            0xC0 => Value::Null,
+            0xC2 => Value::Bool(false),
+            0xC3 => Value::Bool(true),
+            0xCA => float(f32::from_be_bytes(self.fixed()?) as f64)?,
+            0xCB => float(f64::from_be_bytes(self.fixed()?))?,
+            0xCC => Value::from(self.fixed::<1>()?[0]),
+            0xCD => Value::from(u16::from_be_bytes(self.fixed()?)),
+            0xCE => Value::from(u32::from_be_bytes(self.fixed()?)),
+            0xCF => Value::from(u64::from_be_bytes(self.fixed()?)),
+            0xD0 => Value::from(i8::from_be_bytes(self.fixed()?)),
+            0xD1 => Value::from(i16::from_be_bytes(self.fixed()?)),
+            0xD2 => Value::from(i32::from_be_bytes(self.fixed()?)),
+            0xD3 => Value::from(i64::from_be_bytes(self.fixed()?)),
+            0xD9 => {
+                let len = self.fixed::<1>()?[0] as usize;
+                Value::String(self.str(len)?)
+            }
+            0xDA => {
+                let len = u16::from_be_bytes(self.fixed()?) as usize;
+                Value::String(self.str(len)?)
+            }
+            0xDB => {
+                let len = u32::from_be_bytes(self.fixed()?) as usize;
+                Value::String(self.str(len)?)
+            }
+            0xDC => {
+                let len = u16::from_be_bytes(self.fixed()?) as usize;
+                self.array(len, depth)?
+            }
+            0xDD => {
+                let len = u32::from_be_bytes(self.fixed()?) as usize;
+                self.array(len, depth)?
+            }
+            0xDE => {
+                let len = u16::from_be_bytes(self.fixed()?) as usize;
+                self.map(len, depth)?
+            }
+            0xDF => {
+                let len = u32::from_be_bytes(self.fixed()?) as usize;
+                self.map(len, depth)?
+            }
+            0xE0..=0xFF => Value::from(tag as i8),
+            0xC4..=0xC6 => return Err(msgpack_error("binary values are not supported")),
+            _ => return Err(msgpack_error("extension types are not supported")),
+        })
+    }
+
+    fn array(&mut self, len: usize, depth: usize) -> Result<Value, HnswError> {
+        // Every element takes at least a byte, so a bogus length cannot
+        // reserve more than the input
+        let mut items = Vec::with_capacity(len.min(self.data.len() - self.pos));
+        for _ in 0..len {
+            items.push(self.value(depth + 1)?);
+        }
+        Ok(Value::Array(items))
+    }
+
+    fn map(&mut self, len: usize, depth: usize) -> Result<Value, HnswError> {
+        let mut fields = Map::new();
+        for _ in 0..len {
+            let key = match self.value(depth + 1)? {
+                Value::String(key) => key,
+                Value::Number(n) if n.is_i64() || n.is_u64() => n.to_string(),
+                _ => return Err(msgpack_error("map keys must be strings or integers")),
+            };
+            let value = self.value(depth + 1)?;
+            fields.insert(key, value);
+        }
+        Ok(Value::Object(fields))
+    }
+
+    fn str(&mut self, len: usize) -> Result<String, HnswError> {
+        let bytes = self.take(len)?;
+        std::str::from_utf8(bytes)
+            .map(str::to_string)
+            .map_err(|_| msgpack_error("string is not UTF-8"))
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], HnswError> {
+        let bytes = self
+            .pos
+            .checked_add(n)
+            .and_then(|end| self.data.get(self.pos..end))
+            .ok_or_else(|| msgpack_error("value is truncated"))?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn fixed<const N: usize>(&mut self) -> Result<[u8; N], HnswError> {
+        let mut out = [0u8; N];
+        out.copy_from_slice(self.take(N)?);
+        Ok(out)
+    }
+}
+
+fn float(x: f64) -> Result<Value, HnswError> {
+    Number::from_f64(x)
+        .map(Value::Number)
+        .ok_or_else(|| msgpack_error("NaN and infinity are not JSON numbers"))
+}
+
+fn msgpack_error(msg: &str) -> HnswError {
+    HnswError::InvalidParams(format!("MessagePack payload: {}", msg))
+}
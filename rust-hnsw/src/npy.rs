@@ -0,0 +1,402 @@
+//! NumPy `.npy` and `.npz` import
+//!
+//! A `.npy` file is a short header describing dtype, order and shape,
+//! followed by the raw array. Vectors are read from a 2-D `f4` or `f8`
+//! array of either byte order and memory layout; a 1-D array is a single
+//! vector. A `.npz` is a zip of `.npy` members, stored or deflated: ids
+//! come from a member named `ids` (strings or integers), vectors from the
+//! first other member.
+
+use std::borrow::Cow;
+
+use crate::deflate;
+use crate::index::Hnsw;
+use crate::scalar::Scalar;
+use crate::store::VectorStore;
+use crate::HnswError;
+
+const NPY_MAGIC: &[u8] = b"\x93NUMPY";
+const ZIP_LOCAL: u32 = 0x0403_4b50;
+const ZIP_CENTRAL: u32 = 0x0201_4b50;
+const ZIP_END: u32 = 0x0605_4b50;
+const ZIP64_END: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR: u32 = 0x0706_4b50;
+const IDS_MEMBER: &str = "ids.npy";
+
+impl<S: Scalar, V: VectorStore<S>> Hnsw<S, V> {
+    /// Insert every row of a `.npy` matrix or `.npz` archive, returning
+    /// how many were inserted
+    ///
+    /// Rows are named by `ids`, else by the archive's `ids` member, else
+    /// by row number.
+    pub fn import_npy(
+        &mut self,
+        data: &[u8],
+        ids: Option<Vec<String>>,
+    ) -> Result<usize, HnswError> {
+        let (matrix, stored_ids) = if data.starts_with(NPY_MAGIC) {
+            (Array::parse(Cow::Borrowed(data))?, None)
+        } else if data.starts_with(&ZIP_LOCAL.to_le_bytes()) {
+            read_npz(data)?
+        } else {
+            return Err(npy_error("not a .npy or .npz file"));
+        };
+        let (rows, dim) = matrix.matrix_shape()?;
+        let ids = match ids.or(stored_ids) {
+            Some(ids) if ids.len() != rows => {
+                return Err(HnswError::InvalidParams(format!(
+                    "{} ids given for {} rows",
+                    ids.len(),
+                    rows
+                )));
+            }
+            Some(ids) => ids,
+            None => (0..rows).map(|i| i.to_string()).collect(),
+        };
+        if rows == 0 {
+            return Ok(0);
+        }
+        self.insert_batch(ids, &matrix.floats()?, dim)?;
+        Ok(rows)
+    }
+}
+
+/// One parsed `.npy` array, its data not yet decoded
+struct Array<'a> {
+    descr: String,
+    fortran_order: bool,
+    shape: Vec<usize>,
+    data: Cow<'a, [u8]>,
+}
+
+impl<'a> Array<'a> {
+    fn parse(file: Cow<'a, [u8]>) -> Result<Array<'a>, HnswError> {
+        if !file.starts_with(NPY_MAGIC) || file.len() < 10 {
+            return Err(npy_error("missing .npy header"));
+        }
+        let (len, start): (usize, usize) = match file[6] {
+            1 => (u16::from_le_bytes([file[8], file[9]]) as usize, 10),
+            2 | 3 => (u32::from_le_bytes(read(&file, 8)?) as usize, 12),
+            version => return Err(npy_error(&format!("unsupported version {}", version))),
+        };
+        let end = start
+            .checked_add(len)
+            .filter(|&end| end <= file.len())
+            .ok_or_else(|| npy_error("header is truncated"))?;
+        let header =
+            std::str::from_utf8(&file[start..end]).map_err(|_| npy_error("header is not text"))?;
+
+        let descr = header_value(header, "descr")?;
+        let descr = descr
+            .strip_prefix(['\'', '"'])
+            .and_then(|rest| rest.split(['\'', '"']).next())
+            .ok_or_else(|| npy_error("structured dtypes are not supported"))?
+            .to_string();
+        let fortran_order = header_value(header, "fortran_order")?.starts_with("True");
+        let shape = header_value(header, "shape")?
+            .strip_prefix('(')
+            .and_then(|rest| rest.split(')').next())
+            .ok_or_else(|| npy_error("malformed shape"))?
+            .split(',')
+            .map(str::trim)
+            .filter(|dim| !dim.is_empty())
+            .map(|dim| dim.parse().map_err(|_| npy_error("malformed shape")))
+            .collect::<Result<Vec<usize>, _>>()?;
+
+        let data = match file {
+            Cow::Borrowed(bytes) => Cow::Borrowed(&bytes[end..]),
+            Cow::Owned(mut bytes) => {
+                bytes.drain(..end);
+                Cow::Owned(bytes)
+            }
+        };
+        Ok(Array {
+            descr,
+            fortran_order,
+            shape,
+            data,
+        })
+    }
+
+    /// Rows and columns, reading a 1-D array as one row
+    fn matrix_shape(&self) -> Result<(usize, usize), HnswError> {
+        match self.shape[..] {
+            [rows, dim] => Ok((rows, dim)),
+            [dim] => Ok((1, dim)),
+            _ => Err(npy_error(&format!(
+                "expected a matrix, got {} dimensions",
+                self.shape.len()
+            ))),
+        }
+    }
+
+    /// Byte order, kind and element width of the dtype
+    fn dtype(&self) -> Result<(bool, char, usize), HnswError> {
+        let mut chars = self.descr.chars();
+        let big_endian = match chars.next() {
+            Some('>') => true,
+            Some('<' | '|' | '=') => false,
+            _ => return Err(npy_error(&format!("unsupported dtype {}", self.descr))),
+        };
+        let kind = chars.next().unwrap_or('?');
+        let width: usize = chars
+            .as_str()
+            .parse()
+            .map_err(|_| npy_error(&format!("unsupported dtype {}", self.descr)))?;
+        // Unicode lengths count UTF-32 code units
+        Ok((
+            big_endian,
+            kind,
+            if kind == 'U' {
+                width.saturating_mul(4)
+            } else {
+                width
+            },
+        ))
+    }
+
+    /// The raw bytes of each element, in memory order
+    fn elements(&self, width: usize) -> Result<std::slice::ChunksExact<'_, u8>, HnswError> {
+        let count = self
+            .shape
+            .iter()
+            .try_fold(1usize, |n, &dim| n.checked_mul(dim))
+            .ok_or_else(|| npy_error("shape overflows"))?;
+        let bytes = count
+            .checked_mul(width)
+            .and_then(|len| self.data.get(..len))
+            .ok_or_else(|| npy_error("array data is truncated"))?;
+        Ok(bytes.chunks_exact(width.max(1)))
+    }
+
+    /// Elements of a float array in row-major order
+    fn floats<S: Scalar>(&self) -> Result<Vec<S>, HnswError> {
+        let (big_endian, kind, width) = self.dtype()?;
+        let value = |b: &[u8]| -> f64 {
+            match (width, big_endian) {
+                (4, false) => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                (4, true) => f32::from_be_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                (_, false) => f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]),
+                (_, true) => f64::from_be_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]),
+            }
+        };
+        if kind != 'f' || !matches!(width, 4 | 8) {
+            return Err(npy_error(&format!(
+                "vectors must be float32 or float64, got {}",
+                self.descr
+            )));
+        }
+        let values: Vec<S> = self
+            .elements(width)?
+            .map(|b| S::from_f64(value(b)))
+            .collect();
+        match self.shape[..] {
+            [rows, dim] if self.fortran_order && rows > 1 && dim > 1 => {
+                let mut out = Vec::with_capacity(values.len());
+                for row in 0..rows {
+                    out.extend((0..dim).map(|col| values[col * rows + row]));
+                }
+                Ok(out)
+            }
+            _ => Ok(values),
+        }
+    }
+
+    /// Elements of a 1-D string or integer array, as ids
+    fn strings(&self) -> Result<Vec<String>, HnswError> {
+        if self.shape.len() != 1 {
+            return Err(npy_error("ids must be a 1-D array"));
+        }
+        let (big_endian, kind, width) = self.dtype()?;
+        let elements = self.elements(width)?;
+        let id = |b: &[u8]| -> Option<String> {
+            let mut bytes = [0u8; 8];
+            match (kind, width) {
+                ('U', _) => b
+                    .chunks_exact(4)
+                    .map(|c| {
+                        let c = [c[0], c[1], c[2], c[3]];
+                        if big_endian {
+                            u32::from_be_bytes(c)
+                        } else {
+                            u32::from_le_bytes(c)
+                        }
+                    })
+                    .take_while(|&c| c != 0)
+                    .map(char::from_u32)
+                    .collect(),
+                ('S', _) => {
+                    let len = b.iter().position(|&c| c == 0).unwrap_or(b.len());
+                    Some(String::from_utf8_lossy(&b[..len]).into_owned())
+                }
+                ('i' | 'u', 1 | 2 | 4 | 8) => {
+                    // Widen to 8 bytes, sign-extending signed values
+                    let negative = kind == 'i' && b[if big_endian { 0 } else { width - 1 }] >= 0x80;
+                    bytes.fill(if negative { 0xFF } else { 0 });
+                    if big_endian {
+                        b.iter()
+                            .rev()
+                            .zip(bytes.iter_mut())
+                            .for_each(|(b, o)| *o = *b);
+                    } else {
+                        bytes[..width].copy_from_slice(b);
+                    }
+                    Some(if kind == 'i' {
+                        i64::from_le_bytes(bytes).to_string()
+                    } else {
+                        u64::from_le_bytes(bytes).to_string()
+                    })
+                }
+                _ => None,
+            }
+        };
+        elements
+            .map(|b| {
+                id(b).ok_or_else(|| npy_error(&format!("unsupported id dtype {}", self.descr)))
+            })
+            .collect()
+    }
+}
+
+/// The text following `'key':` in a `.npy` header dict
+fn header_value<'h>(header: &'h str, key: &str) -> Result<&'h str, HnswError> {
+    let at = header
+        .find(&format!("'{}':", key))
+        .ok_or_else(|| npy_error(&format!("header has no {}", key)))?;
+    Ok(header[at + key.len() + 3..].trim_start())
+}
+
+/// The vectors of an archive, and its ids when it has an `ids` member
+fn read_npz(data: &[u8]) -> Result<(Array<'_>, Option<Vec<String>>), HnswError> {
+    let entries = zip_entries(data)?;
+    let ids = match entries.iter().find(|e| e.name == IDS_MEMBER) {
+        Some(entry) => Some(Array::parse(entry.extract(data)?)?.strings()?),
+        None => None,
+    };
+    let vectors = entries
+        .iter()
+        .find(|e| e.name != IDS_MEMBER && e.name.ends_with(".npy"))
+        .ok_or_else(|| npy_error("archive holds no vector array"))?;
+    Ok((Array::parse(vectors.extract(data)?)?, ids))
+}
+
+/// A member of a zip archive, from its central directory record
+struct ZipEntry {
+    name: String,
+    method: u16,
+    compressed: u64,
+    size: u64,
+    offset: u64,
+}
+
+impl ZipEntry {
+    fn extract<'a>(&self, data: &'a [u8]) -> Result<Cow<'a, [u8]>, HnswError> {
+        let offset = to_usize(self.offset)?;
+        if u32::from_le_bytes(read(data, offset)?) != ZIP_LOCAL {
+            return Err(npy_error(&format!("bad local header for {}", self.name)));
+        }
+        let name_len = u16::from_le_bytes(read(data, offset + 26)?) as usize;
+        let extra_len = u16::from_le_bytes(read(data, offset + 28)?) as usize;
+        let raw = slice(data, offset + 30 + name_len + extra_len, self.compressed)?;
+        let member = match self.method {
+            0 => Cow::Borrowed(raw),
+            8 => Cow::Owned(
+                deflate::inflate(raw).map_err(|e| npy_error(&format!("{}: {}", self.name, e)))?,
+            ),
+            method => {
+                return Err(npy_error(&format!(
+                    "{} uses unsupported compression method {}",
+                    self.name, method
+                )))
+            }
+        };
+        if member.len() as u64 != self.size {
+            return Err(npy_error(&format!("{} has the wrong size", self.name)));
+        }
+        Ok(member)
+    }
+}
+
+/// Central directory of a zip archive, including zip64 extensions
+fn zip_entries(data: &[u8]) -> Result<Vec<ZipEntry>, HnswError> {
+    // The end record is 22 bytes plus a comment of up to 64 KiB
+    let last = data
+        .len()
+        .checked_sub(22)
+        .ok_or_else(|| npy_error("archive is truncated"))?;
+    let end = (last.saturating_sub(u16::MAX as usize)..=last)
+        .rev()
+        .find(|&at| read(data, at).map(u32::from_le_bytes).ok() == Some(ZIP_END))
+        .ok_or_else(|| npy_error("archive has no central directory"))?;
+    let mut count = u16::from_le_bytes(read(data, end + 10)?) as u64;
+    let mut at = u32::from_le_bytes(read(data, end + 16)?) as u64;
+    if let Some(locator) = end.checked_sub(20) {
+        if u32::from_le_bytes(read(data, locator)?) == ZIP64_LOCATOR {
+            let record = to_usize(u64::from_le_bytes(read(data, locator + 8)?))?;
+            if u32::from_le_bytes(read(data, record)?) != ZIP64_END {
+                return Err(npy_error("bad zip64 end record"));
+            }
+            count = u64::from_le_bytes(read(data, record + 32)?);
+            at = u64::from_le_bytes(read(data, record + 48)?);
+        }
+    }
+
+    let mut at = to_usize(at)?;
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        if u32::from_le_bytes(read(data, at)?) != ZIP_CENTRAL {
+            return Err(npy_error("bad central directory record"));
+        }
+        let name_len = u16::from_le_bytes(read(data, at + 28)?) as usize;
+        let extra_len = u16::from_le_bytes(read(data, at + 30)?) as usize;
+        let comment_len = u16::from_le_bytes(read(data, at + 32)?) as usize;
+        let mut entry = ZipEntry {
+            name: String::from_utf8_lossy(slice(data, at + 46, name_len as u64)?).into_owned(),
+            method: u16::from_le_bytes(read(data, at + 10)?),
+            compressed: u32::from_le_bytes(read(data, at + 20)?) as u64,
+            size: u32::from_le_bytes(read(data, at + 24)?) as u64,
+            offset: u32::from_le_bytes(read(data, at + 42)?) as u64,
+        };
+        // A zip64 extra field holds, in order, the values saturated above
+        let mut extra = slice(data, at + 46 + name_len, extra_len as u64)?;
+        while extra.len() >= 4 {
+            let id = u16::from_le_bytes([extra[0], extra[1]]);
+            let len = (u16::from_le_bytes([extra[2], extra[3]]) as usize).min(extra.len() - 4);
+            if id == 1 {
+                let mut field = &extra[4..4 + len];
+                for value in [&mut entry.size, &mut entry.compressed, &mut entry.offset] {
+                    if *value == u32::MAX as u64 && field.len() >= 8 {
+                        *value = u64::from_le_bytes(read(field, 0)?);
+                        field = &field[8..];
+                    }
+                }
+            }
+            extra = &extra[4 + len..];
+        }
+        entries.push(entry);
+        at += 46 + name_len + extra_len + comment_len;
+    }
+    Ok(entries)
+}
+
+fn read<const N: usize>(data: &[u8], at: usize) -> Result<[u8; N], HnswError> {
+    let mut out = [0u8; N];
+    out.copy_from_slice(slice(data, at, N as u64)?);
+    Ok(out)
+}
+
+fn slice(data: &[u8], at: usize, len: u64) -> Result<&[u8], HnswError> {
+    to_usize(len)
+        .ok()
+        .and_then(|len| at.checked_add(len))
+        .and_then(|end| data.get(at..end))
+        .ok_or_else(|| npy_error("file is truncated"))
+}
+
+fn to_usize(value: u64) -> Result<usize, HnswError> {
+    usize::try_from(value).map_err(|_| npy_error("offset exceeds address space"))
+}
+
+fn npy_error(msg: &str) -> HnswError {
+    HnswError::Deserialization(format!("npy: {}", msg))
+}
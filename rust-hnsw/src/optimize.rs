@@ -0,0 +1,177 @@
+//! Graph maintenance for long-lived indexes
+//!
+//! Construction only prunes a neighbor list once it overflows, keeping the
+//! nearest candidates to fill it, and deletes drop links to removed points
+//! without replacing them. After many inserts, updates and deletes, lists
+//! carry links a search never needs while other nodes are left with few
+//! links, or with none pointing at them. [`Hnsw::optimize_graph`] cuts the
+//! first kind and tops up the second.
+
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+use crate::builder::max_links;
+use crate::index::Hnsw;
+use crate::scalar::Scalar;
+use crate::store::VectorStore;
+
+/// Counts from an [`Hnsw::optimize_graph`] pass
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct GraphReport {
+    /// Links removed because a closer neighbor already reaches their target
+    pub pruned: usize,
+    /// Links added to nodes left with too few links in or out
+    pub added: usize,
+}
+
+impl<S: Scalar, V: VectorStore<S>> Hnsw<S, V> {
+    /// Remove redundant links and reconnect sparsely linked nodes
+    ///
+    /// A link from a node to a neighbor is redundant when another, closer
+    /// neighbor of the node is nearer to that neighbor than the node is:
+    /// a search reaches it through the closer one. Lists lose those links
+    /// down to `m`, the number an insert starts a point with. Nodes left
+    /// with fewer than `m`, usually by deletes, are topped up from their
+    /// neighbors' neighbors, and a node no other node links to gets a link
+    /// back from its nearest neighbor with room for one. Ids, vectors and
+    /// payloads are unchanged; only the graph moves.
+    pub fn optimize_graph(&mut self) -> GraphReport {
+        let mut report = GraphReport::default();
+        let nodes: Vec<u32> = self.point_nodes().collect();
+        let top = self
+            .entry_node()
+            .and_then(|entry| self.node_level(entry))
+            .unwrap_or(0);
+        for layer in 0..=top {
+            let on_layer: Vec<u32> = nodes
+                .iter()
+                .copied()
+                .filter(|&node| self.node_level(node).is_some_and(|level| level >= layer))
+                .collect();
+            self.prune_layer(&on_layer, layer, &mut report);
+            self.refill_layer(&on_layer, layer, &mut report);
+            self.adopt_orphans(&on_layer, layer, &mut report);
+        }
+        report
+    }
+
+    /// Keep the links no closer neighbor dominates, and the nearest
+    /// dominated ones while fewer than `m` remain
+    fn prune_layer(&mut self, nodes: &[u32], layer: usize, report: &mut GraphReport) {
+        let m = self.params().m.max(1);
+        let limit = max_links(self, layer);
+        for &node in nodes {
+            let Some(links) = self.links(node, layer) else {
+                continue;
+            };
+            let links = links.to_vec();
+            let Some(candidates) = self.ranked(node, links.iter().copied()) else {
+                continue;
+            };
+            let kept = self.diverse(&candidates, limit, m);
+            if kept.len() < links.len() {
+                report.pruned += links.len() - kept.len();
+                self.set_links(node, layer, kept);
+            }
+        }
+    }
+
+    /// Bring nodes with fewer than `m` links up to `m` from two hops away
+    fn refill_layer(&mut self, nodes: &[u32], layer: usize, report: &mut GraphReport) {
+        let m = self.params().m.max(1);
+        let limit = max_links(self, layer);
+        let pool = self.params().ef_construction.max(limit);
+        for &node in nodes {
+            let Some(links) = self.links(node, layer) else {
+                continue;
+            };
+            if links.len() >= m {
+                continue;
+            }
+            let before: HashSet<u32> = links.iter().copied().collect();
+            let mut reach = before.clone();
+            for &link in links {
+                reach.extend(self.links(link, layer).unwrap_or(&[]));
+            }
+            reach.remove(&node);
+            let Some(mut candidates) = self.ranked(node, reach.into_iter()) else {
+                continue;
+            };
+            candidates.truncate(pool);
+            let kept = self.diverse(&candidates, limit, m);
+            let added = kept.iter().filter(|link| !before.contains(link)).count();
+            if added > 0 {
+                report.added += added;
+                report.pruned += before.len() + added - kept.len();
+                self.set_links(node, layer, kept);
+            }
+        }
+    }
+
+    /// Link every node nothing links to from its nearest neighbor with
+    /// room for another link
+    fn adopt_orphans(&mut self, nodes: &[u32], layer: usize, report: &mut GraphReport) {
+        let mut linked: HashSet<u32> = HashSet::with_capacity(nodes.len());
+        for &node in nodes {
+            linked.extend(self.links(node, layer).unwrap_or(&[]));
+        }
+        let entry = self.entry_node();
+        let limit = max_links(self, layer);
+        for &node in nodes {
+            if linked.contains(&node) || entry == Some(node) {
+                continue;
+            }
+            let adopter = self.links(node, layer).and_then(|links| {
+                links
+                    .iter()
+                    .copied()
+                    .find(|&link| self.links(link, layer).is_some_and(|l| l.len() < limit))
+            });
+            if let Some(adopter) = adopter {
+                let mut links = self.links(adopter, layer).unwrap_or(&[]).to_vec();
+                links.push(node);
+                self.set_links(adopter, layer, links);
+                report.added += 1;
+            }
+        }
+    }
+
+    /// `nodes` with their distance to `node`, nearest first
+    fn ranked(&self, node: u32, nodes: impl Iterator<Item = u32>) -> Option<Vec<(u32, f32)>> {
+        let base = self.node_vector(node)?;
+        let mut ranked: Vec<(u32, f32)> = nodes
+            .filter_map(|other| self.node_distance(&base, other).map(|dist| (other, dist)))
+            .collect();
+        ranked.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+        Some(ranked)
+    }
+
+    /// Up to `limit` of the nearest-first `candidates`, skipping any that
+    /// is closer to an already kept candidate than to the base, then the
+    /// nearest skipped ones until `floor` are kept
+    fn diverse(&self, candidates: &[(u32, f32)], limit: usize, floor: usize) -> Vec<u32> {
+        let mut kept: Vec<(u32, Cow<'_, [S]>)> = Vec::with_capacity(limit);
+        let mut dominated = Vec::new();
+        for &(candidate, dist) in candidates {
+            if kept.len() >= limit {
+                break;
+            }
+            let Some(vector) = self.node_vector(candidate) else {
+                continue;
+            };
+            if !kept
+                .iter()
+                .any(|(_, other)| S::cosine_distance(&vector, other) < dist)
+            {
+                kept.push((candidate, vector));
+            } else {
+                dominated.push(candidate);
+            }
+        }
+        let mut kept: Vec<u32> = kept.into_iter().map(|(node, _)| node).collect();
+        let fill = floor.saturating_sub(kept.len());
+        kept.extend(dominated.into_iter().take(fill));
+        kept
+    }
+}
@@ -0,0 +1,290 @@
+//! Multithreaded bulk construction
+//!
+//! [`Hnsw::insert_batch_parallel`] links points in rounds. A round stores
+//! its points unlinked, then each thread searches the graph built so far
+//! for the neighbors of its share of them, comparing against the round's
+//! other points directly since the graph cannot reach those yet. The
+//! chosen links are written in one pass; the reverse links every neighbor
+//! gains are then merged and pruned per neighbor, again spread over the
+//! threads. Only the writes are serial, so the graph needs no locks and
+//! the result does not depend on how threads are scheduled.
+//!
+//! Native builds run on OS threads; wasm builds with the `threads`
+//! feature run on the Web Worker pool in `threads.rs`.
+
+use std::collections::{BTreeMap, HashSet};
+#[cfg(not(target_arch = "wasm32"))]
+use std::num::NonZeroUsize;
+#[cfg(not(target_arch = "wasm32"))]
+use std::thread;
+
+use crate::builder::max_links;
+use crate::index::{batch_dim, Hnsw};
+use crate::scalar::Scalar;
+use crate::store::VectorStore;
+#[cfg(target_arch = "wasm32")]
+use crate::threads::{parallel_map, pool_threads};
+use crate::HnswError;
+
+/// Points inserted one at a time before the first round
+const SEED_POINTS: usize = 1024;
+
+/// Points per thread in a round
+///
+/// Round-mates are compared directly, so the extra work grows with the
+/// square of a round; a round also never outnumbers the graph it searches.
+const ROUND_PER_THREAD: usize = 64;
+
+impl<S: Scalar, V: VectorStore<S> + Sync> Hnsw<S, V> {
+    /// [`Hnsw::insert_batch`] on `threads` threads, or one per core for 0
+    ///
+    /// Ids already indexed or repeated within the batch are inserted one
+    /// by one after the rest, so
+    /// [`HNSWParams::on_duplicate`](crate::HNSWParams::on_duplicate)
+    /// applies as usual. Links are chosen with the builder's
+    /// [`select_neighbors`](crate::builder::GraphBuilder::select_neighbors);
+    /// an overridden `insert` or `prune` is not used.
+    pub fn insert_batch_parallel(
+        &mut self,
+        ids: Vec<String>,
+        vectors: &[S],
+        dim: usize,
+        threads: usize,
+    ) -> Result<(), HnswError> {
+        self.check_batch(ids.len(), vectors, dim)?;
+        let threads = match threads {
+            0 => default_threads(),
+            n => n,
+        };
+        self.reserve(ids.len());
+
+        let mut seen = HashSet::new();
+        let mut fresh = Vec::with_capacity(ids.len());
+        let mut repeats = Vec::new();
+        for (id, vector) in ids.into_iter().zip(vectors.chunks_exact(dim)) {
+            if self.contains(&id) || !seen.insert(id.clone()) {
+                repeats.push((id, vector));
+            } else {
+                fresh.push((id, vector));
+            }
+        }
+        drop(seen);
+
+        let mut fresh = fresh.into_iter();
+        while self.len() < SEED_POINTS {
+            match fresh.next() {
+                Some((id, vector)) => self.insert(id, vector.to_vec())?,
+                None => break,
+            }
+        }
+        loop {
+            let size = self.len().clamp(1, ROUND_PER_THREAD * threads);
+            let round: Vec<_> = fresh.by_ref().take(size).collect();
+            if round.is_empty() {
+                break;
+            }
+            self.insert_round(round, threads)?;
+        }
+
+        for (id, vector) in repeats {
+            self.insert(id, vector.to_vec())?;
+        }
+        Ok(())
+    }
+
+    /// [`Hnsw::nearest_batch`] on `threads` threads, or one per core for 0
+    pub fn nearest_batch_parallel(
+        &self,
+        queries: &[S],
+        num_queries: usize,
+        k: usize,
+        threads: usize,
+    ) -> Result<Vec<Vec<(String, f32)>>, HnswError> {
+        let dim = batch_dim(queries.len(), num_queries)?;
+        if dim == 0 {
+            return Ok(vec![Vec::new(); num_queries]);
+        }
+        let threads = match threads {
+            0 => default_threads(),
+            n => n,
+        };
+        let queries: Vec<&[S]> = queries.chunks_exact(dim).collect();
+        parallel_map(&queries, threads, |query| self.nearest(query, k))
+            .into_iter()
+            .collect()
+    }
+
+    /// Store and link one round of new points
+    ///
+    /// A point that cannot be stored ends the round early; the points
+    /// stored before it are still linked.
+    fn insert_round(
+        &mut self,
+        round: Vec<(String, &[S])>,
+        threads: usize,
+    ) -> Result<(), HnswError> {
+        let mut placed = Vec::with_capacity(round.len());
+        let mut failed = None;
+        for (id, vector) in round {
+            let level = self.random_level();
+            match self
+                .prepare_vector(vector.to_vec())
+                .and_then(|vector| self.place(id, vector, level))
+            {
+                Ok(node) => placed.push((node, level)),
+                Err(e) => {
+                    failed = Some(e);
+                    break;
+                }
+            }
+        }
+
+        let index = &*self;
+        let forward = parallel_map(&placed, threads, |&(node, level)| {
+            index.round_links(node, level, &placed)
+        });
+
+        let mut reverse: BTreeMap<(u32, usize), Vec<u32>> = BTreeMap::new();
+        for (&(node, _), layers) in placed.iter().zip(&forward) {
+            for (layer, links) in layers.iter().enumerate() {
+                for &neighbor in links {
+                    reverse.entry((neighbor, layer)).or_default().push(node);
+                }
+            }
+        }
+        for (&(node, _), layers) in placed.iter().zip(forward) {
+            for (layer, links) in layers.into_iter().enumerate() {
+                self.set_links(node, layer, links);
+            }
+        }
+
+        let reverse: Vec<_> = reverse.into_iter().collect();
+        let index = &*self;
+        let merged = parallel_map(&reverse, threads, |((neighbor, layer), added)| {
+            index.merge_links(*neighbor, *layer, added)
+        });
+        for (&((neighbor, layer), _), links) in reverse.iter().zip(merged) {
+            self.set_links(neighbor, layer, links);
+        }
+
+        for (node, level) in placed {
+            self.raise_entry_point(node, level);
+        }
+        failed.map_or(Ok(()), Err)
+    }
+
+    /// Links of a stored but unlinked `node` on layers `0..=level`, chosen
+    /// among its nearest linked points and the other points of its round
+    fn round_links(&self, node: u32, level: usize, round: &[(u32, usize)]) -> Vec<Vec<u32>> {
+        let mut layers = vec![Vec::new(); level + 1];
+        let Some(query) = self.node_vector(node) else {
+            return layers;
+        };
+        let ef = self.params().ef_construction.max(1);
+
+        // Greedy descent through the linked layers above the new point
+        let mut entry_points = Vec::new();
+        let mut top = 0;
+        if let Some(entry) = self.entry_node() {
+            top = self.node_level(entry).unwrap_or(0);
+            entry_points.push(entry);
+            for layer in (level + 1..=top).rev() {
+                if let Some(&(closest, _)) =
+                    self.search_layer(&query, &entry_points, 1, layer).first()
+                {
+                    entry_points = vec![closest];
+                }
+            }
+        }
+
+        for layer in (0..=level).rev() {
+            let mut found = if layer <= top && !entry_points.is_empty() {
+                self.search_layer(&query, &entry_points, ef, layer)
+            } else {
+                Vec::new()
+            };
+            let searched: Vec<u32> = found.iter().map(|&(candidate, _)| candidate).collect();
+
+            found.extend(
+                round
+                    .iter()
+                    .filter(|&&(other, other_level)| other != node && other_level >= layer)
+                    .filter_map(|&(other, _)| Some((other, self.node_distance(&query, other)?))),
+            );
+            found.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+            found.truncate(ef);
+            layers[layer] = self
+                .builder()
+                .select_neighbors(self, &query, &found, self.params().m);
+
+            if !searched.is_empty() {
+                entry_points = searched;
+            }
+        }
+        layers
+    }
+
+    /// Links of `node` on `layer` with `added` appended, cut back with the
+    /// builder's selection when that overfills the layer
+    fn merge_links(&self, node: u32, layer: usize, added: &[u32]) -> Vec<u32> {
+        let mut links = self.links(node, layer).unwrap_or(&[]).to_vec();
+        for &link in added {
+            if !links.contains(&link) {
+                links.push(link);
+            }
+        }
+        let max_links = max_links(self, layer);
+        if links.len() <= max_links {
+            return links;
+        }
+        let Some(base) = self.node_vector(node) else {
+            return links;
+        };
+        let mut candidates: Vec<(u32, f32)> = links
+            .iter()
+            .filter_map(|&link| Some((link, self.node_distance(&base, link)?)))
+            .collect();
+        candidates.sort_by(|a, b| a.1.total_cmp(&b.1));
+        self.builder()
+            .select_neighbors(self, &base, &candidates, max_links)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn default_threads() -> usize {
+    thread::available_parallelism().map_or(1, NonZeroUsize::get)
+}
+
+/// Workers in the pool plus the calling thread
+#[cfg(target_arch = "wasm32")]
+fn default_threads() -> usize {
+    pool_threads() + 1
+}
+
+/// `f` of every item on up to `threads` threads, in item order
+#[cfg(not(target_arch = "wasm32"))]
+fn parallel_map<T: Sync, R: Send>(
+    items: &[T],
+    threads: usize,
+    f: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    if items.is_empty() {
+        return Vec::new();
+    }
+    let chunk = items.len().div_ceil(threads.max(1));
+    let f = &f;
+    thread::scope(|scope| {
+        let workers: Vec<_> = items
+            .chunks(chunk)
+            .map(|part| scope.spawn(move || part.iter().map(f).collect::<Vec<R>>()))
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| {
+                worker
+                    .join()
+                    .unwrap_or_else(|e| std::panic::resume_unwind(e))
+            })
+            .collect()
+    })
+}
@@ -0,0 +1,408 @@
+//! Parquet export of index contents
+//!
+//! One row per point in id order: `id`, `vector` as a list of float, the
+//! point's top `level` and one optional column per top-level payload
+//! field, typed as in the Arrow export. The file is a single row group
+//! of uncompressed PLAIN pages, [`PAGE_ROWS`] rows each, with the thrift
+//! compact metadata written directly. Every column has at most one
+//! definition and repetition level, so levels are 1-bit packed runs.
+
+use crate::arrow::{ColumnKind, PayloadColumn};
+use crate::ids::Id;
+use crate::index::Hnsw;
+use crate::scalar::Scalar;
+use crate::store::VectorStore;
+use crate::HnswError;
+
+const MAGIC: &[u8] = b"PAR1";
+/// Rows per data page
+const PAGE_ROWS: usize = 8 * 1024;
+// `Type`
+const BOOLEAN: i32 = 0;
+const INT32: i32 = 1;
+const INT64: i32 = 2;
+const FLOAT: i32 = 4;
+const DOUBLE: i32 = 5;
+const BYTE_ARRAY: i32 = 6;
+// `FieldRepetitionType`
+const REQUIRED: i32 = 0;
+const OPTIONAL: i32 = 1;
+const REPEATED: i32 = 2;
+// `ConvertedType`
+const UTF8: i32 = 0;
+const LIST: i32 = 3;
+const JSON: i32 = 19;
+// `Encoding`
+const PLAIN: i32 = 0;
+const RLE: i32 = 3;
+// Thrift compact protocol types
+const T_I32: u8 = 5;
+const T_I64: u8 = 6;
+const T_BINARY: u8 = 8;
+const T_LIST: u8 = 9;
+const T_STRUCT: u8 = 12;
+
+/// One leaf column of the export
+enum Column<'a> {
+    Id,
+    Vector,
+    Level,
+    Payload(&'a PayloadColumn),
+}
+
+impl Column<'_> {
+    fn physical(&self) -> i32 {
+        match self {
+            Column::Id => BYTE_ARRAY,
+            Column::Vector => FLOAT,
+            Column::Level => INT32,
+            Column::Payload(column) => match column.kind {
+                ColumnKind::Bool => BOOLEAN,
+                ColumnKind::Int => INT64,
+                ColumnKind::Float => DOUBLE,
+                ColumnKind::Text | ColumnKind::Json => BYTE_ARRAY,
+            },
+        }
+    }
+
+    fn path(&self) -> Vec<&str> {
+        match self {
+            Column::Id => vec!["id"],
+            Column::Vector => vec!["vector", "list", "element"],
+            Column::Level => vec!["level"],
+            Column::Payload(column) => vec![&column.name],
+        }
+    }
+}
+
+/// Levels and PLAIN values of a column over a run of rows
+#[derive(Default)]
+struct Page {
+    values: Vec<u8>,
+    bools: Vec<bool>,
+    definition: Vec<bool>,
+    repetition: Vec<bool>,
+    count: usize,
+}
+
+impl Page {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.values
+            .extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+        self.values.extend_from_slice(bytes);
+    }
+
+    /// Page body: repetition levels, definition levels, then values
+    fn encode(&self, column: &Column) -> Vec<u8> {
+        let mut out = Vec::new();
+        if matches!(column, Column::Vector) {
+            write_levels(&mut out, &self.repetition);
+        }
+        if matches!(column, Column::Vector | Column::Payload(_)) {
+            write_levels(&mut out, &self.definition);
+        }
+        out.extend_from_slice(&self.values);
+        out.extend_from_slice(&pack_bits(&self.bools));
+        out
+    }
+}
+
+impl<S: Scalar, V: VectorStore<S>> Hnsw<S, V> {
+    /// Export ids, stored vectors, levels and payloads as a Parquet file
+    pub fn to_parquet(&self) -> Result<Vec<u8>, HnswError> {
+        let payload_columns = self.payload_columns(&["id", "vector", "level"]);
+        let mut columns = vec![Column::Id, Column::Vector, Column::Level];
+        columns.extend(payload_columns.iter().map(Column::Payload));
+        let ids: Vec<String> = self.ids().map(Id::to_string).collect();
+
+        let mut out = MAGIC.to_vec();
+        let mut chunks = Vec::new();
+        for column in &columns {
+            let start = out.len();
+            let mut values = 0;
+            for rows in ids.chunks(PAGE_ROWS) {
+                let mut page = Page::default();
+                for id in rows {
+                    self.fill_parquet(column, id, &mut page)?;
+                }
+                let body = page.encode(column);
+                let size = i32::try_from(body.len()).map_err(|_| {
+                    HnswError::Serialization("Parquet page exceeds 2 GiB".to_string())
+                })?;
+                let mut header = Compact::default();
+                header.i32(1, 0); // DATA_PAGE
+                header.i32(2, size);
+                header.i32(3, size);
+                header.begin(5);
+                header.i32(1, page.count as i32);
+                header.i32(2, PLAIN);
+                header.i32(3, RLE);
+                header.i32(4, RLE);
+                header.stop();
+                header.stop();
+                out.extend_from_slice(&header.out);
+                out.extend_from_slice(&body);
+                values += page.count;
+            }
+            chunks.push((start, out.len() - start, values));
+        }
+
+        let mut meta = Compact::default();
+        meta.i32(1, 1);
+        self.write_parquet_schema(&mut meta, &payload_columns);
+        meta.i64(3, ids.len() as i64);
+        meta.list(4, T_STRUCT, usize::from(!ids.is_empty()));
+        if !ids.is_empty() {
+            meta.element();
+            meta.list(1, T_STRUCT, columns.len());
+            for (column, &(start, size, values)) in columns.iter().zip(&chunks) {
+                meta.element();
+                meta.i64(2, start as i64);
+                meta.begin(3);
+                meta.i32(1, column.physical());
+                meta.list(2, T_I32, 2);
+                meta.raw_i32(PLAIN);
+                meta.raw_i32(RLE);
+                meta.list(3, T_BINARY, column.path().len());
+                for part in column.path() {
+                    meta.raw_binary(part.as_bytes());
+                }
+                meta.i32(4, 0); // UNCOMPRESSED
+                meta.i64(5, values as i64);
+                meta.i64(6, size as i64);
+                meta.i64(7, size as i64);
+                meta.i64(9, start as i64);
+                meta.stop();
+                meta.stop();
+            }
+            let total: usize = chunks.iter().map(|&(_, size, _)| size).sum();
+            meta.i64(2, total as i64);
+            meta.i64(3, ids.len() as i64);
+            meta.stop();
+        }
+        meta.binary(
+            6,
+            concat!("hnsw version ", env!("CARGO_PKG_VERSION")).as_bytes(),
+        );
+        meta.stop();
+
+        out.extend_from_slice(&meta.out);
+        out.extend_from_slice(&(meta.out.len() as u32).to_le_bytes());
+        out.extend_from_slice(MAGIC);
+        Ok(out)
+    }
+
+    /// Append the levels and values of one point to a page
+    fn fill_parquet(&self, column: &Column, id: &str, page: &mut Page) -> Result<(), HnswError> {
+        match column {
+            Column::Id => page.bytes(id.as_bytes()),
+            Column::Level => {
+                let level = self.level(id).unwrap_or(0) as i32;
+                page.values.extend_from_slice(&level.to_le_bytes());
+            }
+            Column::Vector => {
+                let vector = self
+                    .vector(id)
+                    .ok_or_else(|| HnswError::Storage(format!("vector of {} is missing", id)))?;
+                if vector.is_empty() {
+                    // An empty list is one undefined entry
+                    page.repetition.push(false);
+                    page.definition.push(false);
+                    page.count += 1;
+                }
+                for (i, x) in vector.iter().enumerate() {
+                    page.repetition.push(i > 0);
+                    page.definition.push(true);
+                    page.values
+                        .extend_from_slice(&(x.to_f64() as f32).to_le_bytes());
+                }
+                page.count += vector.len();
+                return Ok(());
+            }
+            Column::Payload(column) => {
+                let value = self
+                    .payload(id)
+                    .and_then(|payload| payload.get(&column.field))
+                    .filter(|value| !value.is_null());
+                page.definition.push(value.is_some());
+                match (value, column.kind) {
+                    (None, _) => {}
+                    (Some(v), ColumnKind::Bool) => page.bools.push(v.as_bool() == Some(true)),
+                    (Some(v), ColumnKind::Int) => page
+                        .values
+                        .extend_from_slice(&v.as_i64().unwrap_or(0).to_le_bytes()),
+                    (Some(v), ColumnKind::Float) => page
+                        .values
+                        .extend_from_slice(&v.as_f64().unwrap_or(0.0).to_le_bytes()),
+                    (Some(v), ColumnKind::Text) => page.bytes(v.as_str().unwrap_or("").as_bytes()),
+                    (Some(v), ColumnKind::Json) => page.bytes(v.to_string().as_bytes()),
+                }
+            }
+        }
+        page.count += 1;
+        Ok(())
+    }
+
+    /// The `schema` list of `FileMetaData`, flattened depth first
+    fn write_parquet_schema(&self, meta: &mut Compact, payload_columns: &[PayloadColumn]) {
+        let element = |meta: &mut Compact,
+                       physical: Option<i32>,
+                       repetition: i32,
+                       name: &str,
+                       children: Option<i32>,
+                       converted: Option<i32>| {
+            meta.element();
+            if let Some(physical) = physical {
+                meta.i32(1, physical);
+            }
+            meta.i32(3, repetition);
+            meta.binary(4, name.as_bytes());
+            if let Some(children) = children {
+                meta.i32(5, children);
+            }
+            if let Some(converted) = converted {
+                meta.i32(6, converted);
+            }
+            meta.stop();
+        };
+        meta.list(2, T_STRUCT, 6 + payload_columns.len());
+        let top = 3 + payload_columns.len() as i32;
+        element(meta, None, REQUIRED, "schema", Some(top), None);
+        element(meta, Some(BYTE_ARRAY), REQUIRED, "id", None, Some(UTF8));
+        element(meta, None, REQUIRED, "vector", Some(1), Some(LIST));
+        element(meta, None, REPEATED, "list", Some(1), None);
+        element(meta, Some(FLOAT), REQUIRED, "element", None, None);
+        element(meta, Some(INT32), REQUIRED, "level", None, None);
+        for column in payload_columns {
+            let physical = Column::Payload(column).physical();
+            let converted = match column.kind {
+                ColumnKind::Text => Some(UTF8),
+                ColumnKind::Json => Some(JSON),
+                _ => None,
+            };
+            element(
+                meta,
+                Some(physical),
+                OPTIONAL,
+                &column.name,
+                None,
+                converted,
+            );
+        }
+    }
+}
+
+/// 1-bit levels as a length-prefixed RLE/bit-packed hybrid of a single
+/// bit-packed run
+fn write_levels(out: &mut Vec<u8>, levels: &[bool]) {
+    let packed = pack_bits(levels);
+    let mut run = Vec::with_capacity(packed.len() + 5);
+    varint(&mut run, ((packed.len() as u64) << 1) | 1);
+    run.extend_from_slice(&packed);
+    out.extend_from_slice(&(run.len() as u32).to_le_bytes());
+    out.extend_from_slice(&run);
+}
+
+/// LSB-first bits, zero-padded to whole bytes
+fn pack_bits(bits: &[bool]) -> Vec<u8> {
+    let mut out = vec![0u8; bits.len().div_ceil(8)];
+    for (i, _) in bits.iter().enumerate().filter(|(_, bit)| **bit) {
+        out[i / 8] |= 1 << (i % 8);
+    }
+    out
+}
+
+fn varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Thrift compact protocol writer for the few shapes Parquet needs
+struct Compact {
+    out: Vec<u8>,
+    /// Last field id of each open struct, for delta-encoded headers
+    last: Vec<i16>,
+}
+
+impl Default for Compact {
+    fn default() -> Self {
+        Compact {
+            out: Vec::new(),
+            last: vec![0],
+        }
+    }
+}
+
+impl Compact {
+    fn header(&mut self, id: i16, ty: u8) {
+        let last = self.last.last_mut().expect("an open struct");
+        match id - *last {
+            delta @ 1..=15 => self.out.push((delta as u8) << 4 | ty),
+            _ => {
+                self.out.push(ty);
+                varint(&mut self.out, zigzag(id as i64));
+            }
+        }
+        *last = id;
+    }
+
+    fn i32(&mut self, id: i16, value: i32) {
+        self.header(id, T_I32);
+        self.raw_i32(value);
+    }
+
+    fn i64(&mut self, id: i16, value: i64) {
+        self.header(id, T_I64);
+        varint(&mut self.out, zigzag(value));
+    }
+
+    fn binary(&mut self, id: i16, value: &[u8]) {
+        self.header(id, T_BINARY);
+        self.raw_binary(value);
+    }
+
+    fn list(&mut self, id: i16, element: u8, len: usize) {
+        self.header(id, T_LIST);
+        if len < 15 {
+            self.out.push((len as u8) << 4 | element);
+        } else {
+            self.out.push(0xF0 | element);
+            varint(&mut self.out, len as u64);
+        }
+    }
+
+    fn raw_i32(&mut self, value: i32) {
+        varint(&mut self.out, zigzag(value as i64));
+    }
+
+    fn raw_binary(&mut self, value: &[u8]) {
+        varint(&mut self.out, value.len() as u64);
+        self.out.extend_from_slice(value);
+    }
+
+    /// Open a struct field, closed by `stop`
+    fn begin(&mut self, id: i16) {
+        self.header(id, T_STRUCT);
+        self.last.push(0);
+    }
+
+    /// Open a struct list element
+    fn element(&mut self) {
+        self.last.push(0);
+    }
+
+    /// Write the stop field closing the innermost struct
+    fn stop(&mut self) {
+        self.out.push(0);
+        if self.last.len() > 1 {
+            self.last.pop();
+        }
+    }
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
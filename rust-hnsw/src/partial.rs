@@ -0,0 +1,85 @@
+//! Lazy partial loading
+//!
+//! [`Hnsw::load_lazy`] decodes the graph, ids and point data of a full
+//! snapshot but none of its vectors, which are instead fetched per point
+//! from a caller-supplied loader the first time a search touches them.
+//! A huge index can then answer queries while its vectors stay in a
+//! backend, a mapped file or behind HTTP range requests.
+//!
+//! [`Hnsw::load_graph`] goes one step further for stores that keep their
+//! vectors themselves, such as a file: it decodes the same parts and puts
+//! them over a store that already holds every vector.
+
+use crate::index::Hnsw;
+use crate::scalar::Scalar;
+use crate::snapshot::{self, Kind};
+use crate::store::{LazyStore, VectorStore};
+use crate::HnswError;
+
+impl<S: Scalar> Hnsw<S, LazyStore<S>> {
+    /// Load the graph of a snapshot, fetching vectors by id from `loader`
+    ///
+    /// `loader` returns stored vectors, as [`Hnsw::vector`] gives them;
+    /// ones of the wrong length count as missing.
+    ///
+    /// Up to `cache_capacity` fetched vectors are kept in memory. The
+    /// vectors inside `data`, if any, are skipped while decoding, and
+    /// vectors are not checked against the index until they are fetched.
+    /// Saving the result writes the graph and only the vectors added
+    /// since, which this method loads again.
+    pub fn load_lazy(
+        data: &[u8],
+        loader: impl Fn(&str) -> Option<Vec<S>> + 'static,
+        cache_capacity: usize,
+    ) -> Result<Hnsw<S, LazyStore<S>>, HnswError> {
+        let mut index: Hnsw<S, LazyStore<S>> = snapshot::decode(
+            data,
+            S::NAME,
+            Kind::Full,
+            |index: &Hnsw<S, LazyStore<S>>| index.dimensions(),
+        )?;
+        index.check_loaded_graph()?;
+
+        let slots = index.slots().map(|(_, slot)| slot as usize + 1).max();
+        let mut ids: Vec<Option<String>> = vec![None; slots.unwrap_or(0)];
+        for (id, slot) in index.slots() {
+            // Points sharing a slot have the same vector
+            ids[slot as usize].get_or_insert_with(|| id.to_string());
+        }
+        let stored_dimensions = index.stored_dimensions();
+        index.store_mut().set_loader(
+            Box::new(move |slot| {
+                let id = ids.get(slot as usize)?.as_deref()?;
+                loader(id).filter(|vector| vector.len() == stored_dimensions)
+            }),
+            cache_capacity,
+        );
+        Ok(index)
+    }
+}
+
+impl<S: Scalar, V: VectorStore<S>> Hnsw<S, V> {
+    /// Load the graph of a snapshot over the store `open` returns, which
+    /// already holds the vectors of the index it was saved from
+    ///
+    /// `open` is given the length of the stored vectors, which is
+    /// `project_to` when that is set. The vectors inside `data`, if any,
+    /// are skipped. Every vector the graph uses is read back from the
+    /// store and checked, so a store that does not match the snapshot
+    /// fails with [`HnswError::CorruptSnapshot`].
+    pub fn load_graph(
+        data: &[u8],
+        open: impl FnOnce(usize) -> Result<V, HnswError>,
+    ) -> Result<Hnsw<S, V>, HnswError> {
+        let index: Hnsw<S, LazyStore<S>> = snapshot::decode(
+            data,
+            S::NAME,
+            Kind::Full,
+            |index: &Hnsw<S, LazyStore<S>>| index.dimensions(),
+        )?;
+        let store = open(index.stored_dimensions())?;
+        let mut index = index.into_store(store);
+        index.check_loaded()?;
+        Ok(index)
+    }
+}
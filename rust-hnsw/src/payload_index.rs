@@ -0,0 +1,230 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::ops::Bound;
+
+use crate::filter::{lookup_values, Condition, Filter, Range};
+use crate::ids::Id;
+use crate::HnswError;
+
+/// Kind of secondary index kept for a payload field
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PayloadIndexKind {
+    /// Inverted index over string values and string array elements
+    Keyword,
+    /// Ordered index over numeric values for range conditions
+    Number,
+}
+
+impl PayloadIndexKind {
+    /// Parse `"keyword"` or `"number"`
+    pub fn parse(kind: &str) -> Result<PayloadIndexKind, HnswError> {
+        match kind {
+            "keyword" => Ok(PayloadIndexKind::Keyword),
+            "number" => Ok(PayloadIndexKind::Number),
+            other => Err(HnswError::InvalidParams(format!(
+                "unknown payload index type {}",
+                other
+            ))),
+        }
+    }
+}
+
+/// Entries of one indexed field
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "entries", rename_all = "lowercase")]
+enum FieldIndex {
+    Keyword(BTreeMap<String, BTreeSet<Id>>),
+    /// Keyed by [`number_key`] so floats sort in numeric order
+    Number(BTreeMap<u64, BTreeSet<Id>>),
+}
+
+/// Secondary indexes over payload fields
+///
+/// Each index maps field values to the ids carrying them, so a filter can
+/// be narrowed to a candidate set up front instead of being evaluated on
+/// every node the search visits.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub(crate) struct PayloadIndexes {
+    fields: BTreeMap<String, FieldIndex>,
+}
+
+impl PayloadIndexes {
+    /// Index `field`, filling it from the given `(id, payload)` pairs
+    pub fn create<'a, I>(&mut self, field: &str, kind: PayloadIndexKind, payloads: I)
+    where
+        I: IntoIterator<Item = (&'a Id, &'a Value)>,
+    {
+        let index = match kind {
+            PayloadIndexKind::Keyword => FieldIndex::Keyword(BTreeMap::new()),
+            PayloadIndexKind::Number => FieldIndex::Number(BTreeMap::new()),
+        };
+        self.fields.insert(field.to_string(), index);
+        for (id, payload) in payloads {
+            self.insert(id, payload);
+        }
+    }
+
+    /// Record the indexed fields of a payload
+    pub fn insert(&mut self, id: &Id, payload: &Value) {
+        for (field, index) in &mut self.fields {
+            for value in lookup_values(Some(payload), field) {
+                match (&mut *index, value) {
+                    (FieldIndex::Keyword(entries), Value::String(s)) => {
+                        entries.entry(s.clone()).or_default().insert(id.clone());
+                    }
+                    (FieldIndex::Number(entries), Value::Number(n)) => {
+                        if let Some(x) = n.as_f64() {
+                            entries.entry(number_key(x)).or_default().insert(id.clone());
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Forget the indexed fields of a payload previously inserted
+    pub fn remove(&mut self, id: &Id, payload: &Value) {
+        for (field, index) in &mut self.fields {
+            for value in lookup_values(Some(payload), field) {
+                match (&mut *index, value) {
+                    (FieldIndex::Keyword(entries), Value::String(s)) => {
+                        remove_entry(entries, s, id);
+                    }
+                    (FieldIndex::Number(entries), Value::Number(n)) => {
+                        if let Some(x) = n.as_f64() {
+                            remove_entry(entries, &number_key(x), id);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Indexed fields and their kinds
+    pub fn kinds(&self) -> Vec<(&str, PayloadIndexKind)> {
+        self.fields
+            .iter()
+            .map(|(field, index)| {
+                let kind = match index {
+                    FieldIndex::Keyword(_) => PayloadIndexKind::Keyword,
+                    FieldIndex::Number(_) => PayloadIndexKind::Number,
+                };
+                (field.as_str(), kind)
+            })
+            .collect()
+    }
+
+    /// Drop every entry, keeping the set of indexed fields
+    pub fn clear_entries(&mut self) {
+        for index in self.fields.values_mut() {
+            match index {
+                FieldIndex::Keyword(entries) => entries.clear(),
+                FieldIndex::Number(entries) => entries.clear(),
+            }
+        }
+    }
+
+    /// Superset of the ids matching `filter`, or `None` if the indexes
+    /// cannot narrow it
+    pub fn candidates(&self, filter: &Filter) -> Option<HashSet<Id>> {
+        match filter {
+            Filter::All(filters) => filters
+                .iter()
+                .filter_map(|f| self.candidates(f))
+                .reduce(|a, b| a.intersection(&b).cloned().collect()),
+            Filter::Any(filters) => filters
+                .iter()
+                .map(|f| self.candidates(f))
+                .collect::<Option<Vec<_>>>()
+                .map(|sets| sets.into_iter().flatten().collect()),
+            Filter::Not(_) => None,
+            Filter::Field { key, condition } => self.field_candidates(key, condition),
+        }
+    }
+
+    fn field_candidates(&self, key: &str, condition: &Condition) -> Option<HashSet<Id>> {
+        let index = self.fields.get(key)?;
+        match (index, condition) {
+            (FieldIndex::Keyword(entries), Condition::Equals(Value::String(s))) => {
+                Some(entries.get(s).into_iter().flatten().cloned().collect())
+            }
+            (FieldIndex::Keyword(entries), Condition::In(options)) => {
+                let mut ids = HashSet::new();
+                for option in options {
+                    match option {
+                        Value::String(s) => {
+                            ids.extend(entries.get(s).into_iter().flatten().cloned())
+                        }
+                        _ => return None,
+                    }
+                }
+                Some(ids)
+            }
+            (FieldIndex::Number(entries), Condition::Range(range)) => {
+                let (lower, upper) = key_bounds(range);
+                if range_is_empty(lower, upper) {
+                    return Some(HashSet::new());
+                }
+                Some(
+                    entries
+                        .range((lower, upper))
+                        .flat_map(|(_, ids)| ids.iter().cloned())
+                        .collect(),
+                )
+            }
+            _ => None,
+        }
+    }
+}
+
+fn remove_entry<K: Ord>(entries: &mut BTreeMap<K, BTreeSet<Id>>, key: &K, id: &Id) {
+    if let Some(ids) = entries.get_mut(key) {
+        ids.remove(id);
+        if ids.is_empty() {
+            entries.remove(key);
+        }
+    }
+}
+
+/// Map a float to a key whose unsigned order matches numeric order
+fn number_key(x: f64) -> u64 {
+    // -0.0 and 0.0 compare equal, so they must share a key
+    let bits = if x == 0.0 { 0 } else { x.to_bits() };
+    if bits >> 63 == 1 {
+        !bits
+    } else {
+        bits | 1 << 63
+    }
+}
+
+/// Tightest key bounds covering a range
+fn key_bounds(range: &Range) -> (Bound<u64>, Bound<u64>) {
+    let lower = match (range.gt, range.gte) {
+        (Some(gt), Some(gte)) if gte > gt => Bound::Included(number_key(gte)),
+        (Some(gt), _) => Bound::Excluded(number_key(gt)),
+        (None, Some(gte)) => Bound::Included(number_key(gte)),
+        (None, None) => Bound::Unbounded,
+    };
+    let upper = match (range.lt, range.lte) {
+        (Some(lt), Some(lte)) if lte < lt => Bound::Included(number_key(lte)),
+        (Some(lt), _) => Bound::Excluded(number_key(lt)),
+        (None, Some(lte)) => Bound::Included(number_key(lte)),
+        (None, None) => Bound::Unbounded,
+    };
+    (lower, upper)
+}
+
+/// Whether no key lies between the bounds; `BTreeMap::range` panics then
+fn range_is_empty(lower: Bound<u64>, upper: Bound<u64>) -> bool {
+    match (lower, upper) {
+        (Bound::Included(a), Bound::Included(b)) => a > b,
+        (Bound::Included(a), Bound::Excluded(b))
+        | (Bound::Excluded(a), Bound::Included(b))
+        | (Bound::Excluded(a), Bound::Excluded(b)) => a >= b,
+        _ => false,
+    }
+}
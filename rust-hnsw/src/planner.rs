@@ -0,0 +1,44 @@
+use serde::Serialize;
+
+/// How a filtered query is answered
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FilterStrategy {
+    /// Score every matching point directly
+    BruteForce,
+    /// Walk the graph through matching points only, hopping over the rest
+    Acorn,
+    /// Run an unfiltered search with a wider beam and drop non-matches
+    PostFilter,
+}
+
+/// Strategy chosen for a filtered query, and the estimate behind it
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryPlan {
+    pub strategy: FilterStrategy,
+    /// Estimated number of points matching the filter
+    pub estimated_matches: usize,
+    /// Whether the estimate came from payload indexes rather than sampling
+    pub from_index: bool,
+}
+
+/// Points sampled to estimate selectivity when no payload index applies
+pub(crate) const SELECTIVITY_SAMPLE: usize = 256;
+
+/// Pick a strategy from the estimated number of matches
+///
+/// Brute force wins while the matches cost no more distance computations
+/// than a graph walk (`ef` expansions of up to `links` neighbors). Filters
+/// passing at least half the points barely disturb an ordinary search, so
+/// post-filtering a wider beam is cheapest; anything in between uses
+/// filter-aware traversal.
+pub(crate) fn choose(estimated: usize, total: usize, ef: usize, links: usize) -> FilterStrategy {
+    if estimated <= ef.saturating_mul(links) {
+        FilterStrategy::BruteForce
+    } else if estimated.saturating_mul(2) >= total {
+        FilterStrategy::PostFilter
+    } else {
+        FilterStrategy::Acorn
+    }
+}
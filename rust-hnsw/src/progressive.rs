@@ -0,0 +1,156 @@
+//! Searches that refine their results over rounds
+//!
+//! [`Hnsw::search_progressive`] answers in rounds. The first walks the
+//! base layer with a beam of `k`, each next one doubles it, and the last
+//! walks at the beam [`Hnsw::search`] would use, so the final results are
+//! the ones a plain search returns. Rounds are independent searches, so
+//! together they cost about twice the last one, while the first results
+//! arrive at a fraction of it. `searchProgressive` runs one round per turn
+//! of the event loop and calls back whenever the results change, so a
+//! page can show early hits while the search refines them.
+
+use js_sys::{Function, Promise};
+use wasm_bindgen::prelude::*;
+
+use crate::beam::Beam;
+use crate::budget::Meter;
+use crate::index::Hnsw;
+use crate::scalar::Scalar;
+use crate::search::SearchOptions;
+use crate::store::VectorStore;
+use crate::turns::Turns;
+use crate::HnswError;
+
+/// Turns results into the array handed to JS
+type ToJs<S> = fn(&Hnsw<S>, Vec<(String, f32)>, &SearchOptions) -> Result<JsValue, JsValue>;
+
+/// Search for the best matches to a query, run a round at a time
+pub struct ProgressiveSearch<S: Scalar> {
+    /// Query in stored space
+    query: Vec<S>,
+    k: usize,
+    options: SearchOptions,
+    /// Beam of the next round, `None` once the last has run
+    next: Option<Beam>,
+    /// Beam of the last round, the one a plain search walks
+    last: Beam,
+    results: Vec<(String, f32)>,
+}
+
+impl<S: Scalar, V: VectorStore<S>> Hnsw<S, V> {
+    /// Start a search for the `k` best matches for `vector` under
+    /// `options`, run by [`ProgressiveSearch::refine`]
+    pub fn search_progressive(
+        &self,
+        vector: &[S],
+        k: usize,
+        options: &SearchOptions,
+    ) -> Result<ProgressiveSearch<S>, HnswError> {
+        let query = self.prepare_query(vector)?;
+        let last = Beam::for_search(options, self.params().ef_search);
+        let first = k.max(1);
+        let next = if first < last.widest(k) {
+            Beam::Fixed(first)
+        } else {
+            last
+        };
+        Ok(ProgressiveSearch {
+            query,
+            k,
+            options: options.clone(),
+            next: Some(next),
+            last,
+            results: Vec::new(),
+        })
+    }
+}
+
+impl<S: Scalar> ProgressiveSearch<S> {
+    /// Run the next round against `index`, returning whether it changed
+    /// the results
+    ///
+    /// Does nothing once every round has run. `maxVisits` and `timeoutMs`
+    /// bound each round on its own.
+    pub fn refine<V: VectorStore<S>>(&mut self, index: &Hnsw<S, V>) -> Result<bool, HnswError> {
+        let Some(beam) = self.next.take() else {
+            return Ok(false);
+        };
+        let meter = Meter::start(self.options.max_visits, self.options.timeout_ms);
+        let (results, _) =
+            index.explain_metered(&self.query, self.k, &self.options, beam, &meter)?;
+        if beam != self.last {
+            let wider = beam.widest(self.k).saturating_mul(2);
+            self.next = Some(if wider < self.last.widest(self.k) {
+                Beam::Fixed(wider)
+            } else {
+                self.last
+            });
+        }
+        let changed = results != self.results;
+        self.results = results;
+        Ok(changed)
+    }
+
+    /// Whether the last round has run
+    pub fn is_done(&self) -> bool {
+        self.next.is_none()
+    }
+
+    /// Results of the latest round as `(id, score)` pairs, best first
+    pub fn results(&self) -> &[(String, f32)] {
+        &self.results
+    }
+
+    /// Options the search was started with
+    pub fn options(&self) -> &SearchOptions {
+        &self.options
+    }
+
+    /// Run the remaining rounds and return the final results
+    pub fn finish<V: VectorStore<S>>(
+        mut self,
+        index: &Hnsw<S, V>,
+    ) -> Result<Vec<(String, f32)>, HnswError> {
+        while !self.is_done() {
+            self.refine(index)?;
+        }
+        Ok(self.results)
+    }
+}
+
+/// Run `search` a round per turn of the event loop, calling
+/// `on_update(results)` whenever a round changes them
+///
+/// Results reach JS through `to_js`. The promise resolves to the final
+/// results, or rejects if a round fails or `on_update` throws.
+///
+/// # Safety
+///
+/// `index` must point to an index that outlives the returned value and
+/// does not move, as for `addBatchAsync`.
+pub(crate) unsafe fn run<S: Scalar>(
+    index: *const Hnsw<S>,
+    mut search: ProgressiveSearch<S>,
+    on_update: Function,
+    to_js: ToJs<S>,
+) -> Result<(Turns, Promise), JsValue> {
+    let round = move || {
+        let (update, last) = {
+            // SAFETY: see `run`; the reference ends before `on_update`
+            // runs, which may call back into the index or free it
+            let index = unsafe { &*index };
+            let changed = search.refine(index)?;
+            let results = |search: &ProgressiveSearch<S>| {
+                to_js(index, search.results().to_vec(), search.options())
+            };
+            let update = changed.then(|| results(&search)).transpose()?;
+            let last = search.is_done().then(|| results(&search)).transpose()?;
+            (update, last)
+        };
+        if let Some(update) = update {
+            on_update.call1(&JsValue::NULL, &update)?;
+        }
+        Ok(last)
+    };
+    Turns::start(round, "index was freed before the search finished")
+}
@@ -0,0 +1,102 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::scalar::Scalar;
+
+/// Distribution used to draw random projection matrices
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProjectionKind {
+    /// Dense matrix with N(0, 1/k) entries
+    #[default]
+    Gaussian,
+    /// Achlioptas sparse matrix: two thirds of the entries are zero
+    Sparse,
+}
+
+/// Training-free random projection from `input_dim` down to `output_dim`
+///
+/// The matrix is drawn once when the index sees its first vector and is
+/// saved with the index, so inserted and query vectors always go through
+/// the same mapping.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct RandomProjection {
+    kind: ProjectionKind,
+    input_dim: usize,
+    output_dim: usize,
+    /// Row-major `output_dim x input_dim` matrix
+    matrix: Vec<f32>,
+}
+
+impl RandomProjection {
+    pub(crate) fn new<R: Rng>(
+        kind: ProjectionKind,
+        input_dim: usize,
+        output_dim: usize,
+        rng: &mut R,
+    ) -> RandomProjection {
+        let len = input_dim * output_dim;
+        let matrix = match kind {
+            ProjectionKind::Gaussian => {
+                let std_dev = (1.0 / output_dim as f32).sqrt();
+                (0..len).map(|_| standard_normal(rng) * std_dev).collect()
+            }
+            ProjectionKind::Sparse => {
+                let scale = (3.0 / output_dim as f32).sqrt();
+                (0..len)
+                    .map(|_| match rng.gen_range(0..6) {
+                        0 => scale,
+                        1 => -scale,
+                        _ => 0.0,
+                    })
+                    .collect()
+            }
+        };
+
+        RandomProjection {
+            kind,
+            input_dim,
+            output_dim,
+            matrix,
+        }
+    }
+
+    pub(crate) fn input_dim(&self) -> usize {
+        self.input_dim
+    }
+
+    pub(crate) fn output_dim(&self) -> usize {
+        self.output_dim
+    }
+
+    /// Project a vector of `input_dim` values into `output_dim` values
+    pub(crate) fn project<S: Scalar>(&self, vector: &[S]) -> Vec<S> {
+        self.matrix
+            .chunks_exact(self.input_dim)
+            .map(|row| {
+                let sum: f64 = row
+                    .iter()
+                    .zip(vector)
+                    .map(|(&w, &x)| w as f64 * x.to_f64())
+                    .sum();
+                S::from_f64(sum)
+            })
+            .collect()
+    }
+
+    /// Whether the stored matrix matches the declared shape
+    pub(crate) fn is_well_formed(&self) -> bool {
+        self.input_dim > 0
+            && self.output_dim > 0
+            && self.matrix.len() == self.input_dim * self.output_dim
+    }
+}
+
+/// Draw from N(0, 1) with the Box-Muller transform
+fn standard_normal<R: Rng>(rng: &mut R) -> f32 {
+    let u1: f32 = rng.gen_range(f32::EPSILON..1.0);
+    let u2: f32 = rng.gen();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f32::consts::PI * u2).cos()
+}
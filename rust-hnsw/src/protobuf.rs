@@ -0,0 +1,333 @@
+//! Protobuf snapshot format
+//!
+//! Reads and writes the `Snapshot` message of `proto/snapshot.proto`, for
+//! tools outside Rust that build or inspect indexes with generated
+//! bindings. Points are listed in id order with their `f32` vectors,
+//! payloads as JSON text and one neighbor list per layer, neighbors given
+//! by position. Producers without a graph of their own can leave the
+//! layers out and have it built on load.
+
+use std::collections::HashMap;
+
+use crate::ids::Id;
+use crate::import::GraphNode;
+use crate::index::Hnsw;
+use crate::scalar::Scalar;
+use crate::store::VectorStore;
+use crate::{DuplicatePolicy, HNSWParams, HnswError};
+
+// Wire types
+const VARINT: u64 = 0;
+const FIXED64: u64 = 1;
+const LEN: u64 = 2;
+const FIXED32: u64 = 5;
+
+impl<S: Scalar, V: VectorStore<S>> Hnsw<S, V> {
+    /// Export as a protobuf `Snapshot` message
+    pub fn to_protobuf(&self) -> Result<Vec<u8>, HnswError> {
+        let ids: Vec<String> = self.ids().map(Id::to_string).collect();
+        let positions: HashMap<u32, u64> = ids
+            .iter()
+            .enumerate()
+            .filter_map(|(i, id)| Some((self.node(id)?, i as u64)))
+            .collect();
+        let params = self.params();
+        let mut out = Vec::new();
+
+        let mut message = Vec::new();
+        write_varint_field(&mut message, 1, params.m as u64);
+        write_varint_field(&mut message, 2, params.ef_construction as u64);
+        write_varint_field(&mut message, 3, params.ef_search as u64);
+        let policy = match params.on_duplicate {
+            DuplicatePolicy::Overwrite => 0,
+            DuplicatePolicy::Ignore => 1,
+            DuplicatePolicy::Error => 2,
+        };
+        write_varint_field(&mut message, 4, policy);
+        write_len_field(&mut out, 1, &message);
+        write_varint_field(&mut out, 2, self.stored_dimensions() as u64);
+
+        let mut point = Vec::new();
+        for id in &ids {
+            point.clear();
+            write_len_field(&mut point, 1, id.as_bytes());
+            let vector = self
+                .vector(id)
+                .ok_or_else(|| HnswError::Storage(format!("vector of {} is missing", id)))?;
+            message.clear();
+            for x in vector.iter() {
+                message.extend_from_slice(&(x.to_f64() as f32).to_le_bytes());
+            }
+            write_len_field(&mut point, 2, &message);
+            let node = self.node(id);
+            for layer in 0..=self.level(id).unwrap_or(0) {
+                let mut neighbors = Vec::new();
+                let links = node.and_then(|node| self.links(node, layer));
+                for link in links.unwrap_or_default() {
+                    if let Some(&position) = positions.get(link) {
+                        write_varint(&mut neighbors, position);
+                    }
+                }
+                message.clear();
+                if !neighbors.is_empty() {
+                    write_len_field(&mut message, 1, &neighbors);
+                }
+                write_len_field(&mut point, 3, &message);
+            }
+            if let Some(payload) = self.payload(id) {
+                let json = serde_json::to_string(payload)
+                    .map_err(|e| HnswError::Serialization(e.to_string()))?;
+                write_len_field(&mut point, 4, json.as_bytes());
+            }
+            write_len_field(&mut out, 3, &point);
+        }
+        Ok(out)
+    }
+}
+
+impl<S: Scalar> Hnsw<S> {
+    /// Load a protobuf `Snapshot` message
+    ///
+    /// Parameters left at zero take their defaults. When no point has
+    /// layers the graph is built from the vectors; otherwise it is kept as
+    /// written, see [`Hnsw::from_graph`].
+    pub fn from_protobuf(data: &[u8]) -> Result<Hnsw<S>, HnswError> {
+        let mut params = HNSWParams::default();
+        let mut dimensions = 0;
+        let mut nodes = Vec::new();
+        let mut payloads = Vec::new();
+        let mut fields = Fields { data, pos: 0 };
+        while let Some((number, value)) = fields.next()? {
+            match (number, value) {
+                (1, Value::Bytes(bytes)) => params = read_params(bytes)?,
+                (2, Value::Varint(n)) => dimensions = n as usize,
+                (3, Value::Bytes(bytes)) => {
+                    let (node, payload) = read_point(bytes)?;
+                    if let Some(payload) = payload {
+                        payloads.push((node.id.clone(), payload));
+                    }
+                    nodes.push(node);
+                }
+                (1..=3, _) => return Err(protobuf_error("field has the wrong wire type")),
+                _ => {}
+            }
+        }
+        if let Some(node) = nodes.iter().find(|n| n.vector.len() != dimensions) {
+            return Err(HnswError::DimensionMismatch {
+                expected: dimensions,
+                got: node.vector.len(),
+            });
+        }
+
+        let mut index = if nodes.iter().all(|n| n.links.is_empty()) {
+            let mut index = Hnsw::with_params(params);
+            for node in nodes {
+                index.insert(node.id, node.vector)?;
+            }
+            index
+        } else {
+            Hnsw::from_graph(params, nodes.into_iter().map(Some).collect())?
+        };
+        for (id, payload) in payloads {
+            index.set_payload(&id, payload)?;
+        }
+        Ok(index)
+    }
+}
+
+fn read_params(data: &[u8]) -> Result<HNSWParams, HnswError> {
+    let mut params = HNSWParams::default();
+    let mut fields = Fields { data, pos: 0 };
+    while let Some((number, value)) = fields.next()? {
+        let n = match value {
+            Value::Varint(0) => continue,
+            Value::Varint(n) => n,
+            _ if (1..=4).contains(&number) => {
+                return Err(protobuf_error("field has the wrong wire type"))
+            }
+            _ => continue,
+        };
+        match number {
+            1 => params.m = n as usize,
+            2 => params.ef_construction = n as usize,
+            3 => params.ef_search = n as usize,
+            4 => {
+                params.on_duplicate = match n {
+                    1 => DuplicatePolicy::Ignore,
+                    2 => DuplicatePolicy::Error,
+                    other => {
+                        return Err(protobuf_error(&format!(
+                            "unknown duplicate policy {}",
+                            other
+                        )))
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(params)
+}
+
+fn read_point<S: Scalar>(
+    data: &[u8],
+) -> Result<(GraphNode<S>, Option<serde_json::Value>), HnswError> {
+    let mut node = GraphNode {
+        id: String::new(),
+        vector: Vec::new(),
+        links: Vec::new(),
+    };
+    let mut payload = None;
+    let mut fields = Fields { data, pos: 0 };
+    while let Some((number, value)) = fields.next()? {
+        match (number, value) {
+            (1, Value::Bytes(bytes)) => {
+                node.id = std::str::from_utf8(bytes)
+                    .map_err(|_| protobuf_error("point id is not UTF-8"))?
+                    .to_string();
+            }
+            // Packed, or a single element when unpacked
+            (2, Value::Bytes(bytes)) => {
+                if bytes.len() % 4 != 0 {
+                    return Err(protobuf_error("packed vector is not whole floats"));
+                }
+                node.vector.extend(
+                    bytes
+                        .chunks_exact(4)
+                        .map(|b| S::from_f64(f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64)),
+                );
+            }
+            (2, Value::Fixed32(bits)) => node.vector.push(S::from_f64(f32::from_bits(bits) as f64)),
+            (3, Value::Bytes(bytes)) => node.links.push(read_layer(bytes)?),
+            (4, Value::Bytes(bytes)) if !bytes.is_empty() => {
+                payload = Some(
+                    serde_json::from_slice(bytes)
+                        .map_err(|e| protobuf_error(&format!("payload of {}: {}", node.id, e)))?,
+                );
+            }
+            (1..=4, Value::Bytes(_)) => {}
+            (1..=4, _) => return Err(protobuf_error("field has the wrong wire type")),
+            _ => {}
+        }
+    }
+    Ok((node, payload))
+}
+
+fn read_layer(data: &[u8]) -> Result<Vec<u32>, HnswError> {
+    let mut neighbors = Vec::new();
+    let mut fields = Fields { data, pos: 0 };
+    while let Some((number, value)) = fields.next()? {
+        match (number, value) {
+            (1, Value::Bytes(bytes)) => {
+                let mut packed = Fields {
+                    data: bytes,
+                    pos: 0,
+                };
+                while packed.pos < bytes.len() {
+                    neighbors.push(position(packed.varint()?)?);
+                }
+            }
+            (1, Value::Varint(n)) => neighbors.push(position(n)?),
+            (1, _) => return Err(protobuf_error("field has the wrong wire type")),
+            _ => {}
+        }
+    }
+    Ok(neighbors)
+}
+
+fn position(n: u64) -> Result<u32, HnswError> {
+    u32::try_from(n).map_err(|_| protobuf_error("neighbor position exceeds u32"))
+}
+
+/// A decoded field value, by wire type
+enum Value<'a> {
+    Varint(u64),
+    Fixed32(u32),
+    Fixed64,
+    Bytes(&'a [u8]),
+}
+
+/// The fields of one message, in order
+struct Fields<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Fields<'a> {
+    fn next(&mut self) -> Result<Option<(u64, Value<'a>)>, HnswError> {
+        if self.pos == self.data.len() {
+            return Ok(None);
+        }
+        let key = self.varint()?;
+        let value = match key & 7 {
+            VARINT => Value::Varint(self.varint()?),
+            FIXED64 => {
+                self.take(8)?;
+                Value::Fixed64
+            }
+            LEN => {
+                let len = usize::try_from(self.varint()?)
+                    .map_err(|_| protobuf_error("length exceeds address space"))?;
+                Value::Bytes(self.take(len)?)
+            }
+            FIXED32 => {
+                let b = self.take(4)?;
+                Value::Fixed32(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            }
+            other => return Err(protobuf_error(&format!("unsupported wire type {}", other))),
+        };
+        Ok(Some((key >> 3, value)))
+    }
+
+    fn varint(&mut self) -> Result<u64, HnswError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = *self
+                .data
+                .get(self.pos)
+                .ok_or_else(|| protobuf_error("message is truncated"))?;
+            self.pos += 1;
+            value |= ((byte & 0x7F) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(protobuf_error("varint is too long"))
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], HnswError> {
+        let bytes = self
+            .pos
+            .checked_add(n)
+            .and_then(|end| self.data.get(self.pos..end))
+            .ok_or_else(|| protobuf_error("message is truncated"))?;
+        self.pos += n;
+        Ok(bytes)
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Write a varint field, omitted when zero as proto3 does
+fn write_varint_field(out: &mut Vec<u8>, number: u64, value: u64) {
+    if value != 0 {
+        write_varint(out, number << 3 | VARINT);
+        write_varint(out, value);
+    }
+}
+
+fn write_len_field(out: &mut Vec<u8>, number: u64, bytes: &[u8]) {
+    write_varint(out, number << 3 | LEN);
+    write_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn protobuf_error(msg: &str) -> HnswError {
+    HnswError::Deserialization(format!("protobuf snapshot: {}", msg))
+}
@@ -0,0 +1,99 @@
+//! 8-bit codes of stored vectors for a cheaper first search pass
+//!
+//! With [`HNSWParams::quantize`](crate::HNSWParams::quantize) on, the
+//! index keeps every stored vector scaled to unit length and rounded to
+//! `i8` alongside the store, a quarter of the memory of `f32` vectors.
+//! Searches walk the graph scoring these codes, then rescore the
+//! candidates they keep, `prefetch` of them when that is set, with the
+//! full-precision vectors from the store. Building the graph always uses
+//! full precision.
+
+use crate::scalar::Scalar;
+
+/// Largest code magnitude; symmetric so negation is exact
+const SCALE: f64 = 127.0;
+
+/// Codes of stored vectors, one row per store slot
+#[derive(Default)]
+pub(crate) struct Codes {
+    /// Values per row, fixed by the first vector coded
+    width: usize,
+    rows: Vec<i8>,
+    /// Whether each slot has been coded
+    coded: Vec<bool>,
+}
+
+impl Codes {
+    /// Code `vector` into `out`, replacing its contents
+    ///
+    /// Cosine distance ignores length, so the vector is scaled to unit
+    /// length first and every component uses the full code range.
+    pub fn encode<S: Scalar>(vector: &[S], out: &mut Vec<i8>) {
+        out.clear();
+        let norm = vector
+            .iter()
+            .map(|v| v.to_f64() * v.to_f64())
+            .sum::<f64>()
+            .sqrt();
+        let scale = if norm > 0.0 { SCALE / norm } else { 0.0 };
+        out.extend(
+            vector
+                .iter()
+                .map(|v| (v.to_f64() * scale).round().clamp(-SCALE, SCALE) as i8),
+        );
+    }
+
+    /// Code the vector stored in `slot`
+    pub fn set<S: Scalar>(&mut self, slot: u32, vector: &[S]) {
+        if self.width == 0 {
+            self.width = vector.len();
+        }
+        if vector.len() != self.width {
+            return;
+        }
+        let slot = slot as usize;
+        if self.coded.len() <= slot {
+            self.coded.resize(slot + 1, false);
+            self.rows.resize((slot + 1) * self.width, 0);
+        }
+        let mut code = Vec::with_capacity(self.width);
+        Codes::encode(vector, &mut code);
+        self.rows[slot * self.width..(slot + 1) * self.width].copy_from_slice(&code);
+        self.coded[slot] = true;
+    }
+
+    /// Whether `slot` has a code
+    pub fn has(&self, slot: u32) -> bool {
+        self.coded.get(slot as usize).copied().unwrap_or(false)
+    }
+
+    /// Cosine distances from a coded query to the codes of `slots`,
+    /// appended to `out`; every slot must have a code
+    pub fn distances(&self, query: &[i8], slots: impl Iterator<Item = u32>, out: &mut Vec<f32>) {
+        let query_norm: i32 = query.iter().map(|&q| q as i32 * q as i32).sum();
+        for slot in slots {
+            let start = slot as usize * self.width;
+            let row = &self.rows[start..start + self.width];
+            let (mut dot, mut norm) = (0i32, 0i32);
+            for (&q, &r) in query.iter().zip(row) {
+                dot += q as i32 * r as i32;
+                norm += r as i32 * r as i32;
+            }
+            out.push(if query_norm == 0 || norm == 0 {
+                1.0
+            } else {
+                1.0 - (dot as f64 / (query_norm as f64 * norm as f64).sqrt()) as f32
+            });
+        }
+    }
+
+    /// Forget every code and the row width
+    pub fn clear(&mut self) {
+        *self = Codes::default();
+    }
+
+    /// Bytes held on the heap
+    pub fn heap_bytes(&self) -> usize {
+        self.rows.capacity() + self.coded.capacity()
+    }
+}
@@ -0,0 +1,225 @@
+//! Vectors-only snapshots
+//!
+//! A full snapshot carries every neighbor list, which for typical `m`
+//! outweighs the vectors themselves. A vectors-only snapshot keeps ids,
+//! stored vectors, payloads, sparse vectors and text statistics, and the
+//! graph is rebuilt by re-inserting the points on load, trading load time
+//! for a much smaller artifact. [`GraphRebuild`] does the re-insertion in
+//! steps so callers can report progress or yield between batches.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use crate::ids::Id;
+use crate::index::Hnsw;
+use crate::payload_index::PayloadIndexKind;
+use crate::projection::RandomProjection;
+use crate::scalar::Scalar;
+use crate::schema::PayloadSchema;
+use crate::snapshot::{self, Kind};
+use crate::sparse::SparseIndex;
+use crate::store::VectorStore;
+use crate::text::TextIndex;
+use crate::{HNSWParams, HnswError};
+
+/// Points linked between progress reports in [`Hnsw::rebuild_from_bytes`]
+const PROGRESS_BATCH: usize = 1024;
+
+#[derive(Serialize)]
+#[serde(bound = "")]
+struct VectorsRef<'a, S: Scalar> {
+    params: &'a HNSWParams,
+    dimensions: usize,
+    projection: Option<&'a RandomProjection>,
+    /// Stored-space vectors in id order
+    points: Vec<(&'a str, Cow<'a, [S]>)>,
+    payloads: &'a HashMap<Id, Value>,
+    payload_indexes: Vec<(&'a str, PayloadIndexKind)>,
+    schema: &'a PayloadSchema,
+    sparse: &'a SparseIndex,
+    text: &'a TextIndex,
+}
+
+#[derive(Deserialize)]
+#[serde(bound = "")]
+struct Vectors<S: Scalar> {
+    params: HNSWParams,
+    dimensions: usize,
+    projection: Option<RandomProjection>,
+    points: Vec<(String, Vec<S>)>,
+    payloads: HashMap<Id, Value>,
+    payload_indexes: Vec<(String, PayloadIndexKind)>,
+    schema: PayloadSchema,
+    sparse: SparseIndex,
+    text: TextIndex,
+}
+
+impl<S: Scalar, V: VectorStore<S>> Hnsw<S, V> {
+    /// Serialize ids, vectors and point data without the graph
+    ///
+    /// Load the result with [`Hnsw::rebuild_from_bytes`] or
+    /// [`GraphRebuild`]; the graph is rebuilt with the saved parameters.
+    pub fn to_vectors_only_bytes(&self, compress: bool) -> Result<Vec<u8>, HnswError> {
+        let ids: Vec<String> = self.ids().map(Id::to_string).collect();
+        let points = ids
+            .iter()
+            .map(|id| {
+                self.vector(id)
+                    .map(|vector| (id.as_str(), vector))
+                    .ok_or_else(|| HnswError::Storage(format!("vector of {} is missing", id)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let snapshot = VectorsRef {
+            params: self.params(),
+            dimensions: self.dimensions(),
+            projection: self.projection(),
+            points,
+            payloads: self.payloads(),
+            payload_indexes: self.payload_index_kinds(),
+            schema: self.schema(),
+            sparse: self.sparse(),
+            text: self.text(),
+        };
+        let mut flags = snapshot::FLAG_VECTORS_ONLY;
+        if compress {
+            flags |= snapshot::FLAG_COMPRESSED;
+        }
+        snapshot::encode(&snapshot, S::NAME, self.dimensions(), flags)
+    }
+}
+
+impl<S: Scalar> Hnsw<S> {
+    /// Load a vectors-only snapshot, rebuilding its graph
+    ///
+    /// `progress(done, total)` is called after each batch of points.
+    pub fn rebuild_from_bytes(
+        data: &[u8],
+        mut progress: impl FnMut(usize, usize),
+    ) -> Result<Hnsw<S>, HnswError> {
+        let mut rebuild = GraphRebuild::new(data)?;
+        while !rebuild.step(PROGRESS_BATCH)? {
+            progress(rebuild.done(), rebuild.total());
+        }
+        progress(rebuild.done(), rebuild.total());
+        rebuild.finish()
+    }
+}
+
+/// Incremental load of a vectors-only snapshot
+///
+/// Each [`GraphRebuild::step`] links a bounded number of points, so a
+/// caller on a UI thread can spread the work over several turns.
+pub struct GraphRebuild<S: Scalar> {
+    index: Hnsw<S>,
+    pending: std::vec::IntoIter<(String, Vec<S>)>,
+    total: usize,
+    payloads: HashMap<Id, Value>,
+    payload_indexes: Vec<(String, PayloadIndexKind)>,
+    sparse: SparseIndex,
+    text: TextIndex,
+}
+
+impl<S: Scalar> GraphRebuild<S> {
+    /// Decode and check a vectors-only snapshot, linking nothing yet
+    pub fn new(data: &[u8]) -> Result<GraphRebuild<S>, HnswError> {
+        let snapshot: Vectors<S> =
+            snapshot::decode(data, S::NAME, Kind::VectorsOnly, |s: &Vectors<S>| {
+                s.dimensions
+            })?;
+        let corrupt = |msg: String| Err(HnswError::CorruptSnapshot(msg));
+
+        if snapshot.params.m < 2 {
+            return corrupt(format!("m is {}, must be at least 2", snapshot.params.m));
+        }
+        let stored_dimensions = match &snapshot.projection {
+            Some(p) if !p.is_well_formed() || p.input_dim() != snapshot.dimensions => {
+                return corrupt(format!(
+                    "projection expects {} dimensions, index has {}",
+                    p.input_dim(),
+                    snapshot.dimensions
+                ));
+            }
+            Some(p) => p.output_dim(),
+            None => snapshot.dimensions,
+        };
+        let mut ids = std::collections::HashSet::new();
+        for (id, vector) in &snapshot.points {
+            if vector.len() != stored_dimensions {
+                return corrupt(format!(
+                    "point {} has {} dimensions, index stores {}",
+                    id,
+                    vector.len(),
+                    stored_dimensions
+                ));
+            }
+            if !ids.insert(id.as_str()) {
+                return corrupt(format!("point {} appears twice", id));
+            }
+        }
+        let stray = snapshot
+            .payloads
+            .keys()
+            .chain(snapshot.sparse.ids())
+            .chain(snapshot.text.ids())
+            .map(Id::to_string)
+            .find(|id| !ids.contains(id.as_str()));
+        if let Some(id) = stray {
+            return corrupt(format!("point data kept for missing point {}", id));
+        }
+
+        let mut index = Hnsw::with_params(snapshot.params);
+        index.set_shape(snapshot.dimensions, snapshot.projection);
+        index
+            .set_schema(snapshot.schema)
+            .map_err(|e| HnswError::CorruptSnapshot(e.to_string()))?;
+        Ok(GraphRebuild {
+            index,
+            total: snapshot.points.len(),
+            pending: snapshot.points.into_iter(),
+            payloads: snapshot.payloads,
+            payload_indexes: snapshot.payload_indexes,
+            sparse: snapshot.sparse,
+            text: snapshot.text,
+        })
+    }
+
+    /// Points in the snapshot
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// Points linked so far
+    pub fn done(&self) -> usize {
+        self.total - self.pending.len()
+    }
+
+    /// Link up to `max_points` more points, returning whether all are in
+    pub fn step(&mut self, max_points: usize) -> Result<bool, HnswError> {
+        for (id, vector) in self.pending.by_ref().take(max_points) {
+            self.index.insert_stored(id, vector)?;
+        }
+        Ok(self.pending.len() == 0)
+    }
+
+    /// Link any remaining points and restore the per-point data
+    pub fn finish(mut self) -> Result<Hnsw<S>, HnswError> {
+        self.step(usize::MAX)?;
+        let mut index = self.index;
+        for (id, payload) in self.payloads {
+            index
+                .set_payload(&id.to_string(), payload)
+                .map_err(|e| HnswError::CorruptSnapshot(e.to_string()))?;
+        }
+        for (field, kind) in &self.payload_indexes {
+            index
+                .create_payload_index(field, *kind)
+                .map_err(|e| HnswError::CorruptSnapshot(e.to_string()))?;
+        }
+        *index.sparse_mut() = self.sparse;
+        *index.text_mut() = self.text;
+        index.check_loaded()?;
+        Ok(index)
+    }
+}
@@ -0,0 +1,193 @@
+//! Queries built from positive and negative examples
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::index::Hnsw;
+use crate::scalar::Scalar;
+use crate::search::SearchOptions;
+use crate::store::VectorStore;
+use crate::HnswError;
+
+/// An example point, given by id or by vector
+#[derive(Clone, Debug, PartialEq)]
+pub enum Example<S> {
+    /// An indexed point, whose stored vector is used
+    Id(String),
+    /// A vector in input space
+    Vector(Vec<S>),
+}
+
+impl<S: Scalar> Example<S> {
+    /// Parse an id string or an array of numbers
+    pub fn parse(value: Value) -> Result<Example<S>, HnswError> {
+        match value {
+            Value::String(id) => Ok(Example::Id(id)),
+            Value::Array(items) => items
+                .iter()
+                .map(|item| item.as_f64().map(S::from_f64))
+                .collect::<Option<Vec<S>>>()
+                .map(Example::Vector)
+                .ok_or_else(|| {
+                    HnswError::InvalidParams("example vectors must hold only numbers".to_string())
+                }),
+            other => Err(HnswError::InvalidParams(format!(
+                "an example must be an id or a vector, got {}",
+                other
+            ))),
+        }
+    }
+
+    /// Parse an array of examples; `null` is an empty list
+    pub fn parse_list(value: Value) -> Result<Vec<Example<S>>, HnswError> {
+        match value {
+            Value::Null => Ok(Vec::new()),
+            Value::Array(items) => items.into_iter().map(Example::parse).collect(),
+            other => Err(HnswError::InvalidParams(format!(
+                "examples must be an array, got {}",
+                other
+            ))),
+        }
+    }
+}
+
+/// How examples are combined into a ranking
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RecommendStrategy {
+    /// Search once with `2 * mean(positives) - mean(negatives)`
+    ///
+    /// Cheap, and good when the positives are alike.
+    #[default]
+    AverageVector,
+    /// Score each point by its closest example
+    ///
+    /// Points nearer a positive than any negative score their best
+    /// positive similarity; the rest score minus their best negative
+    /// similarity. Slower, but keeps disparate positives apart instead of
+    /// searching between them.
+    BestScore,
+}
+
+impl RecommendStrategy {
+    /// Parse `"averageVector"` or `"bestScore"`
+    pub fn parse(strategy: &str) -> Result<RecommendStrategy, HnswError> {
+        match strategy {
+            "averageVector" => Ok(RecommendStrategy::AverageVector),
+            "bestScore" => Ok(RecommendStrategy::BestScore),
+            other => Err(HnswError::InvalidParams(format!(
+                "unknown recommend strategy {}",
+                other
+            ))),
+        }
+    }
+}
+
+impl<S: Scalar, V: VectorStore<S>> Hnsw<S, V> {
+    /// Find `k` points like the `positive` examples and unlike the `negative` ones
+    ///
+    /// Examples given by id are never returned. `options` apply as in
+    /// [`Hnsw::search`].
+    pub fn recommend(
+        &self,
+        positive: &[Example<S>],
+        negative: &[Example<S>],
+        k: usize,
+        strategy: RecommendStrategy,
+        options: &SearchOptions,
+    ) -> Result<Vec<(String, f32)>, HnswError> {
+        if positive.is_empty() {
+            return Err(HnswError::InvalidParams(
+                "recommend needs at least one positive example".to_string(),
+            ));
+        }
+        if self.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut options = options.clone();
+        for example in positive.iter().chain(negative) {
+            if let Example::Id(id) = example {
+                options.exclude.insert(id.clone());
+            }
+        }
+        let positive = self.resolve_examples(positive)?;
+        let negative = self.resolve_examples(negative)?;
+
+        match strategy {
+            RecommendStrategy::AverageVector => {
+                let query = average_query(&positive, &negative);
+                self.explain_query(&query, k, &options)
+                    .map(|(results, _)| results)
+            }
+            RecommendStrategy::BestScore => self.best_score(&positive, &negative, k, &options),
+        }
+    }
+
+    /// Stored-space vectors of examples
+    fn resolve_examples(&self, examples: &[Example<S>]) -> Result<Vec<Vec<S>>, HnswError> {
+        examples
+            .iter()
+            .map(|example| match example {
+                Example::Id(id) => self
+                    .vector(id)
+                    .map(|v| v.into_owned())
+                    .ok_or_else(|| HnswError::UnknownId(id.clone())),
+                Example::Vector(vector) => self.prepare_query(vector),
+            })
+            .collect()
+    }
+
+    /// Rank the neighbors of each positive by their closest example
+    fn best_score(
+        &self,
+        positive: &[Vec<S>],
+        negative: &[Vec<S>],
+        k: usize,
+        options: &SearchOptions,
+    ) -> Result<Vec<(String, f32)>, HnswError> {
+        // Negatives push some neighbors down, so look a little deeper
+        let depth = options.candidates_per_result(if negative.is_empty() { 1 } else { 2 });
+        let candidates = self.gather(positive, k.saturating_mul(depth), options)?;
+
+        let best = |examples: &[Vec<S>], stored: &[S]| {
+            examples
+                .iter()
+                .map(|e| 1.0 - S::cosine_distance(e, stored))
+                .fold(f32::NEG_INFINITY, f32::max)
+        };
+        Ok(self.rerank(candidates, k, options, |stored| {
+            let pos = best(positive, stored);
+            let neg = best(negative, stored);
+            if pos > neg {
+                pos
+            } else {
+                -neg
+            }
+        }))
+    }
+}
+
+/// `2 * mean(positive) - mean(negative)`, or the positive mean alone
+fn average_query<S: Scalar>(positive: &[Vec<S>], negative: &[Vec<S>]) -> Vec<S> {
+    let pos = mean(positive);
+    if negative.is_empty() {
+        return pos.into_iter().map(S::from_f64).collect();
+    }
+    let neg = mean(negative);
+    pos.iter()
+        .zip(&neg)
+        .map(|(p, n)| S::from_f64(2.0 * p - n))
+        .collect()
+}
+
+fn mean<S: Scalar>(vectors: &[Vec<S>]) -> Vec<f64> {
+    let dim = vectors.first().map_or(0, Vec::len);
+    let mut sum = vec![0.0; dim];
+    for vector in vectors {
+        for (total, x) in sum.iter_mut().zip(vector) {
+            *total += x.to_f64();
+        }
+    }
+    let n = vectors.len().max(1) as f64;
+    sum.into_iter().map(|total| total / n).collect()
+}
@@ -0,0 +1,117 @@
+//! Second-stage rescoring of search candidates, e.g. by a cross-encoder
+
+use serde_json::Value;
+
+use crate::index::Hnsw;
+use crate::scalar::Scalar;
+use crate::search::{best_first, SearchOptions};
+use crate::store::VectorStore;
+use crate::HnswError;
+
+/// A search hit handed to a [`Reranker`]
+#[derive(Clone, Copy, Debug)]
+pub struct RerankCandidate<'a> {
+    pub id: &'a str,
+    /// Vector similarity from the first stage
+    pub score: f32,
+    /// Full payload of the point, whatever `withPayload` asks for
+    pub payload: Option<&'a Value>,
+}
+
+/// Rescores candidates, returning one score per candidate in the same order
+///
+/// The reranker owns the query, e.g. the question text a cross-encoder
+/// pairs with each candidate's chunk. Closures taking the candidates work
+/// as rerankers.
+pub trait Reranker {
+    fn rerank(&mut self, candidates: &[RerankCandidate<'_>]) -> Result<Vec<f32>, HnswError>;
+}
+
+impl<F> Reranker for F
+where
+    F: FnMut(&[RerankCandidate<'_>]) -> Result<Vec<f32>, HnswError>,
+{
+    fn rerank(&mut self, candidates: &[RerankCandidate<'_>]) -> Result<Vec<f32>, HnswError> {
+        self(candidates)
+    }
+}
+
+impl<S: Scalar, V: VectorStore<S>> Hnsw<S, V> {
+    /// Fetch `candidates` hits for `vector` and keep the `k` that `reranker`
+    /// scores best
+    ///
+    /// Results carry the reranker's scores, and `minScore` applies to
+    /// them rather than to the vector similarity. Asking for fewer
+    /// candidates than `k` fetches `k`. `diversity` is not supported.
+    pub fn search_reranked<R: Reranker + ?Sized>(
+        &self,
+        vector: &[S],
+        k: usize,
+        candidates: usize,
+        reranker: &mut R,
+        options: &SearchOptions,
+    ) -> Result<Vec<(String, f32)>, HnswError> {
+        let hits = self.rerank_candidates(vector, k, candidates, options)?;
+        let scores = {
+            let candidates: Vec<RerankCandidate> = hits
+                .iter()
+                .map(|(id, score)| RerankCandidate {
+                    id,
+                    score: *score,
+                    payload: self.payload(id),
+                })
+                .collect();
+            reranker.rerank(&candidates)?
+        };
+        apply_rerank(hits, scores, k, options)
+    }
+
+    /// First-stage hits for [`Hnsw::search_reranked`]
+    pub(crate) fn rerank_candidates(
+        &self,
+        vector: &[S],
+        k: usize,
+        candidates: usize,
+        options: &SearchOptions,
+    ) -> Result<Vec<(String, f32)>, HnswError> {
+        if options.diversity.is_some() {
+            return Err(HnswError::InvalidParams(
+                "diversity cannot be combined with reranking".to_string(),
+            ));
+        }
+        // Vector similarities are not comparable with the reranked scores
+        let mut plain = options.clone();
+        plain.min_score = None;
+        self.search(vector, candidates.max(k), &plain)
+    }
+}
+
+/// Replace the scores of `hits` with `scores` and keep the best `k`
+pub(crate) fn apply_rerank(
+    hits: Vec<(String, f32)>,
+    scores: Vec<f32>,
+    k: usize,
+    options: &SearchOptions,
+) -> Result<Vec<(String, f32)>, HnswError> {
+    if scores.len() != hits.len() {
+        return Err(HnswError::InvalidParams(format!(
+            "reranker returned {} scores for {} candidates",
+            scores.len(),
+            hits.len()
+        )));
+    }
+    if let Some(score) = scores.iter().find(|s| !s.is_finite()) {
+        return Err(HnswError::InvalidParams(format!(
+            "reranker scores must be finite, got {}",
+            score
+        )));
+    }
+    let mut results: Vec<(String, f32)> = hits
+        .into_iter()
+        .zip(scores)
+        .map(|((id, _), score)| (id, score))
+        .collect();
+    results.sort_by(best_first);
+    results.truncate(k);
+    Ok(options.finish(results))
+}
@@ -0,0 +1,234 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt::Debug;
+
+/// Element type of stored vectors
+///
+/// Each scalar brings its own cosine kernel so integer vectors are scored
+/// without widening every element to a float first.
+pub trait Scalar:
+    Copy + Default + PartialEq + Debug + Serialize + DeserializeOwned + Send + Sync + 'static
+{
+    /// Human-readable type name used in error messages
+    const NAME: &'static str;
+
+    /// Whether values can be negative or fractional (required by projection)
+    const IS_FLOAT: bool;
+
+    /// Size of one element in bytes
+    const BYTES: usize = std::mem::size_of::<Self>();
+
+    /// Cosine distance in `[0, 2]`; 1.0 when either vector is all zeros
+    fn cosine_distance(a: &[Self], b: &[Self]) -> f32;
+
+    /// Cosine distances from `query` to each `query.len()`-wide row of
+    /// `block`, appended to `out`
+    ///
+    /// Agrees with [`Scalar::cosine_distance`] row by row. The default
+    /// scores rows one at a time; kernels that can share the query's norm
+    /// across rows override it.
+    fn cosine_distances(query: &[Self], block: &[Self], out: &mut Vec<f32>) {
+        if query.is_empty() {
+            return;
+        }
+        out.extend(
+            block
+                .chunks_exact(query.len())
+                .map(|row| Self::cosine_distance(query, row)),
+        );
+    }
+
+    fn to_f64(self) -> f64;
+
+    fn from_f64(value: f64) -> Self;
+
+    /// Append the little-endian encoding of `self` to `out`
+    fn write_le(self, out: &mut Vec<u8>);
+
+    /// Decode a value from exactly `BYTES` little-endian bytes
+    fn read_le(bytes: &[u8]) -> Self;
+}
+
+impl Scalar for f32 {
+    const NAME: &'static str = "f32";
+    const IS_FLOAT: bool = true;
+
+    fn cosine_distance(a: &[f32], b: &[f32]) -> f32 {
+        let (dot, norm_b) = dot_and_norm(a, b);
+        cosine_from_sums(dot, squared_norm(a), norm_b)
+    }
+
+    /// Computes the query norm once, then one fused pass per row
+    fn cosine_distances(query: &[f32], block: &[f32], out: &mut Vec<f32>) {
+        if query.is_empty() {
+            return;
+        }
+        let norm_query = squared_norm(query);
+        out.extend(block.chunks_exact(query.len()).map(|row| {
+            let (dot, norm_row) = dot_and_norm(query, row);
+            cosine_from_sums(dot, norm_query, norm_row)
+        }));
+    }
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+
+    fn from_f64(value: f64) -> Self {
+        value as f32
+    }
+
+    fn write_le(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+
+    fn read_le(bytes: &[u8]) -> Self {
+        let mut buf = [0u8; 4];
+        buf.copy_from_slice(bytes);
+        f32::from_le_bytes(buf)
+    }
+}
+
+/// Independent partial sums in the f32 kernels
+///
+/// Float addition does not reassociate, so a single running sum forces one
+/// add after another. Four lanes fill one 128-bit register (SSE, NEON or
+/// wasm `simd128`); wider sets get split across registers and shuffled,
+/// which measured slower than the plain loop.
+const LANES: usize = 4;
+
+/// `(a · b, b · b)` in one pass
+fn dot_and_norm(a: &[f32], b: &[f32]) -> (f32, f32) {
+    let mut dot = [0.0f32; LANES];
+    let mut norm = [0.0f32; LANES];
+    let len = a.len().min(b.len());
+    let mut a_lanes = a[..len].chunks_exact(LANES);
+    let mut b_lanes = b[..len].chunks_exact(LANES);
+    for (x, y) in (&mut a_lanes).zip(&mut b_lanes) {
+        let (Ok::<&[f32; LANES], _>(x), Ok::<&[f32; LANES], _>(y)) = (x.try_into(), y.try_into())
+        else {
+            unreachable!("chunks_exact yields whole lanes")
+        };
+        // Separate loops keep each sum in its own register
+        for i in 0..LANES {
+            dot[i] += x[i] * y[i];
+        }
+        for i in 0..LANES {
+            norm[i] += y[i] * y[i];
+        }
+    }
+    let (a_rest, b_rest) = (a_lanes.remainder(), b_lanes.remainder());
+    for (i, (&x, &y)) in a_rest.iter().zip(b_rest).enumerate() {
+        dot[i] += x * y;
+        norm[i] += y * y;
+    }
+    (dot.iter().sum(), norm.iter().sum())
+}
+
+/// `a · a`, lane by lane as [`dot_and_norm`] so both round alike
+fn squared_norm(a: &[f32]) -> f32 {
+    let mut norm = [0.0f32; LANES];
+    let mut lanes = a.chunks_exact(LANES);
+    for x in &mut lanes {
+        let Ok::<&[f32; LANES], _>(x) = x.try_into() else {
+            unreachable!("chunks_exact yields whole lanes")
+        };
+        for i in 0..LANES {
+            norm[i] += x[i] * x[i];
+        }
+    }
+    for (i, &x) in lanes.remainder().iter().enumerate() {
+        norm[i] += x * x;
+    }
+    norm.iter().sum()
+}
+
+fn cosine_from_sums(dot: f32, norm_a: f32, norm_b: f32) -> f32 {
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 1.0;
+    }
+    1.0 - (dot / (norm_a.sqrt() * norm_b.sqrt()))
+}
+
+impl Scalar for u8 {
+    const NAME: &'static str = "u8";
+    const IS_FLOAT: bool = false;
+
+    /// Integer kernel: products and norms accumulate exactly in `u64`
+    fn cosine_distance(a: &[u8], b: &[u8]) -> f32 {
+        let mut dot: u64 = 0;
+        let mut norm_a: u64 = 0;
+        let mut norm_b: u64 = 0;
+
+        for (&x, &y) in a.iter().zip(b) {
+            let (x, y) = (x as u32, y as u32);
+            dot += (x * y) as u64;
+            norm_a += (x * x) as u64;
+            norm_b += (y * y) as u64;
+        }
+
+        if norm_a == 0 || norm_b == 0 {
+            return 1.0;
+        }
+
+        (1.0 - dot as f64 / ((norm_a as f64).sqrt() * (norm_b as f64).sqrt())) as f32
+    }
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+
+    fn from_f64(value: f64) -> Self {
+        value.round().clamp(0.0, 255.0) as u8
+    }
+
+    fn write_le(self, out: &mut Vec<u8>) {
+        out.push(self);
+    }
+
+    fn read_le(bytes: &[u8]) -> Self {
+        bytes[0]
+    }
+}
+
+impl Scalar for f64 {
+    const NAME: &'static str = "f64";
+    const IS_FLOAT: bool = true;
+
+    /// Accumulates in double precision; only the final distance is narrowed
+    fn cosine_distance(a: &[f64], b: &[f64]) -> f32 {
+        let mut dot = 0.0;
+        let mut norm_a = 0.0;
+        let mut norm_b = 0.0;
+
+        for (&x, &y) in a.iter().zip(b) {
+            dot += x * y;
+            norm_a += x * x;
+            norm_b += y * y;
+        }
+
+        if norm_a == 0.0 || norm_b == 0.0 {
+            return 1.0;
+        }
+
+        (1.0 - (dot / (norm_a.sqrt() * norm_b.sqrt()))) as f32
+    }
+
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+
+    fn write_le(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+
+    fn read_le(bytes: &[u8]) -> Self {
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(bytes);
+        f64::from_le_bytes(buf)
+    }
+}
@@ -0,0 +1,140 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use crate::filter::{lookup, Condition, Filter};
+use crate::payload_index::PayloadIndexKind;
+use crate::HnswError;
+
+/// Declared type of a payload field
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    /// A string or array of strings matched exactly, e.g. language or tags
+    Keyword,
+    /// A number or array of numbers
+    Number,
+    /// `true` or `false`
+    Bool,
+    /// Free-form string
+    Text,
+}
+
+impl FieldType {
+    fn accepts(self, value: &Value) -> bool {
+        match (self, value) {
+            (FieldType::Keyword, Value::Array(items)) => items.iter().all(Value::is_string),
+            (FieldType::Number, Value::Array(items)) => items.iter().all(Value::is_number),
+            (FieldType::Keyword | FieldType::Text, Value::String(_)) => true,
+            (FieldType::Number, Value::Number(_)) => true,
+            (FieldType::Bool, Value::Bool(_)) => true,
+            _ => false,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            FieldType::Keyword => "keyword",
+            FieldType::Number => "number",
+            FieldType::Bool => "bool",
+            FieldType::Text => "text",
+        }
+    }
+}
+
+/// Declared payload fields, keyed by (dotted) field name
+///
+/// Declared fields may be absent from a payload, but when present they
+/// must have the declared type. Undeclared fields are not checked. Filters
+/// may only apply operators that make sense for the declared type.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PayloadSchema {
+    fields: BTreeMap<String, FieldType>,
+}
+
+impl PayloadSchema {
+    pub fn new(fields: BTreeMap<String, FieldType>) -> PayloadSchema {
+        PayloadSchema { fields }
+    }
+
+    /// Parse a `{ field: "keyword" | "number" | "bool" | "text" }` object
+    pub fn parse(value: Value) -> Result<PayloadSchema, HnswError> {
+        serde_json::from_value(value).map_err(|e| HnswError::InvalidParams(e.to_string()))
+    }
+
+    /// Declared type of a field
+    pub fn field(&self, name: &str) -> Option<FieldType> {
+        self.fields.get(name).copied()
+    }
+
+    /// Check a payload against the declared field types
+    pub fn check_payload(&self, payload: &Value) -> Result<(), HnswError> {
+        for (field, kind) in &self.fields {
+            match lookup(Some(payload), field) {
+                Some(value) if !kind.accepts(value) => {
+                    return Err(HnswError::SchemaViolation(format!(
+                        "field {} must be {}, got {}",
+                        field,
+                        kind.name(),
+                        value
+                    )))
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that a filter only uses operators valid for declared fields
+    pub fn check_filter(&self, filter: &Filter) -> Result<(), HnswError> {
+        match filter {
+            Filter::All(filters) | Filter::Any(filters) => {
+                filters.iter().try_for_each(|f| self.check_filter(f))
+            }
+            Filter::Not(filter) => self.check_filter(filter),
+            Filter::Field { key, condition } => {
+                let kind = match self.field(key) {
+                    Some(kind) => kind,
+                    None => return Ok(()),
+                };
+                let valid = match condition {
+                    Condition::Range(_) => kind == FieldType::Number,
+                    Condition::Equals(value) => kind.accepts(value),
+                    Condition::In(options) => options.iter().all(|v| kind.accepts(v)),
+                };
+                if valid {
+                    Ok(())
+                } else {
+                    Err(HnswError::InvalidFilter(format!(
+                        "condition on {} does not fit its {} type",
+                        key,
+                        kind.name()
+                    )))
+                }
+            }
+        }
+    }
+
+    /// Check that a payload index kind suits a declared field
+    pub fn check_index(&self, field: &str, kind: PayloadIndexKind) -> Result<(), HnswError> {
+        let valid = matches!(
+            (self.field(field), kind),
+            (None, _)
+                | (
+                    Some(FieldType::Keyword | FieldType::Text),
+                    PayloadIndexKind::Keyword
+                )
+                | (Some(FieldType::Number), PayloadIndexKind::Number)
+        );
+        if valid {
+            Ok(())
+        } else {
+            Err(HnswError::SchemaViolation(format!(
+                "field {} is declared {}, which this index type does not support",
+                field,
+                self.field(field).map_or("", FieldType::name)
+            )))
+        }
+    }
+}
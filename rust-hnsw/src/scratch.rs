@@ -0,0 +1,175 @@
+//! Reusable buffers for graph walks
+//!
+//! Every layer walk needs a visited set, a frontier, a result list and a
+//! few smaller lists. Allocating them per query costs as much as the walk
+//! on small graphs, and more in wasm, so an index keeps a [`ScratchPool`]
+//! and each search borrows a [`Scratch`] from it for its duration. A pool
+//! rather than a single set keeps searches started while another is in
+//! progress, such as from inside a filter, correct.
+//!
+//! [`Visited`] keeps one mark per node holding the epoch of the walk that
+//! last saw it, so starting a walk just bumps the epoch instead of
+//! clearing a set. The frontier and the best nodes so far are heaps, so a
+//! step costs a logarithm of the beam rather than a re-sort of it.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::sync::{Mutex, PoisonError};
+
+/// Nodes seen by the current walk
+pub(crate) struct Visited {
+    epoch: u32,
+    marks: Vec<u32>,
+}
+
+impl Visited {
+    /// Mark `node`, returning whether the current walk had not seen it
+    pub fn insert(&mut self, node: u32) -> bool {
+        match self.marks.get_mut(node as usize) {
+            Some(mark) if *mark != self.epoch => {
+                *mark = self.epoch;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Forget every mark and size the set for nodes below `nodes`
+    pub fn start(&mut self, nodes: usize) {
+        if self.marks.len() < nodes {
+            self.marks.resize(nodes, 0);
+        }
+        self.epoch = self.epoch.wrapping_add(1);
+        if self.epoch == 0 {
+            // Marks from 2^32 walks ago would alias the new epoch
+            self.marks.fill(0);
+            self.epoch = 1;
+        }
+    }
+}
+
+/// Working memory of one search over vectors of `S`
+pub(crate) struct Scratch<S> {
+    pub visited: Visited,
+    /// Seeds of the next layer walk
+    pub entries: Vec<u32>,
+    /// Frontier of a walk, nearest on top
+    pub candidates: BinaryHeap<Reverse<Scored>>,
+    /// Best nodes of the walk in progress, farthest on top
+    pub best: BinaryHeap<Scored>,
+    /// Best nodes of the last walk as `(node, distance)`, nearest first
+    pub results: Vec<(u32, f32)>,
+    /// Neighbors gathered for one expansion
+    pub expansion: Vec<u32>,
+    /// Vectors of `expansion` back to back, scored as one block
+    pub block: Vec<S>,
+    /// Distances to the rows of `block`
+    pub distances: Vec<f32>,
+    /// Code of the query when the walk scores vector codes instead of
+    /// vectors; empty otherwise
+    pub coded: Vec<i8>,
+    /// Nodes a widening walk scored but kept off its frontier, as too far
+    /// for the beam at the time
+    pub deferred: Vec<Scored>,
+    /// Nodes a widening walk dropped from `best` as the beam filled
+    pub evicted: Vec<Scored>,
+}
+
+impl<S> Scratch<S> {
+    /// Move the best nodes of a finished walk into `results`, nearest first
+    pub fn finish_walk(&mut self) {
+        self.results.clear();
+        self.results
+            .extend(self.best.drain().map(|Scored(dist, node)| (node, dist)));
+        self.results
+            .sort_unstable_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+    }
+}
+
+/// Idle scratch buffers, one per search that has run at the same time
+///
+/// Locked only to take or return a set, never during a walk, so searches
+/// on several threads each work in their own buffers.
+pub(crate) struct ScratchPool<S> {
+    idle: Mutex<Vec<Scratch<S>>>,
+}
+
+impl<S> Default for ScratchPool<S> {
+    fn default() -> ScratchPool<S> {
+        ScratchPool {
+            idle: Mutex::default(),
+        }
+    }
+}
+
+impl<S> ScratchPool<S> {
+    /// Buffers for one search, emptied
+    pub fn take(&self) -> Scratch<S> {
+        match self.idle().pop() {
+            Some(mut scratch) => {
+                scratch.entries.clear();
+                scratch.candidates.clear();
+                scratch.best.clear();
+                scratch.results.clear();
+                scratch.expansion.clear();
+                scratch.block.clear();
+                scratch.distances.clear();
+                scratch.coded.clear();
+                scratch.deferred.clear();
+                scratch.evicted.clear();
+                scratch
+            }
+            None => Scratch {
+                visited: Visited {
+                    epoch: 0,
+                    marks: Vec::new(),
+                },
+                entries: Vec::new(),
+                candidates: BinaryHeap::new(),
+                best: BinaryHeap::new(),
+                results: Vec::new(),
+                expansion: Vec::new(),
+                block: Vec::new(),
+                distances: Vec::new(),
+                coded: Vec::new(),
+                deferred: Vec::new(),
+                evicted: Vec::new(),
+            },
+        }
+    }
+
+    /// Return buffers for later searches
+    pub fn give(&self, scratch: Scratch<S>) {
+        self.idle().push(scratch);
+    }
+
+    /// Drop idle buffers, e.g. after the graph was cleared
+    pub fn clear(&mut self) {
+        self.idle
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+
+    fn idle(&self) -> std::sync::MutexGuard<'_, Vec<Scratch<S>>> {
+        self.idle.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Candidate ordered by distance, then node
+#[derive(PartialEq)]
+pub(crate) struct Scored(pub f32, pub u32);
+
+impl Eq for Scored {}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Scored) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Scored) -> Ordering {
+        self.0.total_cmp(&other.0).then(self.1.cmp(&other.1))
+    }
+}
@@ -0,0 +1,801 @@
+//! Query options shared by the search entry points
+
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+use crate::beam::Beam;
+use crate::budget::Meter;
+use crate::decay::Decay;
+use crate::filter::{lookup, lookup_values, Condition, Filter};
+use crate::ids::Id;
+use crate::index::Hnsw;
+use crate::planner::QueryPlan;
+use crate::scalar::Scalar;
+use crate::store::VectorStore;
+use crate::HnswError;
+
+/// Results of [`Hnsw::explain`] with the filter plan, if any
+type Explained = (Vec<(String, f32)>, Option<QueryPlan>);
+
+/// Order `(id, score)` hits best first, ties by id
+///
+/// Compares scores with [`f32::total_cmp`], so a NaN score cannot panic
+/// or scramble a sort.
+pub fn best_first<T: Ord>(a: &(T, f32), b: &(T, f32)) -> Ordering {
+    b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0))
+}
+
+/// Optional knobs for [`Hnsw::search`]
+///
+/// Parsed from `{ filter, minScore, exclude, excludePrefix, withVector,
+/// withPayload, diversity, decay, prefetch, maxVisits, timeoutMs, ef,
+/// adaptiveEf }`; every field may be omitted.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SearchOptions {
+    /// Only return points whose payload matches
+    pub filter: Option<Filter>,
+    /// Drop results scoring below this
+    ///
+    /// Scores are cosine similarities, higher is better, so this keeps
+    /// results with `score >= min_score`.
+    pub min_score: Option<f32>,
+    /// Ids never returned, e.g. chunks of the file being edited
+    pub exclude: HashSet<String>,
+    /// Ids starting with this prefix are never returned
+    pub exclude_prefix: Option<String>,
+    /// Return the stored vector of each hit as its `vector`
+    pub with_vector: bool,
+    /// Payload returned with each hit as its `metadata`
+    ///
+    /// `true` (the default) for all of it, `false` for none, or an array
+    /// of field names.
+    pub with_payload: PayloadSelector,
+    /// Maximal marginal relevance trade-off in `[0, 1]`
+    ///
+    /// 0 ranks purely by score; higher values increasingly penalize hits
+    /// similar to ones already returned, so near-duplicate snippets do not
+    /// crowd out the rest.
+    pub diversity: Option<f32>,
+    /// Scale scores down by distance from an origin in a payload field,
+    /// e.g. to prefer recently modified files
+    ///
+    /// Applied before `minScore`. Scores are multiplied by the factor, so
+    /// it is meant for positive similarities. See [`Decay`] for its form.
+    pub decay: Option<Decay>,
+    /// Candidates to fetch from the graph before keeping the best `k`
+    ///
+    /// With [`HNSWParams::quantize`](crate::HNSWParams::quantize) on, the
+    /// walk ranks candidates by their 8-bit codes and this many of the
+    /// best are rescored with the full vectors, so a larger pool wins back
+    /// recall lost to quantization. Otherwise candidates carry exact
+    /// scores and a larger pool only widens the walk. Values below `k`
+    /// have no effect.
+    pub prefetch: Option<usize>,
+    /// Stop after scoring this many points, returning the best so far
+    ///
+    /// [`Hnsw::search_within_budget`] reports whether the search was cut
+    /// short.
+    pub max_visits: Option<usize>,
+    /// Stop after about this many milliseconds, returning the best so far
+    ///
+    /// Checked while walking the graph, so it bounds the search itself,
+    /// not reranking or decay applied to its results.
+    pub timeout_ms: Option<f64>,
+    /// Candidates kept while walking the base layer, in place of the
+    /// index's `ef_search` for this query alone
+    ///
+    /// Lower trades recall for latency, higher the reverse; values below
+    /// `k` act as `k`.
+    pub ef: Option<usize>,
+    /// Widen the beam from `k` toward this many candidates only until
+    /// the top `k` stop changing, instead of walking at `ef_search`
+    ///
+    /// Easy queries settle in a few rounds at a fraction of the cost;
+    /// hard ones keep widening up to the cap. With a filter or exclusions
+    /// the walk steps through rejected points instead of hopping over
+    /// them, so very selective filters are better served at a fixed beam.
+    pub adaptive_ef: Option<usize>,
+}
+
+/// Candidates fetched per requested result when diversifying
+const MMR_CANDIDATES_PER_RESULT: usize = 4;
+
+/// Candidates fetched per requested result when decaying, so recent hits
+/// can rise from below the top `k`
+const DECAY_CANDIDATES_PER_RESULT: usize = 4;
+
+/// Times a grouped search widens its candidate pool before settling
+const GROUP_SEARCH_ROUNDS: usize = 4;
+
+/// Best hits sharing one value of the grouping field, from
+/// [`Hnsw::search_grouped`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchGroup {
+    pub value: Value,
+    /// `(id, similarity)` pairs, best first
+    pub hits: Vec<(String, f32)>,
+}
+
+/// One page of results from [`Hnsw::search_page`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchPage {
+    /// `(id, similarity)` pairs, best first
+    pub results: Vec<(String, f32)>,
+    /// Pass back to get the next page; `None` on the last page
+    pub cursor: Option<String>,
+}
+
+/// Position after the last result of a page: how many results came
+/// before, and the score and id of the last one
+struct PageCursor {
+    returned: usize,
+    score: f32,
+    id: String,
+}
+
+impl PageCursor {
+    fn parse(cursor: &str) -> Result<PageCursor, HnswError> {
+        let invalid = || HnswError::InvalidParams(format!("invalid search cursor {}", cursor));
+        let mut parts = cursor.splitn(3, ':');
+        let (Some(returned), Some(score), Some(id)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid());
+        };
+        Ok(PageCursor {
+            returned: returned.parse().map_err(|_| invalid())?,
+            score: u32::from_str_radix(score, 16)
+                .map(f32::from_bits)
+                .map_err(|_| invalid())?,
+            id: id.to_string(),
+        })
+    }
+
+    fn encode(&self) -> String {
+        format!("{}:{:08x}:{}", self.returned, self.score.to_bits(), self.id)
+    }
+
+    /// Whether a result ranks after the cursor position
+    fn precedes(&self, (id, score): &(String, f32)) -> bool {
+        *score < self.score || (*score == self.score && *id > self.id)
+    }
+}
+
+/// Which part of a payload to return with a hit
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum PayloadSelector {
+    /// The whole payload
+    #[default]
+    All,
+    /// No payload
+    Omit,
+    /// Only these (dotted) fields, keeping their nesting
+    Fields(Vec<String>),
+}
+
+impl PayloadSelector {
+    /// Parse `true`, `false`, or an array of field names
+    pub fn parse(value: Value) -> Result<PayloadSelector, HnswError> {
+        match value {
+            Value::Bool(true) => Ok(PayloadSelector::All),
+            Value::Bool(false) => Ok(PayloadSelector::Omit),
+            Value::Array(_) => serde_json::from_value(value)
+                .map(PayloadSelector::Fields)
+                .map_err(|e| {
+                    HnswError::InvalidParams(format!("withPayload fields must be strings: {}", e))
+                }),
+            other => Err(HnswError::InvalidParams(format!(
+                "withPayload must be a boolean or an array of fields, got {}",
+                other
+            ))),
+        }
+    }
+
+    /// The selected part of a payload, `None` when nothing is returned
+    pub fn select(&self, payload: &Value) -> Option<Value> {
+        match self {
+            PayloadSelector::All => Some(payload.clone()),
+            PayloadSelector::Omit => None,
+            PayloadSelector::Fields(fields) => {
+                let mut selected = Map::new();
+                for field in fields {
+                    if let Some(value) = lookup(Some(payload), field) {
+                        insert_path(&mut selected, field, value.clone());
+                    }
+                }
+                Some(Value::Object(selected))
+            }
+        }
+    }
+}
+
+/// Insert `value` at a dotted path, creating intermediate objects
+fn insert_path(target: &mut Map<String, Value>, field: &str, value: Value) {
+    match field.split_once('.') {
+        Some((head, rest)) => {
+            let child = target
+                .entry(head)
+                .or_insert_with(|| Value::Object(Map::new()));
+            if let Value::Object(child) = child {
+                insert_path(child, rest, value);
+            }
+        }
+        None => {
+            target.insert(field.to_string(), value);
+        }
+    }
+}
+
+impl SearchOptions {
+    /// Parse options from their JSON form
+    pub fn parse(value: Value) -> Result<SearchOptions, HnswError> {
+        let mut map = match value {
+            Value::Null => return Ok(SearchOptions::default()),
+            Value::Object(map) => map,
+            other => {
+                return Err(HnswError::InvalidParams(format!(
+                    "search options must be an object, got {}",
+                    other
+                )))
+            }
+        };
+
+        let mut options = SearchOptions::default();
+        if let Some(filter) = map.remove("filter").filter(|v| !v.is_null()) {
+            options.filter = Some(Filter::parse(filter)?);
+        }
+        if let Some(min_score) = map.remove("minScore").filter(|v| !v.is_null()) {
+            let min_score = min_score.as_f64().ok_or_else(|| {
+                HnswError::InvalidParams(format!("minScore must be a number, got {}", min_score))
+            })?;
+            options.min_score = Some(min_score as f32);
+        }
+        if let Some(exclude) = map.remove("exclude").filter(|v| !v.is_null()) {
+            options.exclude = serde_json::from_value(exclude).map_err(|e| {
+                HnswError::InvalidParams(format!("exclude must be an array of ids: {}", e))
+            })?;
+        }
+        match map.remove("excludePrefix") {
+            None | Some(Value::Null) => {}
+            Some(Value::String(prefix)) => options.exclude_prefix = Some(prefix),
+            Some(other) => {
+                return Err(HnswError::InvalidParams(format!(
+                    "excludePrefix must be a string, got {}",
+                    other
+                )))
+            }
+        }
+        match map.remove("withVector") {
+            None | Some(Value::Null) => {}
+            Some(Value::Bool(with_vector)) => options.with_vector = with_vector,
+            Some(other) => {
+                return Err(HnswError::InvalidParams(format!(
+                    "withVector must be a boolean, got {}",
+                    other
+                )))
+            }
+        }
+        if let Some(with_payload) = map.remove("withPayload").filter(|v| !v.is_null()) {
+            options.with_payload = PayloadSelector::parse(with_payload)?;
+        }
+        if let Some(diversity) = map.remove("diversity").filter(|v| !v.is_null()) {
+            match diversity.as_f64() {
+                Some(d) if (0.0..=1.0).contains(&d) => options.diversity = Some(d as f32),
+                _ => {
+                    return Err(HnswError::InvalidParams(format!(
+                        "diversity must be a number between 0 and 1, got {}",
+                        diversity
+                    )))
+                }
+            }
+        }
+        if let Some(decay) = map.remove("decay").filter(|v| !v.is_null()) {
+            options.decay = Some(Decay::parse(decay)?);
+        }
+        if let Some(prefetch) = map.remove("prefetch").filter(|v| !v.is_null()) {
+            let prefetch = prefetch.as_u64().ok_or_else(|| {
+                HnswError::InvalidParams(format!(
+                    "prefetch must be a non-negative integer, got {}",
+                    prefetch
+                ))
+            })?;
+            options.prefetch = Some(prefetch as usize);
+        }
+        if let Some(max_visits) = map.remove("maxVisits").filter(|v| !v.is_null()) {
+            let max_visits = max_visits.as_u64().ok_or_else(|| {
+                HnswError::InvalidParams(format!(
+                    "maxVisits must be a non-negative integer, got {}",
+                    max_visits
+                ))
+            })?;
+            options.max_visits = Some(max_visits as usize);
+        }
+        if let Some(timeout_ms) = map.remove("timeoutMs").filter(|v| !v.is_null()) {
+            match timeout_ms.as_f64() {
+                Some(ms) if ms >= 0.0 => options.timeout_ms = Some(ms),
+                _ => {
+                    return Err(HnswError::InvalidParams(format!(
+                        "timeoutMs must be a non-negative number, got {}",
+                        timeout_ms
+                    )))
+                }
+            }
+        }
+        if let Some(ef) = map.remove("ef").filter(|v| !v.is_null()) {
+            match ef.as_u64() {
+                Some(ef) if ef > 0 => options.ef = Some(ef as usize),
+                _ => {
+                    return Err(HnswError::InvalidParams(format!(
+                        "ef must be a positive integer, got {}",
+                        ef
+                    )))
+                }
+            }
+        }
+        if let Some(adaptive_ef) = map.remove("adaptiveEf").filter(|v| !v.is_null()) {
+            match adaptive_ef.as_u64() {
+                Some(cap) if cap > 0 => options.adaptive_ef = Some(cap as usize),
+                _ => {
+                    return Err(HnswError::InvalidParams(format!(
+                        "adaptiveEf must be a positive integer, got {}",
+                        adaptive_ef
+                    )))
+                }
+            }
+        }
+        if options.ef.is_some() && options.adaptive_ef.is_some() {
+            return Err(HnswError::InvalidParams(
+                "ef and adaptiveEf cannot be combined".to_string(),
+            ));
+        }
+        if let Some(unknown) = map.keys().next() {
+            return Err(HnswError::InvalidParams(format!(
+                "unknown search option {}",
+                unknown
+            )));
+        }
+        Ok(options)
+    }
+
+    /// Whether `id` is excluded from results
+    pub fn excludes(&self, id: &str) -> bool {
+        self.exclude.contains(id)
+            || self
+                .exclude_prefix
+                .as_deref()
+                .is_some_and(|prefix| id.starts_with(prefix))
+    }
+
+    /// Candidates to fetch per result, at least `base`
+    ///
+    /// Diversifying and decaying need a wider pool to choose from.
+    pub(crate) fn candidates_per_result(&self, base: usize) -> usize {
+        let mut per_result = base;
+        if self.diversity.is_some() {
+            per_result = per_result.max(MMR_CANDIDATES_PER_RESULT);
+        }
+        if self.decay.is_some() {
+            per_result = per_result.max(DECAY_CANDIDATES_PER_RESULT);
+        }
+        per_result
+    }
+
+    pub(crate) fn has_exclusions(&self) -> bool {
+        !self.exclude.is_empty() || self.exclude_prefix.is_some()
+    }
+
+    /// Apply the result-level options to sorted results
+    pub(crate) fn finish(&self, mut results: Vec<(String, f32)>) -> Vec<(String, f32)> {
+        if let Some(min_score) = self.min_score {
+            // Results are best first, so everything after the first miss fails too
+            let keep = results
+                .iter()
+                .position(|(_, score)| *score < min_score)
+                .unwrap_or(results.len());
+            results.truncate(keep);
+        }
+        results
+    }
+}
+
+impl<S: Scalar, V: VectorStore<S>> Hnsw<S, V> {
+    /// Find the `k` best matches for `vector` under `options`
+    pub fn search(
+        &self,
+        vector: &[S],
+        k: usize,
+        options: &SearchOptions,
+    ) -> Result<Vec<(String, f32)>, HnswError> {
+        self.search_within_budget(vector, k, options)
+            .map(|(results, _)| results)
+    }
+
+    /// [`Hnsw::search`], also returning whether `maxVisits` or `timeoutMs`
+    /// cut it short
+    ///
+    /// A truncated search returns the best points it reached, which may
+    /// be fewer or worse than `k` complete results, and is not cached.
+    pub fn search_within_budget(
+        &self,
+        vector: &[S],
+        k: usize,
+        options: &SearchOptions,
+    ) -> Result<(Vec<(String, f32)>, bool), HnswError> {
+        let query = self.prepare_query(vector)?;
+        if let Some(results) = self.query_cache().get(&query, k, options) {
+            return Ok((results, false));
+        }
+        let meter = Meter::start(options.max_visits, options.timeout_ms);
+        let beam = Beam::for_search(options, self.params().ef_search);
+        let (results, _) = self.explain_metered(&query, k, options, beam, &meter)?;
+        if !meter.truncated() {
+            self.query_cache().put(&query, k, options, &results);
+        }
+        Ok((results, meter.truncated()))
+    }
+
+    /// [`Hnsw::search`], also returning the plan used for a filter
+    pub fn explain(
+        &self,
+        vector: &[S],
+        k: usize,
+        options: &SearchOptions,
+    ) -> Result<Explained, HnswError> {
+        let query = self.prepare_query(vector)?;
+        self.explain_query(&query, k, options)
+    }
+
+    /// Find the `k` best matches for the stored vector of `id`
+    ///
+    /// The point itself is never among the results. Its stored vector is
+    /// used as is, so with a projection the query is not projected twice.
+    pub fn search_by_id(
+        &self,
+        id: &str,
+        k: usize,
+        options: &SearchOptions,
+    ) -> Result<Vec<(String, f32)>, HnswError> {
+        let query = self
+            .vector(id)
+            .ok_or_else(|| HnswError::UnknownId(id.to_string()))?;
+        let mut options = options.clone();
+        options.exclude.insert(id.to_string());
+        self.explain_query(&query, k, &options)
+            .map(|(results, _)| results)
+    }
+
+    /// One page of up to `limit` results, continuing after `cursor`
+    ///
+    /// Results are ordered by score, then id. Each page searches deep
+    /// enough to cover everything returned so far and resumes after the
+    /// last result by position, so pages do not repeat hits even though
+    /// deeper searches can shift approximate results. `diversity` is not
+    /// supported, as it does not rank by score.
+    pub fn search_page(
+        &self,
+        vector: &[S],
+        limit: usize,
+        cursor: Option<&str>,
+        options: &SearchOptions,
+    ) -> Result<SearchPage, HnswError> {
+        if options.diversity.is_some() {
+            return Err(HnswError::InvalidParams(
+                "diversity cannot be combined with paging".to_string(),
+            ));
+        }
+        let cursor = cursor.map(PageCursor::parse).transpose()?;
+        let limit = limit.max(1);
+        let returned = cursor.as_ref().map_or(0, |c| c.returned);
+
+        let query = self.prepare_query(vector)?;
+        // One extra tells whether another page follows
+        let fetch = returned.saturating_add(limit).saturating_add(1);
+        let (mut results, _) = self.explain_query(&query, fetch, options)?;
+        results.sort_by(best_first);
+        let mut remaining = results
+            .into_iter()
+            .filter(|hit| cursor.as_ref().iter().all(|c| c.precedes(hit)));
+        let page: Vec<(String, f32)> = remaining.by_ref().take(limit).collect();
+        let cursor = match (remaining.next(), page.last()) {
+            (Some(_), Some((id, score))) => Some(
+                PageCursor {
+                    returned: returned + page.len(),
+                    score: *score,
+                    id: id.clone(),
+                }
+                .encode(),
+            ),
+            _ => None,
+        };
+        Ok(SearchPage {
+            results: page,
+            cursor,
+        })
+    }
+
+    /// Top `groups` values of payload `field` with up to `per_group` hits each
+    ///
+    /// Groups are ordered by their best hit, e.g. the files holding the
+    /// closest chunks. Points without the field are skipped; a point whose
+    /// field is an array joins the group of each element. The candidate
+    /// pool doubles a few times while groups are missing, then groups
+    /// still short of hits are filled by a search filtered to their value.
+    pub fn search_grouped(
+        &self,
+        vector: &[S],
+        field: &str,
+        groups: usize,
+        per_group: usize,
+        options: &SearchOptions,
+    ) -> Result<Vec<SearchGroup>, HnswError> {
+        let query = self.prepare_query(vector)?;
+        let mut fetch = groups.saturating_mul(per_group);
+        if fetch == 0 {
+            return Ok(Vec::new());
+        }
+        let mut round = 1;
+        let mut grouped = loop {
+            let (results, _) = self.explain_query(&query, fetch, options)?;
+            let exhausted = results.len() < fetch || fetch >= self.len();
+            let grouped = self.group_hits(results, field, groups, per_group);
+            if grouped.len() == groups || exhausted || round == GROUP_SEARCH_ROUNDS {
+                break grouped;
+            }
+            fetch = fetch.saturating_mul(2);
+            round += 1;
+        };
+
+        for group in grouped.iter_mut().filter(|g| g.hits.len() < per_group) {
+            let in_group = Filter::Field {
+                key: field.to_string(),
+                condition: Condition::Equals(group.value.clone()),
+            };
+            let mut narrowed = options.clone();
+            narrowed.filter = Some(match narrowed.filter.take() {
+                Some(filter) => Filter::All(vec![filter, in_group]),
+                None => in_group,
+            });
+            group.hits = self.explain_query(&query, per_group, &narrowed)?.0;
+        }
+        Ok(grouped)
+    }
+
+    /// Bucket best-first hits by the values of `field`
+    fn group_hits(
+        &self,
+        results: Vec<(String, f32)>,
+        field: &str,
+        groups: usize,
+        per_group: usize,
+    ) -> Vec<SearchGroup> {
+        let mut grouped: Vec<SearchGroup> = Vec::new();
+        let mut slots: HashMap<String, usize> = HashMap::new();
+        for (id, score) in results {
+            let mut seen = HashSet::new();
+            for value in lookup_values(self.payload(&id), field) {
+                let key = value.to_string();
+                if !seen.insert(key.clone()) {
+                    continue;
+                }
+                let slot = match slots.get(&key) {
+                    Some(&slot) => slot,
+                    None if grouped.len() < groups => {
+                        grouped.push(SearchGroup {
+                            value: value.clone(),
+                            hits: Vec::new(),
+                        });
+                        slots.insert(key, grouped.len() - 1);
+                        grouped.len() - 1
+                    }
+                    None => continue,
+                };
+                if grouped[slot].hits.len() < per_group {
+                    grouped[slot].hits.push((id.clone(), score));
+                }
+            }
+        }
+        grouped
+    }
+
+    /// Top `k` of ids scored outside the graph, e.g. by sparse or text
+    /// search, under the filter, exclusion and `minScore` options
+    ///
+    /// `diversity` needs dense vectors and is rejected; `kind` names the
+    /// search in that error.
+    pub(crate) fn rank_scores<'a>(
+        &self,
+        scores: impl IntoIterator<Item = (&'a Id, f32)>,
+        k: usize,
+        options: &SearchOptions,
+        kind: &str,
+    ) -> Result<Vec<(String, f32)>, HnswError> {
+        if options.diversity.is_some() {
+            return Err(HnswError::InvalidParams(format!(
+                "diversity needs dense vectors and cannot be used with {} search",
+                kind
+            )));
+        }
+        if let Some(filter) = &options.filter {
+            self.schema().check_filter(filter)?;
+        }
+        let excludes = self.exclusions(options);
+        let mut results: Vec<(String, f32)> = scores
+            .into_iter()
+            .filter(|(id, _)| {
+                !excludes(id)
+                    && options
+                        .filter
+                        .iter()
+                        .all(|filter| filter.matches(self.payloads().get(*id)))
+            })
+            .map(|(id, score)| (id.to_string(), score))
+            .collect();
+        results.sort_by(best_first);
+        self.apply_decay(&mut results, options);
+        results.truncate(k);
+        Ok(options.finish(results))
+    }
+
+    /// Test for the ids `options` excludes, with the excluded ids resolved
+    /// once instead of spelling out every id tested
+    pub(crate) fn exclusions<'a>(
+        &'a self,
+        options: &'a SearchOptions,
+    ) -> impl Fn(&Id) -> bool + 'a {
+        let exclude: HashSet<&Id> = options
+            .exclude
+            .iter()
+            .filter_map(|id| self.interned(id))
+            .collect();
+        move |id: &Id| {
+            exclude.contains(id)
+                || options
+                    .exclude_prefix
+                    .as_deref()
+                    .is_some_and(|prefix| id.starts_with(prefix))
+        }
+    }
+
+    /// Ids among the `fetch` best hits of any stored-space query
+    ///
+    /// Score-level options are left to [`Hnsw::rerank`]; filters and
+    /// exclusions apply.
+    pub(crate) fn gather(
+        &self,
+        queries: &[Vec<S>],
+        fetch: usize,
+        options: &SearchOptions,
+    ) -> Result<HashSet<String>, HnswError> {
+        let mut plain = options.clone();
+        plain.min_score = None;
+        plain.diversity = None;
+        plain.decay = None;
+        let mut candidates = HashSet::new();
+        for query in queries {
+            let (results, _) = self.explain_query(query, fetch, &plain)?;
+            candidates.extend(results.into_iter().map(|(id, _)| id));
+        }
+        Ok(candidates)
+    }
+
+    /// Rank candidates by `score` of their stored vectors, keeping `k`
+    /// under the score-level options
+    pub(crate) fn rerank(
+        &self,
+        candidates: HashSet<String>,
+        k: usize,
+        options: &SearchOptions,
+        score: impl Fn(&[S]) -> f32,
+    ) -> Vec<(String, f32)> {
+        let mut results: Vec<(String, f32)> = candidates
+            .into_iter()
+            .filter_map(|id| {
+                let score = score(&self.vector(&id)?);
+                Some((id, score))
+            })
+            .collect();
+        results.sort_by(best_first);
+        self.apply_decay(&mut results, options);
+
+        let mut results = options.finish(results);
+        if let Some(diversity) = options.diversity {
+            return self.diversify(results, k, diversity);
+        }
+        results.truncate(k);
+        results
+    }
+
+    /// Run a query already mapped into stored space
+    pub(crate) fn explain_query(
+        &self,
+        query: &[S],
+        k: usize,
+        options: &SearchOptions,
+    ) -> Result<Explained, HnswError> {
+        let meter = Meter::start(options.max_visits, options.timeout_ms);
+        let beam = Beam::for_search(options, self.params().ef_search);
+        self.explain_metered(query, k, options, beam, &meter)
+    }
+
+    /// [`Hnsw::explain_query`] walking the base layer with `beam` under
+    /// `meter`
+    pub(crate) fn explain_metered(
+        &self,
+        query: &[S],
+        k: usize,
+        options: &SearchOptions,
+        beam: Beam,
+        meter: &Meter,
+    ) -> Result<Explained, HnswError> {
+        let fetch = k
+            .saturating_mul(options.candidates_per_result(1))
+            .max(options.prefetch.unwrap_or(0));
+        // Exclusions are applied during traversal so they do not eat into k
+        let exclude = self.exclusions(options);
+        let (mut results, plan) = match &options.filter {
+            Some(filter) => {
+                let (results, plan) =
+                    self.explain_filtered_excluding(query, fetch, filter, &exclude, beam, meter)?;
+                (results, Some(plan))
+            }
+            None if options.has_exclusions() => (
+                self.nearest_excluding(query, fetch, &exclude, beam, meter)?,
+                None,
+            ),
+            None => (self.nearest_where(query, fetch, None, beam, meter)?, None),
+        };
+        self.apply_decay(&mut results, options);
+        let mut results = options.finish(results);
+        match options.diversity {
+            Some(diversity) => results = self.diversify(results, k, diversity),
+            None => results.truncate(k),
+        }
+        Ok((results, plan))
+    }
+
+    /// Pick `k` of the best-first `candidates` by maximal marginal relevance
+    ///
+    /// Each step takes the candidate maximizing
+    /// `(1 - diversity) * score - diversity * (similarity to closest pick)`.
+    /// Scores stay the original similarities; only the order and selection
+    /// change.
+    pub(crate) fn diversify(
+        &self,
+        mut candidates: Vec<(String, f32)>,
+        k: usize,
+        diversity: f32,
+    ) -> Vec<(String, f32)> {
+        let vectors: Vec<_> = candidates.iter().map(|(id, _)| self.vector(id)).collect();
+        // Similarity of each candidate to its closest pick so far
+        let mut closest = vec![0.0f32; candidates.len()];
+        let mut remaining: Vec<usize> = (0..candidates.len()).collect();
+        let mut picked = Vec::with_capacity(k.min(candidates.len()));
+
+        while picked.len() < k && !remaining.is_empty() {
+            let marginal = |i: usize| (1.0 - diversity) * candidates[i].1 - diversity * closest[i];
+            // Strictly greater, so ties go to the better-ranked candidate
+            let mut slot = 0;
+            for next in 1..remaining.len() {
+                if marginal(remaining[next]) > marginal(remaining[slot]) {
+                    slot = next;
+                }
+            }
+            let best = remaining.remove(slot);
+            picked.push(best);
+
+            if let Some(chosen) = &vectors[best] {
+                for &i in &remaining {
+                    if let Some(other) = &vectors[i] {
+                        let similarity = 1.0 - S::cosine_distance(chosen, other);
+                        closest[i] = closest[i].max(similarity);
+                    }
+                }
+            }
+        }
+
+        picked
+            .into_iter()
+            .map(|i| std::mem::take(&mut candidates[i]))
+            .collect()
+    }
+}
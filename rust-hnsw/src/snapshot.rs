@@ -0,0 +1,401 @@
+//! Versioned snapshot envelope
+//!
+//! Saved bytes start with a fixed header naming the format version, the
+//! scalar type, the metric and the dimensions, followed by the
+//! [`codec`](crate::codec) body, deflated when the compressed flag is set:
+//!
+//! | bytes | field                        |
+//! |-------|------------------------------|
+//! | 0..4  | magic `\x89HNS`              |
+//! | 4..6  | format version, u16 LE       |
+//! | 6     | flags, see `FLAG_*`          |
+//! | 7     | scalar type                  |
+//! | 8     | metric                       |
+//! | 9..13 | dimensions, u32 LE           |
+//! | 13..17| CRC-32 of the body, u32 LE   |
+//!
+//! Snapshots older than the header are format version 0, and version 1
+//! headers end before the checksum. When the layout of a persisted struct
+//! changes, bump [`FORMAT_VERSION`] and add a step to [`MIGRATIONS`]
+//! rewriting the previous version's document.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+use crate::hash::crc32;
+use crate::{codec, deflate, HnswError};
+
+const MAGIC: [u8; 4] = *b"\x89HNS";
+/// The body is deflated
+pub(crate) const FLAG_COMPRESSED: u8 = 1;
+/// The body holds vectors without the graph, see [`crate::rebuild`]
+pub(crate) const FLAG_VECTORS_ONLY: u8 = 2;
+/// The body holds changes since a generation, see [`crate::delta`]
+pub(crate) const FLAG_DELTA: u8 = 4;
+
+/// What a snapshot body holds, from its flags
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Kind {
+    Full,
+    VectorsOnly,
+    Delta,
+}
+
+impl Kind {
+    fn of(flags: u8) -> Kind {
+        if flags & FLAG_DELTA != 0 {
+            Kind::Delta
+        } else if flags & FLAG_VECTORS_ONLY != 0 {
+            Kind::VectorsOnly
+        } else {
+            Kind::Full
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Full => "full",
+            Kind::VectorsOnly => "vectors-only",
+            Kind::Delta => "delta",
+        }
+    }
+}
+
+/// Version written by this build
+pub const FORMAT_VERSION: u16 = 4;
+
+/// Scalar types by header code
+pub(crate) const SCALARS: [&str; 3] = ["f32", "u8", "f64"];
+/// Metrics by header code
+const METRICS: [&str; 1] = ["cosine"];
+
+/// Rewrites a decoded snapshot document into the next format version
+type Migration = fn(&mut Value) -> Result<(), HnswError>;
+
+/// `MIGRATIONS[v]` upgrades version `v` to `v + 1`; `None` where only the
+/// envelope changed and the body decodes as is
+const MIGRATIONS: [Option<Migration>; FORMAT_VERSION as usize] = [
+    None,                  // 0 -> 1: headerless snapshots share the version 1 body
+    None,                  // 1 -> 2: the header gained a checksum
+    Some(number_nodes),    // 2 -> 3: graph links by dense node instead of id
+    Some(flatten_vectors), // 3 -> 4: in-memory vectors in one buffer
+];
+
+/// Header size of a format version
+fn header_len(version: u16) -> usize {
+    if version >= 2 {
+        17
+    } else {
+        13
+    }
+}
+
+/// What a snapshot header declares about the index inside it
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotInfo {
+    pub version: u16,
+    /// Element type of the stored vectors, e.g. `"f32"`
+    pub scalar: String,
+    pub metric: String,
+    pub dimensions: usize,
+    pub compressed: bool,
+    /// Saved without the graph, which is rebuilt on load
+    pub vectors_only: bool,
+    /// Holds only the changes since a generation
+    pub delta: bool,
+}
+
+/// Read the header of a snapshot without decoding its body
+///
+/// Returns `None` for snapshots saved before the header existed.
+pub fn snapshot_info(data: &[u8]) -> Result<Option<SnapshotInfo>, HnswError> {
+    if !data.starts_with(&MAGIC) {
+        return Ok(None);
+    }
+    let truncated = || HnswError::CorruptSnapshot("truncated header".to_string());
+    let version = data
+        .get(4..6)
+        .map(|v| u16::from_le_bytes([v[0], v[1]]))
+        .ok_or_else(truncated)?;
+    let header = data.get(..header_len(version)).ok_or_else(truncated)?;
+    let lookup = |table: &[&str], code: u8, what: &str| {
+        table
+            .get(code as usize)
+            .map(|name| name.to_string())
+            .ok_or_else(|| HnswError::Deserialization(format!("unknown {} code {}", what, code)))
+    };
+    Ok(Some(SnapshotInfo {
+        version,
+        compressed: header[6] & FLAG_COMPRESSED != 0,
+        vectors_only: header[6] & FLAG_VECTORS_ONLY != 0,
+        delta: header[6] & FLAG_DELTA != 0,
+        scalar: lookup(&SCALARS, header[7], "scalar")?,
+        metric: lookup(&METRICS, header[8], "metric")?,
+        dimensions: u32::from_le_bytes([header[9], header[10], header[11], header[12]]) as usize,
+    }))
+}
+
+/// Encode `value` behind a current-version header with the given flags
+pub(crate) fn encode<T: Serialize + ?Sized>(
+    value: &T,
+    scalar: &str,
+    dimensions: usize,
+    flags: u8,
+) -> Result<Vec<u8>, HnswError> {
+    let scalar = SCALARS
+        .iter()
+        .position(|s| *s == scalar)
+        .ok_or_else(|| HnswError::Serialization(format!("unsupported scalar {}", scalar)))?;
+    let dimensions = u32::try_from(dimensions)
+        .map_err(|_| HnswError::Serialization("dimensions exceed u32".to_string()))?;
+    let body = codec::encode(value)?;
+    let body = if flags & FLAG_COMPRESSED != 0 {
+        deflate::compress(&body)
+    } else {
+        body
+    };
+
+    let mut out = Vec::with_capacity(header_len(FORMAT_VERSION) + body.len());
+    out.extend_from_slice(&MAGIC);
+    out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    out.push(flags);
+    out.push(scalar as u8);
+    out.push(0);
+    out.extend_from_slice(&dimensions.to_le_bytes());
+    out.extend_from_slice(&crc32(&body).to_le_bytes());
+    out.extend_from_slice(&body);
+    Ok(out)
+}
+
+/// Decode a snapshot of any supported version holding `scalar` vectors
+///
+/// `kind` is the kind of body expected; snapshots from before the header
+/// are all full. `dimensions` reads the dimensions back from the decoded
+/// value so a header that disagrees with its body is rejected.
+pub(crate) fn decode<T: DeserializeOwned>(
+    data: &[u8],
+    scalar: &str,
+    kind: Kind,
+    dimensions: impl FnOnce(&T) -> usize,
+) -> Result<T, HnswError> {
+    let flags = data.get(6).copied().unwrap_or(0);
+    let info = snapshot_info(data)?;
+    let found = if info.is_some() {
+        Kind::of(flags)
+    } else {
+        Kind::Full
+    };
+    if found != kind {
+        return Err(HnswError::Deserialization(format!(
+            "expected a {} snapshot, got a {} one",
+            kind.name(),
+            found.name()
+        )));
+    }
+    let Some(info) = info else {
+        return migrate(data, 0);
+    };
+    if info.version > FORMAT_VERSION {
+        return Err(HnswError::Deserialization(format!(
+            "snapshot format version {} is newer than the supported version {}",
+            info.version, FORMAT_VERSION
+        )));
+    }
+    if info.scalar != scalar {
+        return Err(HnswError::Deserialization(format!(
+            "snapshot holds {} vectors, expected {}",
+            info.scalar, scalar
+        )));
+    }
+
+    let header_len = header_len(info.version);
+    let body = &data[header_len..];
+    if info.version >= 2 {
+        let stored = &data[header_len - 4..header_len];
+        let stored = u32::from_le_bytes([stored[0], stored[1], stored[2], stored[3]]);
+        if crc32(body) != stored {
+            return Err(HnswError::CorruptSnapshot(
+                "checksum mismatch, the snapshot was truncated or altered".to_string(),
+            ));
+        }
+    }
+    let inflated;
+    let body = if info.compressed {
+        inflated = deflate::decompress(body)?;
+        &inflated[..]
+    } else {
+        body
+    };
+    let value: T = migrate(body, info.version)?;
+
+    let decoded = dimensions(&value);
+    if decoded != info.dimensions {
+        return Err(HnswError::Deserialization(format!(
+            "snapshot header declares {} dimensions but the index has {}",
+            info.dimensions, decoded
+        )));
+    }
+    Ok(value)
+}
+
+/// Decode a body written at `version`, applying every later migration
+fn migrate<T: DeserializeOwned>(body: &[u8], version: u16) -> Result<T, HnswError> {
+    let steps: Vec<Migration> = MIGRATIONS[version as usize..]
+        .iter()
+        .flatten()
+        .copied()
+        .collect();
+    if steps.is_empty() {
+        return codec::decode(body);
+    }
+    let mut document: Value = codec::decode(body)?;
+    for step in steps {
+        step(&mut document)?;
+    }
+    serde_json::from_value(document).map_err(|e| HnswError::Deserialization(e.to_string()))
+}
+
+/// Version 3 numbers the points of the graph: `points` maps each id to a
+/// node, `nodes` holds the id, slot and per-layer links of each node, and
+/// the entry point is a node. Before, links were ids kept in one map per
+/// layer.
+///
+/// Rewrites an index document, or one nested under `index` as repository
+/// indexer snapshots hold it; other documents have no graph.
+fn number_nodes(document: &mut Value) -> Result<(), HnswError> {
+    let Some(index) = index_document(document, "layers") else {
+        return Ok(());
+    };
+    let corrupt = |msg: String| HnswError::CorruptSnapshot(format!("version 2 graph: {}", msg));
+
+    let points = match index.remove("points") {
+        Some(Value::Object(points)) => points,
+        _ => return Err(corrupt("points are not a map".to_string())),
+    };
+    let layers: Vec<Map<String, Value>> = match index.remove("layers") {
+        Some(Value::Array(layers)) => layers
+            .into_iter()
+            .map(|mut layer| match layer.get_mut("links").map(Value::take) {
+                Some(Value::Object(links)) => Ok(links),
+                _ => Err(corrupt("layer links are not a map".to_string())),
+            })
+            .collect::<Result<_, _>>()?,
+        _ => return Err(corrupt("layers are not a list".to_string())),
+    };
+    let numbers: HashMap<&str, u32> = points
+        .keys()
+        .enumerate()
+        .map(|(node, id)| (id.as_str(), node as u32))
+        .collect();
+    let number = |id: &Value| {
+        id.as_str()
+            .and_then(|id| numbers.get(id))
+            .map(|&node| Value::from(node))
+            .ok_or_else(|| corrupt(format!("link to missing point {}", id)))
+    };
+
+    let mut nodes = Vec::with_capacity(points.len());
+    for (id, point) in &points {
+        let level = point
+            .get("level")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| corrupt(format!("point {} has no level", id)))?;
+        let mut links = Vec::new();
+        for layer in 0..=level as usize {
+            let layer_links = layers
+                .get(layer)
+                .and_then(|links| links.get(id))
+                .and_then(Value::as_array)
+                .ok_or_else(|| corrupt(format!("point {} missing from layer {}", id, layer)))?;
+            links.push(Value::Array(
+                layer_links.iter().map(number).collect::<Result<_, _>>()?,
+            ));
+        }
+        let mut node = Map::new();
+        node.insert(
+            "id".to_string(),
+            point.get("id").cloned().unwrap_or(Value::Null),
+        );
+        node.insert(
+            "slot".to_string(),
+            point.get("slot").cloned().unwrap_or(Value::Null),
+        );
+        node.insert("links".to_string(), Value::Array(links));
+        nodes.push(Value::Object(node));
+    }
+    let entry_point = match index.get("entry_point") {
+        Some(Value::Null) | None => Value::Null,
+        Some(entry) => number(entry)?,
+    };
+
+    index.insert(
+        "points".to_string(),
+        Value::Object(
+            numbers
+                .iter()
+                .map(|(id, &node)| (id.to_string(), Value::from(node)))
+                .collect(),
+        ),
+    );
+    index.insert("nodes".to_string(), Value::Array(nodes));
+    index.insert("free_nodes".to_string(), Value::Array(Vec::new()));
+    index.insert("entry_point".to_string(), entry_point);
+    Ok(())
+}
+
+/// Version 4 keeps the vectors of an in-memory store in one buffer:
+/// `store` holds the width `dim`, the slots back to back in `data` and
+/// which slots are `occupied`, instead of one optional vector per slot.
+fn flatten_vectors(document: &mut Value) -> Result<(), HnswError> {
+    let Some(store) = index_document(document, "store")
+        .and_then(|index| index.get_mut("store"))
+        .and_then(Value::as_object_mut)
+    else {
+        return Ok(());
+    };
+    // Other stores persist their own layout
+    let Some(Value::Array(vectors)) = store.remove("vectors") else {
+        return Ok(());
+    };
+    let dim = vectors.iter().find_map(Value::as_array).map_or(0, Vec::len);
+    let mut data = Vec::with_capacity(vectors.len() * dim);
+    let mut occupied = Vec::with_capacity(vectors.len());
+    for vector in vectors {
+        match vector {
+            Value::Array(values) if values.len() == dim => {
+                data.extend(values);
+                occupied.push(Value::Bool(true));
+            }
+            Value::Null => {
+                data.extend(std::iter::repeat(Value::from(0)).take(dim));
+                occupied.push(Value::Bool(false));
+            }
+            _ => {
+                return Err(HnswError::CorruptSnapshot(format!(
+                    "version 3 store: vector is not {} values",
+                    dim
+                )))
+            }
+        }
+    }
+    store.insert("dim".to_string(), Value::from(dim));
+    store.insert("data".to_string(), Value::Array(data));
+    store.insert("occupied".to_string(), Value::Array(occupied));
+    Ok(())
+}
+
+/// The index object of a document holding `key`, at the top or nested
+/// under `index`
+fn index_document<'a>(document: &'a mut Value, key: &str) -> Option<&'a mut Map<String, Value>> {
+    let index = if document.get(key).is_some() {
+        document
+    } else {
+        document
+            .get_mut("index")
+            .filter(|index| index.get(key).is_some())?
+    };
+    index.as_object_mut()
+}
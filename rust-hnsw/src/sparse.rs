@@ -0,0 +1,211 @@
+//! Sparse term-weight vectors kept next to the dense graph
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::ids::{key, Id, IdKey};
+use crate::index::Hnsw;
+use crate::scalar::Scalar;
+use crate::search::SearchOptions;
+use crate::store::VectorStore;
+use crate::HnswError;
+
+/// Term id → weight pairs, e.g. from BM25 or SPLADE, sorted by term
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SparseVector {
+    indices: Vec<u32>,
+    values: Vec<f32>,
+}
+
+impl SparseVector {
+    /// Build from parallel term and weight arrays in any order
+    ///
+    /// Zero weights are dropped; repeated terms and non-finite weights
+    /// are rejected.
+    pub fn new(indices: Vec<u32>, values: Vec<f32>) -> Result<SparseVector, HnswError> {
+        if indices.len() != values.len() {
+            return Err(HnswError::InvalidParams(format!(
+                "sparse vector has {} indices but {} values",
+                indices.len(),
+                values.len()
+            )));
+        }
+        if let Some(value) = values.iter().find(|v| !v.is_finite()) {
+            return Err(HnswError::InvalidParams(format!(
+                "sparse vector weights must be finite, got {}",
+                value
+            )));
+        }
+        let mut pairs: Vec<(u32, f32)> = indices
+            .into_iter()
+            .zip(values)
+            .filter(|(_, value)| *value != 0.0)
+            .collect();
+        pairs.sort_by_key(|(index, _)| *index);
+        if let Some(pair) = pairs.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+            return Err(HnswError::InvalidParams(format!(
+                "sparse vector repeats term {}",
+                pair[0].0
+            )));
+        }
+        let (indices, values) = pairs.into_iter().unzip();
+        Ok(SparseVector { indices, values })
+    }
+
+    /// Term ids, ascending
+    pub fn indices(&self) -> &[u32] {
+        &self.indices
+    }
+
+    /// Weights matching [`SparseVector::indices`]
+    pub fn values(&self) -> &[f32] {
+        &self.values
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    fn terms(&self) -> impl Iterator<Item = (u32, f32)> + '_ {
+        self.indices
+            .iter()
+            .copied()
+            .zip(self.values.iter().copied())
+    }
+}
+
+/// Sparse vectors of indexed points with an inverted list per term
+///
+/// Only the vectors are persisted; postings are rebuilt on load.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(
+    from = "BTreeMap<Id, SparseVector>",
+    into = "BTreeMap<Id, SparseVector>"
+)]
+pub(crate) struct SparseIndex {
+    vectors: BTreeMap<Id, SparseVector>,
+    postings: HashMap<u32, HashMap<Id, f32>>,
+}
+
+impl From<BTreeMap<Id, SparseVector>> for SparseIndex {
+    fn from(vectors: BTreeMap<Id, SparseVector>) -> SparseIndex {
+        let mut index = SparseIndex::default();
+        for (id, vector) in vectors {
+            index.insert(id, vector);
+        }
+        index
+    }
+}
+
+impl From<SparseIndex> for BTreeMap<Id, SparseVector> {
+    fn from(index: SparseIndex) -> BTreeMap<Id, SparseVector> {
+        index.vectors
+    }
+}
+
+impl SparseIndex {
+    pub fn get(&self, id: &str) -> Option<&SparseVector> {
+        self.vectors.get(key(&id))
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = &Id> {
+        self.vectors.keys()
+    }
+
+    /// Set the vector of `id`, replacing any previous one
+    pub fn insert(&mut self, id: Id, vector: SparseVector) {
+        self.remove_key(&id);
+        for (term, weight) in vector.terms() {
+            self.postings
+                .entry(term)
+                .or_default()
+                .insert(id.clone(), weight);
+        }
+        self.vectors.insert(id, vector);
+    }
+
+    /// Drop the vector of `id`, returning whether there was one
+    pub fn remove(&mut self, id: &str) -> bool {
+        self.remove_key(key(&id))
+    }
+
+    fn remove_key(&mut self, id: &dyn IdKey) -> bool {
+        let Some((id, vector)) = self.vectors.remove_entry(id) else {
+            return false;
+        };
+        for term in vector.indices() {
+            if let Some(ids) = self.postings.get_mut(term) {
+                ids.remove(&id);
+                if ids.is_empty() {
+                    self.postings.remove(term);
+                }
+            }
+        }
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.vectors.clear();
+        self.postings.clear();
+    }
+
+    /// Re-key every vector by the id `share` returns for its own
+    pub fn share(&mut self, share: impl Fn(&Id) -> Id) {
+        let vectors = std::mem::take(&mut self.vectors);
+        self.postings.clear();
+        for (id, vector) in vectors {
+            self.insert(share(&id), vector);
+        }
+    }
+
+    /// Dot products with `query` of every vector sharing a term with it
+    pub fn scores(&self, query: &SparseVector) -> HashMap<&Id, f32> {
+        let mut scores: HashMap<&Id, f32> = HashMap::new();
+        for (term, weight) in query.terms() {
+            for (id, stored) in self.postings.get(&term).into_iter().flatten() {
+                *scores.entry(id).or_default() += weight * stored;
+            }
+        }
+        scores
+    }
+}
+
+impl<S: Scalar, V: VectorStore<S>> Hnsw<S, V> {
+    /// Attach a sparse vector to an indexed point, replacing any previous one
+    ///
+    /// An empty vector detaches it. Returns false when the id is not in the
+    /// index; sparse vectors are dropped with their point, but kept when
+    /// an upsert replaces the point's vector.
+    pub fn set_sparse_vector(&mut self, id: &str, vector: SparseVector) -> bool {
+        if !self.contains(id) {
+            return false;
+        }
+        self.touch(id);
+        if vector.is_empty() {
+            self.sparse_mut().remove(id);
+        } else if let Some(id) = self.interned(id).cloned() {
+            self.sparse_mut().insert(id, vector);
+        }
+        true
+    }
+
+    /// Sparse vector attached to a point
+    pub fn sparse_vector(&self, id: &str) -> Option<&SparseVector> {
+        self.sparse().get(id)
+    }
+
+    /// The `k` points with the largest dot product with a sparse `query`
+    ///
+    /// Only points sharing a term with the query can match, and scores are
+    /// raw dot products rather than similarities in `[-1, 1]`. `filter`,
+    /// exclusions and `minScore` apply as in [`Hnsw::search`]; `diversity`
+    /// is not supported.
+    pub fn search_sparse(
+        &self,
+        query: &SparseVector,
+        k: usize,
+        options: &SearchOptions,
+    ) -> Result<Vec<(String, f32)>, HnswError> {
+        self.rank_scores(self.sparse().scores(query), k, options, "sparse")
+    }
+}
@@ -0,0 +1,634 @@
+//! SQLite persistence for native builds
+//!
+//! [`SqliteStore`] keeps an index in a SQLite database as one row per
+//! point, so a long-running process can save after every few writes and
+//! reopen the index after a restart without rewriting a whole snapshot.
+//!
+//! | table    | contents                                                    |
+//! |----------|-------------------------------------------------------------|
+//! | `meta`   | `index`: format, scalar type, params, schema, payload index |
+//! |          | kinds, entry point and generation, codec-encoded            |
+//! |          | `shape`: input dimensions and projection, codec-encoded     |
+//! | `points` | `id`, codec-encoded stored-space `vector` and `links`, JSON |
+//! |          | `payload`, codec-encoded `sparse` and `terms`               |
+//!
+//! `links` lists the neighbor ids of the point on each layer from 0 up.
+//! Payloads are plain JSON so the table can be queried from outside.
+//!
+//! A save through the store that loaded or last saved an index writes
+//! only the points changed since, plus the links of points whose
+//! neighbor lists changed, in one transaction. A save of any other index
+//! rewrites the tables.
+//!
+//! The bindings below link the system libsqlite3 directly; they also back
+//! [`SqliteVectorStore`](crate::store::SqliteVectorStore).
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::path::Path;
+use std::ptr;
+
+use crate::codec;
+use crate::hash::fnv1a;
+use crate::ids::Id;
+use crate::index::Hnsw;
+use crate::payload_index::PayloadIndexKind;
+use crate::projection::RandomProjection;
+use crate::scalar::Scalar;
+use crate::schema::PayloadSchema;
+use crate::sparse::SparseVector;
+use crate::store::VectorStore;
+use crate::text::Terms;
+use crate::{HNSWParams, HnswError};
+
+/// Version of the table layout
+const FORMAT: u32 = 1;
+/// Bound on the layer count, well above what random levels reach
+const MAX_LAYERS: usize = 64;
+
+const TABLES: &str = "
+    CREATE TABLE IF NOT EXISTS meta (key TEXT PRIMARY KEY, value BLOB NOT NULL);
+    CREATE TABLE IF NOT EXISTS points (
+        id TEXT PRIMARY KEY,
+        vector BLOB NOT NULL,
+        links BLOB NOT NULL,
+        payload TEXT,
+        sparse BLOB,
+        terms BLOB
+    );";
+
+#[derive(Serialize)]
+struct MetaRef<'a> {
+    format: u32,
+    scalar: &'a str,
+    params: &'a HNSWParams,
+    schema: &'a PayloadSchema,
+    payload_indexes: Vec<(&'a str, PayloadIndexKind)>,
+    entry_point: Option<&'a Id>,
+    generation: u64,
+}
+
+#[derive(Deserialize)]
+struct Meta {
+    format: u32,
+    scalar: String,
+    params: HNSWParams,
+    schema: PayloadSchema,
+    payload_indexes: Vec<(String, PayloadIndexKind)>,
+    entry_point: Option<String>,
+    generation: u64,
+}
+
+#[derive(Serialize)]
+struct ShapeRef<'a> {
+    dimensions: usize,
+    projection: Option<&'a RandomProjection>,
+}
+
+#[derive(Deserialize)]
+struct Shape {
+    dimensions: usize,
+    projection: Option<RandomProjection>,
+}
+
+/// What the database holds, as of the last save or load through the store
+struct Synced {
+    /// [`Hnsw::instance`] of the index written
+    instance: u64,
+    generation: u64,
+    /// Hash of the encoded `shape` row
+    shape: u64,
+    /// Hash of the encoded links of each point
+    links: HashMap<String, u64>,
+}
+
+/// Index persistence in a SQLite database file
+pub struct SqliteStore {
+    db: Database,
+    synced: Option<Synced>,
+}
+
+impl SqliteStore {
+    /// Open or create the database at `path`
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<SqliteStore, HnswError> {
+        let db = Database::open(path.as_ref())?;
+        db.execute(TABLES)?;
+        Ok(SqliteStore { db, synced: None })
+    }
+
+    /// Read the saved index, or `None` if nothing was saved yet
+    pub fn load<S: Scalar>(&mut self) -> Result<Option<Hnsw<S>>, HnswError> {
+        let Some(meta) = self.read_meta::<Meta>("index")? else {
+            return Ok(None);
+        };
+        if meta.format != FORMAT {
+            return Err(HnswError::Deserialization(format!(
+                "unsupported table format {}",
+                meta.format
+            )));
+        }
+        if meta.scalar != S::NAME {
+            return Err(HnswError::Deserialization(format!(
+                "database holds {} vectors, expected {}",
+                meta.scalar,
+                S::NAME
+            )));
+        }
+        let shape_bytes = self
+            .read_meta_bytes("shape")?
+            .ok_or_else(|| HnswError::CorruptSnapshot("missing shape".to_string()))?;
+        let shape: Shape = codec::decode(&shape_bytes)?;
+        let mut index = Hnsw::with_params(meta.params);
+        index.set_shape(shape.dimensions, shape.projection);
+        index.set_schema(meta.schema)?;
+        let stored_dimensions = index.stored_dimensions();
+
+        let mut rows = Vec::new();
+        let mut select = self
+            .db
+            .prepare("SELECT id, vector, links, payload, sparse, terms FROM points")?;
+        while select.step()? {
+            let id = select
+                .text(0)
+                .ok_or_else(|| HnswError::CorruptSnapshot("point without id".to_string()))?
+                .to_string();
+            let vector: Vec<S> = codec::decode(select.blob(1).unwrap_or_default())?;
+            if vector.len() != stored_dimensions {
+                return Err(HnswError::CorruptSnapshot(format!(
+                    "point {} has {} dimensions, index stores {}",
+                    id,
+                    vector.len(),
+                    stored_dimensions
+                )));
+            }
+            let encoded = select.blob(2).unwrap_or_default();
+            let layers: Vec<Vec<String>> = codec::decode(encoded)?;
+            if layers.is_empty() || layers.len() > MAX_LAYERS {
+                return Err(HnswError::CorruptSnapshot(format!(
+                    "point {} is on {} layers",
+                    id,
+                    layers.len()
+                )));
+            }
+            let payload: Option<Value> = select
+                .text(3)
+                .map(serde_json::from_str)
+                .transpose()
+                .map_err(|e| HnswError::Deserialization(e.to_string()))?;
+            let sparse: Option<SparseVector> = select.blob(4).map(codec::decode).transpose()?;
+            let terms: Option<Terms> = select.blob(5).map(codec::decode).transpose()?;
+
+            index.restore_point(id.clone(), vector, layers.len() - 1)?;
+            if let Some(payload) = payload {
+                index.set_payload(&id, payload)?;
+            }
+            let interned = index.interned(&id).cloned();
+            if let (Some(interned), Some(sparse)) = (&interned, sparse) {
+                index.sparse_mut().insert(interned.clone(), sparse);
+            }
+            if let (Some(interned), Some(terms)) = (interned, terms) {
+                index.text_mut().insert_terms(interned, terms);
+            }
+            rows.push((id, fnv1a(encoded), layers));
+        }
+        drop(select);
+
+        let mut hashes = HashMap::with_capacity(rows.len());
+        for (id, hash, layers) in rows {
+            for (layer, links) in layers.into_iter().enumerate() {
+                index.set_neighbors(&id, layer, links);
+            }
+            hashes.insert(id, hash);
+        }
+        for (field, kind) in &meta.payload_indexes {
+            index.create_payload_index(field, *kind)?;
+        }
+        index.resume(meta.entry_point, meta.generation);
+        index.check_loaded()?;
+
+        self.synced = Some(Synced {
+            instance: index.instance(),
+            generation: index.generation(),
+            shape: fnv1a(&shape_bytes),
+            links: hashes,
+        });
+        Ok(Some(index))
+    }
+
+    /// Write `index` to the database, returning the number of point rows
+    /// written
+    ///
+    /// Each save hashes the links of every point to find those that
+    /// changed, which costs far less than writing them.
+    pub fn save<S: Scalar, V: VectorStore<S>>(
+        &mut self,
+        index: &Hnsw<S, V>,
+    ) -> Result<usize, HnswError> {
+        // Dropped until the transaction commits, so a failed save is
+        // followed by a full one. Changes before the floor are forgotten,
+        // e.g. by clear, so those saves are full too.
+        let synced = self.synced.take().filter(|synced| {
+            synced.instance == index.instance()
+                && (index.changes_floor()..=index.generation()).contains(&synced.generation)
+        });
+        self.db.execute("BEGIN IMMEDIATE")?;
+        match self.write(index, synced) {
+            Ok((synced, written)) => {
+                self.db.execute("COMMIT")?;
+                self.synced = Some(synced);
+                Ok(written)
+            }
+            Err(err) => {
+                let _ = self.db.execute("ROLLBACK");
+                Err(err)
+            }
+        }
+    }
+
+    fn write<S: Scalar, V: VectorStore<S>>(
+        &self,
+        index: &Hnsw<S, V>,
+        synced: Option<Synced>,
+    ) -> Result<(Synced, usize), HnswError> {
+        let changed: Vec<String> = match &synced {
+            Some(synced) => index
+                .changed_since(synced.generation)
+                .map(Id::to_string)
+                .collect(),
+            None => {
+                self.db.execute("DELETE FROM points")?;
+                index.ids().map(Id::to_string).collect()
+            }
+        };
+        let mut synced = synced.unwrap_or(Synced {
+            instance: index.instance(),
+            generation: 0,
+            shape: 0,
+            links: HashMap::new(),
+        });
+        let shape = codec::encode(&ShapeRef {
+            dimensions: index.dimensions(),
+            projection: index.projection(),
+        })?;
+        if fnv1a(&shape) != synced.shape {
+            self.write_meta("shape", &shape)?;
+            synced.shape = fnv1a(&shape);
+        }
+
+        let mut upsert = self.db.prepare(
+            "INSERT OR REPLACE INTO points (id, vector, links, payload, sparse, terms) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )?;
+        let mut delete = self.db.prepare("DELETE FROM points WHERE id = ?1")?;
+        let mut written = 0;
+        for id in &changed {
+            let Some(vector) = index.vector(id) else {
+                delete.run(&[Param::Text(id)])?;
+                synced.links.remove(id);
+                continue;
+            };
+            let links = encode_links(index, id)?;
+            let payload = index
+                .payload(id)
+                .map(serde_json::to_string)
+                .transpose()
+                .map_err(|e| HnswError::Serialization(e.to_string()))?;
+            let sparse = index.sparse().get(id).map(codec::encode).transpose()?;
+            let terms = index.text().terms(id).map(codec::encode).transpose()?;
+            upsert.run(&[
+                Param::Text(id),
+                Param::Blob(&codec::encode(&vector)?),
+                Param::Blob(&links),
+                payload.as_deref().map_or(Param::Null, Param::Text),
+                sparse.as_deref().map_or(Param::Null, Param::Blob),
+                terms.as_deref().map_or(Param::Null, Param::Blob),
+            ])?;
+            synced.links.insert(id.clone(), fnv1a(&links));
+            written += 1;
+        }
+
+        let mut relink = self
+            .db
+            .prepare("UPDATE points SET links = ?1 WHERE id = ?2")?;
+        let mut buf = String::new();
+        for id in index.ids() {
+            let id = id.spell(&mut buf);
+            let links = encode_links(index, id)?;
+            let hash = fnv1a(&links);
+            if synced.links.get(id) != Some(&hash) {
+                relink.run(&[Param::Blob(&links), Param::Text(id)])?;
+                synced.links.insert(id.to_string(), hash);
+                written += 1;
+            }
+        }
+
+        let meta = MetaRef {
+            format: FORMAT,
+            scalar: S::NAME,
+            params: index.params(),
+            schema: index.schema(),
+            payload_indexes: index.payload_index_kinds(),
+            entry_point: index.entry_point(),
+            generation: index.generation(),
+        };
+        self.write_meta("index", &codec::encode(&meta)?)?;
+        synced.generation = index.generation();
+        Ok((synced, written))
+    }
+
+    fn read_meta<T: serde::de::DeserializeOwned>(&self, key: &str) -> Result<Option<T>, HnswError> {
+        self.read_meta_bytes(key)?
+            .map(|bytes| codec::decode(&bytes))
+            .transpose()
+    }
+
+    fn read_meta_bytes(&self, key: &str) -> Result<Option<Vec<u8>>, HnswError> {
+        let mut select = self.db.prepare("SELECT value FROM meta WHERE key = ?1")?;
+        select.bind(&[Param::Text(key)])?;
+        if !select.step()? {
+            return Ok(None);
+        }
+        Ok(Some(select.blob(0).unwrap_or_default().to_vec()))
+    }
+
+    fn write_meta(&self, key: &str, value: &[u8]) -> Result<(), HnswError> {
+        self.db
+            .prepare("INSERT OR REPLACE INTO meta (key, value) VALUES (?1, ?2)")?
+            .run(&[Param::Text(key), Param::Blob(value)])
+    }
+}
+
+/// Neighbor ids of `id` on each of its layers, codec-encoded
+fn encode_links<S: Scalar, V: VectorStore<S>>(
+    index: &Hnsw<S, V>,
+    id: &str,
+) -> Result<Vec<u8>, HnswError> {
+    let levels = index.level(id).map_or(0, |level| level + 1);
+    let layers: Vec<Vec<&Id>> = (0..levels)
+        .map(|layer| index.neighbors(id, layer).unwrap_or_default())
+        .collect();
+    codec::encode(&layers)
+}
+
+#[allow(non_camel_case_types)]
+enum sqlite3 {}
+#[allow(non_camel_case_types)]
+enum sqlite3_stmt {}
+
+const SQLITE_OK: c_int = 0;
+const SQLITE_ROW: c_int = 100;
+const SQLITE_DONE: c_int = 101;
+const SQLITE_NULL: c_int = 5;
+const SQLITE_OPEN_READWRITE: c_int = 0x02;
+const SQLITE_OPEN_CREATE: c_int = 0x04;
+/// Destructor telling SQLite to copy bound values
+const SQLITE_TRANSIENT: isize = -1;
+
+#[link(name = "sqlite3")]
+extern "C" {
+    fn sqlite3_open_v2(
+        filename: *const c_char,
+        db: *mut *mut sqlite3,
+        flags: c_int,
+        vfs: *const c_char,
+    ) -> c_int;
+    fn sqlite3_close_v2(db: *mut sqlite3) -> c_int;
+    fn sqlite3_errmsg(db: *mut sqlite3) -> *const c_char;
+    fn sqlite3_exec(
+        db: *mut sqlite3,
+        sql: *const c_char,
+        callback: *const c_void,
+        arg: *mut c_void,
+        errmsg: *mut *mut c_char,
+    ) -> c_int;
+    fn sqlite3_prepare_v2(
+        db: *mut sqlite3,
+        sql: *const c_char,
+        len: c_int,
+        stmt: *mut *mut sqlite3_stmt,
+        tail: *mut *const c_char,
+    ) -> c_int;
+    fn sqlite3_finalize(stmt: *mut sqlite3_stmt) -> c_int;
+    fn sqlite3_reset(stmt: *mut sqlite3_stmt) -> c_int;
+    fn sqlite3_clear_bindings(stmt: *mut sqlite3_stmt) -> c_int;
+    fn sqlite3_step(stmt: *mut sqlite3_stmt) -> c_int;
+    fn sqlite3_bind_null(stmt: *mut sqlite3_stmt, index: c_int) -> c_int;
+    fn sqlite3_bind_int64(stmt: *mut sqlite3_stmt, index: c_int, value: i64) -> c_int;
+    fn sqlite3_bind_text(
+        stmt: *mut sqlite3_stmt,
+        index: c_int,
+        value: *const c_char,
+        len: c_int,
+        destructor: isize,
+    ) -> c_int;
+    fn sqlite3_bind_blob(
+        stmt: *mut sqlite3_stmt,
+        index: c_int,
+        value: *const c_void,
+        len: c_int,
+        destructor: isize,
+    ) -> c_int;
+    fn sqlite3_column_type(stmt: *mut sqlite3_stmt, column: c_int) -> c_int;
+    fn sqlite3_column_blob(stmt: *mut sqlite3_stmt, column: c_int) -> *const c_void;
+    fn sqlite3_column_text(stmt: *mut sqlite3_stmt, column: c_int) -> *const u8;
+    fn sqlite3_column_bytes(stmt: *mut sqlite3_stmt, column: c_int) -> c_int;
+}
+
+/// Open database connection
+pub(crate) struct Database {
+    handle: *mut sqlite3,
+}
+
+// SAFETY: the connection is only used through `&mut SqliteStore`, so from
+// one thread at a time, which every SQLite threading mode allows
+unsafe impl Send for Database {}
+
+impl Database {
+    pub fn open(path: &Path) -> Result<Database, HnswError> {
+        let path = path
+            .to_str()
+            .and_then(|path| CString::new(path).ok())
+            .ok_or_else(|| HnswError::Storage("database path is not valid UTF-8".to_string()))?;
+        let mut handle = ptr::null_mut();
+        // SAFETY: `path` is NUL-terminated and `handle` is written before
+        // it is read; a handle is returned even on failure and must be closed
+        let code = unsafe {
+            sqlite3_open_v2(
+                path.as_ptr(),
+                &mut handle,
+                SQLITE_OPEN_READWRITE | SQLITE_OPEN_CREATE,
+                ptr::null(),
+            )
+        };
+        let db = Database { handle };
+        if code != SQLITE_OK || handle.is_null() {
+            return Err(db.error());
+        }
+        Ok(db)
+    }
+
+    /// Run one or more statements that return no rows
+    pub fn execute(&self, sql: &str) -> Result<(), HnswError> {
+        let sql = CString::new(sql).map_err(|e| HnswError::Storage(e.to_string()))?;
+        // SAFETY: the handle is open and `sql` is NUL-terminated
+        let code = unsafe {
+            sqlite3_exec(
+                self.handle,
+                sql.as_ptr(),
+                ptr::null(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        };
+        self.check(code)
+    }
+
+    pub fn prepare(&self, sql: &str) -> Result<Statement<'_>, HnswError> {
+        let mut handle = ptr::null_mut();
+        // SAFETY: the handle is open and the length bounds `sql`
+        let code = unsafe {
+            sqlite3_prepare_v2(
+                self.handle,
+                sql.as_ptr().cast(),
+                sql.len() as c_int,
+                &mut handle,
+                ptr::null_mut(),
+            )
+        };
+        self.check(code)?;
+        Ok(Statement { db: self, handle })
+    }
+
+    fn check(&self, code: c_int) -> Result<(), HnswError> {
+        match code {
+            SQLITE_OK => Ok(()),
+            _ => Err(self.error()),
+        }
+    }
+
+    /// Message of the last failed call
+    fn error(&self) -> HnswError {
+        if self.handle.is_null() {
+            return HnswError::Storage("out of memory opening database".to_string());
+        }
+        // SAFETY: the handle is valid and the message is NUL-terminated
+        let message = unsafe { CStr::from_ptr(sqlite3_errmsg(self.handle)) };
+        HnswError::Storage(message.to_string_lossy().into_owned())
+    }
+}
+
+impl Drop for Database {
+    fn drop(&mut self) {
+        // SAFETY: statements borrow the database, so none outlive it
+        unsafe {
+            sqlite3_close_v2(self.handle);
+        }
+    }
+}
+
+/// Value bound to a statement parameter
+pub(crate) enum Param<'a> {
+    Null,
+    Int(i64),
+    Text(&'a str),
+    Blob(&'a [u8]),
+}
+
+pub(crate) struct Statement<'a> {
+    db: &'a Database,
+    handle: *mut sqlite3_stmt,
+}
+
+impl Statement<'_> {
+    /// Bind `params` to `?1` onwards, after resetting the statement
+    pub fn bind(&mut self, params: &[Param<'_>]) -> Result<(), HnswError> {
+        // SAFETY: the statement is valid; text and blobs are copied
+        // (SQLITE_TRANSIENT) within their stated lengths
+        unsafe {
+            sqlite3_reset(self.handle);
+            sqlite3_clear_bindings(self.handle);
+            for (i, param) in params.iter().enumerate() {
+                let index = i as c_int + 1;
+                let code = match param {
+                    Param::Null => sqlite3_bind_null(self.handle, index),
+                    Param::Int(value) => sqlite3_bind_int64(self.handle, index, *value),
+                    Param::Text(value) => sqlite3_bind_text(
+                        self.handle,
+                        index,
+                        value.as_ptr().cast(),
+                        value.len() as c_int,
+                        SQLITE_TRANSIENT,
+                    ),
+                    Param::Blob(value) => sqlite3_bind_blob(
+                        self.handle,
+                        index,
+                        value.as_ptr().cast(),
+                        value.len() as c_int,
+                        SQLITE_TRANSIENT,
+                    ),
+                };
+                self.db.check(code)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Bind `params` and run a statement that returns no rows
+    pub fn run(&mut self, params: &[Param<'_>]) -> Result<(), HnswError> {
+        self.bind(params)?;
+        while self.step()? {}
+        Ok(())
+    }
+
+    /// Advance to the next row, returning false when there is none
+    pub fn step(&mut self) -> Result<bool, HnswError> {
+        // SAFETY: the statement is valid
+        match unsafe { sqlite3_step(self.handle) } {
+            SQLITE_ROW => Ok(true),
+            SQLITE_DONE => Ok(false),
+            _ => Err(self.db.error()),
+        }
+    }
+
+    /// Text of a column, `None` when NULL or not UTF-8
+    fn text(&self, column: c_int) -> Option<&str> {
+        std::str::from_utf8(self.bytes(column, true)?).ok()
+    }
+
+    /// Bytes of a column, `None` when NULL
+    pub fn blob(&self, column: c_int) -> Option<&[u8]> {
+        self.bytes(column, false)
+    }
+
+    fn bytes(&self, column: c_int, text: bool) -> Option<&[u8]> {
+        // SAFETY: the statement is on a row; the pointer is read before
+        // its length, as SQLite requires, and stays valid until the next
+        // step or reset, which need `&mut self`
+        unsafe {
+            if sqlite3_column_type(self.handle, column) == SQLITE_NULL {
+                return None;
+            }
+            let data: *const u8 = if text {
+                sqlite3_column_text(self.handle, column)
+            } else {
+                sqlite3_column_blob(self.handle, column).cast()
+            };
+            let len = sqlite3_column_bytes(self.handle, column) as usize;
+            if data.is_null() {
+                return Some(&[]);
+            }
+            Some(std::slice::from_raw_parts(data, len))
+        }
+    }
+}
+
+impl Drop for Statement<'_> {
+    fn drop(&mut self) {
+        // SAFETY: the statement is valid and not used again
+        unsafe {
+            sqlite3_finalize(self.handle);
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use serde::{Serialize, Serializer};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+
+use super::VectorStore;
+use crate::scalar::Scalar;
+use crate::HnswError;
+
+/// Read-through cache of hot vectors in front of another store
+///
+/// Meant for disk or KV backends such as `MmapStore` that decode on
+/// every read. Up to `capacity` vectors stay in memory; eviction is
+/// second-chance FIFO, so vectors read again since they were cached,
+/// like entry points and hub nodes, survive a pass of the queue. Writes
+/// go through to the wrapped store and are cached as well, since a new
+/// point is compared against its neighbors right after it is stored.
+pub struct CachedStore<S: Scalar, V: VectorStore<S>> {
+    inner: V,
+    cache: RefCell<HotCache<S>>,
+}
+
+struct HotCache<S> {
+    capacity: usize,
+    /// Vector and whether it was read since it last came up for eviction
+    vectors: HashMap<u32, (Vec<S>, bool)>,
+    order: VecDeque<u32>,
+}
+
+impl<S: Scalar> HotCache<S> {
+    fn get(&mut self, slot: u32) -> Option<Vec<S>> {
+        let (vector, referenced) = self.vectors.get_mut(&slot)?;
+        *referenced = true;
+        Some(vector.clone())
+    }
+
+    fn insert(&mut self, slot: u32, vector: Vec<S>) {
+        if self.capacity == 0 || self.vectors.contains_key(&slot) {
+            return;
+        }
+        while self.vectors.len() >= self.capacity {
+            let Some(oldest) = self.order.pop_front() else {
+                break;
+            };
+            match self.vectors.get_mut(&oldest) {
+                Some((_, referenced)) if *referenced => {
+                    *referenced = false;
+                    self.order.push_back(oldest);
+                }
+                _ => {
+                    self.vectors.remove(&oldest);
+                }
+            }
+        }
+        self.order.push_back(slot);
+        self.vectors.insert(slot, (vector, false));
+    }
+
+    fn forget(&mut self, slot: u32) {
+        if self.vectors.remove(&slot).is_some() {
+            self.order.retain(|&s| s != slot);
+        }
+    }
+}
+
+impl<S: Scalar, V: VectorStore<S>> CachedStore<S, V> {
+    /// Wrap `inner`, caching up to `capacity` vectors
+    pub fn new(inner: V, capacity: usize) -> CachedStore<S, V> {
+        CachedStore {
+            inner,
+            cache: RefCell::new(HotCache {
+                capacity,
+                vectors: HashMap::new(),
+                order: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// Wrap `inner`, caching vectors of `dim` elements up to about
+    /// `bytes` of memory
+    pub fn with_budget(inner: V, bytes: usize, dim: usize) -> CachedStore<S, V> {
+        CachedStore::new(inner, bytes / (dim * S::BYTES).max(1))
+    }
+
+    /// Number of vectors currently cached
+    pub fn cached(&self) -> usize {
+        self.cache.borrow().vectors.len()
+    }
+
+    /// The wrapped store
+    pub fn inner(&self) -> &V {
+        &self.inner
+    }
+}
+
+/// Saves as the wrapped store; cached vectors are not part of a snapshot
+impl<S: Scalar, V: VectorStore<S> + Serialize> Serialize for CachedStore<S, V> {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        self.inner.serialize(serializer)
+    }
+}
+
+impl<S: Scalar, V: VectorStore<S>> VectorStore<S> for CachedStore<S, V> {
+    fn get(&self, slot: u32) -> Option<Cow<'_, [S]>> {
+        if let Some(vector) = self.cache.borrow_mut().get(slot) {
+            return Some(Cow::Owned(vector));
+        }
+        let vector = self.inner.get(slot)?.into_owned();
+        self.cache.borrow_mut().insert(slot, vector.clone());
+        Some(Cow::Owned(vector))
+    }
+
+    fn put(&mut self, slot: u32, vector: Vec<S>) -> Result<(), HnswError> {
+        let cache = self.cache.get_mut();
+        cache.forget(slot);
+        if cache.capacity == 0 {
+            return self.inner.put(slot, vector);
+        }
+        self.inner.put(slot, vector.clone())?;
+        self.cache.get_mut().insert(slot, vector);
+        Ok(())
+    }
+
+    fn remove(&mut self, slot: u32) {
+        self.cache.get_mut().forget(slot);
+        self.inner.remove(slot);
+    }
+
+    fn clear(&mut self) {
+        let cache = self.cache.get_mut();
+        cache.vectors.clear();
+        cache.order.clear();
+        self.inner.clear();
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.inner.reserve(additional);
+    }
+
+    fn shrink_to_fit(&mut self) -> usize {
+        self.inner.shrink_to_fit()
+    }
+
+    fn batch_get(&self, slots: &[u32]) -> Vec<Option<Cow<'_, [S]>>> {
+        let mut out: Vec<Option<Cow<'_, [S]>>> = Vec::with_capacity(slots.len());
+        let mut misses = Vec::new();
+        {
+            let mut cache = self.cache.borrow_mut();
+            for &slot in slots {
+                let hit = cache.get(slot);
+                if hit.is_none() {
+                    misses.push(slot);
+                }
+                out.push(hit.map(Cow::Owned));
+            }
+        }
+        if misses.is_empty() {
+            return out;
+        }
+        // One round-trip to the wrapped store for every miss
+        let mut fetched = self.inner.batch_get(&misses).into_iter();
+        let mut cache = self.cache.borrow_mut();
+        for (&slot, entry) in slots.iter().zip(out.iter_mut()) {
+            if entry.is_some() {
+                continue;
+            }
+            if let Some(vector) = fetched.next().flatten() {
+                let vector = vector.into_owned();
+                cache.insert(slot, vector.clone());
+                *entry = Some(Cow::Owned(vector));
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryStore;
+
+    fn cached_slots(store: &CachedStore<f32, MemoryStore<f32>>) -> Vec<u32> {
+        let mut slots: Vec<u32> = store.cache.borrow().vectors.keys().copied().collect();
+        slots.sort_unstable();
+        slots
+    }
+
+    #[test]
+    fn evicts_unreferenced_vectors_first() {
+        // Room for two vectors of two f32 each
+        let mut store = CachedStore::with_budget(MemoryStore::new(), 16, 2);
+        store.put(0, vec![0.0, 1.0]).unwrap();
+        store.put(1, vec![1.0, 0.0]).unwrap();
+        assert_eq!(cached_slots(&store), [0, 1]);
+
+        // Slot 0 was read again, so it gets a second chance and 1 goes
+        assert!(store.get(0).is_some());
+        store.put(2, vec![1.0, 1.0]).unwrap();
+        assert_eq!(cached_slots(&store), [0, 2]);
+
+        // An evicted vector is read through and cached again
+        assert_eq!(store.get(1).as_deref(), Some(&[1.0, 0.0][..]));
+        assert_eq!(store.cached(), 2);
+        assert!(cached_slots(&store).contains(&1));
+        for slot in 0..3 {
+            assert!(store.get(slot).is_some());
+        }
+    }
+
+    #[test]
+    fn zero_budget_caches_nothing() {
+        let mut store = CachedStore::with_budget(MemoryStore::new(), 0, 2);
+        store.put(0, vec![0.0, 1.0]).unwrap();
+        assert!(store.get(0).is_some());
+        assert_eq!(store.cached(), 0);
+    }
+}
@@ -0,0 +1,139 @@
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use super::VectorStore;
+use crate::scalar::Scalar;
+use crate::HnswError;
+
+/// Loader called by [`LazyStore`] for slots that are not resident
+pub type SlotLoader<S> = Box<dyn Fn(u32) -> Option<Vec<S>>>;
+
+/// Store that fetches vectors on demand from a user-supplied loader
+///
+/// Vectors written through [`VectorStore::put`] stay resident. Everything
+/// else is pulled from the loader on first access and kept in a bounded
+/// FIFO cache, which makes this a starting point for remote KV or
+/// encrypted-block backends.
+///
+/// Only resident vectors and removals are serialized. A decoded store has
+/// no loader until [`LazyStore::set_loader`] is called.
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct LazyStore<S: Scalar> {
+    #[serde(skip, default = "no_loader")]
+    loader: SlotLoader<S>,
+    #[serde(default)]
+    resident: HashMap<u32, Vec<S>>,
+    #[serde(default)]
+    removed: HashSet<u32>,
+    #[serde(skip, default = "FetchCache::empty")]
+    cache: RefCell<FetchCache<S>>,
+}
+
+fn no_loader<S>() -> SlotLoader<S> {
+    Box::new(|_| None)
+}
+
+struct FetchCache<S> {
+    capacity: usize,
+    vectors: HashMap<u32, Vec<S>>,
+    order: VecDeque<u32>,
+}
+
+impl<S> FetchCache<S> {
+    fn empty() -> RefCell<FetchCache<S>> {
+        RefCell::new(FetchCache {
+            capacity: 0,
+            vectors: HashMap::new(),
+            order: VecDeque::new(),
+        })
+    }
+}
+
+impl<S: Scalar> LazyStore<S> {
+    /// Create a store backed by `loader`, caching up to `cache_capacity`
+    /// fetched vectors
+    pub fn new(loader: SlotLoader<S>, cache_capacity: usize) -> LazyStore<S> {
+        LazyStore {
+            loader,
+            resident: HashMap::new(),
+            removed: HashSet::new(),
+            cache: RefCell::new(FetchCache {
+                capacity: cache_capacity,
+                vectors: HashMap::new(),
+                order: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// Replace the loader and cache size, dropping cached vectors
+    pub fn set_loader(&mut self, loader: SlotLoader<S>, cache_capacity: usize) {
+        self.loader = loader;
+        let cache = self.cache.get_mut();
+        cache.capacity = cache_capacity;
+        cache.vectors.clear();
+        cache.order.clear();
+    }
+
+    /// Number of fetched vectors currently cached
+    pub fn cached(&self) -> usize {
+        self.cache.borrow().vectors.len()
+    }
+}
+
+impl<S: Scalar> VectorStore<S> for LazyStore<S> {
+    fn get(&self, slot: u32) -> Option<Cow<'_, [S]>> {
+        if let Some(vector) = self.resident.get(&slot) {
+            return Some(Cow::Borrowed(vector));
+        }
+        if self.removed.contains(&slot) {
+            return None;
+        }
+
+        let mut cache = self.cache.borrow_mut();
+        if let Some(vector) = cache.vectors.get(&slot) {
+            return Some(Cow::Owned(vector.clone()));
+        }
+
+        let vector = (self.loader)(slot)?;
+        if cache.capacity > 0 {
+            if cache.vectors.len() >= cache.capacity {
+                if let Some(evicted) = cache.order.pop_front() {
+                    cache.vectors.remove(&evicted);
+                }
+            }
+            cache.order.push_back(slot);
+            cache.vectors.insert(slot, vector.clone());
+        }
+        Some(Cow::Owned(vector))
+    }
+
+    fn put(&mut self, slot: u32, vector: Vec<S>) -> Result<(), HnswError> {
+        self.removed.remove(&slot);
+        let cache = self.cache.get_mut();
+        if cache.vectors.remove(&slot).is_some() {
+            cache.order.retain(|&s| s != slot);
+        }
+        self.resident.insert(slot, vector);
+        Ok(())
+    }
+
+    fn remove(&mut self, slot: u32) {
+        self.resident.remove(&slot);
+        let cache = self.cache.get_mut();
+        if cache.vectors.remove(&slot).is_some() {
+            cache.order.retain(|&s| s != slot);
+        }
+        self.removed.insert(slot);
+    }
+
+    fn clear(&mut self) {
+        self.resident.clear();
+        self.removed.clear();
+        let cache = self.cache.get_mut();
+        cache.vectors.clear();
+        cache.order.clear();
+    }
+}
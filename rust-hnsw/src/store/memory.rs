@@ -0,0 +1,121 @@
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+use super::VectorStore;
+use crate::scalar::Scalar;
+use crate::HnswError;
+
+/// Default in-memory store: every vector back to back in one buffer
+///
+/// Slot `n` holds the `dim` values at offset `n * dim`, so a search walks
+/// one allocation instead of chasing a heap pointer per vector, and the
+/// buffer serializes as a single packed array. The width is fixed by the
+/// first vector stored.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct MemoryStore<S: Scalar> {
+    dim: usize,
+    data: Vec<S>,
+    /// Whether each slot holds a vector
+    occupied: Vec<bool>,
+    /// Slots reserved before the width was known, sized on the first put
+    #[serde(skip)]
+    reserved: usize,
+}
+
+impl<S: Scalar> MemoryStore<S> {
+    pub fn new() -> MemoryStore<S> {
+        MemoryStore {
+            dim: 0,
+            data: Vec::new(),
+            occupied: Vec::new(),
+            reserved: 0,
+        }
+    }
+
+    /// Values per vector, or 0 before the first is stored
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// Every slot's values back to back, empty slots included
+    ///
+    /// Lets callers export the vectors without copying them out one by one.
+    pub fn as_slice(&self) -> &[S] {
+        &self.data
+    }
+}
+
+impl<S: Scalar> VectorStore<S> for MemoryStore<S> {
+    fn get(&self, slot: u32) -> Option<Cow<'_, [S]>> {
+        let slot = slot as usize;
+        if !self.occupied.get(slot).copied().unwrap_or(false) {
+            return None;
+        }
+        // A decoded buffer may be short; treat its missing tail as empty
+        let start = slot.checked_mul(self.dim)?;
+        self.data
+            .get(start..start.checked_add(self.dim)?)
+            .map(Cow::Borrowed)
+    }
+
+    fn put(&mut self, slot: u32, vector: Vec<S>) -> Result<(), HnswError> {
+        if self.data.is_empty() {
+            self.dim = vector.len();
+            self.data
+                .reserve(std::mem::take(&mut self.reserved).saturating_mul(self.dim));
+        } else if vector.len() != self.dim {
+            return Err(HnswError::Storage(format!(
+                "vector of {} values in a store of {}",
+                vector.len(),
+                self.dim
+            )));
+        }
+        let slot = slot as usize;
+        if slot >= self.occupied.len() {
+            self.occupied.resize(slot + 1, false);
+        }
+        let start = slot * self.dim;
+        if self.data.len() < start + self.dim {
+            self.data.resize(start + self.dim, S::default());
+        }
+        self.data[start..start + self.dim].copy_from_slice(&vector);
+        self.occupied[slot] = true;
+        Ok(())
+    }
+
+    fn remove(&mut self, slot: u32) {
+        if let Some(occupied) = self.occupied.get_mut(slot as usize) {
+            *occupied = false;
+        }
+    }
+
+    fn clear(&mut self) {
+        self.dim = 0;
+        self.data.clear();
+        self.occupied.clear();
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.occupied.reserve(additional);
+        if self.data.is_empty() {
+            self.reserved = self.reserved.max(additional);
+        } else {
+            self.data.reserve(additional.saturating_mul(self.dim));
+        }
+    }
+
+    fn shrink_to_fit(&mut self) -> usize {
+        let used = self
+            .occupied
+            .iter()
+            .rposition(|&o| o)
+            .map_or(0, |last| last + 1);
+        let before = self.data.capacity() * S::BYTES + self.occupied.capacity();
+        self.occupied.truncate(used);
+        self.occupied.shrink_to_fit();
+        self.data.truncate(used * self.dim);
+        self.data.shrink_to_fit();
+        before - (self.data.capacity() * S::BYTES + self.occupied.capacity())
+    }
+}
@@ -0,0 +1,128 @@
+use memmap2::MmapMut;
+use std::borrow::Cow;
+use std::fs::{File, OpenOptions};
+use std::marker::PhantomData;
+use std::path::Path;
+
+use super::VectorStore;
+use crate::scalar::Scalar;
+use crate::HnswError;
+
+/// File-backed store using a writable memory map
+///
+/// The file is a flat array of fixed-size little-endian vectors, so slot
+/// `n` lives at byte `n * dim * S::BYTES`. The map grows by doubling when a
+/// slot past the end is written. Slot occupancy is tracked by the index,
+/// not the file.
+pub struct MmapStore<S: Scalar> {
+    file: File,
+    map: Option<MmapMut>,
+    dim: usize,
+    capacity: u32,
+    _scalar: PhantomData<S>,
+}
+
+impl<S: Scalar> MmapStore<S> {
+    /// Open or create the backing file for vectors of `dim` elements
+    pub fn open<P: AsRef<Path>>(path: P, dim: usize) -> Result<MmapStore<S>, HnswError> {
+        if dim == 0 {
+            return Err(HnswError::Storage("mmap store needs dim > 0".to_string()));
+        }
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .map_err(storage_error)?;
+        let len = file.metadata().map_err(storage_error)?.len() as usize;
+
+        let mut store = MmapStore {
+            file,
+            map: None,
+            dim,
+            capacity: (len / (dim * S::BYTES)) as u32,
+            _scalar: PhantomData,
+        };
+        store.remap()?;
+        Ok(store)
+    }
+
+    /// Flush dirty pages to the backing file
+    pub fn flush(&self) -> Result<(), HnswError> {
+        match &self.map {
+            Some(map) => map.flush().map_err(storage_error),
+            None => Ok(()),
+        }
+    }
+
+    fn vector_bytes(&self) -> usize {
+        self.dim * S::BYTES
+    }
+
+    fn remap(&mut self) -> Result<(), HnswError> {
+        let len = self.capacity as usize * self.vector_bytes();
+        self.file.set_len(len as u64).map_err(storage_error)?;
+        self.map = if len == 0 {
+            None
+        } else {
+            // SAFETY: the file is owned by this store and only mutated
+            // through the map; other processes must not truncate it.
+            Some(unsafe { MmapMut::map_mut(&self.file) }.map_err(storage_error)?)
+        };
+        Ok(())
+    }
+}
+
+impl<S: Scalar> VectorStore<S> for MmapStore<S> {
+    fn get(&self, slot: u32) -> Option<Cow<'_, [S]>> {
+        if slot >= self.capacity {
+            return None;
+        }
+        let map = self.map.as_ref()?;
+        let start = slot as usize * self.vector_bytes();
+        let bytes = &map[start..start + self.vector_bytes()];
+        Some(Cow::Owned(
+            bytes.chunks_exact(S::BYTES).map(S::read_le).collect(),
+        ))
+    }
+
+    fn put(&mut self, slot: u32, vector: Vec<S>) -> Result<(), HnswError> {
+        if vector.len() != self.dim {
+            return Err(HnswError::DimensionMismatch {
+                expected: self.dim,
+                got: vector.len(),
+            });
+        }
+        if slot >= self.capacity {
+            self.capacity = (slot + 1).max(self.capacity.saturating_mul(2));
+            self.remap()?;
+        }
+
+        let mut encoded = Vec::with_capacity(self.vector_bytes());
+        for value in vector {
+            value.write_le(&mut encoded);
+        }
+
+        let start = slot as usize * self.vector_bytes();
+        if let Some(map) = self.map.as_mut() {
+            map[start..start + encoded.len()].copy_from_slice(&encoded);
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, _slot: u32) {
+        // Freed slots are simply overwritten when reused
+    }
+
+    fn clear(&mut self) {
+        self.map = None;
+        self.capacity = 0;
+        let _ = self.file.set_len(0);
+    }
+}
+
+fn storage_error(err: std::io::Error) -> HnswError {
+    HnswError::Storage(err.to_string())
+}
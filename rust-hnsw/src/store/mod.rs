@@ -0,0 +1,87 @@
+//! Pluggable vector storage
+//!
+//! The graph addresses vectors by dense `u32` slots; a [`VectorStore`]
+//! decides where the vector behind each slot actually lives.
+
+use std::borrow::Cow;
+
+use crate::scalar::Scalar;
+use crate::HnswError;
+
+mod cached;
+mod lazy;
+mod memory;
+#[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+mod mmap;
+#[cfg(feature = "web")]
+mod opfs;
+#[cfg(all(feature = "sqlite", not(target_arch = "wasm32")))]
+mod sqlite;
+
+pub use cached::CachedStore;
+pub use lazy::LazyStore;
+pub use memory::MemoryStore;
+#[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+pub use mmap::MmapStore;
+#[cfg(feature = "web")]
+pub use opfs::OpfsStore;
+#[cfg(all(feature = "sqlite", not(target_arch = "wasm32")))]
+pub use sqlite::SqliteVectorStore;
+
+/// Browser store that keeps hot vectors in wasm memory and the rest in an
+/// OPFS file
+///
+/// Every vector is written to the file; reads are served from a cache
+/// bounded by [`CachedStore::with_budget`] and paged back in from the
+/// file on a miss, so the graph and hot vectors stay within the memory
+/// budget of a tab however large the index grows.
+#[cfg(feature = "web")]
+pub type TieredStore<S> = CachedStore<S, OpfsStore<S>>;
+
+/// Native store that keeps hot vectors in memory and the rest in SQLite
+///
+/// The disk counterpart of `TieredStore`: reads are served from a cache
+/// bounded by [`CachedStore::with_budget`] and read through to the table
+/// on a miss. Ids, payloads and the graph itself stay in memory.
+#[cfg(all(feature = "sqlite", not(target_arch = "wasm32")))]
+pub type DiskStore<S> = CachedStore<S, SqliteVectorStore<S>>;
+
+/// Storage backend for the vectors behind graph slots
+///
+/// Slots are allocated by the index: dense, starting at 0, and reused
+/// after [`VectorStore::remove`]. Implementations only have to map a slot
+/// to its vector; they never see external ids.
+pub trait VectorStore<S: Scalar> {
+    /// Fetch the vector in `slot`, or `None` if the slot is empty
+    fn get(&self, slot: u32) -> Option<Cow<'_, [S]>>;
+
+    /// Store `vector` in `slot`, replacing whatever was there
+    fn put(&mut self, slot: u32, vector: Vec<S>) -> Result<(), HnswError>;
+
+    /// Release `slot`; the index may hand it out again later
+    fn remove(&mut self, slot: u32);
+
+    /// Drop every stored vector
+    fn clear(&mut self);
+
+    /// Make room for `additional` more slots ahead of a bulk insert
+    ///
+    /// Only a hint; the default ignores it.
+    fn reserve(&mut self, _additional: usize) {}
+
+    /// Release memory held for empty slots, returning the bytes freed
+    ///
+    /// Called after [`Hnsw::compact`](crate::Hnsw::compact) has moved every
+    /// vector to the lowest slots. The default frees nothing.
+    fn shrink_to_fit(&mut self) -> usize {
+        0
+    }
+
+    /// Fetch several slots at once
+    ///
+    /// The default forwards to [`VectorStore::get`]; backends with a real
+    /// round-trip cost (remote KV, disk) should override it.
+    fn batch_get(&self, slots: &[u32]) -> Vec<Option<Cow<'_, [S]>>> {
+        slots.iter().map(|&slot| self.get(slot)).collect()
+    }
+}
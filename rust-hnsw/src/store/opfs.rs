@@ -0,0 +1,144 @@
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::borrow::Cow;
+use std::marker::PhantomData;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{FileSystemReadWriteOptions, FileSystemSyncAccessHandle};
+
+use super::VectorStore;
+use crate::scalar::Scalar;
+use crate::HnswError;
+
+/// Store backed by a file in the Origin Private File System
+///
+/// Uses the same layout as `MmapStore`: a flat array
+/// of fixed-size little-endian vectors, slot `n` at byte
+/// `n * dim * S::BYTES`. Reads and writes go straight to a synchronous
+/// access handle, which browsers only hand out inside dedicated workers:
+///
+/// ```js
+/// const root = await navigator.storage.getDirectory();
+/// const file = await root.getFileHandle("vectors.bin", { create: true });
+/// const handle = await file.createSyncAccessHandle();
+/// ```
+///
+/// The handle keeps the file locked until the store is dropped.
+///
+/// The vectors stay in the file when the index is saved, so a snapshot
+/// holds only the graph; reopen the file and pass both to
+/// [`Hnsw::load_graph`](crate::Hnsw::load_graph) to load it again.
+pub struct OpfsStore<S: Scalar> {
+    handle: FileSystemSyncAccessHandle,
+    dim: usize,
+    capacity: u32,
+    _scalar: PhantomData<S>,
+}
+
+impl<S: Scalar> OpfsStore<S> {
+    /// Use the file behind `handle` for vectors of `dim` elements
+    pub fn open(handle: FileSystemSyncAccessHandle, dim: usize) -> Result<OpfsStore<S>, HnswError> {
+        if dim == 0 {
+            return Err(HnswError::Storage("OPFS store needs dim > 0".to_string()));
+        }
+        let len = handle.get_size().map_err(storage_error)? as usize;
+        Ok(OpfsStore {
+            handle,
+            dim,
+            capacity: (len / (dim * S::BYTES)) as u32,
+            _scalar: PhantomData,
+        })
+    }
+
+    /// Persist written vectors to disk
+    pub fn flush(&self) -> Result<(), HnswError> {
+        self.handle.flush().map_err(storage_error)
+    }
+
+    fn vector_bytes(&self) -> usize {
+        self.dim * S::BYTES
+    }
+
+    fn at(&self, slot: u32) -> FileSystemReadWriteOptions {
+        let options = FileSystemReadWriteOptions::new();
+        options.set_at((slot as usize * self.vector_bytes()) as f64);
+        options
+    }
+}
+
+impl<S: Scalar> VectorStore<S> for OpfsStore<S> {
+    fn get(&self, slot: u32) -> Option<Cow<'_, [S]>> {
+        if slot >= self.capacity {
+            return None;
+        }
+        let mut bytes = vec![0u8; self.vector_bytes()];
+        let read = self
+            .handle
+            .read_with_u8_array_and_options(&mut bytes, &self.at(slot))
+            .ok()?;
+        if read as usize != bytes.len() {
+            return None;
+        }
+        Some(Cow::Owned(
+            bytes.chunks_exact(S::BYTES).map(S::read_le).collect(),
+        ))
+    }
+
+    fn put(&mut self, slot: u32, vector: Vec<S>) -> Result<(), HnswError> {
+        if vector.len() != self.dim {
+            return Err(HnswError::DimensionMismatch {
+                expected: self.dim,
+                got: vector.len(),
+            });
+        }
+
+        let mut encoded = Vec::with_capacity(self.vector_bytes());
+        for value in vector {
+            value.write_le(&mut encoded);
+        }
+
+        // Writing past the end grows the file, zero-filling any gap
+        let written = self
+            .handle
+            .write_with_u8_array_and_options(&encoded, &self.at(slot))
+            .map_err(storage_error)?;
+        if written as usize != encoded.len() {
+            return Err(HnswError::Storage(format!(
+                "short write to OPFS: {} of {} bytes",
+                written,
+                encoded.len()
+            )));
+        }
+        self.capacity = self.capacity.max(slot + 1);
+        Ok(())
+    }
+
+    fn remove(&mut self, _slot: u32) {
+        // Freed slots are simply overwritten when reused
+    }
+
+    fn clear(&mut self) {
+        self.capacity = 0;
+        let _ = self.handle.truncate_with_u32(0);
+    }
+}
+
+impl<S: Scalar> Serialize for OpfsStore<S> {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serializer.serialize_struct("OpfsStore", 0)?.end()
+    }
+}
+
+impl<S: Scalar> Drop for OpfsStore<S> {
+    fn drop(&mut self) {
+        let _ = self.handle.flush();
+        self.handle.close();
+    }
+}
+
+fn storage_error(err: JsValue) -> HnswError {
+    let message = err
+        .dyn_ref::<js_sys::Error>()
+        .map(|e| String::from(e.message()))
+        .or_else(|| err.as_string())
+        .unwrap_or_else(|| "OPFS access failed".to_string());
+    HnswError::Storage(message)
+}
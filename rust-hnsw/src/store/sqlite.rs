@@ -0,0 +1,144 @@
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::marker::PhantomData;
+use std::path::Path;
+
+use super::VectorStore;
+use crate::scalar::Scalar;
+use crate::sqlite::{Database, Param, Statement};
+use crate::HnswError;
+
+const TABLE: &str =
+    "CREATE TABLE IF NOT EXISTS vectors (slot INTEGER PRIMARY KEY, vector BLOB NOT NULL)";
+
+/// Store keeping vectors in a table of a SQLite database
+///
+/// Each slot is one row of little-endian values, so only the graph, ids
+/// and point data of the index stay in memory and an index can grow past
+/// it. Put a [`CachedStore`](super::CachedStore) in front, as
+/// [`DiskStore`](super::DiskStore) does, to keep hot vectors resident.
+///
+/// Writes are grouped into one transaction until [`SqliteVectorStore::flush`]
+/// or the store is dropped, so inserting does not wait for the disk per
+/// point. Like `OpfsStore`, saving the index leaves the vectors out; pass
+/// the snapshot and a store reopened on the same file to
+/// [`Hnsw::load_graph`](crate::Hnsw::load_graph).
+pub struct SqliteVectorStore<S: Scalar> {
+    /// Reused by every read; declared before `db` so it is dropped first
+    select: RefCell<Statement<'static>>,
+    db: Box<Database>,
+    dim: usize,
+    /// Whether writes since the last flush are in an open transaction
+    writing: Cell<bool>,
+    _scalar: PhantomData<S>,
+}
+
+impl<S: Scalar> SqliteVectorStore<S> {
+    /// Open or create the database at `path` for vectors of `dim` elements
+    pub fn open<P: AsRef<Path>>(path: P, dim: usize) -> Result<SqliteVectorStore<S>, HnswError> {
+        if dim == 0 {
+            return Err(HnswError::Storage(
+                "SQLite vector store needs dim > 0".to_string(),
+            ));
+        }
+        let db = Box::new(Database::open(path.as_ref())?);
+        db.execute(TABLE)?;
+        let select = db.prepare("SELECT vector FROM vectors WHERE slot = ?1")?;
+        // SAFETY: the statement borrows the boxed database, which does not
+        // move with the store and is dropped after it
+        let select = unsafe { std::mem::transmute::<Statement<'_>, Statement<'static>>(select) };
+        Ok(SqliteVectorStore {
+            select: RefCell::new(select),
+            db,
+            dim,
+            writing: Cell::new(false),
+            _scalar: PhantomData,
+        })
+    }
+
+    /// Commit the writes made so far
+    pub fn flush(&self) -> Result<(), HnswError> {
+        if self.writing.get() {
+            self.db.execute("COMMIT")?;
+            self.writing.set(false);
+        }
+        Ok(())
+    }
+
+    /// Run `sql` with `params` inside the open write transaction
+    fn write(&mut self, sql: &str, params: &[Param<'_>]) -> Result<(), HnswError> {
+        if !self.writing.get() {
+            self.db.execute("BEGIN IMMEDIATE")?;
+            self.writing.set(true);
+        }
+        self.db.prepare(sql)?.run(params)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Option<Vec<S>> {
+        if bytes.len() != self.dim * S::BYTES {
+            return None;
+        }
+        Some(bytes.chunks_exact(S::BYTES).map(S::read_le).collect())
+    }
+}
+
+impl<S: Scalar> VectorStore<S> for SqliteVectorStore<S> {
+    fn get(&self, slot: u32) -> Option<Cow<'_, [S]>> {
+        self.batch_get(&[slot]).pop().flatten()
+    }
+
+    fn put(&mut self, slot: u32, vector: Vec<S>) -> Result<(), HnswError> {
+        if vector.len() != self.dim {
+            return Err(HnswError::DimensionMismatch {
+                expected: self.dim,
+                got: vector.len(),
+            });
+        }
+        let mut encoded = Vec::with_capacity(self.dim * S::BYTES);
+        for value in vector {
+            value.write_le(&mut encoded);
+        }
+        self.write(
+            "INSERT OR REPLACE INTO vectors (slot, vector) VALUES (?1, ?2)",
+            &[Param::Int(slot as i64), Param::Blob(&encoded)],
+        )
+    }
+
+    fn remove(&mut self, slot: u32) {
+        let _ = self.write(
+            "DELETE FROM vectors WHERE slot = ?1",
+            &[Param::Int(slot as i64)],
+        );
+    }
+
+    fn clear(&mut self) {
+        let _ = self.write("DELETE FROM vectors", &[]);
+    }
+
+    fn batch_get(&self, slots: &[u32]) -> Vec<Option<Cow<'_, [S]>>> {
+        let mut select = self.select.borrow_mut();
+        slots
+            .iter()
+            .map(|&slot| {
+                select.bind(&[Param::Int(slot as i64)]).ok()?;
+                if !select.step().ok()? {
+                    return None;
+                }
+                self.decode(select.blob(0)?).map(Cow::Owned)
+            })
+            .collect()
+    }
+}
+
+impl<S: Scalar> Serialize for SqliteVectorStore<S> {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serializer.serialize_struct("SqliteVectorStore", 0)?.end()
+    }
+}
+
+impl<S: Scalar> Drop for SqliteVectorStore<S> {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
@@ -0,0 +1,241 @@
+//! BM25 keyword index over point text
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::ids::{key, Id, IdKey};
+use crate::index::Hnsw;
+use crate::scalar::Scalar;
+use crate::search::SearchOptions;
+use crate::store::VectorStore;
+use crate::HnswError;
+
+/// Term frequency saturation
+const BM25_K1: f32 = 1.2;
+/// Document length normalization
+const BM25_B: f32 = 0.75;
+
+/// Term counts of one document
+pub(crate) type Terms = BTreeMap<String, u32>;
+
+/// Split text into lowercase terms, breaking identifiers apart
+///
+/// `parseFilter`, `parse_filter` and `PARSE_FILTER` all yield `parse` and
+/// `filter`; compound identifiers also yield their parts joined, here
+/// `parsefilter`, so a whole identifier match outscores one on its parts
+/// in any naming convention. Acronyms stay together:
+/// `HTTPServer` gives `http` and `server`.
+pub fn tokenize(text: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+    for word in text.split(|c: char| !(c.is_alphanumeric() || c == '_')) {
+        let parts: Vec<String> = word
+            .split('_')
+            .flat_map(split_case)
+            .map(|part| part.to_lowercase())
+            .collect();
+        if parts.len() > 1 {
+            terms.push(parts.concat());
+        }
+        terms.extend(parts);
+    }
+    terms
+}
+
+/// Split a word at lower→upper and acronym→word case changes
+fn split_case(word: &str) -> Vec<&str> {
+    let chars: Vec<(usize, char)> = word.char_indices().collect();
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for i in 1..chars.len() {
+        let (at, cur) = chars[i];
+        let prev = chars[i - 1].1;
+        let next_lower = chars.get(i + 1).is_some_and(|(_, c)| c.is_lowercase());
+        if cur.is_uppercase()
+            && (prev.is_lowercase() || prev.is_numeric() || (prev.is_uppercase() && next_lower))
+        {
+            parts.push(&word[start..at]);
+            start = at;
+        }
+    }
+    if start < word.len() {
+        parts.push(&word[start..]);
+    }
+    parts
+}
+
+/// Term statistics of indexed text with an inverted list per term
+///
+/// Only per-document term counts are persisted, never the text itself;
+/// postings and lengths are rebuilt on load.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(from = "BTreeMap<Id, Terms>", into = "BTreeMap<Id, Terms>")]
+pub(crate) struct TextIndex {
+    docs: BTreeMap<Id, Terms>,
+    /// Term → (id → count)
+    postings: HashMap<String, HashMap<Id, u32>>,
+    lengths: HashMap<Id, u32>,
+    total_length: u64,
+}
+
+impl From<BTreeMap<Id, Terms>> for TextIndex {
+    fn from(docs: BTreeMap<Id, Terms>) -> TextIndex {
+        let mut index = TextIndex::default();
+        for (id, terms) in docs {
+            index.insert_terms(id, terms);
+        }
+        index
+    }
+}
+
+impl From<TextIndex> for BTreeMap<Id, Terms> {
+    fn from(index: TextIndex) -> BTreeMap<Id, Terms> {
+        index.docs
+    }
+}
+
+impl TextIndex {
+    pub fn ids(&self) -> impl Iterator<Item = &Id> {
+        self.docs.keys()
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.docs.contains_key(key(&id))
+    }
+
+    /// Index the text of `id`, replacing any previous text
+    pub fn insert(&mut self, id: Id, text: &str) {
+        let mut terms = Terms::new();
+        for term in tokenize(text) {
+            *terms.entry(term).or_default() += 1;
+        }
+        self.insert_terms(id, terms);
+    }
+
+    /// Term counts of `id`, if it has text
+    pub fn terms(&self, id: &str) -> Option<&Terms> {
+        self.docs.get(key(&id))
+    }
+
+    /// Index already counted terms of `id`, replacing any previous text
+    pub fn insert_terms(&mut self, id: Id, terms: Terms) {
+        self.remove_key(&id);
+        if terms.is_empty() {
+            return;
+        }
+        let length: u32 = terms.values().sum();
+        for (term, count) in &terms {
+            self.postings
+                .entry(term.clone())
+                .or_default()
+                .insert(id.clone(), *count);
+        }
+        self.total_length += length as u64;
+        self.lengths.insert(id.clone(), length);
+        self.docs.insert(id, terms);
+    }
+
+    /// Drop the text of `id`, returning whether there was any
+    pub fn remove(&mut self, id: &str) -> bool {
+        self.remove_key(key(&id))
+    }
+
+    fn remove_key(&mut self, id: &dyn IdKey) -> bool {
+        let Some((id, terms)) = self.docs.remove_entry(id) else {
+            return false;
+        };
+        for term in terms.keys() {
+            if let Some(ids) = self.postings.get_mut(term) {
+                ids.remove(&id);
+                if ids.is_empty() {
+                    self.postings.remove(term);
+                }
+            }
+        }
+        if let Some(length) = self.lengths.remove(&id) {
+            self.total_length -= length as u64;
+        }
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.docs.clear();
+        self.postings.clear();
+        self.lengths.clear();
+        self.total_length = 0;
+    }
+
+    /// Re-key every document by the id `share` returns for its own
+    pub fn share(&mut self, share: impl Fn(&Id) -> Id) {
+        let docs = std::mem::take(&mut self.docs);
+        self.clear();
+        for (id, terms) in docs {
+            self.insert_terms(share(&id), terms);
+        }
+    }
+
+    /// BM25 scores of every document sharing a term with `query`
+    pub fn scores(&self, query: &str) -> HashMap<&Id, f32> {
+        let mut scores: HashMap<&Id, f32> = HashMap::new();
+        let docs = self.docs.len() as f32;
+        if docs == 0.0 {
+            return scores;
+        }
+        let average = self.total_length as f32 / docs;
+
+        let mut terms = tokenize(query);
+        terms.sort();
+        terms.dedup();
+        for term in &terms {
+            let Some(postings) = self.postings.get(term) else {
+                continue;
+            };
+            let df = postings.len() as f32;
+            let idf = (1.0 + (docs - df + 0.5) / (df + 0.5)).ln();
+            for (id, &count) in postings {
+                let tf = count as f32;
+                let length = self.lengths.get(id).copied().unwrap_or(0) as f32;
+                let norm = BM25_K1 * (1.0 - BM25_B + BM25_B * length / average);
+                *scores.entry(id).or_default() += idf * tf * (BM25_K1 + 1.0) / (tf + norm);
+            }
+        }
+        scores
+    }
+}
+
+impl<S: Scalar, V: VectorStore<S>> Hnsw<S, V> {
+    /// Index the text of a point for [`Hnsw::search_text`], replacing any
+    /// previous text
+    ///
+    /// Only term counts are kept. Text without terms detaches it. Returns
+    /// false when the id is not in the index; text is dropped with its
+    /// point, but kept when an upsert replaces the point's vector.
+    pub fn set_text(&mut self, id: &str, text: &str) -> bool {
+        if !self.contains(id) {
+            return false;
+        }
+        self.touch(id);
+        if let Some(id) = self.interned(id).cloned() {
+            self.text_mut().insert(id, text);
+        }
+        true
+    }
+
+    /// Whether a point has indexed text
+    pub fn has_text(&self, id: &str) -> bool {
+        self.text().contains(id)
+    }
+
+    /// The `k` points whose text best matches `query` under BM25
+    ///
+    /// Scores are unbounded BM25 scores. `filter`, exclusions and
+    /// `minScore` apply as in [`Hnsw::search`]; `diversity` is not
+    /// supported.
+    pub fn search_text(
+        &self,
+        query: &str,
+        k: usize,
+        options: &SearchOptions,
+    ) -> Result<Vec<(String, f32)>, HnswError> {
+        self.rank_scores(self.text().scores(query), k, options, "text")
+    }
+}
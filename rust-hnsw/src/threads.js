@@ -0,0 +1,39 @@
+// Worker side of the thread pool in threads.rs
+//
+// The same file runs in two places: imported by the module, it starts
+// workers; as a worker's script, it loads the module on the shared
+// memory it is sent and enters the job loop.
+
+const WORKER_NAME = 'hnsw_thread';
+
+export function startWorkers(module, memory, count) {
+  const ready = [];
+  for (let i = 0; i < count; i++) {
+    const worker = new Worker(new URL('./threads.js', import.meta.url), {
+      type: 'module',
+      name: WORKER_NAME,
+    });
+    ready.push(
+      new Promise((resolve, reject) => {
+        worker.addEventListener('message', () => resolve(), { once: true });
+        worker.addEventListener('error', reject, { once: true });
+      }),
+    );
+    worker.postMessage({ module, memory });
+  }
+  return Promise.all(ready).then(() => undefined);
+}
+
+if (typeof WorkerGlobalScope !== 'undefined' && self.name === WORKER_NAME) {
+  self.addEventListener(
+    'message',
+    async ({ data }) => {
+      // wasm-pack puts the package entry three levels above its snippets
+      const pkg = await import('../../../hnsw.js');
+      await pkg.default({ module_or_path: data.module, memory: data.memory });
+      postMessage('ready');
+      pkg.__hnswWorkerLoop();
+    },
+    { once: true },
+  );
+}
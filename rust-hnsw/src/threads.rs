@@ -0,0 +1,146 @@
+//! Web Worker thread pool for wasm builds
+//!
+//! Browsers give wasm no threads of its own. `initThreadPool(n)` starts
+//! `n` Web Workers that instantiate this module on the same shared memory
+//! and then wait for jobs; [`Hnsw::insert_batch_parallel`] and
+//! [`Hnsw::nearest_batch_parallel`] split their work over them and the
+//! calling thread, as native builds do over OS threads.
+//!
+//! Shared memory needs the standard library rebuilt with atomics, which
+//! takes a nightly toolchain:
+//!
+//! ```text
+//! RUSTFLAGS='-C target-feature=+atomics,+bulk-memory,+mutable-globals' \
+//!   rustup run nightly wasm-pack build --target web -- \
+//!   --features threads -Z build-std=panic_abort,std
+//! ```
+//!
+//! The page must also be cross-origin isolated (COOP `same-origin`, COEP
+//! `require-corp`) for `SharedArrayBuffer` to exist. A parallel call
+//! blocks until the workers are done, which browsers only allow off the
+//! main thread, so the index itself has to live in a worker. Before the
+//! pool is started, parallel calls run on the calling thread alone.
+//!
+//! [`Hnsw::insert_batch_parallel`]: crate::Hnsw::insert_batch_parallel
+//! [`Hnsw::nearest_batch_parallel`]: crate::Hnsw::nearest_batch_parallel
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex, PoisonError};
+use wasm_bindgen::prelude::*;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Jobs waiting for a worker
+static JOBS: Mutex<VecDeque<Job>> = Mutex::new(VecDeque::new());
+static JOB_ADDED: Condvar = Condvar::new();
+/// Workers that have entered [`worker_loop`]
+static WORKERS: AtomicUsize = AtomicUsize::new(0);
+
+#[wasm_bindgen(module = "/src/threads.js")]
+extern "C" {
+    #[wasm_bindgen(js_name = startWorkers)]
+    fn start_workers(module: JsValue, memory: JsValue, count: usize) -> js_sys::Promise;
+}
+
+/// Start `threads` Web Workers for the parallel batch methods
+///
+/// Resolves once every worker has loaded the module. Calling it again
+/// adds workers.
+#[wasm_bindgen(js_name = initThreadPool)]
+pub fn init_thread_pool(threads: usize) -> js_sys::Promise {
+    start_workers(wasm_bindgen::module(), wasm_bindgen::memory(), threads)
+}
+
+/// Job loop of a pool worker, entered by `threads.js`; never returns
+#[doc(hidden)]
+#[wasm_bindgen(js_name = __hnswWorkerLoop)]
+pub fn worker_loop() {
+    WORKERS.fetch_add(1, Ordering::SeqCst);
+    loop {
+        let mut jobs = JOBS.lock().unwrap_or_else(PoisonError::into_inner);
+        let job = loop {
+            match jobs.pop_front() {
+                Some(job) => break job,
+                None => jobs = JOB_ADDED.wait(jobs).unwrap_or_else(PoisonError::into_inner),
+            }
+        };
+        drop(jobs);
+        job();
+    }
+}
+
+/// Workers ready to take jobs
+pub(crate) fn pool_threads() -> usize {
+    WORKERS.load(Ordering::SeqCst)
+}
+
+/// Results of the chunks handed to workers, and how many are in
+struct Chunks<R> {
+    results: Mutex<(usize, Vec<Option<Vec<R>>>)>,
+    done: Condvar,
+}
+
+/// `f` of every item on up to `threads` threads, in item order
+///
+/// The calling thread maps the first chunk itself and then waits for the
+/// workers; with no pool it maps everything.
+pub(crate) fn parallel_map<T: Sync, R: Send>(
+    items: &[T],
+    threads: usize,
+    f: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    if items.is_empty() {
+        return Vec::new();
+    }
+    let threads = threads.clamp(1, pool_threads() + 1);
+    let chunk = items.len().div_ceil(threads);
+    let mut parts = items.chunks(chunk);
+    let own = parts.next().unwrap_or_default();
+    let parts: Vec<&[T]> = parts.collect();
+
+    let chunks = Chunks {
+        results: Mutex::new((0, parts.iter().map(|_| None).collect())),
+        done: Condvar::new(),
+    };
+    {
+        let f = &f;
+        let chunks = &chunks;
+        let mut jobs = JOBS.lock().unwrap_or_else(PoisonError::into_inner);
+        for (i, part) in parts.into_iter().enumerate() {
+            let job: Box<dyn FnOnce() + Send + '_> = Box::new(move || {
+                let mapped = part.iter().map(f).collect();
+                let mut results = chunks
+                    .results
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner);
+                results.0 += 1;
+                results.1[i] = Some(mapped);
+                chunks.done.notify_one();
+            });
+            // SAFETY: the job borrows `items`, `f` and `chunks`, and this
+            // function does not return before every job has reported in
+            // below. Wasm panics abort, so no job can be cut short and
+            // leave the wait hanging.
+            let job: Job = unsafe { std::mem::transmute(job) };
+            jobs.push_back(job);
+        }
+        JOB_ADDED.notify_all();
+    }
+
+    let mut out: Vec<R> = own.iter().map(&f).collect();
+    let mut results = chunks
+        .results
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    while results.0 < results.1.len() {
+        results = chunks
+            .done
+            .wait(results)
+            .unwrap_or_else(PoisonError::into_inner);
+    }
+    for part in results.1.drain(..) {
+        out.extend(part.unwrap_or_default());
+    }
+    out
+}
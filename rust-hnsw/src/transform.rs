@@ -0,0 +1,102 @@
+//! Pluggable query preprocessing
+//!
+//! A [`QueryTransform`] set through
+//! [`Hnsw::set_query_transform`](crate::Hnsw::set_query_transform) rewrites
+//! every query vector before it is checked and searched, so corrections
+//! for a mismatch between the query and document embedding models live
+//! in one place instead of in every caller. [`LinearTransform`] covers the
+//! usual adapter of a matrix, a bias and normalization.
+
+use serde::{Deserialize, Serialize};
+
+use crate::scalar::Scalar;
+use crate::HnswError;
+
+/// Rewrites query vectors before search
+///
+/// Transforms see queries in input space, before any random projection,
+/// and must return vectors with the index's dimensions. Stored vectors
+/// and `searchById` queries are left alone.
+pub trait QueryTransform<S: Scalar>: Send + Sync {
+    fn transform(&self, query: &[S]) -> Result<Vec<S>, HnswError>;
+}
+
+/// `normalize(matrix * query + bias)`, each step optional
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LinearTransform {
+    /// Rows of the matrix, one per output dimension
+    pub matrix: Option<Vec<Vec<f32>>>,
+    /// Added after the matrix
+    pub bias: Option<Vec<f32>>,
+    /// Scale the result to unit length; meant for float indexes, as
+    /// integer scalars cannot hold unit vectors
+    pub normalize: bool,
+}
+
+impl LinearTransform {
+    /// Check that the matrix is rectangular and the bias fits its output
+    pub fn validate(&self) -> Result<(), HnswError> {
+        if let Some(matrix) = &self.matrix {
+            let columns = matrix.first().map_or(0, Vec::len);
+            if columns == 0 || matrix.iter().any(|row| row.len() != columns) {
+                return Err(HnswError::InvalidParams(
+                    "transform matrix must be non-empty with rows of equal length".to_string(),
+                ));
+            }
+            if let Some(bias) = self.bias.as_ref().filter(|b| b.len() != matrix.len()) {
+                return Err(HnswError::InvalidParams(format!(
+                    "transform bias has {} entries but the matrix has {} rows",
+                    bias.len(),
+                    matrix.len()
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S: Scalar> QueryTransform<S> for LinearTransform {
+    fn transform(&self, query: &[S]) -> Result<Vec<S>, HnswError> {
+        let mut out: Vec<f64> = match &self.matrix {
+            Some(matrix) => {
+                if let Some(row) = matrix.first().filter(|row| row.len() != query.len()) {
+                    return Err(HnswError::DimensionMismatch {
+                        expected: row.len(),
+                        got: query.len(),
+                    });
+                }
+                matrix
+                    .iter()
+                    .map(|row| {
+                        row.iter()
+                            .zip(query)
+                            .map(|(w, x)| *w as f64 * x.to_f64())
+                            .sum::<f64>()
+                    })
+                    .collect()
+            }
+            None => query.iter().map(|x| x.to_f64()).collect(),
+        };
+        if let Some(bias) = &self.bias {
+            if bias.len() != out.len() {
+                return Err(HnswError::DimensionMismatch {
+                    expected: bias.len(),
+                    got: out.len(),
+                });
+            }
+            for (x, b) in out.iter_mut().zip(bias) {
+                *x += *b as f64;
+            }
+        }
+        if self.normalize {
+            let norm = out.iter().map(|x| x * x).sum::<f64>().sqrt();
+            if norm > 0.0 {
+                for x in &mut out {
+                    *x /= norm;
+                }
+            }
+        }
+        Ok(out.into_iter().map(S::from_f64).collect())
+    }
+}
@@ -0,0 +1,118 @@
+//! Work spread over turns of the event loop
+//!
+//! [`Turns`] runs a step on each of a series of event loop turns, each
+//! scheduled with `setTimeout(0)`, so a page keeps painting and handling
+//! input between steps. A promise settles with the step that finishes the
+//! work or fails. `addBatchAsync` and `searchProgressive` run on it.
+
+use js_sys::{Function, Promise, Reflect};
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+/// Steps still to run behind a promise
+///
+/// Dropping it cancels the remaining steps and rejects the promise.
+pub(crate) struct Turns {
+    state: Rc<RefCell<State>>,
+}
+
+struct State {
+    /// Handle of the `setTimeout` for the next step
+    timer: JsValue,
+    tick: Option<Closure<dyn FnMut()>>,
+    /// `resolve` and `reject` of the promise, until it settles
+    settle: Option<(Function, Function)>,
+    /// Rejection message when cancelled
+    cancelled: &'static str,
+}
+
+impl Turns {
+    /// Run `step` on later turns of the event loop until it returns
+    /// `Ok(Some(value))`, resolving the promise to `value`, or fails,
+    /// rejecting it; `Ok(None)` asks for another turn
+    ///
+    /// If dropped first, the promise is rejected with `cancelled`.
+    pub fn start(
+        mut step: impl FnMut() -> Result<Option<JsValue>, JsValue> + 'static,
+        cancelled: &'static str,
+    ) -> Result<(Turns, Promise), JsValue> {
+        let mut settle = None;
+        let promise = Promise::new(&mut |resolve, reject| settle = Some((resolve, reject)));
+        let state = Rc::new(RefCell::new(State {
+            timer: JsValue::UNDEFINED,
+            tick: None,
+            settle,
+            cancelled,
+        }));
+
+        let weak = Rc::downgrade(&state);
+        let tick = Closure::<dyn FnMut()>::new(move || {
+            let Some(state) = weak.upgrade() else {
+                return;
+            };
+            let outcome = step();
+
+            // The step may call back into JS, which may have cancelled
+            let mut state = state.borrow_mut();
+            let Some((resolve, reject)) = state.settle.take() else {
+                return;
+            };
+            match outcome {
+                Ok(Some(value)) => {
+                    let _ = resolve.call1(&JsValue::NULL, &value);
+                }
+                Err(e) => {
+                    let _ = reject.call1(&JsValue::NULL, &e);
+                }
+                Ok(None) => match state.tick.as_ref().map(schedule) {
+                    Some(Ok(timer)) => {
+                        state.timer = timer;
+                        state.settle = Some((resolve, reject));
+                    }
+                    Some(Err(e)) => {
+                        let _ = reject.call1(&JsValue::NULL, &e);
+                    }
+                    None => {}
+                },
+            }
+        });
+
+        let timer = schedule(&tick)?;
+        {
+            let mut state = state.borrow_mut();
+            state.timer = timer;
+            state.tick = Some(tick);
+        }
+        Ok((Turns { state }, promise))
+    }
+
+    /// Whether steps are still to run
+    pub fn is_running(&self) -> bool {
+        self.state.borrow().settle.is_some()
+    }
+}
+
+/// Run `tick` on a later turn of the event loop
+fn schedule(tick: &Closure<dyn FnMut()>) -> Result<JsValue, JsValue> {
+    let global = js_sys::global();
+    let set_timeout: Function = Reflect::get(&global, &"setTimeout".into())?.dyn_into()?;
+    set_timeout.call2(&global, tick.as_ref().unchecked_ref(), &JsValue::from(0))
+}
+
+impl Drop for Turns {
+    fn drop(&mut self) {
+        let mut state = self.state.borrow_mut();
+        let Some((_, reject)) = state.settle.take() else {
+            return;
+        };
+        let global = js_sys::global();
+        if let Ok(clear) = Reflect::get(&global, &"clearTimeout".into()) {
+            if let Some(clear) = clear.dyn_ref::<Function>() {
+                let _ = clear.call1(&global, &state.timer);
+            }
+        }
+        let _ = reject.call1(&JsValue::NULL, &js_sys::Error::new(state.cancelled));
+    }
+}
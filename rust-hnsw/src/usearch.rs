@@ -0,0 +1,275 @@
+//! usearch index files
+//!
+//! Reads and writes the `index_dense_gt` layout of usearch 2.x as `save`
+//! writes it: the vector matrix, prefixed by `u32` row count and row
+//! bytes; a 64-byte head; then the graph, as a 40-byte header, the `i16`
+//! level of every node, and every node as its key, level and one
+//! `u32`-counted list of `u32` slots per layer, padded to capacity.
+//!
+//! usearch keys are `u64`. Ids that are all decimal numbers are exported
+//! as those keys, others by position in id order, and imported keys
+//! become decimal ids. Exports are `f32` under the cosine metric; imports
+//! take `f64`, `f32`, `f16` or `bf16` vectors and keep the graph as
+//! built, whatever its metric.
+
+use std::collections::HashMap;
+
+use crate::builder::max_links;
+use crate::ids::Id;
+use crate::import::{GraphNode, MAX_LEVEL};
+use crate::index::Hnsw;
+use crate::scalar::Scalar;
+use crate::store::VectorStore;
+use crate::{HNSWParams, HnswError};
+
+const MAGIC: &[u8] = b"usearch";
+const VERSION: [u16; 3] = [2, 0, 0];
+const HEAD_BYTES: usize = 64;
+const METRIC_COS: u8 = b'c';
+// `scalar_kind_t`
+const SCALAR_BF16: u8 = 4;
+const SCALAR_F64: u8 = 10;
+const SCALAR_F32: u8 = 11;
+const SCALAR_F16: u8 = 12;
+const SCALAR_U64: u8 = 14;
+const SCALAR_U32: u8 = 15;
+/// Key of removed entries
+const FREE_KEY: u64 = u64::MAX;
+
+impl<S: Scalar, V: VectorStore<S>> Hnsw<S, V> {
+    /// Export as a usearch 2.x dense index file
+    pub fn to_usearch(&self) -> Result<Vec<u8>, HnswError> {
+        let ids: Vec<String> = self.ids().map(Id::to_string).collect();
+        let keys: Vec<u64> = ids
+            .iter()
+            .map(|id| {
+                id.parse::<u64>()
+                    .ok()
+                    .filter(|key| *key != FREE_KEY && key.to_string() == *id)
+            })
+            .collect::<Option<_>>()
+            .unwrap_or_else(|| (0..ids.len() as u64).collect());
+        let nodes: Vec<u32> = ids.iter().filter_map(|id| self.node(id)).collect();
+        let slots: HashMap<u32, u32> = nodes
+            .iter()
+            .enumerate()
+            .map(|(i, &node)| (node, i as u32))
+            .collect();
+        let dim = self.stored_dimensions();
+        let too_large =
+            |what: &str| HnswError::Serialization(format!("{} exceed usearch limits", what));
+        let rows = u32::try_from(ids.len()).map_err(|_| too_large("points"))?;
+        let row_bytes = u32::try_from(dim * 4).map_err(|_| too_large("dimensions"))?;
+
+        let mut out = Vec::new();
+        out.extend_from_slice(&rows.to_le_bytes());
+        out.extend_from_slice(&row_bytes.to_le_bytes());
+        for id in &ids {
+            let vector = self
+                .vector(id)
+                .ok_or_else(|| HnswError::Storage(format!("vector of {} is missing", id)))?;
+            for x in vector.iter() {
+                out.extend_from_slice(&(x.to_f64() as f32).to_le_bytes());
+            }
+        }
+
+        let head = out.len();
+        out.extend_from_slice(MAGIC);
+        for part in VERSION {
+            out.extend_from_slice(&part.to_le_bytes());
+        }
+        out.extend_from_slice(&[METRIC_COS, SCALAR_F32, SCALAR_U64, SCALAR_U32]);
+        out.extend_from_slice(&(ids.len() as u64).to_le_bytes());
+        out.extend_from_slice(&0u64.to_le_bytes()); // deleted
+        out.extend_from_slice(&(dim as u64).to_le_bytes());
+        out.push(0); // multi
+        out.resize(head + HEAD_BYTES, 0);
+
+        let m = max_links(self, 1);
+        let base = max_links(self, 0);
+        let entry = self.entry_node();
+        // usearch keeps the top level as `i16`, so an empty graph has -1
+        let max_level = entry
+            .and_then(|node| self.node_level(node))
+            .map_or(-1, |l| l as i64);
+        let entry_slot = entry
+            .and_then(|node| slots.get(&node))
+            .copied()
+            .unwrap_or(0);
+        for value in [ids.len() as u64, m as u64, base as u64, max_level as u64] {
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        out.extend_from_slice(&(entry_slot as u64).to_le_bytes());
+        let levels: Vec<i16> = nodes
+            .iter()
+            .map(|&node| self.node_level(node).unwrap_or(0) as i16)
+            .collect();
+        for level in &levels {
+            out.extend_from_slice(&level.to_le_bytes());
+        }
+        for ((&node, key), level) in nodes.iter().zip(keys).zip(levels) {
+            out.extend_from_slice(&key.to_le_bytes());
+            out.extend_from_slice(&level.to_le_bytes());
+            for layer in 0..=level as usize {
+                let capacity = if layer == 0 { base } else { m };
+                let links: Vec<u32> = self
+                    .links(node, layer)
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|link| slots.get(link).copied())
+                    .take(capacity)
+                    .collect();
+                out.extend_from_slice(&(links.len() as u32).to_le_bytes());
+                for slot in (0..capacity).map(|i| links.get(i).copied().unwrap_or(0)) {
+                    out.extend_from_slice(&slot.to_le_bytes());
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+impl<S: Scalar> Hnsw<S> {
+    /// Load a usearch 2.x dense index file saved with its vectors
+    ///
+    /// `params.m` is taken from the file; see [`Hnsw::from_graph`] for
+    /// what else is kept.
+    pub fn from_usearch(data: &[u8], mut params: HNSWParams) -> Result<Hnsw<S>, HnswError> {
+        let mut reader = Reader { data, pos: 0 };
+        let rows = reader.u32()? as usize;
+        let row_bytes = reader.u32()? as usize;
+        let matrix = reader.take(rows.saturating_mul(row_bytes))?;
+
+        let head = reader.take(HEAD_BYTES)?;
+        if &head[..7] != MAGIC {
+            return Err(usearch_error("missing usearch header"));
+        }
+        if u16::from_le_bytes([head[7], head[8]]) != VERSION[0] {
+            return Err(usearch_error("only format version 2 is supported"));
+        }
+        let (scalar, key, slot) = (head[14], head[15], head[16]);
+        let dim = u64::from_le_bytes(head[33..41].try_into().expect("8 bytes")) as usize;
+        if key != SCALAR_U64 || slot != SCALAR_U32 {
+            return Err(usearch_error("only u64 keys and u32 slots are supported"));
+        }
+        if head[41] != 0 {
+            return Err(usearch_error("multi-key indexes are not supported"));
+        }
+        let width = match scalar {
+            SCALAR_F64 => 8,
+            SCALAR_F32 => 4,
+            SCALAR_F16 | SCALAR_BF16 => 2,
+            other => return Err(usearch_error(&format!("unsupported scalar kind {}", other))),
+        };
+
+        let size = reader.u64()?;
+        let m = reader.u64()? as usize;
+        let base = reader.u64()? as usize;
+        reader.take(16)?; // max level, entry slot
+        if size != rows as u64 {
+            return Err(usearch_error("file was saved without its vectors"));
+        }
+        if rows == 0 {
+            return Ok(Hnsw::with_params(params));
+        }
+        if dim == 0 || Some(row_bytes) != dim.checked_mul(width) {
+            return Err(usearch_error("vector rows do not match the dimensions"));
+        }
+        reader.take(rows * 2)?; // levels, repeated in each node
+        params.m = m.max(1);
+
+        let mut nodes = Vec::with_capacity(rows);
+        for row in matrix.chunks_exact(row_bytes) {
+            let key = reader.u64()?;
+            let level = i16::from_le_bytes(reader.fixed()?);
+            let level = usize::try_from(level)
+                .ok()
+                .filter(|&level| level <= MAX_LEVEL)
+                .ok_or_else(|| usearch_error(&format!("node level {} out of range", level)))?;
+            let mut links = Vec::with_capacity(level + 1);
+            for layer in 0..=level {
+                let capacity = if layer == 0 { base } else { m };
+                let count = reader.u32()? as usize;
+                let slots = reader.take(capacity.saturating_mul(4))?;
+                if count > capacity {
+                    return Err(usearch_error("neighbor list overflows its capacity"));
+                }
+                links.push(
+                    slots
+                        .chunks_exact(4)
+                        .take(count)
+                        .map(|s| u32::from_le_bytes([s[0], s[1], s[2], s[3]]))
+                        .collect(),
+                );
+            }
+            nodes.push((key != FREE_KEY).then(|| {
+                GraphNode {
+                    id: key.to_string(),
+                    vector: row
+                        .chunks_exact(width)
+                        .map(|b| S::from_f64(decode_scalar(scalar, b)))
+                        .collect(),
+                    links,
+                }
+            }));
+        }
+        Hnsw::from_graph(params, nodes)
+    }
+}
+
+fn decode_scalar(kind: u8, b: &[u8]) -> f64 {
+    match kind {
+        SCALAR_F64 => f64::from_le_bytes(b.try_into().expect("8 bytes")),
+        SCALAR_F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+        SCALAR_BF16 => f32::from_bits((u16::from_le_bytes([b[0], b[1]]) as u32) << 16) as f64,
+        _ => f16_to_f64(u16::from_le_bytes([b[0], b[1]])),
+    }
+}
+
+/// IEEE 754 half precision
+fn f16_to_f64(bits: u16) -> f64 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1F) as i32;
+    let fraction = (bits & 0x3FF) as f64;
+    sign * match exponent {
+        0 => fraction * 2f64.powi(-24),
+        0x1F if fraction == 0.0 => f64::INFINITY,
+        0x1F => f64::NAN,
+        _ => (1.0 + fraction / 1024.0) * 2f64.powi(exponent - 15),
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], HnswError> {
+        let bytes = self
+            .pos
+            .checked_add(n)
+            .and_then(|end| self.data.get(self.pos..end))
+            .ok_or_else(|| usearch_error("file is truncated"))?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn fixed<const N: usize>(&mut self) -> Result<[u8; N], HnswError> {
+        let mut out = [0u8; N];
+        out.copy_from_slice(self.take(N)?);
+        Ok(out)
+    }
+
+    fn u32(&mut self) -> Result<u32, HnswError> {
+        Ok(u32::from_le_bytes(self.fixed()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, HnswError> {
+        Ok(u64::from_le_bytes(self.fixed()?))
+    }
+}
+
+fn usearch_error(msg: &str) -> HnswError {
+    HnswError::Deserialization(format!("usearch index: {}", msg))
+}
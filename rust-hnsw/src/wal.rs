@@ -0,0 +1,74 @@
+//! Append-only change log between full snapshots
+//!
+//! [`Hnsw::flush_log`] returns the points changed since the previous
+//! flush as one record, to be appended to durable storage; it is cheap
+//! enough to call after every few writes. On startup, load the last full
+//! snapshot and [`Hnsw::replay_log`] the log written since. Records hold
+//! the state of each changed point rather than the operation, so a record
+//! that overlaps the snapshot replays harmlessly, and the log can be
+//! truncated whenever a new full snapshot is saved.
+//!
+//! Each record is a little-endian `u32` length followed by a delta
+//! snapshot, which carries its own checksum. A record cut short by a
+//! crash can only be the last one and is skipped.
+
+use crate::index::Hnsw;
+use crate::scalar::Scalar;
+use crate::store::VectorStore;
+use crate::HnswError;
+
+impl<S: Scalar, V: VectorStore<S>> Hnsw<S, V> {
+    /// Log record of the points changed since the previous flush
+    ///
+    /// Empty when nothing changed. Append the bytes to the log as is. The
+    /// index stops tracking the flushed changes, as by
+    /// [`Hnsw::forget_changes`].
+    pub fn flush_log(&mut self) -> Result<Vec<u8>, HnswError> {
+        if self.generation() == self.logged_generation() {
+            return Ok(Vec::new());
+        }
+        let delta = self.save_delta(self.logged_generation(), false)?;
+        let len = u32::try_from(delta.len())
+            .map_err(|_| HnswError::Serialization("log record exceeds 4 GiB".to_string()))?;
+        let mut record = Vec::with_capacity(4 + delta.len());
+        record.extend_from_slice(&len.to_le_bytes());
+        record.extend_from_slice(&delta);
+        self.mark_logged();
+        Ok(record)
+    }
+
+    /// Apply the records of a log in order, returning how many were applied
+    ///
+    /// A torn final record is ignored; damage anywhere before it is an
+    /// error.
+    pub fn replay_log(&mut self, log: &[u8]) -> Result<usize, HnswError> {
+        let mut rest = log;
+        let mut applied = 0;
+        while !rest.is_empty() {
+            let Some(len) = rest.get(..4) else {
+                break;
+            };
+            let len = u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize;
+            let Some(record) = rest.get(4..4 + len) else {
+                break;
+            };
+            let last = rest.len() == 4 + len;
+            match self.apply_delta(record) {
+                Ok(_) => applied += 1,
+                Err(HnswError::CorruptSnapshot(_)) if last => break,
+                Err(HnswError::CorruptSnapshot(msg)) => {
+                    return Err(HnswError::CorruptSnapshot(format!(
+                        "log record {}: {}",
+                        applied + 1,
+                        msg
+                    )))
+                }
+                Err(e) => return Err(e),
+            }
+            rest = &rest[4 + len..];
+        }
+        // Everything replayed is durable already
+        self.mark_logged();
+        Ok(applied)
+    }
+}